@@ -1,60 +1,90 @@
-use anyhow::{Context, Result};
-use std::path::Path;
-use std::fs;
-use crate::parser::Fragment;
-use crate::utils;
+use anyhow::Result;
+use dialoguer::Confirm;
+use std::path::{Path, PathBuf};
+use crate::engine::FragmentEngine;
+use crate::parser::{self, Fragment};
+use crate::state::{Claims, Resource, State};
+use crate::{order, utils};
+use sapphire_core::history::{self, Kind, Outcome};
+
+/// How fragments are applied
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApplyOptions {
+    /// Only show the changes
+    pub dry_run: bool,
+    /// Show the changes before making them
+    pub show_diff: bool,
+    /// Leave links, files, defaults and launch agents removed from a fragment
+    /// in place and forget them, instead of cleaning them up
+    pub keep_orphans: bool,
+    /// Make changes that may disrupt network connectivity without asking
+    pub yes: bool,
+}
 
 /// Apply configuration fragments
-pub fn apply<P: AsRef<Path>>(path: P, dry_run: bool) -> Result<()> {
-    let path = path.as_ref();
-    
-    // Verify the path exists
-    if !utils::path_exists(path) {
-        anyhow::bail!("Fragment file not found: {}", path.display());
-    }
-    
-    let files = if path.is_dir() {
-        // Get all .toml files in the directory
-        let entries = fs::read_dir(path)
-            .with_context(|| format!("Failed to read directory: {}", path.display()))?;
-            
-        let mut yaml_files = Vec::new();
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
-            
-            if path.is_file() && path.extension().map(|ext| ext == "yaml" || ext == "yml").unwrap_or(false) {
-                yaml_files.push(path);
-            }
-        }
-        
-        yaml_files
-    } else {
-        // Single file
-        vec![path.to_path_buf()]
-    };
+///
+/// Fragments are applied after those listed in their `after`, a fragment is
+/// skipped if one of those failed. Fragments whose `when` does not match this
+/// machine are skipped, without holding back those applied after them.
+/// Resources no fragment declares any more since they were applied are
+/// cleaned up, including those of fragment files deleted or disabled since.
+pub fn apply(path: &str, options: ApplyOptions) -> Result<()> {
+    let files = utils::fragment_files(path)?;
     
     if files.is_empty() {
-        tracing::warn!("No fragment files found at: {}", path.display());
+        tracing::warn!("No fragment files found at: {}", path);
         return Ok(());
     }
     
+    let engine = FragmentEngine::new();
+    let mut state = State::load()?;
+    let (fragments, mut failed) = order::load(files.clone());
+    let apply_order = order::resolve(&fragments)?;
+    let mut claims = Claims::new(&engine, &state, &files, &fragments);
     let mut applied = 0;
-    let mut failed = 0;
+    let mut succeeded = vec![false; fragments.len()];
     
-    for file in &files {
-        match apply_fragment(file, dry_run) {
+    for index in apply_order.order {
+        let (file, fragment) = &fragments[index];
+        if let Some(blocker) = apply_order.after[index].iter().find(|dependency| !succeeded[**dependency]) {
+            tracing::error!("Skipping fragment {}, it is applied after {} which failed",
+                file.display(), fragments[*blocker].0.display());
+            failed += 1;
+            continue;
+        }
+        if let Some(reason) = fragment.when.unmatched() {
+            tracing::info!("Skipping fragment {}, {}", file.display(), reason);
+            succeeded[index] = true;
+            continue;
+        }
+        
+        let declared = claims.declared[index].take();
+        match apply_fragment(&engine, &mut state, file, fragment, declared, &claims.claimed, options) {
             Ok(_) => {
                 applied += 1;
+                succeeded[index] = true;
             }
             Err(err) => {
-                tracing::error!("Failed to apply fragment {}: {}", file.display(), err);
+                tracing::error!("Failed to apply fragment {}: {:#}", file.display(), err);
                 failed += 1;
             }
         }
     }
     
-    tracing::info!("Applied {} fragments, {} failed", applied, failed);
+    // Last, so resources moved out of a removed fragment are recorded by the one declaring them now
+    let scope = PathBuf::from(shellexpand::tilde(path).into_owned());
+    for file in state.gone(&scope, &files) {
+        if let Err(err) = remove_fragment(&engine, &mut state, &file, &claims.claimed, options) {
+            tracing::error!("Failed to clean up after removed fragment {}: {:#}", file.display(), err);
+            failed += 1;
+        }
+    }
+    
+    if options.dry_run {
+        tracing::info!("Dry run - no changes were made");
+    } else {
+        tracing::info!("Applied {} fragments, {} failed", applied, failed);
+    }
     
     if failed > 0 {
         anyhow::bail!("Failed to apply {} fragments", failed);
@@ -63,22 +93,155 @@ pub fn apply<P: AsRef<Path>>(path: P, dry_run: bool) -> Result<()> {
     Ok(())
 }
 
+/// Plan the fragments again after applying and fail if anything would still change
+///
+/// Used by `apply --check` to verify that applying converges, e.g. in CI.
+/// Scripts and restarts run on every apply and are not counted.
+pub fn check_converged(path: &str) -> Result<()> {
+    let engine = FragmentEngine::new();
+    let mut pending = 0;
+    
+    for file in utils::fragment_files(path)? {
+        let fragment = Fragment::from_file(&file)?;
+        if fragment.when.unmatched().is_some() {
+            continue;
+        }
+        let plan = engine.plan(&fragment, file.parent().unwrap_or(Path::new(".")))?;
+        
+        for change in plan.pending() {
+            tracing::error!("Still pending in {}: {}", file.display(), change.target);
+            pending += 1;
+        }
+    }
+    
+    if pending > 0 {
+        anyhow::bail!("Not converged, {} change(s) still pending after apply", pending);
+    }
+    
+    tracing::info!("Converged: applying again would not change anything");
+    Ok(())
+}
+
 /// Apply a single fragment file
-fn apply_fragment(path: &Path, dry_run: bool) -> Result<()> {
-    if !utils::path_exists(path) {
-        anyhow::bail!("Fragment file does not exist: {}", path.display());
+///
+/// `declared` are the resources of the fragment if they could be worked out
+/// beforehand, `claimed` those of every fragment applied with it.
+fn apply_fragment(
+    engine: &FragmentEngine,
+    state: &mut State,
+    path: &Path,
+    fragment: &Fragment,
+    declared: Option<Vec<Resource>>,
+    claimed: &[Resource],
+    options: ApplyOptions,
+) -> Result<()> {
+    // Unknown keys are ignored when applying, point out likely typos
+    if let Ok(issues) = utils::read_file(path).and_then(|content| parser::check_strict(&content)) {
+        for issue in issues {
+            tracing::warn!("{}: {} (see `fragment validate`)", path.display(), issue);
+        }
+    }
+    
+    tracing::debug!("Fragment type: {:?}, Description: {}", fragment.fragment_type, fragment.description);
+    
+    // Checked before planning, so nothing is applied on a machine that cannot take the fragment
+    let unmet = fragment.requires.unmet();
+    for problem in &unmet {
+        tracing::error!("{}: requirement not met, {}", path.display(), problem);
+    }
+    if !unmet.is_empty() && !options.dry_run {
+        anyhow::bail!("{} requirement(s) not met on this machine", unmet.len());
+    }
+    
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut plan = engine.plan(fragment, base_dir)?;
+    let declared = match declared {
+        Some(declared) => declared,
+        // Worked out again for the error
+        None => engine.resources(fragment, base_dir, &state.all())?,
+    };
+    if !options.keep_orphans && !plan.unsupported {
+        plan.changes.extend(engine.plan_removals(state.managed(path), claimed));
     }
     
-    let fragment = Fragment::from_file(path)?;
+    if options.dry_run || options.show_diff {
+        plan.render(&path.display().to_string());
+    }
+    
+    if options.dry_run {
+        return Ok(());
+    }
+    
+    let disruptive: Vec<&str> = plan.changes.iter()
+        .filter(|change| change.operation.is_disruptive())
+        .map(|change| change.target.as_str())
+        .collect();
+    if !disruptive.is_empty() && !options.yes {
+        confirm_disruptive(&disruptive)?;
+    }
     
     tracing::info!("Applying fragment: {}", path.display());
-    tracing::info!("Fragment type: {:?}, Description: {}", fragment.fragment_type, fragment.description);
+    let result = engine.apply(&plan);
     
-    if dry_run {
-        tracing::info!("Dry run - no changes will be made");
+    // After a failure the orphans stay recorded, so the next apply removes them
+    if !plan.unsupported {
+        state.record(path, declared, claimed, result.is_err());
+        if let Err(e) = state.save() {
+            tracing::warn!("Could not record the resources of {}: {:#}", path.display(), e);
+        }
     }
     
-    // TODO: Implement fragment application based on type
+    let outcome = Outcome { success: result.is_ok(), ..Outcome::default() };
+    if let Err(e) = history::record(Kind::Fragment, &path.display().to_string(), outcome) {
+        tracing::warn!("Could not record apply of {}: {}", path.display(), e);
+    }
+    result
+}
+
+/// Clean up the resources of a fragment file deleted or disabled since it was applied
+fn remove_fragment(
+    engine: &FragmentEngine,
+    state: &mut State,
+    path: &Path,
+    claimed: &[Resource],
+    options: ApplyOptions,
+) -> Result<()> {
+    let mut plan = engine.plan_removed(state.managed(path), claimed);
+    if options.keep_orphans {
+        plan.changes.clear();
+    }
+    
+    if options.dry_run || options.show_diff {
+        plan.render(&format!("{} (removed)", path.display()));
+    }
+    
+    if options.dry_run {
+        return Ok(());
+    }
+    
+    tracing::info!("Cleaning up after removed fragment: {}", path.display());
+    let result = engine.apply(&plan);
     
+    state.record(path, Vec::new(), claimed, result.is_err());
+    if let Err(e) = state.save() {
+        tracing::warn!("Could not record the resources of {}: {:#}", path.display(), e);
+    }
+    result
+}
+
+/// Ask before changes that may cut the network connection, e.g. to a DNS server that is not reachable
+fn confirm_disruptive(targets: &[&str]) -> Result<()> {
+    let targets = targets.join(", ");
+    if !console::user_attended() {
+        anyhow::bail!("Changing {} may disrupt network connectivity, pass --yes to apply it without a terminal", targets);
+    }
+    
+    let confirmed = Confirm::new()
+        .with_prompt(format!("Changing {} may disrupt network connectivity. Continue?", targets))
+        .default(false)
+        .interact()?;
+    if !confirmed {
+        anyhow::bail!("Not applied, changing {} was declined", targets);
+    }
     Ok(())
-}
\ No newline at end of file
+}
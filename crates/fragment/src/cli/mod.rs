@@ -1,18 +1,27 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Parser, Subcommand};
 use tracing::{Level, debug};
 use tracing_subscriber::{fmt, EnvFilter};
-use crate::{apply, diff, init};
+use sapphire_core::paths::SapphirePaths;
+use sapphire_core::read_only;
+use crate::apply::{self, ApplyOptions};
+use crate::{diff, init, manage, utils, validate};
+use std::path::PathBuf;
 use std::sync::Once;
 
 // Static to ensure we only initialize logging once
 static INIT_LOGGER: Once = Once::new();
 
-// Initialize logging with the specified verbosity level
-fn init_logging(verbose: bool) {
+// Initialize logging: errors only when quiet, debug for -v and trace for -vv
+fn init_logging(quiet: bool, verbose: u8) {
     // Only initialize once
     INIT_LOGGER.call_once(|| {
-        let level = if verbose { Level::DEBUG } else { Level::INFO };
+        let level = match (quiet, verbose) {
+            (true, _) => Level::ERROR,
+            (false, 0) => Level::INFO,
+            (false, 1) => Level::DEBUG,
+            (false, _) => Level::TRACE,
+        };
         
         // Create a custom filter
         let filter = EnvFilter::from_default_env()
@@ -22,7 +31,7 @@ fn init_logging(verbose: bool) {
         if let Err(e) = fmt::Subscriber::builder()
             .with_env_filter(filter)
             .with_target(false)
-            .with_ansi(true)
+            .with_ansi(console::colors_enabled())
             .try_init() {
             eprintln!("Warning: Could not initialize logging: {}", e);
         } else {
@@ -34,9 +43,25 @@ fn init_logging(verbose: bool) {
 #[derive(Debug, Parser)]
 #[command(author, version, about = "Fragment configuration tool", long_about = None)]
 pub struct Cli {
-    /// Enable verbose output
-    #[arg(short, long)]
-    verbose: bool,
+    /// Enable verbose output, repeat (-vv) for trace output
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Only print errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Disable colored output, also disabled by setting NO_COLOR
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Wait for another running sapphire process instead of failing
+    #[arg(long, global = true)]
+    wait: bool,
+
+    /// Sapphire directory to use instead of ~/.sapphire, SAPPHIRE_HOME or the configured one
+    #[arg(long, global = true, value_name = "DIR")]
+    root: Option<PathBuf>,
 
     #[command(subcommand)]
     command: Commands,
@@ -46,31 +71,71 @@ pub struct Cli {
 enum Commands {
     /// Apply configuration fragment
     Apply {
-        /// Path to fragment file
-        #[arg(default_value = "~/.sapphire/fragments/user")]
-        path: String,
+        /// Path to a fragment file or a directory, e.g. ~/.sapphire/fragments to apply all enabled fragments.
+        /// Defaults to the user fragment
+        path: Option<String>,
         
-        /// Dry run without making changes
+        /// Show the changes without making them
         #[arg(short, long)]
         dry_run: bool,
+        
+        /// Show the changes before making them
+        #[arg(long, conflicts_with = "dry_run")]
+        diff: bool,
+        
+        /// Fail if applying again would still change something
+        #[arg(long, conflicts_with = "dry_run")]
+        check: bool,
+        
+        /// Leave links, defaults and launch agents removed from a fragment in place instead of cleaning them up
+        #[arg(long)]
+        keep_orphans: bool,
+        
+        /// Make changes that may disrupt network connectivity without asking
+        #[arg(short, long)]
+        yes: bool,
     },
     
     /// Check fragment for changes
     Diff {
-        /// Path to fragment file
-        #[arg(default_value = "~/.sapphire/fragments/user")]
-        path: String,
+        /// Path to a fragment file or a directory of fragments, the user fragment by default
+        path: Option<String>,
+        
+        /// Output format: text or json
+        #[arg(short, long, default_value = "text", alias = "output")]
+        format: String,
+    },
+    
+    /// Stop applying a fragment (moves it to ~/.sapphire/fragments/disabled)
+    Disable {
+        /// File name of the fragment without extension, or its path relative to ~/.sapphire/fragments
+        name: String,
+    },
+    
+    /// Apply a previously disabled fragment again
+    Enable {
+        /// File name of the fragment without extension, or its path relative to ~/.sapphire/fragments
+        name: String,
+    },
+    
+    /// Check fragments for unknown sections, keys and invalid entries
+    Validate {
+        /// Path to fragment file or directory, the user fragment by default
+        path: Option<String>,
     },
     
     /// Create new fragment from template
     Init {
-        /// Fragment type
+        /// Fragment type (see --list)
         #[arg(long, default_value = "dotfiles")]
         fragment_type: String,
         
-        /// Path to create fragment file
-        #[arg(default_value = "~/.sapphire/fragments/user")]
-        path: String,
+        /// List available fragment types and exit
+        #[arg(long)]
+        list: bool,
+        
+        /// Path to create fragment file, the user fragment by default
+        path: Option<String>,
         
         /// Override existing fragment
         #[arg(short, long)]
@@ -99,33 +164,96 @@ enum Commands {
         task: String,
         
         /// Fragment path (default is user fragment)
-        #[arg(short, long, default_value = "~/.sapphire/fragments/user")]
-        fragment: String,
+        #[arg(short, long)]
+        fragment: Option<String>,
     },
     
     /// List all available tasks in a fragment
     Tasks {
-        /// Fragment path
-        #[arg(default_value = "~/.sapphire/fragments/user")]
-        fragment: String,
+        /// Fragment path, the user fragment by default
+        fragment: Option<String>,
     },
 }
 
+impl Commands {
+    /// Whether the command changes files or settings, refused in read-only mode
+    ///
+    /// Tasks run arbitrary commands, so they count as changes.
+    fn is_mutating(&self) -> bool {
+        match self {
+            Commands::Apply { dry_run, .. } => !dry_run,
+            Commands::Config { value, .. } => value.is_some(),
+            Commands::Init { list, .. } => !list,
+            Commands::Disable { .. } | Commands::Enable { .. } | Commands::Run { .. } => true,
+            Commands::Diff { .. } | Commands::Validate { .. } | Commands::Tasks { .. } => false,
+        }
+    }
+
+    /// Whether the command holds the sapphire lock while it runs
+    ///
+    /// Tasks are not locked, since they may run shard or fragment themselves.
+    fn takes_lock(&self) -> bool {
+        self.is_mutating() && !matches!(self, Commands::Run { .. })
+    }
+}
+
 pub fn run() -> Result<()> {
     let cli = Cli::parse();
     
+    // Everything below resolves its paths from the root
+    if let Some(root) = &cli.root {
+        SapphirePaths::set_root(root);
+    }
+    
     // Initialize logger
-    init_logging(cli.verbose);
+    if cli.no_color {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    init_logging(cli.quiet, cli.verbose);
+    
+    if cli.command.is_mutating() {
+        read_only::ensure_writable()?;
+    }
+    
+    // Held until the command finished
+    let _lock = if cli.command.takes_lock() {
+        Some(sapphire_core::lock::acquire(cli.wait)?)
+    } else {
+        None
+    };
     
     match cli.command {
-        Commands::Apply { path, dry_run } => {
-            apply::apply(&path, dry_run)
+        Commands::Apply { path, dry_run, diff, check, keep_orphans, yes } => {
+            let path = path.unwrap_or_else(utils::user_fragment);
+            apply::apply(&path, ApplyOptions { dry_run, show_diff: diff, keep_orphans, yes })?;
+            if check {
+                apply::check_converged(&path)?;
+            }
+            Ok(())
+        },
+        Commands::Diff { path, format } => {
+            let path = path.unwrap_or_else(utils::user_fragment);
+            diff::diff(&path, &format)
+        },
+        Commands::Disable { name } => {
+            manage::disable(&name)
+        },
+        Commands::Enable { name } => {
+            manage::enable(&name)
         },
-        Commands::Diff { path } => {
-            diff::diff(&path)
+        Commands::Validate { path } => {
+            let path = path.unwrap_or_else(utils::user_fragment);
+            validate::validate(&path)
         },
-        Commands::Init { fragment_type, path, force: _ } => {
-            init::init(&fragment_type, &path)
+        Commands::Init { fragment_type, list, path, force } => {
+            let path = path.unwrap_or_else(utils::user_fragment);
+            if list {
+                init::list();
+                Ok(())
+            } else {
+                init::init(&fragment_type, &path, force)
+            }
         },
         Commands::Config { domain, key, value: _, r#type: _ } => {
             // TODO: Implement config functionality
@@ -133,14 +261,48 @@ pub fn run() -> Result<()> {
             Ok(())
         },
         Commands::Run { task, fragment } => {
+            let fragment = fragment.unwrap_or_else(utils::user_fragment);
             // TODO: Implement task runner
             println!("Running task {} from fragment {}", task, fragment);
             Ok(())
         },
         Commands::Tasks { fragment } => {
+            let fragment = fragment.unwrap_or_else(utils::user_fragment);
             // TODO: Implement task listing
             println!("Listing tasks from fragment {}", fragment);
             Ok(())
         },
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str]) -> Commands {
+        Cli::try_parse_from([&["fragment"], args].concat()).unwrap().command
+    }
+
+    #[test]
+    fn commands_changing_the_system_are_gated() {
+        for args in [&["apply"][..], &["disable", "work"], &["enable", "work"], &["init"], &["run", "build"],
+                     &["config", "com.apple.dock", "autohide", "true"]] {
+            assert!(command(args).is_mutating(), "{:?} should be refused in read-only mode", args);
+        }
+    }
+
+    #[test]
+    fn previews_and_queries_are_not_gated() {
+        for args in [&["apply", "--dry-run"][..], &["diff"], &["validate"], &["init", "--list"], &["tasks"],
+                     &["config", "com.apple.dock", "autohide"]] {
+            assert!(!command(args).is_mutating(), "{:?} should work in read-only mode", args);
+        }
+    }
+
+    #[test]
+    fn tasks_are_gated_but_not_locked() {
+        let run = command(&["run", "build"]);
+        assert!(run.is_mutating());
+        assert!(!run.takes_lock());
+        assert!(command(&["apply"]).takes_lock());
+    }
+}
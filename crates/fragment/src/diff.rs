@@ -1,85 +1,240 @@
-use anyhow::{Context, Result};
-use std::path::Path;
-use std::fs;
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use crate::engine::FragmentEngine;
 use crate::parser::Fragment;
-use crate::utils;
+use crate::plan::{ChangeKind, FragmentPlan};
+use crate::state::{Claims, Resource, State};
+use crate::{order, utils};
 
-/// Check for differences in configuration fragments
-pub fn diff<P: AsRef<Path>>(path: P) -> Result<()> {
-    let path = path.as_ref();
+/// Differences of one fragment, as printed by `diff --format json`
+#[derive(Debug, Serialize)]
+struct FragmentReport {
+    path: String,
+    fragment_type: String,
+    /// Whether changes can be previewed for the fragment type
+    supported: bool,
+    /// Number of settings compared, for fragments reporting compliance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checked: Option<usize>,
+    /// Set if the fragment could not be checked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Why the fragment does not apply to this machine, if it doesn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<String>,
+    counts: Counts,
+    sections: Vec<SectionReport>,
+}
+
+/// Changes of one section, e.g. files or preferences
+#[derive(Debug, Serialize)]
+struct SectionReport {
+    section: String,
+    changes: Vec<ChangeReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChangeReport {
+    kind: ChangeKind,
+    target: String,
+    current: Option<String>,
+    desired: String,
+}
+
+/// Number of changes by kind
+#[derive(Debug, Default, Serialize)]
+struct Counts {
+    create: usize,
+    update: usize,
+    remove: usize,
+    run: usize,
+    manual: usize,
+}
+
+impl Counts {
+    fn of(plan: &FragmentPlan) -> Self {
+        Counts {
+            create: plan.count(ChangeKind::Create),
+            update: plan.count(ChangeKind::Update),
+            remove: plan.count(ChangeKind::Remove),
+            run: plan.count(ChangeKind::Run),
+            manual: plan.count(ChangeKind::Manual),
+        }
+    }
+
+    fn add(&mut self, other: &Counts) {
+        self.create += other.create;
+        self.update += other.update;
+        self.remove += other.remove;
+        self.run += other.run;
+        self.manual += other.manual;
+    }
+}
+
+impl FragmentReport {
+    fn new(path: &Path, plan: &FragmentPlan) -> Self {
+        let sections = plan.sections().into_iter()
+            .map(|(section, changes)| SectionReport {
+                section: section.to_string(),
+                changes: changes.into_iter()
+                    .map(|change| ChangeReport {
+                        kind: change.kind,
+                        target: change.target.clone(),
+                        current: change.current.clone(),
+                        desired: change.desired.clone(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        
+        FragmentReport {
+            path: path.display().to_string(),
+            fragment_type: plan.fragment_type.to_string(),
+            supported: !plan.unsupported,
+            checked: plan.checked,
+            error: None,
+            skipped: None,
+            counts: Counts::of(plan),
+            sections,
+        }
+    }
     
-    // Verify the path exists
-    if !utils::path_exists(path) {
-        anyhow::bail!("Fragment file not found: {}", path.display());
+    fn failed(path: &Path, fragment: &Fragment, err: &anyhow::Error) -> Self {
+        FragmentReport {
+            path: path.display().to_string(),
+            fragment_type: fragment.fragment_type.to_string(),
+            supported: true,
+            checked: None,
+            error: Some(format!("{:#}", err)),
+            skipped: None,
+            counts: Counts::default(),
+            sections: Vec::new(),
+        }
     }
     
-    let files = if path.is_dir() {
-        // Get all .toml files in the directory
-        let entries = fs::read_dir(path)
-            .with_context(|| format!("Failed to read directory: {}", path.display()))?;
-            
-        let mut yaml_files = Vec::new();
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
-            
-            if path.is_file() && path.extension().map(|ext| ext == "yaml" || ext == "yml").unwrap_or(false) {
-                yaml_files.push(path);
-            }
+    fn skipped(path: &Path, fragment: &Fragment, reason: String) -> Self {
+        FragmentReport {
+            path: path.display().to_string(),
+            fragment_type: fragment.fragment_type.to_string(),
+            supported: true,
+            checked: None,
+            error: None,
+            skipped: Some(reason),
+            counts: Counts::default(),
+            sections: Vec::new(),
         }
-        
-        yaml_files
-    } else {
-        // Single file
-        vec![path.to_path_buf()]
+    }
+}
+
+/// Check for differences in configuration fragments
+///
+/// Shows the same changes as `apply --dry-run`, in apply order and grouped
+/// by section. `format` is "text" for a colored report or "json". Fragment
+/// files deleted or disabled since they were applied are reported with the
+/// removal of their resources.
+pub fn diff(path: &str, format: &str) -> Result<()> {
+    let json = match format {
+        "text" => false,
+        "json" => true,
+        other => anyhow::bail!("Unsupported format '{}', expected \"text\" or \"json\"", other),
     };
     
+    let files = utils::fragment_files(path)?;
+    
     if files.is_empty() {
-        tracing::warn!("No fragment files found at: {}", path.display());
+        if json {
+            println!("[]");
+        } else {
+            tracing::warn!("No fragment files found at: {}", path);
+        }
         return Ok(());
     }
     
-    let mut checked = 0;
-    let mut with_diffs = 0;
+    let engine = FragmentEngine::new();
+    let state = State::load()?;
+    let (fragments, _) = order::load(files.clone());
+    let apply_order = order::resolve(&fragments)?;
+    let mut claims = Claims::new(&engine, &state, &files, &fragments);
+    let mut reports = Vec::new();
     
-    for file in &files {
-        match check_fragment_diff(file) {
-            Ok(has_diffs) => {
-                checked += 1;
-                if has_diffs {
-                    with_diffs += 1;
+    for index in apply_order.order {
+        let (file, fragment) = &fragments[index];
+        tracing::debug!("Fragment type: {:?}, Description: {}", fragment.fragment_type, fragment.description);
+        if let Some(reason) = fragment.when.unmatched() {
+            if !json {
+                tracing::info!("Skipping fragment {}, {}", file.display(), reason);
+            }
+            reports.push(FragmentReport::skipped(file, fragment, reason));
+            continue;
+        }
+        let declared = claims.declared[index].take();
+        match plan_fragment(&engine, &state, file, fragment, declared, &claims.claimed) {
+            Ok(plan) => {
+                if !json {
+                    plan.render(&file.display().to_string());
                 }
+                reports.push(FragmentReport::new(file, &plan));
             }
             Err(err) => {
-                tracing::error!("Failed to check fragment {}: {}", file.display(), err);
+                if !json {
+                    tracing::error!("Failed to check fragment {}: {:#}", file.display(), err);
+                }
+                reports.push(FragmentReport::failed(file, fragment, &err));
             }
         }
     }
     
-    tracing::info!("Checked {} fragments, {} with differences", checked, with_diffs);
-    
-    Ok(())
-}
-
-/// Check for differences in a single fragment file
-fn check_fragment_diff(path: &Path) -> Result<bool> {
-    if !utils::path_exists(path) {
-        anyhow::bail!("Fragment file does not exist: {}", path.display());
+    let scope = PathBuf::from(shellexpand::tilde(path).into_owned());
+    for file in state.gone(&scope, &files) {
+        let plan = engine.plan_removed(state.managed(&file), &claims.claimed);
+        if !json {
+            plan.render(&format!("{} (removed)", file.display()));
+        }
+        reports.push(FragmentReport::new(&file, &plan));
     }
     
-    let fragment = Fragment::from_file(path)?;
-    
-    tracing::info!("Checking fragment: {}", path.display());
-    tracing::info!("Fragment type: {:?}, Description: {}", fragment.fragment_type, fragment.description);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
     
-    // TODO: Implement diff checking based on fragment type
+    let checked: Vec<&FragmentReport> = reports.iter()
+        .filter(|report| report.error.is_none() && report.skipped.is_none())
+        .collect();
+    let mut total = Counts::default();
+    for report in &checked {
+        total.add(&report.counts);
+    }
+    let with_diffs = checked.iter()
+        .filter(|report| report.counts.create + report.counts.update + report.counts.remove + report.counts.run + report.counts.manual > 0)
+        .count();
     
-    // Placeholder for diff detection
-    let has_diffs = false;
+    tracing::info!(
+        "Checked {} fragments, {} with differences: {} to create, {} to update, {} to remove, {} to run, {} to fix by hand",
+        checked.len(), with_diffs, total.create, total.update, total.remove, total.run, total.manual
+    );
     
-    if !has_diffs {
-        tracing::info!("No differences found in {}", path.display());
+    Ok(())
+}
+
+/// Plan a fragment the way apply does, including the removal of orphans
+fn plan_fragment(
+    engine: &FragmentEngine,
+    state: &State,
+    path: &Path,
+    fragment: &Fragment,
+    declared: Option<Vec<Resource>>,
+    claimed: &[Resource],
+) -> Result<FragmentPlan> {
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut plan = engine.plan(fragment, base_dir)?;
+    if !plan.unsupported {
+        if declared.is_none() {
+            // Worked out again for the error
+            engine.resources(fragment, base_dir, &state.all())?;
+        }
+        plan.changes.extend(engine.plan_removals(state.managed(path), claimed));
     }
-    
-    Ok(has_diffs)
-}
\ No newline at end of file
+    Ok(plan)
+}
@@ -1,7 +1,22 @@
-use anyhow::Result;
-use crate::parser::{Fragment, FragmentType};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::parser::{
+    BackupFragment, CustomFragment, DotfilesFragment, Fragment, FragmentType, NetworkFragment, ProxySettings,
+    SecurityBaselineFragment, ServiceState, ServicesFragment, SystemFragment,
+};
+use crate::plan::{Change, ChangeKind, FragmentPlan, Operation};
+use crate::state::{self, Resource};
 
 /// Engine for applying fragments
+///
+/// Every fragment is first turned into a [`FragmentPlan`] of concrete changes.
+/// Previews (`apply --dry-run`, `apply --diff` and `diff`) render that plan,
+/// applying executes it, so both always agree.
 pub struct FragmentEngine;
 
 impl FragmentEngine {
@@ -9,76 +24,1059 @@ impl FragmentEngine {
     pub fn new() -> Self {
         Self
     }
-    
-    /// Apply a fragment
-    pub fn apply(&self, fragment: &Fragment, dry_run: bool) -> Result<()> {
+
+    /// Work out the changes applying a fragment would make
+    ///
+    /// Relative paths in the fragment are resolved against `base_dir`, the
+    /// directory containing the fragment file.
+    pub fn plan(&self, fragment: &Fragment, base_dir: &Path) -> Result<FragmentPlan> {
         match fragment.fragment_type {
-            FragmentType::Dotfiles => self.apply_dotfiles(fragment, dry_run),
-            FragmentType::System => self.apply_system(fragment, dry_run),
-            FragmentType::Network => self.apply_network(fragment, dry_run),
-            FragmentType::Custom => self.apply_custom(fragment, dry_run),
+            FragmentType::Dotfiles => self.plan_dotfiles(fragment, base_dir),
+            FragmentType::System | FragmentType::MacosDefaults => self.plan_defaults(fragment),
+            FragmentType::Services => self.plan_services(fragment),
+            FragmentType::Custom => self.plan_custom(fragment, base_dir),
+            FragmentType::SecurityBaseline => self.plan_security(fragment),
+            FragmentType::Network => self.plan_network(fragment),
+            FragmentType::Backup => self.plan_backup(fragment),
+            FragmentType::DevShell => {
+                Ok(FragmentPlan::unsupported(fragment.fragment_type.clone()))
+            }
+        }
+    }
+
+    /// Execute a plan, stopping at the first failing change
+    ///
+    /// If changes need administrator rights, sudo asks for the password once
+    /// before anything is changed.
+    pub fn apply(&self, plan: &FragmentPlan) -> Result<()> {
+        if plan.unsupported {
+            tracing::warn!("Applying {} fragments is not supported yet", plan.fragment_type);
+            return Ok(());
+        }
+
+        let privileged: Vec<&str> = plan.changes.iter()
+            .filter(|change| change.operation.needs_sudo())
+            .map(|change| change.target.as_str())
+            .collect();
+        if !privileged.is_empty() {
+            ensure_sudo(&privileged)?;
+        }
+
+        for change in &plan.changes {
+            tracing::debug!("Applying {:?}", change.operation);
+            self.execute(&change.operation)
+                .with_context(|| format!("Failed to apply change to {}", change.target))?;
         }
+
+        Ok(())
     }
-    
-    /// Check for differences in a fragment
-    pub fn diff(&self, fragment: &Fragment) -> Result<bool> {
+
+    /// Resources the fragment manages, recorded after applying it
+    ///
+    /// `managed` are the resources recorded for the fragment so far. Defaults
+    /// keep the previous values recorded there, the current values of
+    /// defaults the fragment did not manage before are read now.
+    pub fn resources(&self, fragment: &Fragment, base_dir: &Path, managed: &[Resource]) -> Result<Vec<Resource>> {
         match fragment.fragment_type {
-            FragmentType::Dotfiles => self.diff_dotfiles(fragment),
-            FragmentType::System => self.diff_system(fragment),
-            FragmentType::Network => self.diff_network(fragment),
-            FragmentType::Custom => self.diff_custom(fragment),
+            FragmentType::Dotfiles => {
+                let section: DotfilesFragment = fragment.section()?;
+                let files = section.files.iter().map(|file| match file.template {
+                    true => Resource::File { target: expand_path(&file.target) },
+                    false => Resource::Link { target: expand_path(&file.target), source: resolve_path(base_dir, &file.source) },
+                });
+                let directories = section.directories.iter()
+                    .map(|dir| Resource::Link { target: expand_path(&dir.target), source: resolve_path(base_dir, &dir.source) });
+                Ok(files.chain(directories).collect())
+            }
+            FragmentType::System | FragmentType::MacosDefaults => {
+                let section: SystemFragment = fragment.section()?;
+                section.preferences.iter()
+                    .map(|preference| {
+                        let recorded = managed.iter().find(|recorded| matches!(recorded,
+                            Resource::Default { domain, key, .. } if *domain == preference.domain && *key == preference.key));
+                        if let Some(recorded) = recorded {
+                            return Ok(recorded.clone());
+                        }
+                        let previous = read_default(&preference.domain, &preference.key)?
+                            .map(|raw| normalize_default(&preference.value_type, &raw));
+                        Ok(Resource::Default {
+                            domain: preference.domain.clone(),
+                            key: preference.key.clone(),
+                            value_type: preference.value_type.clone(),
+                            previous,
+                        })
+                    })
+                    .collect()
+            }
+            FragmentType::Services => {
+                let section: ServicesFragment = fragment.section()?;
+                Ok(section.launch_agents.iter()
+                    .map(|agent| Resource::LaunchAgent {
+                        label: agent.label.clone(),
+                        path: expand_path(&format!("~/Library/LaunchAgents/{}.plist", agent.label)),
+                    })
+                    .collect())
+            }
+            FragmentType::Backup => {
+                let section: BackupFragment = fragment.section()?;
+                Ok(section.time_machine.iter()
+                    .flat_map(|time_machine| &time_machine.exclusions)
+                    .map(|path| Resource::BackupExclusion { path: expand_path(path) })
+                    .collect())
+            }
+            _ => Ok(Vec::new()),
         }
     }
-    
+
+    /// Changes cleaning up the resources of `managed` that no fragment in `claimed` declares
+    pub fn plan_removals(&self, managed: &[Resource], claimed: &[Resource]) -> Vec<Change> {
+        state::orphans(managed, claimed).into_iter()
+            .filter_map(|resource| match resource {
+                Resource::Link { target, source } => {
+                    // Left alone if something else replaced the link since
+                    let link = fs::read_link(target).ok()?;
+                    (link == *source).then(|| Change {
+                        kind: ChangeKind::Remove,
+                        target: target.display().to_string(),
+                        current: Some(format!("link to {}", source.display())),
+                        desired: "removed".to_string(),
+                        operation: Operation::RemoveFile { path: target.clone() },
+                    })
+                }
+                Resource::File { target } => target.is_file().then(|| Change {
+                    kind: ChangeKind::Remove,
+                    target: target.display().to_string(),
+                    current: Some("rendered file".to_string()),
+                    desired: "removed".to_string(),
+                    operation: Operation::RemoveFile { path: target.clone() },
+                }),
+                Resource::Default { domain, key, value_type, previous } => Some(Change {
+                    kind: ChangeKind::Remove,
+                    target: format!("{} {}", domain, key),
+                    current: None,
+                    desired: previous.as_ref()
+                        .map_or("deleted".to_string(), |values| format!("restored to {}", values.join(", "))),
+                    operation: Operation::RestoreDefault {
+                        domain: domain.clone(),
+                        key: key.clone(),
+                        value_type: value_type.clone(),
+                        previous: previous.clone(),
+                    },
+                }),
+                Resource::BackupExclusion { path } => {
+                    // Left alone if it is gone or was included again since
+                    let excluded = path.exists() && backup_excluded(path).unwrap_or(false);
+                    excluded.then(|| Change {
+                        kind: ChangeKind::Remove,
+                        target: path.display().to_string(),
+                        current: Some("excluded".to_string()),
+                        desired: "backed up".to_string(),
+                        operation: Operation::BackupExclusion { path: path.clone(), excluded: false },
+                    })
+                }
+                Resource::LaunchAgent { label, path } => path.exists().then(|| Change {
+                    kind: ChangeKind::Remove,
+                    target: format!("launch agent {}", label),
+                    current: Some(path.display().to_string()),
+                    desired: "removed".to_string(),
+                    operation: Operation::RemoveLaunchAgent { path: path.clone() },
+                }),
+            })
+            .collect()
+    }
+
+    /// Plan cleaning up after a fragment file deleted or disabled since it was
+    /// applied, `managed` are the resources recorded for it
+    pub fn plan_removed(&self, managed: &[Resource], claimed: &[Resource]) -> FragmentPlan {
+        let fragment_type = managed.first().map_or(FragmentType::Dotfiles, Resource::fragment_type);
+        FragmentPlan::new(fragment_type, self.plan_removals(managed, claimed))
+    }
+
     // Dotfiles fragment handlers
-    fn apply_dotfiles(&self, _fragment: &Fragment, _dry_run: bool) -> Result<()> {
-        tracing::info!("Applying dotfiles fragment");
-        // TODO: Implement dotfiles application
-        Ok(())
+    fn plan_dotfiles(&self, fragment: &Fragment, base_dir: &Path) -> Result<FragmentPlan> {
+        let section: DotfilesFragment = fragment.section()?;
+        let entries = section.files.iter().map(|f| (&f.source, &f.target, f.backup, f.template))
+            .chain(section.directories.iter().map(|d| (&d.source, &d.target, d.backup, false)));
+
+        let mut changes = Vec::new();
+        for (source, target, backup, template) in entries {
+            let source_path = resolve_path(base_dir, source);
+            let target_path = expand_path(target);
+
+            if !source_path.exists() {
+                anyhow::bail!("Source of {} does not exist: {}", target, source_path.display());
+            }
+
+            if template {
+                changes.extend(plan_template(source_path, target, target_path, backup)?);
+                continue;
+            }
+
+            let current = match fs::symlink_metadata(&target_path) {
+                Err(_) => None,
+                Ok(meta) if meta.file_type().is_symlink() => {
+                    let link = fs::read_link(&target_path)?;
+                    if link == source_path {
+                        continue;
+                    }
+                    Some(format!("link to {}", link.display()))
+                }
+                Ok(meta) => {
+                    let kind = if meta.is_dir() { "directory" } else { "file" };
+                    let handling = if backup { "backed up" } else { "conflicts, set backup: true" };
+                    Some(format!("existing {} ({})", kind, handling))
+                }
+            };
+
+            changes.push(Change {
+                kind: if current.is_some() { ChangeKind::Update } else { ChangeKind::Create },
+                target: target.clone(),
+                current,
+                desired: format!("link to {}", source_path.display()),
+                operation: Operation::Symlink { source: source_path, target: target_path, backup },
+            });
+        }
+
+        Ok(FragmentPlan::new(fragment.fragment_type.clone(), changes))
     }
-    
-    fn diff_dotfiles(&self, _fragment: &Fragment) -> Result<bool> {
-        tracing::info!("Checking dotfiles fragment for differences");
-        // TODO: Implement dotfiles diff checking
-        Ok(false)
-    }
-    
-    // System fragment handlers
-    fn apply_system(&self, _fragment: &Fragment, _dry_run: bool) -> Result<()> {
-        tracing::info!("Applying system fragment");
-        // TODO: Implement system preferences application
-        Ok(())
+
+    // System and macOS defaults fragment handlers
+    fn plan_defaults(&self, fragment: &Fragment) -> Result<FragmentPlan> {
+        let section: SystemFragment = fragment.section()?;
+
+        let mut changes = Vec::new();
+        for preference in &section.preferences {
+            let desired = default_values(&preference.value_type, &preference.value)
+                .with_context(|| format!("Invalid value for {} {}", preference.domain, preference.key))?;
+
+            let current = read_default(&preference.domain, &preference.key)?
+                .map(|raw| normalize_default(&preference.value_type, &raw));
+            if current.as_ref() == Some(&desired) {
+                continue;
+            }
+
+            changes.push(Change {
+                kind: if current.is_some() { ChangeKind::Update } else { ChangeKind::Create },
+                target: format!("{} {}", preference.domain, preference.key),
+                current: current.map(|values| values.join(", ")),
+                desired: desired.join(", "),
+                operation: Operation::WriteDefault {
+                    domain: preference.domain.clone(),
+                    key: preference.key.clone(),
+                    value_type: preference.value_type.clone(),
+                    values: desired,
+                },
+            });
+        }
+
+        // Restarts are only needed if a preference actually changes
+        if !changes.is_empty() {
+            changes.extend(section.restart.iter().map(|app| Change {
+                kind: ChangeKind::Run,
+                target: "restart".to_string(),
+                current: None,
+                desired: app.clone(),
+                operation: Operation::Restart { app: app.clone() },
+            }));
+        }
+
+        Ok(FragmentPlan::new(fragment.fragment_type.clone(), changes))
     }
-    
-    fn diff_system(&self, _fragment: &Fragment) -> Result<bool> {
-        tracing::info!("Checking system fragment for differences");
-        // TODO: Implement system preferences diff checking
-        Ok(false)
+
+    // Services fragment handlers
+    fn plan_services(&self, fragment: &Fragment) -> Result<FragmentPlan> {
+        let section: ServicesFragment = fragment.section()?;
+        let mut changes = Vec::new();
+
+        if !section.services.is_empty() {
+            let statuses = brew_service_statuses()?;
+            for service in &section.services {
+                let status = statuses.get(&service.name).map(String::as_str).unwrap_or("not installed");
+                let running = matches!(status, "started" | "scheduled");
+                let start = service.state == ServiceState::Started;
+                if running == start {
+                    continue;
+                }
+
+                changes.push(Change {
+                    kind: ChangeKind::Update,
+                    target: format!("service {}", service.name),
+                    current: Some(status.to_string()),
+                    desired: if start { "started" } else { "stopped" }.to_string(),
+                    operation: Operation::Service { name: service.name.clone(), start },
+                });
+            }
+        }
+
+        for agent in &section.launch_agents {
+            let path = expand_path(&format!("~/Library/LaunchAgents/{}.plist", agent.label));
+            let program = expand_path(&agent.program).to_string_lossy().into_owned();
+            let content = launch_agent_plist(&agent.label, &program, &agent.arguments, agent.interval);
+
+            // An agent with an up to date definition may still not be loaded
+            let current = match fs::read_to_string(&path).ok() {
+                Some(current) if current == content && launch_agent_loaded(&agent.label) => continue,
+                Some(current) if current == content => Some("not loaded".to_string()),
+                Some(_) => Some("different definition".to_string()),
+                None => None,
+            };
+
+            changes.push(Change {
+                kind: if current.is_some() { ChangeKind::Update } else { ChangeKind::Create },
+                target: format!("launch agent {}", agent.label),
+                current,
+                desired: program,
+                operation: Operation::LaunchAgent { path, content },
+            });
+        }
+
+        Ok(FragmentPlan::new(fragment.fragment_type.clone(), changes))
+    }
+
+    // Security baseline fragment handlers
+    fn plan_security(&self, fragment: &Fragment) -> Result<FragmentPlan> {
+        let section: SecurityBaselineFragment = fragment.section()?;
+        let mut changes = Vec::new();
+        let mut checked = 0;
+
+        if let Some(firewall) = &section.firewall {
+            let settings = [
+                ("firewall", firewall.enabled, "--getglobalstate", "--setglobalstate"),
+                ("firewall stealth mode", firewall.stealth_mode, "--getstealthmode", "--setstealthmode"),
+                ("firewall block all incoming", firewall.block_all_incoming, "--getblockall", "--setblockall"),
+            ];
+            for (target, desired, query, set) in settings {
+                let Some(desired) = desired else {
+                    continue;
+                };
+                checked += 1;
+                let current = toggle_state(&capture(Command::new(SOCKETFILTERFW).arg(query))?);
+                changes.extend(toggle_change("security", target, current, desired, vec![
+                    SOCKETFILTERFW.to_string(), set.to_string(), on_off(desired).to_string(),
+                ]));
+            }
+        }
+
+        if section.filevault.as_ref().is_some_and(|filevault| filevault.required) {
+            checked += 1;
+            let status = capture(Command::new("fdesetup").arg("status"))?;
+            if !status.contains("FileVault is On") {
+                changes.push(Change {
+                    kind: ChangeKind::Manual,
+                    target: "filevault".to_string(),
+                    current: Some(status.lines().next().unwrap_or("unknown").trim_end_matches('.').to_string()),
+                    desired: "on".to_string(),
+                    operation: Operation::Verify {
+                        section: "security",
+                        remedy: "Turn on FileVault in System Settings > Privacy & Security > FileVault".to_string(),
+                    },
+                });
+            }
+        }
+
+        if let Some(screensaver) = &section.screensaver {
+            let settings = [
+                ("askForPassword", screensaver.ask_for_password.map(u64::from)),
+                ("askForPasswordDelay", screensaver.delay_seconds),
+            ];
+            for (key, desired) in settings {
+                let Some(desired) = desired else {
+                    continue;
+                };
+                checked += 1;
+                let desired = vec![desired.to_string()];
+                let current = read_default(SCREENSAVER_DOMAIN, key)?.map(|raw| normalize_default("int", &raw));
+                if current.as_ref() == Some(&desired) {
+                    continue;
+                }
+                changes.push(Change {
+                    kind: if current.is_some() { ChangeKind::Update } else { ChangeKind::Create },
+                    target: format!("{} {}", SCREENSAVER_DOMAIN, key),
+                    current: current.map(|values| values.join(", ")),
+                    desired: desired.join(", "),
+                    operation: Operation::WriteDefault {
+                        domain: SCREENSAVER_DOMAIN.to_string(),
+                        key: key.to_string(),
+                        value_type: "int".to_string(),
+                        values: desired,
+                    },
+                });
+            }
+        }
+
+        if let Some(gatekeeper) = &section.gatekeeper {
+            checked += 1;
+            let current = capture(Command::new("spctl").arg("--status"))?.contains("assessments enabled");
+            let flag = if gatekeeper.enabled { "--master-enable" } else { "--master-disable" };
+            changes.extend(toggle_change("security", "gatekeeper", current, gatekeeper.enabled, vec![
+                "spctl".to_string(), flag.to_string(),
+            ]));
+        }
+
+        if let Some(remote_login) = &section.remote_login {
+            checked += 1;
+            changes.extend(toggle_change("security", "remote login", remote_login_enabled()?, remote_login.enabled, vec![
+                "systemsetup".to_string(), "-f".to_string(), "-setremotelogin".to_string(),
+                on_off(remote_login.enabled).to_string(),
+            ]));
+        }
+
+        if let Some(updates) = &section.software_updates {
+            let settings = [
+                ("AutomaticCheckEnabled", updates.automatic_check),
+                ("CriticalUpdateInstall", updates.install_security_updates),
+                ("ConfigDataInstall", updates.install_security_updates),
+            ];
+            for (key, desired) in settings {
+                let Some(desired) = desired else {
+                    continue;
+                };
+                checked += 1;
+                // Unset means the system default, which checks and installs security updates
+                let current = read_default(SOFTWARE_UPDATE_DOMAIN, key)?
+                    .is_none_or(|raw| normalize_default("bool", &raw) == ["true"]);
+                changes.extend(toggle_change("security", &format!("software update {}", key), current, desired, vec![
+                    "defaults".to_string(), "write".to_string(), SOFTWARE_UPDATE_DOMAIN.to_string(),
+                    key.to_string(), "-bool".to_string(), desired.to_string(),
+                ]));
+            }
+        }
+
+        let mut plan = FragmentPlan::new(fragment.fragment_type.clone(), changes);
+        plan.checked = Some(checked);
+        Ok(plan)
     }
-    
+
     // Network fragment handlers
-    fn apply_network(&self, _fragment: &Fragment, _dry_run: bool) -> Result<()> {
-        tracing::info!("Applying network fragment");
-        // TODO: Implement network configuration application
-        Ok(())
+    fn plan_network(&self, fragment: &Fragment) -> Result<FragmentPlan> {
+        let section: NetworkFragment = fragment.section()?;
+        let mut changes = Vec::new();
+
+        // Settings belong to a location, those of the current one say nothing
+        // about the location switched to, so they are all written after switching
+        let mut switching = false;
+        if let Some(location) = &section.location {
+            let current = networksetup(&["-getcurrentlocation"])?.trim().to_string();
+            if current != *location {
+                switching = true;
+                let exists = networksetup(&["-listlocations"])?.lines().any(|line| line.trim() == location);
+                let mut commands = Vec::new();
+                if !exists {
+                    commands.push(vec!["-createlocation".to_string(), location.clone(), "populate".to_string()]);
+                }
+                commands.push(vec!["-switchtolocation".to_string(), location.clone()]);
+                changes.push(Change {
+                    kind: if exists { ChangeKind::Update } else { ChangeKind::Create },
+                    target: "location".to_string(),
+                    current: Some(current),
+                    desired: location.clone(),
+                    operation: Operation::Network { commands, disruptive: true },
+                });
+            }
+        }
+
+        if !switching && !section.services.is_empty() {
+            let available = network_services()?;
+            if let Some(missing) = section.services.iter().find(|service| !available.contains(&service.name)) {
+                anyhow::bail!("Network service '{}' not found, this machine has {}", missing.name, available.join(", "));
+            }
+        }
+
+        for service in &section.services {
+            let name = &service.name;
+            if let Some(servers) = &service.dns_servers {
+                changes.extend(network_list_change(name, "DNS servers", "dnsservers", servers, true, switching)?);
+            }
+            if let Some(domains) = &service.search_domains {
+                changes.extend(network_list_change(name, "search domains", "searchdomains", domains, false, switching)?);
+            }
+            if let Some(proxy) = &service.web_proxy {
+                changes.extend(proxy_change(name, "web proxy", "webproxy", proxy, switching)?);
+            }
+            if let Some(proxy) = &service.secure_web_proxy {
+                changes.extend(proxy_change(name, "secure web proxy", "securewebproxy", proxy, switching)?);
+            }
+        }
+
+        Ok(FragmentPlan::new(fragment.fragment_type.clone(), changes))
     }
-    
-    fn diff_network(&self, _fragment: &Fragment) -> Result<bool> {
-        tracing::info!("Checking network fragment for differences");
-        // TODO: Implement network configuration diff checking
-        Ok(false)
+
+    // Backup fragment handlers
+    fn plan_backup(&self, fragment: &Fragment) -> Result<FragmentPlan> {
+        let section: BackupFragment = fragment.section()?;
+        let mut changes = Vec::new();
+        let mut checked = 0;
+
+        if let Some(time_machine) = &section.time_machine {
+            if time_machine.destination_required {
+                checked += 1;
+                // Lists `Name : <disk>` per destination, fails or says so if there is none
+                let output = Command::new("tmutil").arg("destinationinfo").output()
+                    .context("Failed to run tmutil, Time Machine can only be checked on macOS")?;
+                if !String::from_utf8_lossy(&output.stdout).contains("Name") {
+                    changes.push(Change {
+                        kind: ChangeKind::Manual,
+                        target: "time machine destination".to_string(),
+                        current: Some("none".to_string()),
+                        desired: "configured".to_string(),
+                        operation: Operation::Verify {
+                            section: "backup",
+                            remedy: "Add a backup disk in System Settings > General > Time Machine".to_string(),
+                        },
+                    });
+                }
+            }
+
+            if let Some(desired) = time_machine.auto_backup {
+                checked += 1;
+                let current = read_default(TIME_MACHINE_DOMAIN, "AutoBackup")?
+                    .is_some_and(|raw| normalize_default("bool", &raw) == ["true"]);
+                let action = if desired { "enable" } else { "disable" };
+                changes.extend(toggle_change("backup", "automatic backups", current, desired, vec![
+                    "tmutil".to_string(), action.to_string(),
+                ]));
+            }
+
+            for exclusion in &time_machine.exclusions {
+                checked += 1;
+                let path = expand_path(exclusion);
+                if !path.exists() {
+                    tracing::debug!("Not excluding {} from backups, it does not exist", path.display());
+                    continue;
+                }
+                if backup_excluded(&path)? {
+                    continue;
+                }
+                changes.push(Change {
+                    kind: ChangeKind::Update,
+                    target: exclusion.clone(),
+                    current: Some("backed up".to_string()),
+                    desired: "excluded".to_string(),
+                    operation: Operation::BackupExclusion { path, excluded: true },
+                });
+            }
+        }
+
+        let mut plan = FragmentPlan::new(fragment.fragment_type.clone(), changes);
+        plan.checked = Some(checked);
+        Ok(plan)
     }
-    
+
     // Custom fragment handlers
-    fn apply_custom(&self, _fragment: &Fragment, _dry_run: bool) -> Result<()> {
-        tracing::info!("Applying custom fragment");
-        // TODO: Implement custom script execution
-        Ok(())
+    fn plan_custom(&self, fragment: &Fragment, base_dir: &Path) -> Result<FragmentPlan> {
+        let section: CustomFragment = fragment.section()?;
+        let path = resolve_path(base_dir, &section.script_path);
+
+        if !path.is_file() {
+            anyhow::bail!("Script not found: {}", path.display());
+        }
+
+        // Parameters are passed as FRAGMENT_<KEY> environment variables
+        let env: Vec<(String, String)> = section.parameters.iter()
+            .filter_map(|(key, value)| Some((
+                format!("FRAGMENT_{}", key.as_str()?.to_uppercase().replace('-', "_")),
+                yaml_scalar(value)?,
+            )))
+            .collect();
+        for (key, value) in &env {
+            crate::template::secret_names(value).with_context(|| format!("Invalid value of {}", key))?;
+        }
+
+        // Scripts cannot report their state, so they always run
+        let change = Change {
+            kind: ChangeKind::Run,
+            target: "script".to_string(),
+            current: None,
+            desired: path.display().to_string(),
+            operation: Operation::RunScript { path, env },
+        };
+
+        Ok(FragmentPlan::new(fragment.fragment_type.clone(), vec![change]))
+    }
+
+    /// Carry out a single operation
+    fn execute(&self, operation: &Operation) -> Result<()> {
+        match operation {
+            Operation::Symlink { source, target, backup } => {
+                if let Ok(meta) = fs::symlink_metadata(target) {
+                    if meta.file_type().is_symlink() {
+                        fs::remove_file(target)?;
+                    } else if *backup {
+                        let backup_path = PathBuf::from(format!("{}.backup", target.display()));
+                        fs::rename(target, &backup_path)
+                            .with_context(|| format!("Failed to back up {}", target.display()))?;
+                        tracing::info!("Backed up {} to {}", target.display(), backup_path.display());
+                    } else {
+                        anyhow::bail!("{} already exists, set backup: true to replace it", target.display());
+                    }
+                }
+
+                if let Some(parent) = target.parent() {
+                    crate::utils::ensure_dir_exists(parent)?;
+                }
+                std::os::unix::fs::symlink(source, target)
+                    .with_context(|| format!("Failed to link {} to {}", target.display(), source.display()))
+            }
+            Operation::Render { source, target, backup } => {
+                let content = render_template(source)?;
+
+                if let Ok(meta) = fs::symlink_metadata(target) {
+                    if meta.is_dir() {
+                        anyhow::bail!("{} is a directory", target.display());
+                    }
+                    if meta.file_type().is_symlink() {
+                        fs::remove_file(target)?;
+                    } else if *backup {
+                        let backup_path = PathBuf::from(format!("{}.backup", target.display()));
+                        fs::rename(target, &backup_path)
+                            .with_context(|| format!("Failed to back up {}", target.display()))?;
+                        tracing::info!("Backed up {} to {}", target.display(), backup_path.display());
+                    }
+                }
+
+                if let Some(parent) = target.parent() {
+                    crate::utils::ensure_dir_exists(parent)?;
+                }
+                // Only the owner may read files that can hold secrets
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .mode(0o600)
+                    .open(target)
+                    .with_context(|| format!("Failed to write {}", target.display()))?;
+                file.set_permissions(fs::Permissions::from_mode(0o600))?;
+                file.write_all(content.as_bytes())
+                    .with_context(|| format!("Failed to write {}", target.display()))
+            }
+            Operation::WriteDefault { domain, key, value_type, values } => {
+                run(Command::new("defaults")
+                    .args(["write", domain, key, &format!("-{}", value_type)])
+                    .args(values))
+            }
+            Operation::Restart { app } => {
+                // Not running is fine, it picks up the changes on its next start
+                let _ = Command::new("killall").arg(app).output();
+                Ok(())
+            }
+            Operation::Service { name, start } => {
+                let action = if *start { "start" } else { "stop" };
+                run(Command::new("brew").args(["services", action, name]))
+            }
+            Operation::LaunchAgent { path, content } => {
+                crate::utils::write_file(path, content)?;
+                // Reload so a changed definition takes effect
+                let _ = Command::new("launchctl").arg("unload").arg(path).output();
+                run(Command::new("launchctl").args(["load", "-w"]).arg(path))
+            }
+            Operation::RunScript { path, env } => {
+                let env = env.iter()
+                    .map(|(key, value)| Ok((key, crate::template::render(value)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                let status = Command::new(path)
+                    .envs(env)
+                    .status()
+                    .with_context(|| format!("Failed to run {}", path.display()))?;
+                if !status.success() {
+                    anyhow::bail!("{} exited with {}", path.display(), status);
+                }
+                Ok(())
+            }
+            Operation::RemoveFile { path } => {
+                fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            Operation::RestoreDefault { domain, key, value_type, previous } => match previous {
+                Some(values) => run(Command::new("defaults")
+                    .args(["write", domain, key, &format!("-{}", value_type)])
+                    .args(values)),
+                // Already deleted is fine
+                None => {
+                    let _ = Command::new("defaults").args(["delete", domain, key]).output();
+                    Ok(())
+                }
+            },
+            Operation::RemoveLaunchAgent { path } => {
+                let _ = Command::new("launchctl").arg("unload").arg(path).output();
+                fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            Operation::Privileged { command, .. } => {
+                // Never prompts, the password was asked for before applying
+                run(Command::new("sudo").arg("-n").args(command))
+            }
+            Operation::Verify { remedy, .. } => {
+                tracing::warn!("Not compliant and cannot be changed automatically. {}", remedy);
+                Ok(())
+            }
+            Operation::BackupExclusion { path, excluded } => {
+                let action = if *excluded { "addexclusion" } else { "removeexclusion" };
+                run(Command::new("tmutil").arg(action).arg(path))
+            }
+            Operation::Network { commands, .. } => {
+                for command in commands {
+                    run(Command::new("sudo").args(["-n", "networksetup"]).args(command))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Whether launchd has loaded the agent with `label`
+fn launch_agent_loaded(label: &str) -> bool {
+    Command::new("launchctl")
+        .args(["list", label])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Change writing a dotfile template, `None` if the target is up to date
+///
+/// The template is rendered to compare it with the target, the result is
+/// not kept in the plan. Templates own their target: an existing file is
+/// replaced, or moved aside first with `backup: true`.
+fn plan_template(source: PathBuf, target: &str, target_path: PathBuf, backup: bool) -> Result<Option<Change>> {
+    if !source.is_file() {
+        anyhow::bail!("Template of {} must be a file: {}", target, source.display());
+    }
+    let content = render_template(&source)?;
+
+    let current = match fs::symlink_metadata(&target_path) {
+        Err(_) => None,
+        Ok(meta) if meta.file_type().is_symlink() => {
+            Some(format!("link to {}", fs::read_link(&target_path)?.display()))
+        }
+        Ok(meta) if meta.is_dir() => anyhow::bail!("Target of template {} is a directory", target),
+        Ok(_) => {
+            if fs::read_to_string(&target_path).is_ok_and(|existing| existing == content) {
+                return Ok(None);
+            }
+            Some(if backup { "different file (backed up)" } else { "different file" }.to_string())
+        }
+    };
+
+    Ok(Some(Change {
+        kind: if current.is_some() { ChangeKind::Update } else { ChangeKind::Create },
+        target: target.to_string(),
+        current,
+        desired: format!("rendered from {}", source.display()),
+        operation: Operation::Render { source, target: target_path, backup },
+    }))
+}
+
+/// Content of a dotfile template with its secrets resolved
+fn render_template(source: &Path) -> Result<String> {
+    let template = fs::read_to_string(source)
+        .with_context(|| format!("Failed to read template {}", source.display()))?;
+    crate::template::render(&template)
+        .with_context(|| format!("Failed to render template {}", source.display()))
+}
+
+/// Expand `~` in a path
+fn expand_path(path: &str) -> PathBuf {
+    PathBuf::from(shellexpand::tilde(path).into_owned())
+}
+
+/// Expand `~` and resolve relative paths against `base_dir`
+fn resolve_path(base_dir: &Path, path: &str) -> PathBuf {
+    let path = expand_path(path);
+    if path.is_absolute() { path } else { base_dir.join(path) }
+}
+
+/// Command line tool of the application firewall
+const SOCKETFILTERFW: &str = "/usr/libexec/ApplicationFirewall/socketfilterfw";
+
+/// Preferences of the screen saver of the current user
+const SCREENSAVER_DOMAIN: &str = "com.apple.screensaver";
+
+/// System wide software update preferences
+const SOFTWARE_UPDATE_DOMAIN: &str = "/Library/Preferences/com.apple.SoftwareUpdate";
+
+/// System wide Time Machine preferences
+const TIME_MACHINE_DOMAIN: &str = "/Library/Preferences/com.apple.TimeMachine";
+
+/// Whether Time Machine skips a path, from `[Excluded]` or `[Included]` before it in `tmutil isexcluded`
+fn backup_excluded(path: &Path) -> Result<bool> {
+    Ok(capture(Command::new("tmutil").arg("isexcluded").arg(path))?.contains("[Excluded]"))
+}
+
+/// Change turning a setting on or off through a privileged command, `None` if it already is
+fn toggle_change(section: &'static str, target: &str, current: bool, desired: bool, command: Vec<String>) -> Option<Change> {
+    (current != desired).then(|| Change {
+        kind: ChangeKind::Update,
+        target: target.to_string(),
+        current: Some(on_off(current).to_string()),
+        desired: on_off(desired).to_string(),
+        operation: Operation::Privileged { section, command },
+    })
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled { "on" } else { "off" }
+}
+
+/// Whether a status line like `Firewall is enabled. (State = 1)` reports the setting as on
+fn toggle_state(status: &str) -> bool {
+    let status = status.to_lowercase();
+    !(status.contains("disabled") || status.contains("is off") || status.contains("state = 0"))
+}
+
+/// Whether the SSH server is enabled, readable without administrator rights unlike `systemsetup`
+fn remote_login_enabled() -> Result<bool> {
+    let overrides = capture(Command::new("launchctl").args(["print-disabled", "system"]))?;
+    // `"com.openssh.sshd" => disabled`, or `=> true` on older releases; disabled if not listed
+    Ok(overrides.lines()
+        .find(|line| line.contains("\"com.openssh.sshd\""))
+        .is_some_and(|line| line.contains("=> enabled") || line.contains("=> false")))
+}
+
+/// Change setting a list of a network service like its DNS servers, `None` if it is set already
+///
+/// `flag` is the name in the `networksetup -get<flag>` and `-set<flag>`
+/// options. While switching locations the current value is not read, it
+/// belongs to the old location.
+fn network_list_change(
+    service: &str,
+    setting: &str,
+    flag: &str,
+    desired: &[String],
+    disruptive: bool,
+    switching: bool,
+) -> Result<Option<Change>> {
+    let current = if switching {
+        None
+    } else {
+        // "There aren't any DNS Servers set on Wi-Fi." if unset
+        let output = networksetup(&[&format!("-get{}", flag), service])?;
+        let values: Vec<String> = match output.starts_with("There aren't any") {
+            true => Vec::new(),
+            false => output.lines().map(str::trim).filter(|line| !line.is_empty()).map(String::from).collect(),
+        };
+        if values == desired {
+            return Ok(None);
+        }
+        Some(values)
+    };
+
+    let mut command = vec![format!("-set{}", flag), service.to_string()];
+    match desired.is_empty() {
+        true => command.push("Empty".to_string()),
+        false => command.extend(desired.iter().cloned()),
+    }
+    let describe = |values: &[String]| match values.is_empty() {
+        true => "from DHCP".to_string(),
+        false => values.join(", "),
+    };
+
+    Ok(Some(Change {
+        kind: ChangeKind::Update,
+        target: format!("{} {}", service, setting),
+        current: current.map(|values| describe(&values)),
+        desired: describe(desired),
+        operation: Operation::Network { commands: vec![command], disruptive },
+    }))
+}
+
+/// Change setting a proxy of a network service, `None` if it is set already
+///
+/// `flag` is the proxy in the `networksetup` options, e.g. `webproxy` for
+/// `-getwebproxy`, `-setwebproxy` and `-setwebproxystate`.
+fn proxy_change(service: &str, setting: &str, flag: &str, desired: &ProxySettings, switching: bool) -> Result<Option<Change>> {
+    if desired.enabled && (desired.server.is_empty() || desired.port == 0) {
+        anyhow::bail!("The {} of {} needs a server and port to be enabled", setting, service);
+    }
+    let describe = |enabled: bool, server: &str, port: u16| match enabled {
+        true => format!("{}:{}", server, port),
+        false => "off".to_string(),
+    };
+
+    let current = if switching {
+        None
+    } else {
+        // "Enabled: Yes", "Server: proxy.example.com" and "Port: 8080" lines
+        let output = networksetup(&[&format!("-get{}", flag), service])?;
+        let field = |name: &str| output.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default();
+        let enabled = field("Enabled:") == "Yes";
+        let server = field("Server:");
+        let port = field("Port:").parse().unwrap_or(0);
+
+        let up_to_date = match desired.enabled {
+            true => enabled && server == desired.server && port == desired.port,
+            false => !enabled,
+        };
+        if up_to_date {
+            return Ok(None);
+        }
+        Some(describe(enabled, &server, port))
+    };
+
+    let mut commands = Vec::new();
+    if desired.enabled {
+        commands.push(vec![format!("-set{}", flag), service.to_string(), desired.server.clone(), desired.port.to_string()]);
+    }
+    commands.push(vec![format!("-set{}state", flag), service.to_string(), on_off(desired.enabled).to_string()]);
+
+    Ok(Some(Change {
+        kind: ChangeKind::Update,
+        target: format!("{} {}", service, setting),
+        current,
+        desired: describe(desired.enabled, &desired.server, desired.port),
+        operation: Operation::Network { commands, disruptive: true },
+    }))
+}
+
+/// Names of the network services, e.g. `Wi-Fi`, including disabled ones
+fn network_services() -> Result<Vec<String>> {
+    // The first line explains that disabled services are marked with an asterisk
+    Ok(networksetup(&["-listallnetworkservices"])?.lines()
+        .skip(1)
+        .map(|line| line.trim_start_matches('*').trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect())
+}
+
+/// Output of a reading `networksetup` command
+fn networksetup(args: &[&str]) -> Result<String> {
+    let output = capture(Command::new("networksetup").args(args))?;
+    // Errors like "** Error: The parameters were not valid." do not fail the command
+    if let Some(error) = output.lines().find(|line| line.starts_with("**")) {
+        anyhow::bail!("networksetup {} failed: {}", args.join(" "), error.trim_start_matches('*').trim());
+    }
+    Ok(output)
+}
+
+/// Make sure sudo can run commands without asking, asking for the password once if needed
+fn ensure_sudo(targets: &[&str]) -> Result<()> {
+    let cached = Command::new("sudo").args(["-n", "true"]).output().is_ok_and(|output| output.status.success());
+    if cached {
+        return Ok(());
+    }
+    if !console::user_attended() {
+        anyhow::bail!("Changing {} needs administrator rights, apply in a terminal to enter your password",
+            targets.join(", "));
+    }
+
+    tracing::info!("Changing {} needs administrator rights", targets.join(", "));
+    let status = Command::new("sudo").arg("-v").status().context("Failed to run sudo")?;
+    if !status.success() {
+        anyhow::bail!("Could not get administrator rights to change {}", targets.join(", "));
+    }
+    Ok(())
+}
+
+/// Output of a command that reports a setting, failing if it cannot be run
+fn capture(cmd: &mut Command) -> Result<String> {
+    let output = cmd.output()
+        .with_context(|| format!("Failed to run {:?}, these settings can only be checked on macOS", cmd.get_program()))?;
+    if !output.status.success() {
+        anyhow::bail!("{:?} failed: {}", cmd.get_program(), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Run a command, failing with its stderr if it does not succeed
+fn run(cmd: &mut Command) -> Result<()> {
+    let output = cmd.output()
+        .with_context(|| format!("Failed to run {:?}", cmd.get_program()))?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Current value of a default, `None` if it is not set
+fn read_default(domain: &str, key: &str) -> Result<Option<String>> {
+    let output = Command::new("defaults")
+        .args(["read", domain, key])
+        .output()
+        .context("Failed to run defaults, macOS defaults are only available on macOS")?;
+
+    Ok(output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+}
+
+/// Values passed to `defaults write` for a preference
+fn default_values(value_type: &str, value: &serde_yaml::Value) -> Result<Vec<String>> {
+    let scalar = || yaml_scalar(value).context("Expected a single value");
+
+    match value_type {
+        "bool" => Ok(vec![value.as_bool().context("Expected true or false")?.to_string()]),
+        "int" => Ok(vec![value.as_i64().context("Expected an integer")?.to_string()]),
+        "float" => Ok(vec![value.as_f64().context("Expected a number")?.to_string()]),
+        "string" => Ok(vec![scalar()?]),
+        "array" => value.as_sequence()
+            .context("Expected a list")?
+            .iter()
+            .map(|item| yaml_scalar(item).context("Array items must be single values"))
+            .collect(),
+        other => anyhow::bail!("Unsupported value type '{}', use bool, int, float, string or array", other),
+    }
+}
+
+/// Bring the output of `defaults read` into the form of [`default_values`]
+fn normalize_default(value_type: &str, raw: &str) -> Vec<String> {
+    match value_type {
+        "bool" => vec![(raw == "1" || raw.eq_ignore_ascii_case("true")).to_string()],
+        "int" | "float" => vec![raw.parse::<f64>().map(|n| n.to_string()).unwrap_or_else(|_| raw.to_string())],
+        // Arrays are printed as "(\n    a,\n    \"b c\"\n)"
+        "array" => raw.trim_start_matches('(').trim_end_matches(')')
+            .split(',')
+            .map(|item| item.trim().trim_matches('"').to_string())
+            .filter(|item| !item.is_empty())
+            .collect(),
+        _ => vec![raw.to_string()],
+    }
+}
+
+/// String form of a YAML scalar
+fn yaml_scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Status of each Homebrew service from `brew services list`
+fn brew_service_statuses() -> Result<HashMap<String, String>> {
+    let output = Command::new("brew")
+        .args(["services", "list"])
+        .output()
+        .context("Failed to run brew services")?;
+    if !output.status.success() {
+        anyhow::bail!("brew services list failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    // Columns: Name Status User File, after a header line
+    Ok(String::from_utf8_lossy(&output.stdout).lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            Some((columns.next()?.to_string(), columns.next()?.to_string()))
+        })
+        .collect())
+}
+
+/// Property list of a launchd agent
+fn launch_agent_plist(label: &str, program: &str, arguments: &[String], interval: Option<u64>) -> String {
+    let escape = |s: &str| s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+
+    let mut plist = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n<dict>\n",
+    ));
+    plist.push_str(&format!("    <key>Label</key>\n    <string>{}</string>\n", escape(label)));
+    plist.push_str("    <key>ProgramArguments</key>\n    <array>\n");
+    for argument in std::iter::once(program).chain(arguments.iter().map(String::as_str)) {
+        plist.push_str(&format!("        <string>{}</string>\n", escape(argument)));
     }
-    
-    fn diff_custom(&self, _fragment: &Fragment) -> Result<bool> {
-        tracing::info!("Checking custom fragment for differences");
-        // TODO: Implement custom script diff checking
-        Ok(false)
+    plist.push_str("    </array>\n");
+    if let Some(interval) = interval {
+        plist.push_str(&format!("    <key>StartInterval</key>\n    <integer>{}</integer>\n", interval));
     }
-}
\ No newline at end of file
+    plist.push_str("</dict>\n</plist>\n");
+    plist
+}
@@ -1,61 +1,83 @@
 use crate::utils;
 use anyhow::Result;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use crate::parser::{Fragment, FragmentType};
 use serde_yaml::{Mapping, Value};
 
+/// Commented starter files for fragment types that ship one
+const COMMENTED_TEMPLATES: &[(&str, &str)] = &[
+    ("macos-defaults", include_str!("../templates/macos-defaults.yaml")),
+    ("dev-shell", include_str!("../templates/dev-shell.yaml")),
+    ("services", include_str!("../templates/services.yaml")),
+    ("network", include_str!("../templates/network.yaml")),
+    ("backup", include_str!("../templates/backup.yaml")),
+    ("security-baseline", include_str!("../templates/security-baseline.yaml")),
+];
+
 /// Initialize a new fragment file
-pub fn init<P: AsRef<Path>>(fragment_type: &str, path: P) -> Result<()> {
-    let path = path.as_ref();
-    
-    // Ensure the parent directory exists
-    if let Some(parent) = path.parent() {
-        utils::ensure_dir_exists(parent)?;
-    }
+///
+/// If `path` is a directory the fragment is created as `<type>.yaml` inside
+/// it. Existing fragments are only replaced when `force` is set.
+pub fn init<P: AsRef<Path>>(fragment_type: &str, path: P, force: bool) -> Result<()> {
+    let path = PathBuf::from(shellexpand::tilde(&path.as_ref().to_string_lossy()).into_owned());
     
     // Parse fragment type
-    let fragment_type = match fragment_type.to_lowercase().as_str() {
-        "dotfiles" => FragmentType::Dotfiles,
-        "system" => FragmentType::System,
-        "network" => FragmentType::Network,
-        "custom" => FragmentType::Custom,
-        _ => anyhow::bail!("Invalid fragment type: {}. Must be one of: dotfiles, system, network, custom", fragment_type),
-    };
+    let fragment_type: FragmentType = fragment_type.parse()?;
     
     // Create path with extension if needed
-    let file_path = if path.is_dir() {
-        let filename = format!("{}.toml", fragment_type.to_string().to_lowercase());
-        path.join(filename)
+    let file_path = if path.is_dir() || path.extension().is_none() {
+        path.join(format!("{}.yaml", fragment_type))
     } else {
-        path.to_path_buf()
+        path
     };
     
-    // Check if the fragment already exists
-    if utils::path_exists(&file_path) {
-        anyhow::bail!("Fragment already exists: {}", file_path.display());
+    // Ensure the parent directory exists
+    if let Some(parent) = file_path.parent() {
+        utils::ensure_dir_exists(parent)?;
     }
     
-    // Create fragment content based on type
-    let (description, content) = create_template_content(&fragment_type);
-    
-    // Clone the fragment_type for later use (inexpensive for enums)
-    let fragment_type_for_log = fragment_type.clone();
+    // Check if the fragment already exists
+    if utils::path_exists(&file_path) && !force {
+        anyhow::bail!("Fragment already exists: {} (use --force to overwrite)", file_path.display());
+    }
     
-    // Create the fragment
-    let fragment = Fragment {
-        fragment_type,
-        description,
-        content: Value::Mapping(content),
-    };
+    let type_name = fragment_type.to_string();
     
-    // Save the fragment
-    fragment.to_file(&file_path)?;
+    if let Some((_, template)) = COMMENTED_TEMPLATES.iter().find(|(name, _)| *name == type_name) {
+        // Written verbatim to keep the explanatory comments
+        utils::write_file(&file_path, template)?;
+    } else {
+        // Create fragment content based on type
+        let (description, content) = create_template_content(&fragment_type);
+        
+        // Create the fragment
+        let fragment = Fragment {
+            fragment_type,
+            description,
+            after: Vec::new(),
+            requires: Default::default(),
+            when: Default::default(),
+            include: Vec::new(),
+            content: Value::Mapping(content),
+        };
+        
+        // Save the fragment
+        fragment.to_file(&file_path)?;
+    }
     
-    tracing::info!("Created new {} fragment at: {}", fragment_type_for_log.to_string().to_lowercase(), file_path.display());
+    tracing::info!("Created new {} fragment at: {}", type_name, file_path.display());
     
     Ok(())
 }
 
+/// Print the available fragment types
+pub fn list() {
+    println!("Available fragment types:");
+    for fragment_type in FragmentType::ALL {
+        println!("  {:<20} {}", fragment_type.to_string(), fragment_type.description());
+    }
+}
+
 /// Create template content for a fragment based on its type
 fn create_template_content(fragment_type: &FragmentType) -> (String, Mapping) {
     let mut content = Mapping::new();
@@ -119,30 +141,6 @@ fn create_template_content(fragment_type: &FragmentType) -> (String, Mapping) {
             
             (description, content)
         },
-        FragmentType::Network => {
-            // Create a template for network configuration
-            let description = "Network configuration settings".to_string();
-            
-            let mut networks = Vec::new();
-            let network1 = make_mapping([
-                ("name", "Home Network"),
-                ("type", "wifi"),
-                ("ssid", "HomeWiFi"),
-                ("priority", "10"),
-            ]);
-            networks.push(Value::Mapping(network1));
-            
-            content.insert(Value::String("networks".to_string()), Value::Sequence(networks));
-            
-            let mut proxy = Mapping::new();
-            proxy.insert(Value::String("enabled".to_string()), Value::Bool(false));
-            proxy.insert(Value::String("server".to_string()), Value::String("proxy.example.com".to_string()));
-            proxy.insert(Value::String("port".to_string()), Value::Number(8080.into()));
-            
-            content.insert(Value::String("proxy".to_string()), Value::Mapping(proxy));
-            
-            (description, content)
-        },
         FragmentType::Custom => {
             // Create a template for custom script
             let description = "Custom configuration using external script".to_string();
@@ -160,6 +158,15 @@ fn create_template_content(fragment_type: &FragmentType) -> (String, Mapping) {
             
             (description, content)
         },
+        FragmentType::MacosDefaults
+        | FragmentType::Network
+        | FragmentType::DevShell
+        | FragmentType::Services
+        | FragmentType::SecurityBaseline
+        | FragmentType::Backup => {
+            // These types are created from COMMENTED_TEMPLATES
+            (fragment_type.description().to_string(), content)
+        },
     }
 }
 
@@ -5,7 +5,13 @@ pub mod apply;
 pub mod diff;
 pub mod engine;
 pub mod init;
+pub mod manage;
+pub mod order;
 pub mod parser;
+pub mod plan;
+pub mod state;
+pub mod template;
+pub mod validate;
 
 // CLI handling
 pub mod cli;
@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::utils::{self, DISABLED_DIR, fragments_dir};
+
+/// Move a fragment to `fragments/disabled/` so it is no longer applied
+///
+/// `name` is the file name without extension, or the path relative to the
+/// fragments directory (`user/dotfiles`) if several fragments share a name.
+/// The fragment keeps its place below `disabled/`, enabling restores it.
+pub fn disable(name: &str) -> Result<()> {
+    let root = fragments_dir();
+    let disabled = root.join(DISABLED_DIR);
+
+    let Some(source) = find(&root, name)? else {
+        if find(&disabled, name)?.is_some() {
+            tracing::warn!("Fragment '{}' is already disabled", name);
+            return Ok(());
+        }
+        anyhow::bail!("Fragment not found: {}", name);
+    };
+
+    let target = disabled.join(source.strip_prefix(&root)?);
+    move_fragment(&source, &target)?;
+    tracing::info!("Disabled fragment: {}", name);
+    Ok(())
+}
+
+/// Move a disabled fragment back to where it was disabled from
+pub fn enable(name: &str) -> Result<()> {
+    let root = fragments_dir();
+    let disabled = root.join(DISABLED_DIR);
+
+    let Some(source) = find(&disabled, name)? else {
+        if find(&root, name)?.is_some() {
+            tracing::warn!("Fragment '{}' is already enabled", name);
+            return Ok(());
+        }
+        anyhow::bail!("Fragment not found: {}", name);
+    };
+
+    let target = root.join(source.strip_prefix(&disabled)?);
+    move_fragment(&source, &target)?;
+    tracing::info!("Enabled fragment: {}", name);
+    Ok(())
+}
+
+/// Fragment file below `dir` matching a name or a relative path
+fn find(dir: &Path, name: &str) -> Result<Option<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+
+    let matches: Vec<PathBuf> = utils::fragment_files(&dir.to_string_lossy())?
+        .into_iter()
+        .filter(|path| {
+            let relative = path.strip_prefix(dir).unwrap_or(path).with_extension("");
+            relative.to_string_lossy() == name || path.file_stem().is_some_and(|stem| stem == name)
+        })
+        .collect();
+
+    match matches.as_slice() {
+        [] => Ok(None),
+        [path] => Ok(Some(path.clone())),
+        paths => {
+            let names: Vec<String> = paths.iter()
+                .map(|path| path.strip_prefix(dir).unwrap_or(path).with_extension("").display().to_string())
+                .collect();
+            anyhow::bail!("Several fragments are named '{}', use one of: {}", name, names.join(", "))
+        }
+    }
+}
+
+fn move_fragment(source: &Path, target: &Path) -> Result<()> {
+    if target.exists() {
+        anyhow::bail!("{} already exists", target.display());
+    }
+    if let Some(parent) = target.parent() {
+        utils::ensure_dir_exists(parent)?;
+    }
+    fs::rename(source, target)
+        .with_context(|| format!("Failed to move {} to {}", source.display(), target.display()))
+}
@@ -2,5 +2,9 @@
 pub mod apply;
 pub mod diff;
 pub mod init;
+pub mod manage;
+pub mod order;
 pub mod parser;
-pub mod engine;
\ No newline at end of file
+pub mod engine;
+pub mod plan;
+pub mod validate;
\ No newline at end of file
@@ -0,0 +1,116 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use crate::parser::Fragment;
+
+/// Order in which fragments are applied
+#[derive(Debug, Clone)]
+pub struct ApplyOrder {
+    /// Indices into the fragments, each after the fragments it depends on
+    pub order: Vec<usize>,
+    /// Indices of the fragments each fragment is applied after
+    pub after: Vec<Vec<usize>>,
+}
+
+/// Work out the apply order from the `after` lists of the fragments
+///
+/// An `after` entry names a fragment file without extension (`fonts`) or a
+/// fragment type (`dotfiles`), which matches every fragment of that type.
+/// Independent fragments keep their file name order. References to fragments
+/// that are not applied are an error, unless a single file is applied on its
+/// own. Cycles are an error.
+pub fn resolve(fragments: &[(PathBuf, Fragment)]) -> Result<ApplyOrder> {
+    let mut after = Vec::with_capacity(fragments.len());
+    for (index, (path, fragment)) in fragments.iter().enumerate() {
+        let mut dependencies = BTreeSet::new();
+        for name in &fragment.after {
+            let matches: Vec<usize> = fragments.iter()
+                .enumerate()
+                .filter(|(other, (other_path, other_fragment))| *other != index
+                    && (file_name(other_path) == *name || other_fragment.fragment_type.to_string() == *name))
+                .map(|(other, _)| other)
+                .collect();
+
+            if matches.is_empty() {
+                if fragments.len() == 1 {
+                    tracing::warn!("{} is applied after '{}', which is not part of this apply", path.display(), name);
+                    continue;
+                }
+                anyhow::bail!("{} is applied after '{}', but no fragment file or type has that name",
+                    path.display(), name);
+            }
+            dependencies.extend(matches);
+        }
+        after.push(dependencies.into_iter().collect::<Vec<_>>());
+    }
+
+    // Kahn's algorithm, always picking the first ready fragment by file name
+    let mut remaining: Vec<usize> = after.iter().map(Vec::len).collect();
+    let mut ready: BTreeSet<usize> = (0..fragments.len()).filter(|index| remaining[*index] == 0).collect();
+    let mut order = Vec::with_capacity(fragments.len());
+    while let Some(index) = ready.pop_first() {
+        order.push(index);
+        for (dependent, dependencies) in after.iter().enumerate() {
+            if dependencies.contains(&index) {
+                remaining[dependent] -= 1;
+                if remaining[dependent] == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+    }
+
+    if order.len() < fragments.len() {
+        let cycle = find_cycle(&after, &order);
+        let names: Vec<String> = cycle.iter().map(|index| file_name(&fragments[*index].0)).collect();
+        anyhow::bail!("Fragments depend on each other in a cycle: {}", names.join(" → "));
+    }
+
+    Ok(ApplyOrder { order, after })
+}
+
+/// A cycle among the fragments that could not be ordered, closed by its first fragment
+fn find_cycle(after: &[Vec<usize>], ordered: &[usize]) -> Vec<usize> {
+    // Every unordered fragment depends on another unordered one, so following
+    // those dependencies has to come back to a fragment already visited
+    let unordered = |index: &usize| !ordered.contains(index);
+    let Some(mut current) = (0..after.len()).find(unordered) else {
+        return Vec::new();
+    };
+    let mut path = Vec::new();
+    while !path.contains(&current) {
+        path.push(current);
+        match after[current].iter().copied().find(unordered) {
+            Some(next) => current = next,
+            None => break,
+        }
+    }
+
+    let start = path.iter().position(|index| *index == current).unwrap_or(0);
+    let mut cycle = path.split_off(start);
+    // Listed in apply order, each fragment before the ones applied after it
+    cycle.reverse();
+    cycle.push(cycle[0]);
+    cycle
+}
+
+/// File name without extension, as used in `after`
+pub fn file_name(path: &Path) -> String {
+    path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Parse fragment files, logging and counting those that fail to parse
+pub fn load(files: Vec<PathBuf>) -> (Vec<(PathBuf, Fragment)>, usize) {
+    let mut fragments = Vec::with_capacity(files.len());
+    let mut failed = 0;
+    for file in files {
+        match Fragment::from_file(&file) {
+            Ok(fragment) => fragments.push((file, fragment)),
+            Err(err) => {
+                tracing::error!("Failed to load fragment {}: {:#}", file.display(), err);
+                failed += 1;
+            }
+        }
+    }
+    (fragments, failed)
+}
@@ -1,15 +1,106 @@
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
+use sapphire_core::conditions::Conditions;
+use sapphire_core::requirements::Requirements;
 
 /// Fragment type enum
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
-#[serde(rename_all = "lowercase")]
+#[serde(rename_all = "kebab-case")]
 pub enum FragmentType {
     Dotfiles,
     System,
     Network,
     Custom,
+    MacosDefaults,
+    DevShell,
+    Services,
+    SecurityBaseline,
+    Backup,
+}
+
+impl FragmentType {
+    /// All fragment types, in the order they are listed to users
+    pub const ALL: [FragmentType; 9] = [
+        FragmentType::Dotfiles,
+        FragmentType::System,
+        FragmentType::Network,
+        FragmentType::Custom,
+        FragmentType::MacosDefaults,
+        FragmentType::DevShell,
+        FragmentType::Services,
+        FragmentType::SecurityBaseline,
+        FragmentType::Backup,
+    ];
+
+    /// Short description shown by `fragment init --list`
+    pub fn description(&self) -> &'static str {
+        match self {
+            FragmentType::Dotfiles => "Symlinked configuration files and directories",
+            FragmentType::System => "macOS system preferences",
+            FragmentType::Network => "Network location, DNS servers, search domains and proxies",
+            FragmentType::Custom => "Configuration applied by an external script",
+            FragmentType::MacosDefaults => "Application and system `defaults` with restarts",
+            FragmentType::DevShell => "Login shell, environment variables, PATH and aliases",
+            FragmentType::Services => "Homebrew services and launchd agents",
+            FragmentType::SecurityBaseline => "Firewall, FileVault, Gatekeeper, remote login and update settings",
+            FragmentType::Backup => "Time Machine destination, automatic backups and exclusions",
+        }
+    }
+}
+
+impl FragmentType {
+    /// Sections of this fragment type and the keys of their entries
+    ///
+    /// Sections with `None` hold values or free-form mappings whose keys are
+    /// not checked.
+    pub fn sections(&self) -> &'static [(&'static str, Option<&'static [&'static str]>)] {
+        const PATH_ENTRY: &[&str] = &["source", "target", "backup", "mode"];
+        const FILE_ENTRY: &[&str] = &["source", "target", "backup", "mode", "template"];
+        const PREFERENCE: &[&str] = &["domain", "key", "value_type", "value"];
+        match self {
+            FragmentType::Dotfiles => &[("files", Some(FILE_ENTRY)), ("directories", Some(PATH_ENTRY))],
+            FragmentType::System | FragmentType::MacosDefaults => &[
+                ("preferences", Some(PREFERENCE)),
+                ("restart", None),
+            ],
+            FragmentType::Network => &[
+                ("location", None),
+                ("services", Some(&["name", "dns_servers", "search_domains", "web_proxy", "secure_web_proxy"])),
+            ],
+            FragmentType::Custom => &[("script_path", None), ("parameters", None)],
+            FragmentType::DevShell => &[("shell", None), ("environment", None), ("path", None), ("aliases", None)],
+            FragmentType::Services => &[
+                ("services", Some(&["name", "state"])),
+                ("launch_agents", Some(&["label", "program", "arguments", "interval"])),
+            ],
+            FragmentType::SecurityBaseline => &[
+                ("firewall", Some(&["enabled", "stealth_mode", "block_all_incoming"])),
+                ("filevault", Some(&["required"])),
+                ("screensaver", Some(&["ask_for_password", "delay_seconds"])),
+                ("gatekeeper", Some(&["enabled"])),
+                ("remote_login", Some(&["enabled"])),
+                ("software_updates", Some(&["automatic_check", "install_security_updates"])),
+            ],
+            FragmentType::Backup => &[
+                ("time_machine", Some(&["destination_required", "auto_backup", "exclusions"])),
+            ],
+        }
+    }
+}
+
+impl std::str::FromStr for FragmentType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.to_lowercase();
+        FragmentType::ALL.into_iter()
+            .find(|fragment_type| fragment_type.to_string() == name)
+            .ok_or_else(|| {
+                let names: Vec<String> = FragmentType::ALL.iter().map(|t| t.to_string()).collect();
+                anyhow::anyhow!("Invalid fragment type: {}. Must be one of: {}", s, names.join(", "))
+            })
+    }
 }
 
 impl std::fmt::Display for FragmentType {
@@ -19,6 +110,11 @@ impl std::fmt::Display for FragmentType {
             FragmentType::System => write!(f, "system"),
             FragmentType::Network => write!(f, "network"),
             FragmentType::Custom => write!(f, "custom"),
+            FragmentType::MacosDefaults => write!(f, "macos-defaults"),
+            FragmentType::DevShell => write!(f, "dev-shell"),
+            FragmentType::Services => write!(f, "services"),
+            FragmentType::SecurityBaseline => write!(f, "security-baseline"),
+            FragmentType::Backup => write!(f, "backup"),
         }
     }
 }
@@ -33,6 +129,22 @@ pub struct Fragment {
     #[serde(default)]
     pub description: String,
     
+    /// Fragment files (without extension) or types applied before this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+    
+    /// Checks the machine has to pass before the fragment is applied
+    #[serde(default, skip_serializing_if = "Requirements::is_empty")]
+    pub requires: Requirements,
+    
+    /// Machines the fragment applies to, it is skipped on all others
+    #[serde(default, skip_serializing_if = "Conditions::is_empty")]
+    pub when: Conditions,
+    
+    /// Fragment files merged into this one, relative to its directory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    
     /// Additional fields specific to fragment type
     #[serde(flatten)]
     pub content: serde_yaml::Value,
@@ -59,6 +171,11 @@ pub struct FileEntry {
     
     #[serde(default)]
     pub mode: Option<String>,
+    
+    /// Render `{{ secret "name" }}` placeholders in the source into the
+    /// target instead of linking it
+    #[serde(default)]
+    pub template: bool,
 }
 
 /// Directory entry in a dotfiles fragment
@@ -74,11 +191,15 @@ pub struct DirectoryEntry {
     pub mode: Option<String>,
 }
 
-/// System fragment content
+/// System and macOS defaults fragment content
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SystemFragment {
     #[serde(default)]
     pub preferences: Vec<PreferenceEntry>,
+    
+    /// Applications restarted after preferences changed
+    #[serde(default)]
+    pub restart: Vec<String>,
 }
 
 /// System preference entry
@@ -90,6 +211,184 @@ pub struct PreferenceEntry {
     pub value: serde_yaml::Value,
 }
 
+/// Services fragment content
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServicesFragment {
+    #[serde(default)]
+    pub services: Vec<ServiceEntry>,
+    
+    #[serde(default)]
+    pub launch_agents: Vec<LaunchAgentEntry>,
+}
+
+/// Homebrew service and its desired state
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServiceEntry {
+    pub name: String,
+    pub state: ServiceState,
+}
+
+/// Desired state of a Homebrew service
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceState {
+    Started,
+    Stopped,
+}
+
+/// launchd agent written to ~/Library/LaunchAgents
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LaunchAgentEntry {
+    pub label: String,
+    pub program: String,
+    
+    #[serde(default)]
+    pub arguments: Vec<String>,
+    
+    /// Run every `interval` seconds
+    #[serde(default)]
+    pub interval: Option<u64>,
+}
+
+/// Network fragment content
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct NetworkFragment {
+    /// Network location to switch to, created if it does not exist
+    #[serde(default)]
+    pub location: Option<String>,
+    
+    #[serde(default)]
+    pub services: Vec<NetworkServiceEntry>,
+}
+
+/// Settings of a network service like `Wi-Fi`, settings left out are not changed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NetworkServiceEntry {
+    /// Name as listed by `networksetup -listallnetworkservices`
+    pub name: String,
+    
+    /// DNS servers, an empty list uses those handed out by DHCP
+    #[serde(default)]
+    pub dns_servers: Option<Vec<String>>,
+    
+    /// Search domains, an empty list uses those handed out by DHCP
+    #[serde(default)]
+    pub search_domains: Option<Vec<String>>,
+    
+    /// HTTP proxy
+    #[serde(default)]
+    pub web_proxy: Option<ProxySettings>,
+    
+    /// HTTPS proxy
+    #[serde(default)]
+    pub secure_web_proxy: Option<ProxySettings>,
+}
+
+/// Proxy of a network service
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProxySettings {
+    pub enabled: bool,
+    
+    #[serde(default)]
+    pub server: String,
+    
+    #[serde(default)]
+    pub port: u16,
+}
+
+/// Security baseline fragment content, settings left out are not checked
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SecurityBaselineFragment {
+    #[serde(default)]
+    pub firewall: Option<FirewallSettings>,
+    
+    #[serde(default)]
+    pub filevault: Option<FileVaultSettings>,
+    
+    #[serde(default)]
+    pub screensaver: Option<ScreensaverSettings>,
+    
+    #[serde(default)]
+    pub gatekeeper: Option<ToggleSettings>,
+    
+    /// SSH access to this machine
+    #[serde(default)]
+    pub remote_login: Option<ToggleSettings>,
+    
+    #[serde(default)]
+    pub software_updates: Option<SoftwareUpdateSettings>,
+}
+
+/// Application firewall
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FirewallSettings {
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    
+    /// Do not answer probing requests like ping
+    #[serde(default)]
+    pub stealth_mode: Option<bool>,
+    
+    #[serde(default)]
+    pub block_all_incoming: Option<bool>,
+}
+
+/// FileVault disk encryption, only verified
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FileVaultSettings {
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Password after sleep or screen saver
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScreensaverSettings {
+    #[serde(default)]
+    pub ask_for_password: Option<bool>,
+    
+    #[serde(default)]
+    pub delay_seconds: Option<u64>,
+}
+
+/// A setting that is either on or off
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ToggleSettings {
+    pub enabled: bool,
+}
+
+/// Automatic software updates
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SoftwareUpdateSettings {
+    #[serde(default)]
+    pub automatic_check: Option<bool>,
+    
+    #[serde(default)]
+    pub install_security_updates: Option<bool>,
+}
+
+/// Backup fragment content
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BackupFragment {
+    #[serde(default)]
+    pub time_machine: Option<TimeMachineSettings>,
+}
+
+/// Expected Time Machine configuration, settings left out are not checked
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TimeMachineSettings {
+    /// A backup destination has to be configured, only verified
+    #[serde(default)]
+    pub destination_required: bool,
+    
+    /// Back up automatically every hour
+    #[serde(default)]
+    pub auto_backup: Option<bool>,
+    
+    /// Paths excluded from backups, paths that do not exist are skipped
+    #[serde(default)]
+    pub exclusions: Vec<String>,
+}
+
 /// Custom fragment content
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CustomFragment {
@@ -100,13 +399,49 @@ pub struct CustomFragment {
 }
 
 impl Fragment {
-    /// Load a fragment from a file
+    /// Parse the type specific content of the fragment
+    pub fn section<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        serde_yaml::from_value(self.content.clone())
+            .with_context(|| format!("Invalid {} fragment", self.fragment_type))
+    }
+    
+    /// Load a fragment from a file, failing on unknown sections and keys
+    ///
+    /// The type specific content is parsed as well, so anything apply would
+    /// reject is reported here.
+    pub fn from_file_strict<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read fragment file: {}", path.display()))?;
+        
+        let issues = check_strict(&content)
+            .with_context(|| format!("Failed to parse fragment file: {}", path.display()))?;
+        if !issues.is_empty() {
+            let lines: Vec<String> = issues.iter().map(|issue| format!("  {}", issue)).collect();
+            anyhow::bail!("{} problem(s) in {}:\n{}", issues.len(), path.display(), lines.join("\n"));
+        }
+        
+        let fragment = Self::from_file(path)?;
+        match fragment.fragment_type {
+            FragmentType::Dotfiles => { fragment.section::<DotfilesFragment>()?; }
+            FragmentType::System | FragmentType::MacosDefaults => { fragment.section::<SystemFragment>()?; }
+            FragmentType::Services => { fragment.section::<ServicesFragment>()?; }
+            FragmentType::Custom => { fragment.section::<CustomFragment>()?; }
+            FragmentType::SecurityBaseline => { fragment.section::<SecurityBaselineFragment>()?; }
+            FragmentType::Backup => { fragment.section::<BackupFragment>()?; }
+            FragmentType::Network => { fragment.section::<NetworkFragment>()?; }
+            FragmentType::DevShell => {}
+        }
+        Ok(fragment)
+    }
+    
+    /// Load a fragment from a file, with the files it includes merged in
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let file = std::fs::File::open(path.as_ref())
-            .with_context(|| format!("Failed to open fragment file: {}", path.as_ref().display()))?;
+        let path = path.as_ref();
+        let value = load_with_includes(path, &mut Vec::new())?;
         
-        serde_yaml::from_reader(file)
-            .with_context(|| format!("Failed to parse fragment file: {}", path.as_ref().display()))
+        serde_yaml::from_value(value)
+            .with_context(|| format!("Failed to parse fragment file: {}", path.display()))
     }
     
     /// Save a fragment to a file
@@ -117,4 +452,232 @@ impl Fragment {
         serde_yaml::to_writer(file, self)
             .with_context(|| format!("Failed to write fragment file: {}", path.as_ref().display()))
     }
-}
\ No newline at end of file
+}
+
+/// Problem found by strict validation
+#[derive(Debug, Clone)]
+pub struct Issue {
+    /// Line in the fragment file, starting at 1, if it could be located
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Keys every fragment has besides its sections
+const COMMON_KEYS: &[&str] = &["fragment_type", "description", "after", "requires", "when", "include"];
+
+/// Keys of an included file that only concern the file itself
+const INCLUDE_ONLY_KEYS: &[&str] = &["when", "include"];
+
+/// Read a fragment file as YAML and merge the files it includes into it
+///
+/// Included files are merged in the order listed, the including file last:
+/// lists are concatenated and mappings merged, other values of later files
+/// replace earlier ones. Entries of the including file therefore come after
+/// those it includes and win where both configure the same thing. Included
+/// files may be partial, leaving out `fragment_type`, and are skipped if
+/// their own `when` does not match this machine. `stack` holds the files
+/// being loaded, to reject include cycles.
+fn load_with_includes(path: &Path, stack: &mut Vec<PathBuf>) -> Result<serde_yaml::Value> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to open fragment file: {}", path.display()))?;
+    let mut value: serde_yaml::Value = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse fragment file: {}", path.display()))?;
+    let Some(mapping) = value.as_mapping_mut() else {
+        return Ok(value);
+    };
+    let Some(includes) = mapping.get("include").cloned() else {
+        return Ok(value);
+    };
+    let includes: Vec<String> = serde_yaml::from_value(includes)
+        .with_context(|| format!("include of {} must be a list of files", path.display()))?;
+    
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    stack.push(canonical);
+    
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let fragment_type = mapping.get("fragment_type").cloned();
+    let mut merged = serde_yaml::Value::Mapping(serde_yaml::Mapping::new());
+    for include in &includes {
+        let expanded = PathBuf::from(shellexpand::tilde(include).into_owned());
+        let include_path = if expanded.is_absolute() { expanded } else { base_dir.join(expanded) };
+        let canonical = include_path.canonicalize().unwrap_or_else(|_| include_path.clone());
+        if stack.contains(&canonical) {
+            anyhow::bail!("{} includes {}, which includes it back", path.display(), include_path.display());
+        }
+        
+        let mut included = load_with_includes(&include_path, stack)
+            .with_context(|| format!("Failed to include {} in {}", include, path.display()))?;
+        let Some(included_mapping) = included.as_mapping_mut() else {
+            anyhow::bail!("Included file {} must be a mapping of sections", include_path.display());
+        };
+        
+        if let Some(when) = included_mapping.get("when") {
+            let when: Conditions = serde_yaml::from_value(when.clone())
+                .with_context(|| format!("Invalid when in {}", include_path.display()))?;
+            if let Some(reason) = when.unmatched() {
+                tracing::debug!("Not including {} in {}, {}", include_path.display(), path.display(), reason);
+                continue;
+            }
+        }
+        let theirs = included_mapping.get("fragment_type");
+        if fragment_type.is_some() && theirs.is_some() && fragment_type.as_ref() != theirs {
+            anyhow::bail!("{} includes {}, which is a fragment of another type",
+                path.display(), include_path.display());
+        }
+        for key in INCLUDE_ONLY_KEYS {
+            included_mapping.remove(*key);
+        }
+        merge(&mut merged, included);
+    }
+    
+    stack.pop();
+    merge(&mut merged, value);
+    Ok(merged)
+}
+
+/// Merge `overlay` into `base`: lists are appended, mappings merged, anything else replaced
+fn merge(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+    match (base, overlay) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => { base.insert(key, value); }
+                }
+            }
+        }
+        (serde_yaml::Value::Sequence(base), serde_yaml::Value::Sequence(overlay)) => base.extend(overlay),
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Unknown fragment types, sections and entry keys in a fragment
+///
+/// Serde ignores keys it doesn't know, so a typo like `file:` for `files:`
+/// would otherwise silently do nothing. Fails only if the YAML is invalid.
+pub fn check_strict(content: &str) -> Result<Vec<Issue>> {
+    let root: serde_yaml::Value = serde_yaml::from_str(content)?;
+    let Some(root) = root.as_mapping() else {
+        anyhow::bail!("A fragment must be a mapping of sections");
+    };
+    
+    let mut issues = Vec::new();
+    let type_name = root.get("fragment_type").and_then(|value| value.as_str());
+    let Some(type_name) = type_name else {
+        issues.push(Issue { line: None, message: "Missing fragment_type".to_string() });
+        return Ok(issues);
+    };
+    let Ok(fragment_type) = type_name.parse::<FragmentType>() else {
+        let names: Vec<String> = FragmentType::ALL.iter().map(ToString::to_string).collect();
+        let candidates: Vec<&str> = names.iter().map(String::as_str).collect();
+        issues.push(Issue {
+            line: find_key(content, "fragment_type", 0, true),
+            message: format!("Unknown fragment type '{}'{}", type_name, did_you_mean(type_name, &candidates)),
+        });
+        return Ok(issues);
+    };
+    
+    let sections = fragment_type.sections();
+    let top_level: Vec<&str> = COMMON_KEYS.iter().copied()
+        .chain(sections.iter().map(|(name, _)| *name))
+        .collect();
+    
+    for (key, value) in root {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let section_line = find_key(content, key, 0, true);
+        
+        let Some((_, entry_keys)) = sections.iter().find(|(name, _)| *name == key) else {
+            if !COMMON_KEYS.contains(&key) {
+                issues.push(Issue {
+                    line: section_line,
+                    message: format!("Unknown section '{}' in {} fragment{}", key, fragment_type,
+                        did_you_mean(key, &top_level)),
+                });
+            }
+            continue;
+        };
+        let Some(entry_keys) = entry_keys else {
+            continue;
+        };
+        
+        // A section holds one mapping or a list of them
+        let entries: Vec<&serde_yaml::Mapping> = match value {
+            serde_yaml::Value::Mapping(mapping) => vec![mapping],
+            serde_yaml::Value::Sequence(items) => items.iter().filter_map(|item| item.as_mapping()).collect(),
+            _ => Vec::new(),
+        };
+        
+        // Unknown keys appear in file order, so each is searched after the previous one
+        let mut cursor = section_line.unwrap_or(0);
+        for entry in entries {
+            for entry_key in entry.keys().filter_map(|key| key.as_str()) {
+                if entry_keys.contains(&entry_key) {
+                    continue;
+                }
+                let line = find_key(content, entry_key, cursor, false);
+                cursor = line.unwrap_or(cursor);
+                issues.push(Issue {
+                    line,
+                    message: format!("Unknown key '{}' in {}{}", entry_key, key, did_you_mean(entry_key, entry_keys)),
+                });
+            }
+        }
+    }
+    
+    Ok(issues)
+}
+
+/// Line of the first `key:` after line `after`, starting at 1
+///
+/// With `top_level`, only unindented keys match. List items (`- key:`) match
+/// as well.
+fn find_key(content: &str, key: &str, after: usize, top_level: bool) -> Option<usize> {
+    content.lines()
+        .enumerate()
+        .skip(after)
+        .find(|(_, line)| {
+            if top_level && line.starts_with(char::is_whitespace) {
+                return false;
+            }
+            let line = line.trim_start();
+            let line = line.strip_prefix("- ").unwrap_or(line).trim_start();
+            line.strip_prefix(key).is_some_and(|rest| rest.trim_start().starts_with(':'))
+        })
+        .map(|(index, _)| index + 1)
+}
+
+/// `, did you mean 'x'?` for the closest candidate, if one is close enough
+fn did_you_mean(name: &str, candidates: &[&str]) -> String {
+    candidates.iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= (candidate.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| format!(", did you mean '{}'?", candidate))
+        .unwrap_or_default()
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
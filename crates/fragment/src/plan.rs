@@ -0,0 +1,197 @@
+use std::path::PathBuf;
+use console::style;
+use serde::Serialize;
+use crate::parser::FragmentType;
+
+/// A concrete step that brings the system in line with a fragment
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    /// Point `target` at `source`, moving an existing file aside if `backup` is set
+    Symlink { source: PathBuf, target: PathBuf, backup: bool },
+    /// Write `source` to `target` with its secret placeholders resolved
+    Render { source: PathBuf, target: PathBuf, backup: bool },
+    /// `defaults write <domain> <key> -<value_type> <values>`
+    WriteDefault { domain: String, key: String, value_type: String, values: Vec<String> },
+    /// Restart an application so it picks up changed defaults
+    Restart { app: String },
+    /// `brew services start` or `brew services stop`
+    Service { name: String, start: bool },
+    /// Write a launchd agent and load it
+    LaunchAgent { path: PathBuf, content: String },
+    /// Run a custom script with its parameters in the environment, placeholders
+    /// in the values are resolved when it runs
+    RunScript { path: PathBuf, env: Vec<(String, String)> },
+    /// Remove a link or rendered file the fragment no longer declares
+    RemoveFile { path: PathBuf },
+    /// Write back the values a default had before the fragment set it, delete it if it had none
+    RestoreDefault { domain: String, key: String, value_type: String, previous: Option<Vec<String>> },
+    /// Unload a launchd agent and remove its property list
+    RemoveLaunchAgent { path: PathBuf },
+    /// Run a system command that needs administrator rights through sudo
+    Privileged { section: &'static str, command: Vec<String> },
+    /// A setting that cannot be changed automatically, applying reports how to fix it
+    Verify { section: &'static str, remedy: String },
+    /// Exclude a path from Time Machine backups, or include it again
+    BackupExclusion { path: PathBuf, excluded: bool },
+    /// `networksetup` commands run in order through sudo, `disruptive` if they may cut the connection
+    Network { commands: Vec<Vec<String>>, disruptive: bool },
+}
+
+impl Operation {
+    /// Part of the system the operation changes, changes are grouped by it
+    pub fn section(&self) -> &'static str {
+        match self {
+            Operation::Symlink { .. } | Operation::Render { .. } | Operation::RemoveFile { .. } => "files",
+            Operation::WriteDefault { .. } | Operation::RestoreDefault { .. } => "preferences",
+            Operation::Restart { .. } => "restarts",
+            Operation::Service { .. } => "services",
+            Operation::LaunchAgent { .. } | Operation::RemoveLaunchAgent { .. } => "launch agents",
+            Operation::RunScript { .. } => "scripts",
+            Operation::Privileged { section, .. } | Operation::Verify { section, .. } => section,
+            Operation::BackupExclusion { .. } => "backup",
+            Operation::Network { .. } => "network",
+        }
+    }
+
+    /// Whether the operation runs through sudo
+    pub fn needs_sudo(&self) -> bool {
+        matches!(self, Operation::Privileged { .. } | Operation::Network { .. })
+    }
+
+    /// Whether the operation may cut the network connection
+    pub fn is_disruptive(&self) -> bool {
+        matches!(self, Operation::Network { disruptive: true, .. })
+    }
+}
+
+/// How a change affects its target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Create,
+    Update,
+    /// Clean up something the fragment no longer declares
+    Remove,
+    Run,
+    /// Has to be fixed by hand
+    Manual,
+}
+
+/// A single planned change
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// What is changed, e.g. a file or `domain key`
+    pub target: String,
+    /// Current state, if there is one
+    pub current: Option<String>,
+    /// State after the change
+    pub desired: String,
+    pub operation: Operation,
+}
+
+/// Changes applying a fragment would make
+#[derive(Debug, Clone)]
+pub struct FragmentPlan {
+    pub fragment_type: FragmentType,
+    pub changes: Vec<Change>,
+    /// Planning is not implemented for this fragment type yet
+    pub unsupported: bool,
+    /// Number of settings compared, for fragments reporting compliance
+    pub checked: Option<usize>,
+}
+
+impl FragmentPlan {
+    pub fn new(fragment_type: FragmentType, changes: Vec<Change>) -> Self {
+        Self { fragment_type, changes, unsupported: false, checked: None }
+    }
+
+    pub fn unsupported(fragment_type: FragmentType) -> Self {
+        Self { fragment_type, changes: Vec::new(), unsupported: true, checked: None }
+    }
+
+    /// True if applying the fragment would not change anything
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Changes to state, without scripts and restarts that run on every apply
+    pub fn pending(&self) -> impl Iterator<Item = &Change> {
+        self.changes.iter().filter(|change| change.kind != ChangeKind::Run)
+    }
+
+    /// Number of changes of a kind
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        self.changes.iter().filter(|change| change.kind == kind).count()
+    }
+
+    /// Changes grouped by section, sections in the order they are applied
+    pub fn sections(&self) -> Vec<(&'static str, Vec<&Change>)> {
+        let mut sections: Vec<(&'static str, Vec<&Change>)> = Vec::new();
+        for change in &self.changes {
+            let section = change.operation.section();
+            match sections.iter_mut().find(|(name, _)| *name == section) {
+                Some((_, changes)) => changes.push(change),
+                None => sections.push((section, vec![change])),
+            }
+        }
+        sections
+    }
+
+    /// Print the plan, used by both `apply --dry-run` and `diff`
+    pub fn render(&self, name: &str) {
+        println!("{} {}", style(name).bold(), style(format!("({})", self.fragment_type)).dim());
+
+        if self.unsupported {
+            println!("  {}", style("changes cannot be previewed for this fragment type yet").dim());
+            return;
+        }
+
+        if let Some(checked) = self.checked {
+            let compliant = checked.saturating_sub(self.changes.len());
+            println!("  {}", style(format!("{} of {} settings compliant", compliant, checked)).dim());
+        }
+
+        if self.changes.is_empty() {
+            println!("  {}", style("up to date").dim());
+            return;
+        }
+
+        for (section, changes) in self.sections() {
+            println!("  {} {}", style(section).bold(), style(format!("({})", describe_counts(&changes))).dim());
+            for change in changes {
+                let mut line = match &change.current {
+                    Some(current) => format!("{}: {} → {}", change.target, current, change.desired),
+                    None => format!("{}: {}", change.target, change.desired),
+                };
+                if change.operation.needs_sudo() {
+                    line.push_str(" (sudo)");
+                }
+                match change.kind {
+                    ChangeKind::Create => println!("    {} {}", style("+").green(), style(line).green()),
+                    ChangeKind::Update => println!("    {} {}", style("~").yellow(), style(line).yellow()),
+                    ChangeKind::Remove => println!("    {} {}", style("-").red(), style(line).red()),
+                    ChangeKind::Run => println!("    {} {}", style("!").cyan(), style(line).cyan()),
+                    ChangeKind::Manual => println!("    {} {}", style("?").magenta(), style(line).magenta()),
+                }
+            }
+        }
+    }
+}
+
+/// Counts of changes by kind, e.g. `1 to create, 2 to update`
+pub fn describe_counts(changes: &[&Change]) -> String {
+    [
+        (ChangeKind::Create, "to create"),
+        (ChangeKind::Update, "to update"),
+        (ChangeKind::Remove, "to remove"),
+        (ChangeKind::Run, "to run"),
+        (ChangeKind::Manual, "to fix by hand"),
+    ]
+        .into_iter()
+        .map(|(kind, label)| (changes.iter().filter(|change| change.kind == kind).count(), label))
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
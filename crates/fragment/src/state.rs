@@ -0,0 +1,286 @@
+//! What applied fragments put in place.
+//!
+//! A fragment only describes what it wants, so once an entry is removed from
+//! it, apply no longer knows it ever managed that link or default. Every apply
+//! therefore records the resources of each fragment in
+//! `~/.sapphire/state/fragments.json`, keyed by the fragment file. Recorded
+//! resources that no fragment of an apply declares any more are orphans, the
+//! apply cleans them up unless `--keep-orphans` is given. That includes the
+//! resources of fragment files deleted or disabled since they were applied,
+//! but not those moved from one fragment to another.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use sapphire_core::paths::SapphirePaths;
+use crate::engine::FragmentEngine;
+use crate::parser::{Fragment, FragmentType};
+
+/// Name of the record in the state directory
+const STATE_FILE: &str = "fragments.json";
+
+/// Something a fragment manages
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Resource {
+    /// A symlink at `target` pointing at `source`
+    Link { target: PathBuf, source: PathBuf },
+    /// A file rendered from a template
+    File { target: PathBuf },
+    /// A default, `previous` holds its values from before the fragment first wrote it
+    Default { domain: String, key: String, value_type: String, previous: Option<Vec<String>> },
+    /// A launchd agent and its property list
+    LaunchAgent { label: String, path: PathBuf },
+    /// A path excluded from Time Machine backups
+    BackupExclusion { path: PathBuf },
+}
+
+impl Resource {
+    /// Whether both refer to the same thing, e.g. the same file or default
+    pub fn same(&self, other: &Resource) -> bool {
+        match (self, other) {
+            (Resource::Link { target: a, .. } | Resource::File { target: a },
+             Resource::Link { target: b, .. } | Resource::File { target: b }) => a == b,
+            (Resource::Default { domain: a, key: x, .. }, Resource::Default { domain: b, key: y, .. }) => a == b && x == y,
+            (Resource::LaunchAgent { label: a, .. }, Resource::LaunchAgent { label: b, .. }) => a == b,
+            (Resource::BackupExclusion { path: a }, Resource::BackupExclusion { path: b }) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Type of the fragments declaring this kind of resource
+    pub fn fragment_type(&self) -> FragmentType {
+        match self {
+            Resource::Link { .. } | Resource::File { .. } => FragmentType::Dotfiles,
+            Resource::Default { .. } => FragmentType::System,
+            Resource::LaunchAgent { .. } => FragmentType::Services,
+            Resource::BackupExclusion { .. } => FragmentType::Backup,
+        }
+    }
+}
+
+/// The resources of every applied fragment
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    fragments: BTreeMap<String, Vec<Resource>>,
+}
+
+impl State {
+    /// Read the record, empty if nothing was applied yet
+    pub fn load() -> Result<Self> {
+        let path = state_file();
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Write the record
+    pub fn save(&self) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        crate::utils::write_file(&state_file(), &content)
+    }
+
+    /// Resources recorded for a fragment file
+    pub fn managed(&self, fragment: &Path) -> &[Resource] {
+        self.fragments.get(&key(fragment)).map_or(&[], Vec::as_slice)
+    }
+
+    /// Resources recorded for any fragment file
+    pub fn all(&self) -> Vec<Resource> {
+        self.fragments.values().flatten().cloned().collect()
+    }
+
+    /// Recorded fragment files below `scope` that are not among `present`,
+    /// because they were deleted or disabled since they were applied
+    ///
+    /// `scope` is the fragment file or directory applied, only fragments
+    /// below it are part of that apply.
+    pub fn gone(&self, scope: &Path, present: &[PathBuf]) -> Vec<PathBuf> {
+        let scope = PathBuf::from(key(scope));
+        let present: Vec<String> = present.iter().map(|file| key(file)).collect();
+        self.fragments.keys()
+            .filter(|recorded| Path::new(recorded).starts_with(&scope) && !present.contains(recorded))
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    /// Record the resources of a fragment file after applying it
+    ///
+    /// Resources it no longer declares are forgotten, except those `claimed`
+    /// by another fragment that has not recorded them yet, so they are still
+    /// cleaned up if that fragment is never applied. After a `failed` apply
+    /// the orphans stay recorded too, so the next apply removes them. What the
+    /// fragment declares is no longer recorded for any other fragment.
+    pub fn record(&mut self, fragment: &Path, declared: Vec<Resource>, claimed: &[Resource], failed: bool) {
+        let kept: Vec<Resource> = self.managed(fragment).iter()
+            .filter(|resource| !declared.iter().any(|other| other.same(resource)))
+            .filter(|resource| match claimed.iter().any(|other| other.same(resource)) {
+                true => !self.recorded_elsewhere(fragment, resource),
+                false => failed,
+            })
+            .cloned()
+            .collect();
+        
+        let fragment = key(fragment);
+        for (_, resources) in self.fragments.iter_mut().filter(|(other, _)| **other != fragment) {
+            resources.retain(|resource| !declared.iter().any(|other| other.same(resource)));
+        }
+        self.fragments.retain(|_, resources| !resources.is_empty());
+        self.set(Path::new(&fragment), kept.into_iter().chain(declared).collect());
+    }
+
+    /// Whether a fragment file other than `fragment` recorded the resource
+    fn recorded_elsewhere(&self, fragment: &Path, resource: &Resource) -> bool {
+        let fragment = key(fragment);
+        self.fragments.iter()
+            .any(|(other, resources)| *other != fragment && resources.iter().any(|recorded| recorded.same(resource)))
+    }
+
+    /// Record the resources of a fragment file, forgetting it if there are none
+    fn set(&mut self, fragment: &Path, resources: Vec<Resource>) {
+        if resources.is_empty() {
+            self.fragments.remove(&key(fragment));
+        } else {
+            self.fragments.insert(key(fragment), resources);
+        }
+    }
+}
+
+/// What the fragments of one apply declare
+///
+/// A recorded resource is only an orphan if no fragment of the apply declares
+/// it, so an entry moved to another fragment is not removed, whichever of the
+/// two is applied first. Fragments whose resources are not known, as they
+/// failed to load or their `when` does not match, claim what is recorded for them.
+pub struct Claims {
+    /// Resources each fragment declares, `None` if they could not be worked out
+    pub declared: Vec<Option<Vec<Resource>>>,
+    /// Resources declared or claimed by any fragment
+    pub claimed: Vec<Resource>,
+}
+
+impl Claims {
+    /// Work out the resources of the fragments loaded from `files`
+    pub fn new(engine: &FragmentEngine, state: &State, files: &[PathBuf], fragments: &[(PathBuf, Fragment)]) -> Self {
+        // Defaults moved between fragments keep the values recorded from before either wrote them
+        let recorded = state.all();
+        let declared: Vec<Option<Vec<Resource>>> = fragments.iter()
+            .map(|(file, fragment)| {
+                if fragment.when.unmatched().is_some() {
+                    return None;
+                }
+                engine.resources(fragment, file.parent().unwrap_or(Path::new(".")), &recorded).ok()
+            })
+            .collect();
+        
+        let unknown = fragments.iter().zip(&declared)
+            .filter(|(_, declared)| declared.is_none())
+            .map(|((file, _), _)| file)
+            .chain(files.iter().filter(|file| !fragments.iter().any(|(loaded, _)| loaded == *file)));
+        let mut claimed: Vec<Resource> = unknown.flat_map(|file| state.managed(file)).cloned().collect();
+        claimed.extend(declared.iter().flatten().flatten().cloned());
+        
+        Claims { declared, claimed }
+    }
+}
+
+/// Resources of `managed` that no fragment in `claimed` declares
+pub fn orphans<'a>(managed: &'a [Resource], claimed: &[Resource]) -> Vec<&'a Resource> {
+    managed.iter()
+        .filter(|resource| !claimed.iter().any(|other| other.same(resource)))
+        .collect()
+}
+
+/// Fragments are recorded by absolute path, so relative invocations agree
+fn key(fragment: &Path) -> String {
+    std::path::absolute(fragment)
+        .unwrap_or_else(|_| fragment.to_path_buf())
+        .display()
+        .to_string()
+}
+
+fn state_file() -> PathBuf {
+    SapphirePaths::resolve().state_dir().join(STATE_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(target: &str) -> Resource {
+        Resource::Link { target: PathBuf::from(target), source: PathBuf::from("/dotfiles").join(target.trim_start_matches('/')) }
+    }
+
+    fn dotfiles(path: &str, targets: &[&str]) -> (PathBuf, Fragment) {
+        let files: String = targets.iter()
+            .map(|target| format!("  - source: {}\n    target: {}\n", target.trim_start_matches('/'), target))
+            .collect();
+        let fragment = serde_yaml::from_str(&format!("fragment_type: dotfiles\nfiles:\n{}", files)).unwrap();
+        (PathBuf::from(path), fragment)
+    }
+
+    #[test]
+    fn resources_moved_between_fragments_are_not_orphans() {
+        let mut state = State::default();
+        state.set(Path::new("/dotfiles/a.yaml"), vec![link("/dotfiles/zshrc"), link("/dotfiles/vimrc")]);
+        let fragments = vec![dotfiles("/dotfiles/a.yaml", &["/dotfiles/vimrc"]), dotfiles("/dotfiles/b.yaml", &["/dotfiles/zshrc"])];
+        let files: Vec<PathBuf> = fragments.iter().map(|(file, _)| file.clone()).collect();
+        let claims = Claims::new(&FragmentEngine::new(), &state, &files, &fragments);
+
+        assert!(orphans(state.managed(Path::new("/dotfiles/a.yaml")), &claims.claimed).is_empty());
+
+        // Kept by the old fragment until the new one recorded it
+        let [a, b] = [0, 1].map(|index| claims.declared[index].clone().unwrap());
+        state.record(Path::new("/dotfiles/a.yaml"), a.clone(), &claims.claimed, false);
+        assert_eq!(state.managed(Path::new("/dotfiles/a.yaml")).len(), 2);
+        state.record(Path::new("/dotfiles/b.yaml"), b.clone(), &claims.claimed, false);
+        assert_eq!(state.managed(Path::new("/dotfiles/a.yaml")), a.as_slice());
+        assert_eq!(state.managed(Path::new("/dotfiles/b.yaml")), b.as_slice());
+    }
+
+    #[test]
+    fn resources_no_fragment_declares_are_orphans() {
+        let mut state = State::default();
+        state.set(Path::new("/dotfiles/a.yaml"), vec![link("/dotfiles/zshrc")]);
+        let fragments = vec![dotfiles("/dotfiles/a.yaml", &[]), dotfiles("/dotfiles/b.yaml", &["/dotfiles/vimrc"])];
+        let files: Vec<PathBuf> = fragments.iter().map(|(file, _)| file.clone()).collect();
+        let claims = Claims::new(&FragmentEngine::new(), &state, &files, &fragments);
+
+        assert_eq!(orphans(state.managed(Path::new("/dotfiles/a.yaml")), &claims.claimed), vec![&link("/dotfiles/zshrc")]);
+
+        // Still recorded after a failed apply, forgotten once removed
+        state.record(Path::new("/dotfiles/a.yaml"), Vec::new(), &claims.claimed, true);
+        assert_eq!(state.managed(Path::new("/dotfiles/a.yaml")), [link("/dotfiles/zshrc")]);
+        state.record(Path::new("/dotfiles/a.yaml"), Vec::new(), &claims.claimed, false);
+        assert!(state.managed(Path::new("/dotfiles/a.yaml")).is_empty());
+    }
+
+    #[test]
+    fn fragments_that_fail_to_load_claim_their_record() {
+        let mut state = State::default();
+        state.set(Path::new("/dotfiles/broken.yaml"), vec![link("/dotfiles/zshrc")]);
+        state.set(Path::new("/dotfiles/a.yaml"), vec![link("/dotfiles/zshrc")]);
+        let fragments = vec![dotfiles("/dotfiles/a.yaml", &[])];
+        let files = vec![PathBuf::from("/dotfiles/a.yaml"), PathBuf::from("/dotfiles/broken.yaml")];
+        let claims = Claims::new(&FragmentEngine::new(), &state, &files, &fragments);
+
+        assert!(orphans(state.managed(Path::new("/dotfiles/a.yaml")), &claims.claimed).is_empty());
+    }
+
+    #[test]
+    fn deleted_and_disabled_fragments_within_the_scope_are_gone() {
+        let mut state = State::default();
+        for file in ["/fragments/user/a.yaml", "/fragments/user/deleted.yaml", "/fragments/disabled/user/b.yaml", "/elsewhere/c.yaml"] {
+            state.set(Path::new(file), vec![link("/dotfiles/zshrc")]);
+        }
+        let present = [PathBuf::from("/fragments/user/a.yaml")];
+
+        assert_eq!(state.gone(Path::new("/fragments"), &present),
+            [PathBuf::from("/fragments/disabled/user/b.yaml"), PathBuf::from("/fragments/user/deleted.yaml")]);
+        assert!(state.gone(Path::new("/fragments/user/a.yaml"), &present).is_empty());
+    }
+}
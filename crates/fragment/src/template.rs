@@ -0,0 +1,63 @@
+//! Secret placeholders in dotfile templates and script parameters.
+//!
+//! A placeholder like `{{ secret "github_token" }}` is replaced by the secret
+//! stored with `sapphire secret set github_token`. Placeholders are resolved
+//! only while applying, so fragment files and the templates next to them can
+//! be kept in git without the secrets themselves. Rendered values are never
+//! logged or shown in previews.
+
+use anyhow::{Context, Result};
+use sapphire_core::secrets;
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+/// Names of the secrets referenced in `content`
+pub fn secret_names(content: &str) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    render_with(content, |name| {
+        names.push(name.to_string());
+        Ok(String::new())
+    })?;
+    Ok(names)
+}
+
+/// Replace every placeholder with the secret it references
+pub fn render(content: &str) -> Result<String> {
+    render_with(content, |name| {
+        secrets::get(name).with_context(|| format!("Failed to read secret '{}'", name))
+    })
+}
+
+/// Replace placeholders with the value `resolve` returns for their secret name
+fn render_with(content: &str, mut resolve: impl FnMut(&str) -> Result<String>) -> Result<String> {
+    let mut output = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(start) = rest.find(OPEN) {
+        let Some(end) = rest[start..].find(CLOSE) else {
+            break;
+        };
+        let expression = &rest[start + OPEN.len()..start + end];
+        let name = parse_secret(expression)
+            .with_context(|| format!("Invalid placeholder '{}{}{}', expected {{{{ secret \"name\" }}}}",
+                OPEN, expression, CLOSE))?;
+
+        output.push_str(&rest[..start]);
+        output.push_str(&resolve(name)?);
+        rest = &rest[start + end + CLOSE.len()..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Secret name of a `secret "name"` expression
+fn parse_secret(expression: &str) -> Option<&str> {
+    let argument = expression.trim().strip_prefix("secret")?;
+    if !argument.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let name = argument.trim().strip_prefix('"')?.strip_suffix('"')?;
+    (!name.is_empty() && !name.contains('"')).then_some(name)
+}
@@ -1,6 +1,7 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
 use anyhow::{Result, Context, anyhow};
+use sapphire_core::paths::SapphirePaths;
 
 // Result type for Fragment operations
 pub type FragmentResult<T> = Result<T>;
@@ -24,6 +25,66 @@ pub fn ensure_dir_exists(path: &Path) -> FragmentResult<()> {
     Ok(())
 }
 
+/// Directory holding all fragments
+pub fn fragments_dir() -> PathBuf {
+    SapphirePaths::resolve().fragments_dir()
+}
+
+/// The user fragment, applied when no path is given
+pub fn user_fragment() -> String {
+    fragments_dir().join("user").to_string_lossy().into_owned()
+}
+
+/// Directory below a fragment directory whose fragments are not applied
+pub const DISABLED_DIR: &str = "disabled";
+
+/// Directory below a fragment directory for files that are only included by other fragments
+pub const INCLUDES_DIR: &str = "includes";
+
+/// Fragment files at a path: the file itself, or the YAML files in a directory
+///
+/// `~` is expanded. Directories are searched recursively, except for
+/// `disabled` and `includes` directories. Files are returned in path order.
+pub fn fragment_files(path: &str) -> FragmentResult<Vec<PathBuf>> {
+    let path = PathBuf::from(shellexpand::tilde(path).into_owned());
+
+    if !path_exists(&path) {
+        return Err(anyhow!("Fragment file not found: {}", path.display()));
+    }
+
+    if !path.is_dir() {
+        return Ok(vec![path]);
+    }
+
+    let mut files = Vec::new();
+    collect_fragment_files(&path, &mut files)?;
+    files.sort();
+
+    Ok(files)
+}
+
+fn collect_fragment_files(dir: &Path, files: &mut Vec<PathBuf>) -> FragmentResult<()> {
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            if path.file_name().is_some_and(|name| name != DISABLED_DIR && name != INCLUDES_DIR) {
+                collect_fragment_files(&path, files)?;
+            }
+        } else if is_fragment_file(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Whether a path has a fragment extension
+pub fn is_fragment_file(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|ext| ext == "yaml" || ext == "yml")
+}
+
 pub fn read_file(path: &Path) -> FragmentResult<String> {
     fs::read_to_string(path)
         .with_context(|| format!("Failed to read file: {}", path.display()))
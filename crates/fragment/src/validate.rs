@@ -0,0 +1,31 @@
+use anyhow::Result;
+use crate::parser::Fragment;
+use crate::utils;
+
+/// Check fragments for unknown sections and keys and invalid entries
+///
+/// `path` is a fragment file or a directory of them. Every file is checked,
+/// the command fails if any has a problem.
+pub fn validate(path: &str) -> Result<()> {
+    let files = utils::fragment_files(path)?;
+    if files.is_empty() {
+        tracing::warn!("No fragment files found at: {}", path);
+        return Ok(());
+    }
+    
+    let mut invalid = 0;
+    for file in &files {
+        match Fragment::from_file_strict(file) {
+            Ok(fragment) => tracing::info!("{} is a valid {} fragment", file.display(), fragment.fragment_type),
+            Err(err) => {
+                tracing::error!("{:#}", err);
+                invalid += 1;
+            }
+        }
+    }
+    
+    if invalid > 0 {
+        anyhow::bail!("{} of {} fragment(s) are invalid", invalid, files.len());
+    }
+    Ok(())
+}
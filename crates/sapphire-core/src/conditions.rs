@@ -0,0 +1,133 @@
+//! Conditions restricting shards and fragments to some machines, declared with `when`.
+//!
+//! ```yaml
+//! when:
+//!   hostname: ["studio", "work-*"]
+//!   os_version: ">=14.0"
+//! ```
+//!
+//! Unlike [`requirements`](crate::requirements), a condition that does not
+//! hold is not an error: whatever declares it is skipped on that machine, so
+//! one set of files can configure several Macs.
+
+use std::process::Command;
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::requirements::macos_version;
+use crate::version::Version;
+
+/// Machines a shard or fragment applies to
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Conditions {
+    /// Host names, `*` matches any characters, e.g. `work-*`
+    ///
+    /// Matched case-insensitively against the full host name and the name
+    /// before its first dot. A single name may be given without a list.
+    #[serde(default, deserialize_with = "one_or_many", skip_serializing_if = "Vec::is_empty")]
+    pub hostname: Vec<String>,
+
+    /// macOS version, e.g. `14` for any 14.x, or a comparison like `>=14.0` or `<15`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+}
+
+impl Conditions {
+    /// True if there are no conditions
+    pub fn is_empty(&self) -> bool {
+        self.hostname.is_empty() && self.os_version.is_none()
+    }
+
+    /// Why this machine does not match, `None` if it does
+    ///
+    /// Conditions that cannot be checked do not match.
+    pub fn unmatched(&self) -> Option<String> {
+        if !self.hostname.is_empty() {
+            match hostname() {
+                Ok(host) if self.hostname.iter().any(|pattern| hostname_matches(pattern, &host)) => {}
+                Ok(host) => return Some(format!("host {} is not one of {}", host, self.hostname.join(", "))),
+                Err(e) => return Some(format!("could not get the host name: {}", e)),
+            }
+        }
+
+        if let Some(constraint) = &self.os_version {
+            match macos_version() {
+                Ok(current) if version_matches(constraint, &current) => {}
+                Ok(current) => return Some(format!("macOS {} does not match {}", current, constraint)),
+                Err(e) => return Some(format!("macOS {} required, could not check: {}", constraint, e)),
+            }
+        }
+
+        None
+    }
+}
+
+/// Accept a single string where a list is expected
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Raw::deserialize(deserializer)? {
+        Raw::One(value) => vec![value],
+        Raw::Many(values) => values,
+    })
+}
+
+/// Name of this machine, from `hostname`
+fn hostname() -> Result<String, String> {
+    let output = Command::new("hostname").output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Whether a host name pattern matches the full or short host name
+fn hostname_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let host = host.to_lowercase();
+    let short = host.split('.').next().unwrap_or(&host);
+    glob_matches(&pattern, &host) || glob_matches(&pattern, short)
+}
+
+/// Match `text` against a pattern where `*` stands for any characters
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            // Try every split of the remaining text for the rest of the pattern
+            (0..=text.len())
+                .filter(|index| text.is_char_boundary(*index))
+                .any(|index| glob_matches(rest, &text[index..]))
+        }
+    }
+}
+
+/// Whether a version matches a constraint like `14`, `=14.2`, `>=14.0` or `<15`
+///
+/// Without an operator, the version matches if it starts with the given
+/// components, so `14` matches 14.0 and 14.5 but not 15.0.
+fn version_matches(constraint: &str, current: &Version) -> bool {
+    let constraint = constraint.trim();
+    let (operator, required) = [">=", "<=", ">", "<", "="].iter()
+        .find_map(|operator| constraint.strip_prefix(operator).map(|rest| (*operator, rest.trim())))
+        .unwrap_or(("", constraint));
+    let required_version = Version::parse(required);
+
+    match operator {
+        ">=" => *current >= required_version,
+        "<=" => *current <= required_version,
+        ">" => *current > required_version,
+        "<" => *current < required_version,
+        "=" => *current == required_version,
+        _ => {
+            let current = current.as_str();
+            current == required || current.starts_with(&format!("{}.", required))
+        }
+    }
+}
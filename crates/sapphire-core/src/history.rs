@@ -0,0 +1,183 @@
+//! When shards and fragments were last applied.
+//!
+//! Every apply is recorded in `~/.sapphire/state/applied`, one tab separated
+//! line of kind, name, Unix timestamp, user and outcome per applied shard or
+//! fragment. Brew commands run through `shard brew` are recorded the same way,
+//! named after their command line. Only the most recent run of each is kept.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::paths::SapphirePaths;
+
+/// Name of the record in the state directory
+const HISTORY_FILE: &str = "applied";
+
+/// What was applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Shard,
+    Fragment,
+    /// A brew command run through shard
+    Brew,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Shard => "shard",
+            Kind::Fragment => "fragment",
+            Kind::Brew => "brew",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "shard" => Some(Kind::Shard),
+            "fragment" => Some(Kind::Fragment),
+            "brew" => Some(Kind::Brew),
+            _ => None,
+        }
+    }
+}
+
+/// Result of an apply
+///
+/// The counts are those of the whole run, `apply all` records the same
+/// counts for every shard it applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Outcome {
+    pub success: bool,
+    pub installed: usize,
+    pub upgraded: usize,
+    pub removed: usize,
+    pub failed: usize,
+}
+
+impl Outcome {
+    /// Short description, e.g. `ok, 2 installed`
+    pub fn describe(&self) -> String {
+        let counts: Vec<String> = [
+            (self.installed, "installed"),
+            (self.upgraded, "upgraded"),
+            (self.removed, "removed"),
+            (self.failed, "failed"),
+        ]
+            .into_iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, label)| format!("{} {}", count, label))
+            .collect();
+        let status = if self.success { "ok" } else { "failed" };
+        if counts.is_empty() {
+            status.to_string()
+        } else {
+            format!("{}, {}", status, counts.join(", "))
+        }
+    }
+}
+
+/// The most recent apply of a shard or fragment
+#[derive(Debug, Clone)]
+pub struct Applied {
+    pub kind: Kind,
+    /// Shard name or fragment file
+    pub name: String,
+    pub at: SystemTime,
+    /// User who applied, empty if unknown
+    pub user: String,
+    /// `None` for applies recorded by older versions, which only recorded successes
+    pub outcome: Option<Outcome>,
+}
+
+impl Applied {
+    /// Whether the apply succeeded
+    pub fn succeeded(&self) -> bool {
+        self.outcome.is_none_or(|outcome| outcome.success)
+    }
+
+    /// Time since the apply, e.g. `3 days ago`
+    pub fn describe_age(&self) -> String {
+        let secs = self.at.elapsed().unwrap_or_default().as_secs();
+        let (value, unit) = match secs {
+            0..60 => return "just now".to_string(),
+            60..3_600 => (secs / 60, "minute"),
+            3_600..86_400 => (secs / 3_600, "hour"),
+            _ => (secs / 86_400, "day"),
+        };
+        format!("{} {}{} ago", value, unit, if value == 1 { "" } else { "s" })
+    }
+}
+
+/// Record that a shard or fragment was applied just now
+pub fn record(kind: Kind, name: &str, outcome: Outcome) -> io::Result<()> {
+    let mut entries: Vec<Applied> = load()?
+        .into_iter()
+        .filter(|entry| !(entry.kind == kind && entry.name == name))
+        .collect();
+    let user = std::env::var("USER").unwrap_or_default();
+    entries.push(Applied { kind, name: name.to_string(), at: SystemTime::now(), user, outcome: Some(outcome) });
+    entries.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.name.cmp(&b.name)));
+
+    let content: String = entries.iter()
+        .map(|entry| {
+            let secs = entry.at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            // Tabs and line breaks would corrupt the record
+            let name = entry.name.replace(['\t', '\n'], " ");
+            let user = entry.user.replace(['\t', '\n'], " ");
+            let mut line = format!("{}\t{}\t{}\t{}", entry.kind.as_str(), name, secs, user);
+            if let Some(outcome) = entry.outcome {
+                line.push_str(&format!("\t{}\t{}\t{}\t{}\t{}",
+                    if outcome.success { "ok" } else { "failed" },
+                    outcome.installed, outcome.upgraded, outcome.removed, outcome.failed));
+            }
+            line + "\n"
+        })
+        .collect();
+
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+/// Everything applied so far, empty if nothing was recorded yet
+pub fn load() -> io::Result<Vec<Applied>> {
+    let content = match fs::read_to_string(history_path()) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    // Malformed lines are skipped rather than failing every apply
+    Ok(content.lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let kind = Kind::parse(fields.next()?)?;
+            let name = fields.next()?.to_string();
+            let secs = fields.next()?.trim().parse().ok()?;
+            // Older records end after the timestamp
+            let user = fields.next().unwrap_or_default().to_string();
+            let outcome = parse_outcome(fields.collect());
+            Some(Applied { kind, name, at: UNIX_EPOCH + Duration::from_secs(secs), user, outcome })
+        })
+        .collect())
+}
+
+fn parse_outcome(fields: Vec<&str>) -> Option<Outcome> {
+    let [status, installed, upgraded, removed, failed] = fields.as_slice() else {
+        return None;
+    };
+    Some(Outcome {
+        success: *status == "ok",
+        installed: installed.parse().ok()?,
+        upgraded: upgraded.parse().ok()?,
+        removed: removed.parse().ok()?,
+        failed: failed.parse().ok()?,
+    })
+}
+
+fn history_path() -> PathBuf {
+    SapphirePaths::resolve().state_dir().join(HISTORY_FILE)
+}
@@ -0,0 +1,25 @@
+// Sapphire core - functionality shared by sapphire, shard and fragment
+
+// Machines shards and fragments apply to
+pub mod conditions;
+
+// When shards and fragments were last applied
+pub mod history;
+
+// Exclusive lock of commands that change the system
+pub mod lock;
+
+// Locations of configuration, shards, fragments and state
+pub mod paths;
+
+// Refusal of changes on machines where they must go through review
+pub mod read_only;
+
+// Pre-flight checks declared by shards and fragments
+pub mod requirements;
+
+// Secret storage in the macOS keychain
+pub mod secrets;
+
+// Parsing and comparison of brew package versions
+pub mod version;
@@ -0,0 +1,78 @@
+//! Exclusive lock held by commands that change the system.
+//!
+//! Commands of shard and fragment that install packages or edit shards and
+//! files take an advisory `flock` on `~/.sapphire/.lock`, so a manual run and
+//! a scheduled one never interleave. The holder writes its pid into the file
+//! for the error shown to the other process. The lock is released when the
+//! [`RunLock`] is dropped, or by the kernel if the process dies.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use thiserror::Error;
+use crate::paths::SapphirePaths;
+
+/// Errors acquiring the lock
+#[derive(Error, Debug)]
+pub enum LockError {
+    #[error("Another sapphire process is running{}. Retry later or pass --wait", describe_pid(*.pid))]
+    Busy { pid: Option<u32> },
+
+    #[error("Failed to lock {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+}
+
+fn describe_pid(pid: Option<u32>) -> String {
+    pid.map(|pid| format!(" (pid {})", pid)).unwrap_or_default()
+}
+
+/// Held lock, released on drop
+#[derive(Debug)]
+pub struct RunLock {
+    _file: File,
+}
+
+/// Take the lock, waiting for the current holder if `wait` is set
+///
+/// Commands check [read-only mode](crate::read_only) themselves before,
+/// also those that change the system without the lock.
+pub fn acquire(wait: bool) -> Result<RunLock, LockError> {
+    let path = lock_path();
+    let io_error = |source| LockError::Io { path: path.clone(), source };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    // Not truncated on open, the file still names the current holder
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(io_error)?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) if wait => file.lock().map_err(io_error)?,
+        Err(TryLockError::WouldBlock) => return Err(LockError::Busy { pid: holder_pid(&mut file) }),
+        Err(TryLockError::Error(e)) => return Err(io_error(e)),
+    }
+
+    // The pid is informational, failing to record it does not matter
+    let _ = file.set_len(0)
+        .and_then(|_| file.seek(SeekFrom::Start(0)))
+        .and_then(|_| writeln!(file, "{}", std::process::id()));
+
+    Ok(RunLock { _file: file })
+}
+
+fn holder_pid(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+fn lock_path() -> PathBuf {
+    SapphirePaths::resolve().lock_file()
+}
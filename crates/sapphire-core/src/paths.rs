@@ -0,0 +1,174 @@
+//! Where the Sapphire tools keep their files.
+//!
+//! Everything lives below one root directory, `~/.sapphire` by default. The
+//! root is resolved from, in order:
+//!
+//! 1. the `--root` flag of the command line tools, see [`SapphirePaths::set_root`]
+//! 2. the `SAPPHIRE_HOME` environment variable
+//! 3. `home = "<dir>"` in `$XDG_CONFIG_HOME/sapphire/config.toml`
+//!    (`~/.config/sapphire/config.toml` if `XDG_CONFIG_HOME` is unset)
+//! 4. `~/.sapphire`, if it exists
+//! 5. `$XDG_DATA_HOME/sapphire`, if `XDG_DATA_HOME` is set
+//! 6. `~/.sapphire`
+//!
+//! so existing setups keep their directory while tests and additional
+//! profiles can point the tools somewhere else.
+
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Environment variable overriding the root directory
+pub const HOME_ENV: &str = "SAPPHIRE_HOME";
+
+/// Directory below the home directory used when nothing else is configured
+const DEFAULT_DIR: &str = ".sapphire";
+
+/// Root set for this process, taking precedence over everything else
+static ROOT_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Locations below the Sapphire root directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SapphirePaths {
+    root: PathBuf,
+}
+
+impl SapphirePaths {
+    /// Paths below an explicit root directory
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Resolve the root directory from the environment and configuration
+    pub fn resolve() -> Self {
+        if let Some(root) = ROOT_OVERRIDE.read().ok().and_then(|root| root.clone()) {
+            return Self::new(root);
+        }
+        if let Some(root) = std::env::var_os(HOME_ENV).filter(|root| !root.is_empty()) {
+            return Self::new(expand_tilde(&PathBuf::from(root)));
+        }
+        if let Some(root) = configured_root() {
+            return Self::new(root);
+        }
+
+        let default = home_dir().join(DEFAULT_DIR);
+        if default.exists() {
+            return Self::new(default);
+        }
+        match std::env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+            Some(data_home) => Self::new(PathBuf::from(data_home).join("sapphire")),
+            None => Self::new(default),
+        }
+    }
+
+    /// Use `root` for every later [`resolve`](Self::resolve) in this process
+    ///
+    /// Set from `--root` to operate on another profile, such as a test
+    /// sandbox or another user's directory mounted for repair. `~` is
+    /// expanded and relative paths are taken from the working directory.
+    pub fn set_root(root: impl AsRef<Path>) {
+        let root = expand_tilde(root.as_ref());
+        let root = std::path::absolute(&root).unwrap_or(root);
+        if let Ok(mut current) = ROOT_OVERRIDE.write() {
+            *current = Some(root);
+        }
+    }
+
+    /// The root directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Shared configuration file of all tools
+    pub fn config_file(&self) -> PathBuf {
+        self.root.join("config.toml")
+    }
+
+    /// Enabled shards
+    pub fn shards_dir(&self) -> PathBuf {
+        self.root.join("shards")
+    }
+
+    /// Disabled shards
+    pub fn disabled_dir(&self) -> PathBuf {
+        self.root.join("disabled")
+    }
+
+    /// Backups of shards taken before they are changed
+    pub fn backups_dir(&self) -> PathBuf {
+        self.root.join("backups")
+    }
+
+    /// User templates for new shards
+    pub fn templates_dir(&self) -> PathBuf {
+        self.root.join("templates")
+    }
+
+    /// Saved sets of enabled shards
+    pub fn profiles_dir(&self) -> PathBuf {
+        self.root.join("profiles")
+    }
+
+    /// Fragments applied by fragment
+    pub fn fragments_dir(&self) -> PathBuf {
+        self.root.join("fragments")
+    }
+
+    /// Files fragments link or copy into place
+    pub fn dotfiles_dir(&self) -> PathBuf {
+        self.root.join("dotfiles")
+    }
+
+    /// Scripts run by fragments
+    pub fn scripts_dir(&self) -> PathBuf {
+        self.root.join("scripts")
+    }
+
+    /// Plugin definitions, one TOML file per plugin
+    pub fn plugins_dir(&self) -> PathBuf {
+        self.root.join("plugins")
+    }
+
+    /// Data fetched from brew that can be fetched again
+    pub fn cache_dir(&self) -> PathBuf {
+        self.root.join("cache")
+    }
+
+    /// Records of past runs
+    pub fn state_dir(&self) -> PathBuf {
+        self.root.join("state")
+    }
+
+    /// Lock file held by commands that change the system
+    pub fn lock_file(&self) -> PathBuf {
+        self.root.join(".lock")
+    }
+}
+
+impl Default for SapphirePaths {
+    fn default() -> Self {
+        Self::resolve()
+    }
+}
+
+/// The `home` of the configuration file in the XDG config directory
+fn configured_root() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir().join(".config"));
+    let content = std::fs::read_to_string(config_home.join("sapphire/config.toml")).ok()?;
+    let config: toml::Table = toml::from_str(&content).ok()?;
+    let root = config.get("home")?.as_str()?;
+    Some(expand_tilde(Path::new(root)))
+}
+
+fn expand_tilde(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => home_dir().join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+fn home_dir() -> PathBuf {
+    std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default()
+}
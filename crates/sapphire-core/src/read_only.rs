@@ -0,0 +1,96 @@
+//! Read-only mode for machines where changes must go through review.
+//!
+//! With `SAPPHIRE_READ_ONLY=1` in the environment, or `read_only = true` at
+//! the top of `~/.sapphire/config.toml`, commands that change the system
+//! refuse to run. Every CLI checks [`ensure_writable`] before such a command,
+//! whether or not it takes the [`lock`](crate::lock), so diffs, listings,
+//! searches and reports keep working.
+
+use std::path::Path;
+use thiserror::Error;
+use crate::paths::SapphirePaths;
+
+/// Environment variable that turns read-only mode on
+pub const READ_ONLY_ENV: &str = "SAPPHIRE_READ_ONLY";
+
+/// A command that changes the system was refused
+#[derive(Error, Debug)]
+#[error("Read-only mode is on ({reason}), changes must go through review. Preview them with diff or --dry-run")]
+pub struct ReadOnlyError {
+    pub reason: String,
+}
+
+/// Why read-only mode is on, `None` if it is off
+///
+/// Either source turns it on, the environment cannot turn off the config.
+pub fn reason() -> Option<String> {
+    let env = std::env::var(READ_ONLY_ENV).ok();
+    let path = SapphirePaths::resolve().config_file();
+    let config = std::fs::read_to_string(&path).ok();
+    reason_from(env.as_deref(), config.as_deref(), &path)
+}
+
+/// Fail if read-only mode is on
+pub fn ensure_writable() -> Result<(), ReadOnlyError> {
+    match reason() {
+        Some(reason) => Err(ReadOnlyError { reason }),
+        None => Ok(()),
+    }
+}
+
+/// Why read-only mode is on given the environment variable and the config at `path`
+fn reason_from(env: Option<&str>, config: Option<&str>, path: &Path) -> Option<String> {
+    if matches!(env.unwrap_or_default().trim().to_lowercase().as_str(), "1" | "true" | "yes") {
+        return Some(format!("{} is set", READ_ONLY_ENV));
+    }
+
+    let config: toml::Table = toml::from_str(config?).ok()?;
+    config.get("read_only")?.as_bool()?
+        .then(|| format!("read_only is set in {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "/Users/me/.sapphire/config.toml";
+
+    fn reason(env: Option<&str>, config: Option<&str>) -> Option<String> {
+        reason_from(env, config, Path::new(CONFIG))
+    }
+
+    #[test]
+    fn off_by_default() {
+        assert_eq!(reason(None, None), None);
+        assert_eq!(reason(None, Some("[shard]\nnotify = true\n")), None);
+    }
+
+    #[test]
+    fn environment_turns_it_on() {
+        for value in ["1", "true", "YES", " 1\n"] {
+            assert_eq!(reason(Some(value), None), Some("SAPPHIRE_READ_ONLY is set".to_string()));
+        }
+        for value in ["", "0", "false", "no"] {
+            assert_eq!(reason(Some(value), None), None);
+        }
+    }
+
+    #[test]
+    fn config_turns_it_on() {
+        assert_eq!(reason(None, Some("read_only = true\n")), Some(format!("read_only is set in {}", CONFIG)));
+        assert_eq!(reason(None, Some("read_only = false\n")), None);
+        // Only the top-level key counts
+        assert_eq!(reason(None, Some("[shard]\nread_only = true\n")), None);
+    }
+
+    #[test]
+    fn environment_cannot_turn_off_the_config() {
+        assert!(reason(Some("0"), Some("read_only = true\n")).is_some());
+    }
+
+    #[test]
+    fn unreadable_config_does_not_turn_it_on() {
+        assert_eq!(reason(None, Some("read_only = ")), None);
+        assert_eq!(reason(None, Some("read_only = \"yes\"\n")), None);
+    }
+}
@@ -0,0 +1,150 @@
+//! Pre-flight checks that shards and fragments declare with `requires`.
+//!
+//! ```toml
+//! [requires]
+//! commands = ["xcode-select", "git"]
+//! disk_space_gb = 20
+//! reachable = ["github.com", "artifacts.example.com:8443"]
+//! macos = "14.0"
+//! ```
+//!
+//! The checks run before anything is applied, so a machine that cannot take
+//! a shard or fragment is turned away instead of being left half applied.
+
+use std::env;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::version::Version;
+
+/// How long a host may take to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Port checked for hosts given without one
+const DEFAULT_PORT: u16 = 443;
+
+/// Volume whose free space is checked
+const SYSTEM_VOLUME: &str = "/";
+
+/// What a machine needs before a shard or fragment is applied
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Requirements {
+    /// Executables that must be on the PATH
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<String>,
+
+    /// Free space on the system volume, in gigabytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_space_gb: Option<u64>,
+
+    /// Hosts that must accept connections, as `host` or `host:port`
+    ///
+    /// Port 443 is checked for hosts without one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reachable: Vec<String>,
+
+    /// Lowest macOS version, e.g. `14.0`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub macos: Option<String>,
+}
+
+impl Requirements {
+    /// True if nothing is required
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty() && self.disk_space_gb.is_none() && self.reachable.is_empty() && self.macos.is_none()
+    }
+
+    /// Check every requirement and describe those that are not met
+    ///
+    /// Requirements that cannot be checked count as not met.
+    pub fn unmet(&self) -> Vec<String> {
+        let mut unmet = Vec::new();
+
+        for command in &self.commands {
+            if !command_exists(command) {
+                unmet.push(format!("command '{}' is not installed or not on the PATH", command));
+            }
+        }
+
+        if let Some(required) = self.disk_space_gb {
+            match free_space_gb(SYSTEM_VOLUME) {
+                Ok(free) if free >= required => {}
+                Ok(free) => unmet.push(format!("{} GB of free disk space required, {} GB available", required, free)),
+                Err(e) => unmet.push(format!("{} GB of free disk space required, could not check: {}", required, e)),
+            }
+        }
+
+        for host in &self.reachable {
+            if let Err(e) = connect(host) {
+                unmet.push(format!("{} is not reachable: {}", host, e));
+            }
+        }
+
+        if let Some(required) = &self.macos {
+            match macos_version() {
+                Ok(current) if current >= Version::parse(required) => {}
+                Ok(current) => unmet.push(format!("macOS {} or later required, this is macOS {}", required, current)),
+                Err(e) => unmet.push(format!("macOS {} or later required, could not check: {}", required, e)),
+            }
+        }
+
+        unmet
+    }
+}
+
+/// Whether an executable is found, either as a path or on the PATH
+fn command_exists(command: &str) -> bool {
+    let is_executable = |path: &Path| path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false);
+
+    if command.contains('/') {
+        return is_executable(Path::new(command));
+    }
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).any(|dir| is_executable(&dir.join(command))))
+        .unwrap_or(false)
+}
+
+/// Free space of the volume containing `path`, from `df`
+fn free_space_gb(path: &str) -> Result<u64, String> {
+    let output = Command::new("df").args(["-Pk", path]).output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    // POSIX format: filesystem, blocks, used, available, capacity, mount point
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines()
+        .nth(1)
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|available| available.parse::<u64>().ok())
+        .map(|kilobytes| kilobytes / (1024 * 1024))
+        .ok_or_else(|| "unexpected output of df".to_string())
+}
+
+/// Open and close a TCP connection to a host
+fn connect(host: &str) -> Result<(), String> {
+    let address = if host.contains(':') { host.to_string() } else { format!("{}:{}", host, DEFAULT_PORT) };
+    let resolved = address.to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| "no address found".to_string())?;
+    TcpStream::connect_timeout(&resolved, CONNECT_TIMEOUT).map(drop).map_err(|e| e.to_string())
+}
+
+/// Version of the running macOS, from `sw_vers`
+pub(crate) fn macos_version() -> Result<Version, String> {
+    if !cfg!(target_os = "macos") {
+        return Err("not running on macOS".to_string());
+    }
+
+    let output = Command::new("sw_vers").arg("-productVersion").output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(Version::parse(String::from_utf8_lossy(&output.stdout).trim()))
+}
@@ -0,0 +1,137 @@
+//! Secrets stored in the macOS login keychain.
+//!
+//! Secrets are generic passwords with the service [`SERVICE`] and the secret
+//! name as account, so they show up as "sapphire" in Keychain Access. All
+//! access goes through the `security` command line tool. Values are never
+//! passed as command line arguments, where other users could see them.
+
+use std::io::{self, Write};
+use std::process::{Command, Output, Stdio};
+use thiserror::Error;
+
+/// Keychain service under which sapphire stores its secrets
+pub const SERVICE: &str = "sapphire";
+
+/// Exit code of `security` when an item does not exist
+const ITEM_NOT_FOUND: i32 = 44;
+
+/// Errors accessing the keychain
+#[derive(Error, Debug)]
+pub enum SecretError {
+    #[error("Invalid secret name '{0}': use letters, numbers, dots, dashes and underscores")]
+    InvalidName(String),
+
+    #[error("Secret '{0}' not found in the keychain")]
+    NotFound(String),
+
+    #[error("The keychain is only available on macOS")]
+    Unsupported,
+
+    #[error("Failed to run security: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Keychain error: {0}")]
+    Keychain(String),
+}
+
+pub type SecretResult<T> = Result<T, SecretError>;
+
+/// Read a secret stored by [`set`]
+pub fn get(name: &str) -> SecretResult<String> {
+    validate_name(name)?;
+    read_keychain_item(SERVICE, Some(name))
+}
+
+/// Store a secret, replacing an existing one of the same name
+pub fn set(name: &str, value: &str) -> SecretResult<()> {
+    validate_name(name)?;
+    ensure_supported()?;
+    if value.contains('\n') {
+        return Err(SecretError::Keychain("Secrets cannot contain line breaks".to_string()));
+    }
+
+    // Commands read from stdin keep the value out of the process list
+    let command = format!("add-generic-password -U -s {} -a {} -w {}\n", SERVICE, name, quote(value));
+    let mut child = Command::new("security")
+        .arg("-i")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(command.as_bytes())?;
+    }
+
+    // The interactive mode exits successfully even if the command failed
+    let output = child.wait_with_output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        return Err(SecretError::Keychain(stderr.trim().to_string()));
+    }
+    check(&output, name)
+}
+
+/// Delete a secret
+pub fn delete(name: &str) -> SecretResult<()> {
+    validate_name(name)?;
+    ensure_supported()?;
+
+    let output = Command::new("security")
+        .args(["delete-generic-password", "-s", SERVICE, "-a", name])
+        .output()?;
+    check(&output, name)
+}
+
+/// Read the password of any generic keychain item
+///
+/// Without `account`, the first item of the service is returned.
+pub fn read_keychain_item(service: &str, account: Option<&str>) -> SecretResult<String> {
+    ensure_supported()?;
+
+    let mut cmd = Command::new("security");
+    cmd.args(["find-generic-password", "-w", "-s", service]);
+    if let Some(account) = account {
+        cmd.args(["-a", account]);
+    }
+
+    let output = cmd.output()?;
+    check(&output, account.unwrap_or(service))?;
+
+    let value = String::from_utf8_lossy(&output.stdout);
+    Ok(value.strip_suffix('\n').unwrap_or(&value).to_string())
+}
+
+fn ensure_supported() -> SecretResult<()> {
+    if cfg!(target_os = "macos") {
+        Ok(())
+    } else {
+        Err(SecretError::Unsupported)
+    }
+}
+
+fn validate_name(name: &str) -> SecretResult<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('-')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(SecretError::InvalidName(name.to_string()))
+    }
+}
+
+/// Map the exit status of `security` to an error
+fn check(output: &Output, name: &str) -> SecretResult<()> {
+    match output.status.code() {
+        Some(0) => Ok(()),
+        Some(ITEM_NOT_FOUND) => Err(SecretError::NotFound(name.to_string())),
+        _ => Err(SecretError::Keychain(String::from_utf8_lossy(&output.stderr).trim().to_string())),
+    }
+}
+
+/// Quote a value for the interactive mode of `security`
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,220 @@
+//! Package versions as Homebrew writes them.
+//!
+//! Most versions are dotted numbers such as `3.11.9`, optionally followed by
+//! a revision `_1` for rebuilds of the same upstream version. Others look like
+//! `r128`, `1.0.0-beta.2`, `2024.01.05` or `1.2,345` (casks with a build
+//! number). Versions are split into numeric and alphabetic parts that are
+//! compared one by one, so `1.10` is newer than `1.9` and `r128` newer than
+//! `r99`. Text after the numbers marks a pre-release: `1.0-rc1` < `1.0`.
+
+use std::cmp::Ordering;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+/// A parsed version, comparable with other versions
+#[derive(Debug, Clone)]
+pub struct Version {
+    raw: String,
+    parts: Vec<Part>,
+    /// Brew's rebuild counter, the `1` of `1.2.3_1`
+    revision: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Number(u64),
+    Text(String),
+}
+
+/// How far apart two versions are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    Same,
+    /// Same upstream version, rebuilt by brew
+    Revision,
+    Patch,
+    Minor,
+    Major,
+    /// The new version is older
+    Downgrade,
+}
+
+impl Change {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Change::Same => "same",
+            Change::Revision => "revision",
+            Change::Patch => "patch",
+            Change::Minor => "minor",
+            Change::Major => "major",
+            Change::Downgrade => "downgrade",
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Version {
+    /// Parse a version, any string is accepted
+    pub fn parse(raw: &str) -> Self {
+        let raw = raw.trim();
+        let (version, revision) = match raw.rsplit_once('_') {
+            Some((version, revision)) if !version.is_empty() => match revision.parse() {
+                Ok(revision) => (version, revision),
+                Err(_) => (raw, 0),
+            },
+            _ => (raw, 0),
+        };
+
+        Self {
+            raw: raw.to_string(),
+            parts: split_parts(version),
+            revision,
+        }
+    }
+
+    /// The version as it was written
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    /// Brew's rebuild counter, 0 if the version has none
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// How big the step from this version to `newer` is
+    ///
+    /// The position of the first difference among the numbers decides: the
+    /// first is a major, the second a minor and any later one a patch change.
+    pub fn change_to(&self, newer: &Version) -> Change {
+        match self.cmp(newer) {
+            Ordering::Equal => return Change::Same,
+            Ordering::Greater => return Change::Downgrade,
+            Ordering::Less => {}
+        }
+
+        if self.cmp_parts(newer) == Ordering::Equal {
+            return Change::Revision;
+        }
+
+        // Count only numbers so a prefix such as the `r` of `r128` is no position
+        let position = self.parts.iter().zip(&newer.parts)
+            .take_while(|(old, new)| old == new)
+            .filter(|(old, _)| matches!(old, Part::Number(_)))
+            .count();
+        match position {
+            0 => Change::Major,
+            1 => Change::Minor,
+            _ => Change::Patch,
+        }
+    }
+
+    fn cmp_parts(&self, other: &Version) -> Ordering {
+        for index in 0..self.parts.len().max(other.parts.len()) {
+            let ordering = match (self.parts.get(index), other.parts.get(index)) {
+                (Some(a), Some(b)) => cmp_part(a, b),
+                (Some(rest), None) => cmp_rest(rest),
+                (None, Some(rest)) => cmp_rest(rest).reverse(),
+                (None, None) => Ordering::Equal,
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+/// Split at separators and between digits and letters: `1.0rc2` → 1, 0, rc, 2
+fn split_parts(version: &str) -> Vec<Part> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+
+    let mut flush = |current: &mut String| {
+        if current.is_empty() {
+            return;
+        }
+        let part = match current.parse() {
+            Ok(number) => Part::Number(number),
+            Err(_) => Part::Text(current.to_lowercase()),
+        };
+        parts.push(part);
+        current.clear();
+    };
+
+    for c in version.chars() {
+        if !c.is_alphanumeric() {
+            flush(&mut current);
+            continue;
+        }
+        if current.chars().last().is_some_and(|last| last.is_ascii_digit() != c.is_ascii_digit()) {
+            flush(&mut current);
+        }
+        current.push(c);
+    }
+    flush(&mut current);
+
+    parts
+}
+
+fn cmp_part(a: &Part, b: &Part) -> Ordering {
+    match (a, b) {
+        (Part::Number(a), Part::Number(b)) => a.cmp(b),
+        (Part::Text(a), Part::Text(b)) => a.cmp(b),
+        // 1.0.1 is newer than 1.0.beta
+        (Part::Number(_), Part::Text(_)) => Ordering::Greater,
+        (Part::Text(_), Part::Number(_)) => Ordering::Less,
+    }
+}
+
+/// Order of a version with extra parts against one that ended
+///
+/// Trailing zeros change nothing (`1.0.0` = `1.0`), trailing text is a
+/// pre-release (`1.0-beta` < `1.0`) and other numbers a later release.
+fn cmp_rest(rest: &Part) -> Ordering {
+    match rest {
+        Part::Number(0) => Ordering::Equal,
+        Part::Number(_) => Ordering::Greater,
+        Part::Text(_) => Ordering::Less,
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_parts(other).then(self.revision.cmp(&other.revision))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl FromStr for Version {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::parse(s))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
@@ -1,54 +1,106 @@
-use std::path::Path;
-use anyhow::{Context, Result};
-use crate::core::config::SapphireConfig;
-use crate::utils::fs;
-
-/// Apply a Sapphire configuration
-pub fn apply<P: AsRef<Path>>(path: P, dry_run: bool) -> Result<()> {
-    let path = path.as_ref();
-    
-    // Determine the configuration file path
-    let config_path = if fs::path_exists(path) && path.is_dir() {
-        path.join("sapphire.yml")
-    } else if fs::path_exists(path) {
-        path.to_path_buf()
+use anyhow::{Result, bail};
+use console::style;
+
+/// How a step of a whole-machine apply ended
+enum Outcome {
+    Done,
+    Skipped(String),
+    Failed(String),
+}
+
+/// Converge the whole machine: packages from shards first, then configuration from fragments
+///
+/// Packages come first so fragments can configure what they installed. When
+/// applying packages fails, configuration is skipped. With `dry_run` both
+/// steps only show what would change.
+pub fn apply(dry_run: bool, skip_cleanup: bool, force: bool) -> Result<()> {
+    if dry_run {
+        tracing::info!("Dry run - no changes will be made");
+    }
+
+    let mut steps = Vec::new();
+
+    let packages = apply_packages(dry_run, skip_cleanup, force);
+    let packages_ok = !matches!(packages, Outcome::Failed(_));
+    steps.push(("Packages", packages));
+
+    let configuration = if packages_ok || dry_run {
+        apply_configuration(dry_run)
     } else {
-        // Use current directory
-        std::env::current_dir()?.join("sapphire.yml")
+        Outcome::Skipped("packages failed".to_string())
     };
-    
-    // Check if the config file exists
-    if !fs::path_exists(&config_path) {
-        anyhow::bail!("Configuration file does not exist: {}", config_path.display());
+    steps.push(("Configuration", configuration));
+
+    println!();
+    println!("{}", style(if dry_run { "Plan" } else { "Summary" }).bold());
+    let mut failed = 0;
+    for (step, outcome) in &steps {
+        match outcome {
+            Outcome::Done => println!("  {:<16} {}", step, style(if dry_run { "checked" } else { "applied" }).green()),
+            Outcome::Skipped(reason) => println!("  {:<16} {}", step, style(format!("skipped, {}", reason)).yellow()),
+            Outcome::Failed(err) => {
+                failed += 1;
+                println!("  {:<16} {}", step, style(format!("failed: {}", err)).red());
+            }
+        }
     }
-    
-    // Load the configuration
-    let config = SapphireConfig::from_file(&config_path)
-        .context(format!("Failed to load configuration from {}", config_path.display()))?;
-    
-    if dry_run {
-        tracing::info!("Dry run - no changes will be made");
+
+    if failed > 0 {
+        bail!("Failed to apply {} of {} steps", failed, steps.len());
     }
-    
-    // Apply system configuration if specified
-    if !config.system.preferences.is_empty() {
-        tracing::info!("Applying system preferences...");
-        // TODO: Implement system preference application
+
+    Ok(())
+}
+
+#[cfg(feature = "shard")]
+fn apply_packages(dry_run: bool, skip_cleanup: bool, force: bool) -> Outcome {
+    use shard::brew::ClientOverrides;
+    use shard::shard::{apply, diff, update};
+
+    println!("{}", style("Packages").bold());
+    let overrides = ClientOverrides::default();
+    let result = update::refresh(update::UpdatePolicy::Auto, overrides).and_then(|_| {
+        if dry_run {
+            diff::diff("all", false, overrides)
+        } else {
+            apply::apply_all_enabled_shards(skip_cleanup, force, &[], overrides)
+        }
+    });
+
+    match result {
+        Ok(_) => Outcome::Done,
+        Err(err) => Outcome::Failed(err.to_string()),
     }
-    
-    // Apply hostname if specified
-    if let Some(hostname) = &config.system.hostname {
-        tracing::info!("Setting hostname to: {}", hostname);
-        // TODO: Implement hostname setting
+}
+
+#[cfg(not(feature = "shard"))]
+fn apply_packages(_dry_run: bool, _skip_cleanup: bool, _force: bool) -> Outcome {
+    Outcome::Skipped("built without shard support".to_string())
+}
+
+#[cfg(feature = "fragment")]
+fn apply_configuration(dry_run: bool) -> Outcome {
+    let fragments_dir = fragment::utils::fragments_dir();
+    if !fragments_dir.exists() {
+        return Outcome::Skipped(format!("{} does not exist", fragments_dir.display()));
     }
-    
-    // Apply packages
-    if !config.packages.is_empty() {
-        tracing::info!("Applying package configuration...");
-        // TODO: Implement package management
+    let fragments_dir = fragments_dir.to_string_lossy();
+
+    println!();
+    println!("{}", style("Configuration").bold());
+    let result = if dry_run {
+        fragment::diff::diff(&fragments_dir, "text")
+    } else {
+        fragment::apply::apply(&fragments_dir, fragment::apply::ApplyOptions::default())
+    };
+
+    match result {
+        Ok(_) => Outcome::Done,
+        Err(err) => Outcome::Failed(format!("{:#}", err)),
     }
-    
-    tracing::info!("Configuration applied successfully");
-    
-    Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(not(feature = "fragment"))]
+fn apply_configuration(_dry_run: bool) -> Outcome {
+    Outcome::Skipped("built without fragment support".to_string())
+}
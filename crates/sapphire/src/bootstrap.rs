@@ -1,77 +1,330 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use anyhow::{Context, Result};
+use dialoguer::Confirm;
+use crate::{apply, manager, setup, utils};
+
+/// Official Homebrew install script
+const HOMEBREW_INSTALL_URL: &str = "https://raw.githubusercontent.com/Homebrew/install/HEAD/install.sh";
+
+/// Locations the install script puts brew in (Apple Silicon, Intel)
+const BREW_LOCATIONS: &[&str] = &["/opt/homebrew/bin/brew", "/usr/local/bin/brew"];
 
 /// Bootstrap the system with required dependencies
-pub fn bootstrap_system() -> Result<()> {
+///
+/// Installs Homebrew if it is missing (asking first unless `assume_yes` is
+/// set), makes sure the user's shell loads its environment and verifies that
+/// brew works before anything else relies on it.
+pub fn bootstrap_system(assume_yes: bool) -> Result<()> {
     // Check if Homebrew is installed
-    let homebrew_installed = check_homebrew_installed()?;
-    
-    if !homebrew_installed {
-        install_homebrew()
-            .context("Failed to install Homebrew")?;
-    }
-    
+    let brew_path = match find_brew() {
+        Some(path) => path,
+        None => {
+            if !confirm_install(assume_yes)? {
+                anyhow::bail!("Homebrew is required. Install it from https://brew.sh and run setup again");
+            }
+
+            install_homebrew()
+                .context("Failed to install Homebrew")?;
+
+            find_brew().context("Homebrew was installed but the brew executable could not be found")?
+        }
+    };
+
+    tracing::debug!("Using brew at {}", brew_path.display());
+
+    configure_shellenv(&brew_path)?;
+    verify_homebrew(&brew_path)?;
+
     // Check for required dependencies
     check_dependencies()?;
-    
+
+    Ok(())
+}
+
+/// Set up a new machine from a bundle of shards and fragments
+///
+/// `source` is a git repository, an https URL of a `.tar.gz`, `.tgz` or
+/// `.zip` archive, or a local directory. The bundle's `shards/`,
+/// `fragments/` and other files are placed under `~/.sapphire`, Homebrew is
+/// installed if missing and everything is applied. Existing files are kept
+/// unless `force` is set.
+pub fn bootstrap_from(source: &str, assume_yes: bool, force: bool, skip_apply: bool) -> Result<()> {
+    bootstrap_system(assume_yes)?;
+
+    let staging = std::env::temp_dir().join(format!("sapphire-bootstrap-{}", std::process::id()));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)
+            .with_context(|| format!("Failed to clean up {}", staging.display()))?;
+    }
+
+    let result = fetch_bundle(source, &staging).and_then(|_| {
+        let root = bundle_root(&staging)?;
+        let base_dir = manager::get_sapphire_dir()?;
+        let (copied, kept) = copy_dir(&root, &base_dir, force)?;
+        tracing::info!("Placed {} files from {} under {}", copied, source, base_dir.display());
+        if kept > 0 {
+            tracing::warn!("Kept {} existing files, use --force to replace them", kept);
+        }
+        Ok(())
+    });
+
+    if let Err(e) = std::fs::remove_dir_all(&staging) {
+        tracing::debug!("Could not remove {}: {}", staging.display(), e);
+    }
+    result?;
+
+    // Fills in what the bundle does not provide, keeping its config
+    setup::initialize("managed")?;
+
+    if skip_apply {
+        tracing::info!("Skipping apply, run `sapphire apply` when ready");
+        return Ok(());
+    }
+
+    apply::apply(false, false, false)
+}
+
+/// Clone, download or copy a bundle into `dest`
+fn fetch_bundle(source: &str, dest: &Path) -> Result<()> {
+    let local = PathBuf::from(shellexpand::tilde(source).into_owned());
+    if local.is_dir() {
+        tracing::info!("Copying bundle from {}", local.display());
+        copy_dir(&local, dest, true)?;
+        return Ok(());
+    }
+
+    let lower = source.to_lowercase();
+    let archive = [".tar.gz", ".tgz", ".zip"].iter().any(|ext| lower.ends_with(ext));
+    if !archive {
+        tracing::info!("Cloning {}", source);
+        return run(Command::new("git").args(["clone", "--depth", "1", source]).arg(dest), "git clone");
+    }
+
+    utils::ensure_dir_exists(dest)?;
+    let file_name = if lower.ends_with(".zip") { "bundle.zip" } else { "bundle.tar.gz" };
+    let download = dest.join(file_name);
+
+    tracing::info!("Downloading {}", source);
+    run(Command::new("curl").args(["-fsSL", "-o"]).arg(&download).arg(source), "curl")?;
+
+    if lower.ends_with(".zip") {
+        run(Command::new("unzip").arg("-q").arg(&download).arg("-d").arg(dest), "unzip")?;
+    } else {
+        run(Command::new("tar").arg("-xzf").arg(&download).arg("-C").arg(dest), "tar")?;
+    }
+
+    std::fs::remove_file(&download)
+        .with_context(|| format!("Failed to remove {}", download.display()))
+}
+
+fn run(command: &mut Command, name: &str) -> Result<()> {
+    let status = command.status()
+        .with_context(|| format!("Failed to run {}", name))?;
+
+    if !status.success() {
+        anyhow::bail!("{} failed", name);
+    }
+
     Ok(())
 }
 
-fn check_homebrew_installed() -> Result<bool> {
+/// Directory holding `shards/` or `fragments/`, either the bundle itself or
+/// its only top-level directory as in archives of a repository
+fn bundle_root(dir: &Path) -> Result<PathBuf> {
+    let is_root = |dir: &Path| dir.join("shards").is_dir() || dir.join("fragments").is_dir();
+    if is_root(dir) {
+        return Ok(dir.to_path_buf());
+    }
+
+    let entries: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+
+    match entries.as_slice() {
+        [only] if is_root(only) => Ok(only.clone()),
+        _ => anyhow::bail!("The bundle contains neither a shards nor a fragments directory"),
+    }
+}
+
+/// Copy a directory tree, returning how many files were copied and how many
+/// existing ones were kept
+fn copy_dir(from: &Path, to: &Path, force: bool) -> Result<(usize, usize)> {
+    utils::ensure_dir_exists(to)?;
+    let (mut copied, mut kept) = (0, 0);
+
+    let entries = std::fs::read_dir(from)
+        .with_context(|| format!("Failed to read {}", from.display()))?;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let target = to.join(entry.file_name());
+
+        // The bundle's history and machine specific files stay behind
+        if [".git", "backups"].iter().any(|skip| entry.file_name() == *skip) {
+            continue;
+        }
+
+        if path.is_dir() {
+            let (dir_copied, dir_kept) = copy_dir(&path, &target, force)?;
+            copied += dir_copied;
+            kept += dir_kept;
+        } else if target.exists() && !force {
+            tracing::debug!("Keeping existing {}", target.display());
+            kept += 1;
+        } else {
+            std::fs::copy(&path, &target)
+                .with_context(|| format!("Failed to copy {} to {}", path.display(), target.display()))?;
+            copied += 1;
+        }
+    }
+
+    Ok((copied, kept))
+}
+
+/// Locate the brew executable, also outside of PATH since a fresh
+/// installation is not on PATH until the shell is restarted
+fn find_brew() -> Option<PathBuf> {
     // Run a simple command to check if Homebrew is available
     let output = std::process::Command::new("sh")
         .arg("-c")
         .arg("command -v brew")
         .output()
-        .context("Failed to check for Homebrew")?;
-    
-    Ok(output.status.success())
+        .ok()?;
+
+    if output.status.success() {
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !path.is_empty() {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    BREW_LOCATIONS.iter()
+        .map(PathBuf::from)
+        .find(|path| utils::file_exists(path))
+}
+
+fn confirm_install(assume_yes: bool) -> Result<bool> {
+    if assume_yes {
+        return Ok(true);
+    }
+
+    if !console::user_attended() {
+        tracing::warn!("Homebrew is not installed and no terminal is attached to confirm the installation");
+        return Ok(false);
+    }
+
+    Confirm::new()
+        .with_prompt("Homebrew is not installed. Install it now using the official install script?")
+        .default(true)
+        .interact()
+        .context("Failed to read confirmation")
 }
 
 fn install_homebrew() -> Result<()> {
     tracing::info!("Installing Homebrew...");
-    
-    // This URL might change - should probably be configurable
-    let install_cmd = "bash -c \"$(curl -fsSL https://raw.githubusercontent.com/Homebrew/install/HEAD/install.sh)\"";
-    
+
+    let install_cmd = format!("/bin/bash -c \"$(curl -fsSL {})\"", HOMEBREW_INSTALL_URL);
+
+    // NONINTERACTIVE skips the script's own "Press RETURN" prompt
     let status = std::process::Command::new("bash")
         .arg("-c")
         .arg(install_cmd)
+        .env("NONINTERACTIVE", "1")
         .status()
         .context("Failed to execute Homebrew installation script")?;
-    
+
     if !status.success() {
         anyhow::bail!("Homebrew installation failed");
     }
-    
+
     tracing::info!("Homebrew installed successfully");
     Ok(())
 }
 
+/// Add `brew shellenv` to the login rc file of the user's shell
+fn configure_shellenv(brew_path: &Path) -> Result<()> {
+    let home_dir = dirs::home_dir()
+        .context("Unable to determine home directory")?;
+    let shell = std::env::var("SHELL").unwrap_or_default();
+
+    let (rc_path, line) = if shell.ends_with("fish") {
+        (home_dir.join(".config/fish/config.fish"), format!("{} shellenv | source", brew_path.display()))
+    } else if shell.ends_with("zsh") {
+        (home_dir.join(".zprofile"), format!("eval \"$({} shellenv)\"", brew_path.display()))
+    } else if shell.ends_with("bash") {
+        (home_dir.join(".bash_profile"), format!("eval \"$({} shellenv)\"", brew_path.display()))
+    } else {
+        (home_dir.join(".profile"), format!("eval \"$({} shellenv)\"", brew_path.display()))
+    };
+
+    let existing = if utils::file_exists(&rc_path) {
+        utils::read_file(&rc_path)?
+    } else {
+        String::new()
+    };
+
+    if existing.contains("brew shellenv") || existing.contains(&line) {
+        tracing::debug!("Homebrew shellenv already configured in {}", rc_path.display());
+        return Ok(());
+    }
+
+    let mut content = existing;
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str("\n# Added by sapphire: Homebrew environment\n");
+    content.push_str(&line);
+    content.push('\n');
+
+    utils::write_file(&rc_path, &content)?;
+    tracing::info!("Configured Homebrew environment in {}", rc_path.display());
+    Ok(())
+}
+
+/// Make sure brew runs before continuing
+fn verify_homebrew(brew_path: &Path) -> Result<()> {
+    let output = std::process::Command::new(brew_path)
+        .arg("--version")
+        .output()
+        .context("Failed to run brew --version")?;
+
+    if !output.status.success() {
+        anyhow::bail!("brew --version failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    let version = String::from_utf8_lossy(&output.stdout);
+    tracing::info!("{}", version.lines().next().unwrap_or("Homebrew").trim());
+    Ok(())
+}
+
 fn check_dependencies() -> Result<()> {
     // List of required dependencies
     let dependencies = [
         "git",
         "jq",
     ];
-    
+
     let mut missing_deps = Vec::new();
-    
+
     for dep in dependencies.iter() {
         let output = std::process::Command::new("sh")
             .arg("-c")
             .arg(format!("command -v {}", dep))
             .output()
             .context(format!("Failed to check for {}", dep))?;
-        
+
         if !output.status.success() {
             missing_deps.push(*dep);
         }
     }
-    
+
     if !missing_deps.is_empty() {
         tracing::warn!("Missing dependencies: {}", missing_deps.join(", "));
         // Could automatically install these with Homebrew, but let's just warn for now
     }
-    
+
     Ok(())
-}
\ No newline at end of file
+}
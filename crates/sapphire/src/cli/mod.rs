@@ -1,28 +1,38 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Parser, Subcommand};
 use tracing::{Level, debug};
 use tracing_subscriber::{fmt, EnvFilter};
-use crate::setup;
+use sapphire_core::paths::SapphirePaths;
+use sapphire_core::read_only;
+use crate::{apply, bootstrap, secret, setup, status};
+use std::path::PathBuf;
 use std::sync::Once;
 
 // Static to ensure we only initialize logging once
 static INIT_LOGGER: Once = Once::new();
 
-// Initialize logging with the specified verbosity level
-fn init_logging(verbose: bool) {
+// Initialize logging: errors only when quiet, debug for -v and trace for -vv
+fn init_logging(quiet: bool, verbose: u8) {
     // Only initialize once
     INIT_LOGGER.call_once(|| {
-        let level = if verbose { Level::DEBUG } else { Level::INFO };
+        let level = match (quiet, verbose) {
+            (true, _) => Level::ERROR,
+            (false, 0) => Level::INFO,
+            (false, 1) => Level::DEBUG,
+            (false, _) => Level::TRACE,
+        };
         
         // Create a custom filter
         let filter = EnvFilter::from_default_env()
-            .add_directive(format!("sapphire={}", level).parse().unwrap());
+            .add_directive(format!("sapphire={}", level).parse().unwrap())
+            .add_directive(format!("shard={}", level).parse().unwrap())
+            .add_directive(format!("fragment={}", level).parse().unwrap());
         
         // Initialize the tracing subscriber
         if let Err(e) = fmt::Subscriber::builder()
             .with_env_filter(filter)
             .with_target(false)
-            .with_ansi(true)
+            .with_ansi(console::colors_enabled())
             .try_init() {
             eprintln!("Warning: Could not initialize logging: {}", e);
         } else {
@@ -34,9 +44,25 @@ fn init_logging(verbose: bool) {
 #[derive(Debug, Parser)]
 #[command(author, version, about = "Sapphire system management tool", long_about = None)]
 pub struct Cli {
-    /// Enable verbose output
-    #[arg(short, long)]
-    verbose: bool,
+    /// Enable verbose output, repeat (-vv) for trace output
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Only print errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Disable colored output, also disabled by setting NO_COLOR
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Wait for another running sapphire process instead of failing
+    #[arg(long, global = true)]
+    wait: bool,
+
+    /// Sapphire directory to use instead of ~/.sapphire, SAPPHIRE_HOME or the configured one
+    #[arg(long, global = true, value_name = "DIR")]
+    root: Option<PathBuf>,
 
     #[command(subcommand)]
     command: Commands,
@@ -49,13 +75,62 @@ enum Commands {
         /// Installation mode (local or managed)
         #[arg(long, default_value = "local")]
         mode: String,
+
+        /// Install missing dependencies like Homebrew without asking
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Walk through setup with a guided wizard
+        #[arg(short, long)]
+        interactive: bool,
+    },
+
+    /// Apply packages from enabled shards, then configuration from fragments
+    Apply {
+        /// Only show what would change
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Skip cleanup of packages not in any enabled shard
+        #[arg(long)]
+        skip_cleanup: bool,
+
+        /// Apply all shards even if nothing changed since the last apply
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Set up this machine from a bundle of shards and fragments and apply it
+    Bootstrap {
+        /// Git repository, https URL of a .tar.gz or .zip archive, or local directory
+        source: String,
+
+        /// Install missing dependencies like Homebrew without asking
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Replace files that already exist in ~/.sapphire
+        #[arg(long)]
+        force: bool,
+
+        /// Only place the files, apply later with `sapphire apply`
+        #[arg(long)]
+        no_apply: bool,
     },
 
     /// Update Sapphire application
     Update,
     
-    /// Show status of Sapphire components
-    Status,
+    /// Show when shards and fragments were last applied
+    Status {
+        /// Print drift, package and apply metrics in Prometheus text format
+        #[arg(long)]
+        metrics: bool,
+        
+        /// Write the metrics to a file instead, e.g. for node exporter's textfile collector
+        #[arg(short, long, requires = "metrics")]
+        output: Option<String>,
+    },
     
     /// Configure Sapphire settings
     Config {
@@ -65,29 +140,117 @@ enum Commands {
         /// Value to set
         value: Option<String>,
     },
+    
+    /// Manage secrets stored in the macOS keychain
+    Secret {
+        #[command(subcommand)]
+        command: SecretCommands,
+    },
+}
+
+impl Commands {
+    /// Whether the command changes the system, refused in read-only mode
+    fn is_mutating(&self) -> bool {
+        match self {
+            Commands::Apply { dry_run, .. } => !dry_run,
+            Commands::Config { value, .. } => value.is_some(),
+            Commands::Secret { command } => !matches!(command, SecretCommands::Get { .. }),
+            Commands::Setup { .. } | Commands::Bootstrap { .. } | Commands::Update => true,
+            Commands::Status { .. } => false,
+        }
+    }
+
+    /// Whether the command installs packages or places files and holds the
+    /// sapphire lock while it runs, apply across both of its steps
+    fn takes_lock(&self) -> bool {
+        match self {
+            Commands::Apply { dry_run, .. } => !dry_run,
+            Commands::Setup { .. } | Commands::Bootstrap { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum SecretCommands {
+    /// Store a secret, prompting for the value unless given
+    Set {
+        /// Name of the secret
+        name: String,
+        
+        /// Value (visible in the shell history and process list, prefer the prompt)
+        value: Option<String>,
+    },
+    
+    /// Print a secret
+    Get {
+        /// Name of the secret
+        name: String,
+    },
+    
+    /// Delete a secret
+    Rm {
+        /// Name of the secret
+        name: String,
+    },
 }
 
 /// Run the sapphire CLI
 pub fn run() -> Result<()> {
     let cli = Cli::parse();
     
+    // Everything below resolves its paths from the root
+    if let Some(root) = &cli.root {
+        SapphirePaths::set_root(root);
+    }
+    
     // Initialize logger
-    init_logging(cli.verbose);
+    if cli.no_color {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    init_logging(cli.quiet, cli.verbose);
+    #[cfg(feature = "shard")]
+    {
+        shard::Logger::set_level(shard::LogLevel::from_flags(cli.quiet, cli.verbose));
+        if let Ok(config) = shard::core::config::ShardConfig::load() {
+            shard::Logger::set_glyphs(config.output.glyphs);
+        }
+    }
+    
+    if cli.command.is_mutating() {
+        read_only::ensure_writable()?;
+    }
+    
+    // Held until the command finished
+    let _lock = if cli.command.takes_lock() {
+        Some(sapphire_core::lock::acquire(cli.wait)?)
+    } else {
+        None
+    };
     
     match cli.command {
-        Commands::Setup { mode } => {
-            setup::initialize(&mode)
+        Commands::Apply { dry_run, skip_cleanup, force } => {
+            apply::apply(dry_run, skip_cleanup, force)
+        },
+        Commands::Setup { mode, yes, interactive } => {
+            bootstrap::bootstrap_system(yes)?;
+            if interactive {
+                setup::initialize_interactive()
+            } else {
+                setup::initialize(&mode)
+            }
+        },
+        Commands::Bootstrap { source, yes, force, no_apply } => {
+            bootstrap::bootstrap_from(&source, yes, force, no_apply)
         },
         Commands::Update => {
             println!("Updating Sapphire...");
             // TODO: Implement update logic
             Ok(())
         },
-        Commands::Status => {
-            println!("Sapphire Status:");
-            // TODO: Implement status check
-            Ok(())
-        },
+        Commands::Status { metrics: true, output } => status::metrics(output.as_deref()),
+        Commands::Status { .. } => status::status(),
         Commands::Config { key, value } => {
             if let Some(k) = key {
                 if let Some(v) = value {
@@ -104,6 +267,42 @@ pub fn run() -> Result<()> {
                 // TODO: List all config
                 Ok(())
             }
+        },
+        Commands::Secret { command } => match command {
+            SecretCommands::Set { name, value } => secret::set(&name, value),
+            SecretCommands::Get { name } => secret::get(&name),
+            SecretCommands::Rm { name } => secret::remove(&name),
+        },
+    }
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str]) -> Commands {
+        Cli::try_parse_from([&["sapphire"], args].concat()).unwrap().command
+    }
+
+    #[test]
+    fn commands_changing_the_system_are_gated() {
+        for args in [&["apply"][..], &["setup"], &["setup", "--interactive"], &["bootstrap", "~/bundle"],
+                     &["update"], &["config", "key", "value"], &["secret", "set", "token"], &["secret", "rm", "token"]] {
+            assert!(command(args).is_mutating(), "{:?} should be refused in read-only mode", args);
+        }
+    }
+
+    #[test]
+    fn previews_and_queries_are_not_gated() {
+        for args in [&["apply", "--dry-run"][..], &["status"], &["status", "--metrics"], &["config", "key"],
+                     &["secret", "get", "token"]] {
+            assert!(!command(args).is_mutating(), "{:?} should work in read-only mode", args);
         }
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn setup_holds_the_lock() {
+        assert!(command(&["setup"]).takes_lock());
+        assert!(command(&["apply"]).takes_lock());
+        assert!(!command(&["apply", "--dry-run"]).takes_lock());
+    }
+}
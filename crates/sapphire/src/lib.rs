@@ -1,9 +1,12 @@
 // Sapphire - System management tool for macOS
 
 // System management functionality
+pub mod apply;
 pub mod bootstrap;
 pub mod manager;
+pub mod secret;
 pub mod setup;
+pub mod status;
 
 // CLI handling
 pub mod cli;
@@ -1,17 +1,15 @@
 use anyhow::{Context, Result};
 use std::fs;
+use sapphire_core::paths::SapphirePaths;
 
 /// Get the current Sapphire version
 pub fn get_version() -> &'static str {
     env!("CARGO_PKG_VERSION")
 }
 
-/// Get the sapphire directory path (~/.sapphire)
+/// Get the sapphire directory path (~/.sapphire unless overridden, see `SapphirePaths`)
 pub fn get_sapphire_dir() -> Result<std::path::PathBuf> {
-    let home_dir = dirs::home_dir()
-        .context("Unable to determine home directory")?;
-    
-    Ok(home_dir.join(".sapphire"))
+    Ok(SapphirePaths::resolve().root().to_path_buf())
 }
 
 /// Get the configuration directory path (same as sapphire directory)
@@ -0,0 +1,44 @@
+use std::io::{self, BufRead};
+use anyhow::{Context, Result};
+use dialoguer::Password;
+use sapphire_core::secrets;
+
+/// Store a secret in the keychain
+///
+/// Without a value, the secret is read from a hidden prompt or, if no
+/// terminal is attached, from the first line of stdin.
+pub fn set(name: &str, value: Option<String>) -> Result<()> {
+    let value = match value {
+        Some(value) => value,
+        None if console::user_attended() => Password::new()
+            .with_prompt(format!("Value for {}", name))
+            .interact()
+            .context("Failed to read secret")?,
+        None => {
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line).context("Failed to read secret from stdin")?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+
+    if value.is_empty() {
+        anyhow::bail!("Refusing to store an empty secret");
+    }
+
+    secrets::set(name, &value)?;
+    tracing::info!("Stored secret '{}' in the keychain", name);
+    Ok(())
+}
+
+/// Print a secret to stdout, e.g. for use in scripts
+pub fn get(name: &str) -> Result<()> {
+    println!("{}", secrets::get(name)?);
+    Ok(())
+}
+
+/// Delete a secret from the keychain
+pub fn remove(name: &str) -> Result<()> {
+    secrets::delete(name)?;
+    tracing::info!("Deleted secret '{}'", name);
+    Ok(())
+}
@@ -1,6 +1,9 @@
 use std::path::Path;
+use std::process::Command;
 use anyhow::{Context, Result};
-use crate::utils;
+use dialoguer::{Confirm, Input, Select};
+use crate::{manager, utils};
+
 
 /// Initialize Sapphire environment for first-time setup
 pub fn initialize(mode: &str) -> Result<()> {
@@ -15,12 +18,8 @@ pub fn initialize(mode: &str) -> Result<()> {
 
     tracing::info!("Initializing Sapphire in {} mode", mode);
     
-    // Get the user's home directory
-    let home_dir = dirs::home_dir()
-        .context("Unable to determine home directory")?;
-    
-    // Create the .sapphire directory in home
-    let base_dir = home_dir.join(".sapphire");
+    // Create the Sapphire directory, ~/.sapphire unless overridden
+    let base_dir = manager::get_sapphire_dir()?;
     
     // Create directory structure
     create_directory_structure(&base_dir)?;
@@ -35,6 +34,171 @@ pub fn initialize(mode: &str) -> Result<()> {
     Ok(())
 }
 
+/// Walk a new user through setting up Sapphire
+///
+/// Asks for the installation mode, optionally snapshots the currently
+/// installed packages into a shard, initializes the selected fragment types
+/// and configures a git remote for syncing `~/.sapphire`.
+pub fn initialize_interactive() -> Result<()> {
+    if !console::user_attended() {
+        anyhow::bail!("Interactive setup requires a terminal. Run `sapphire setup --mode <mode>` instead");
+    }
+
+    println!("{}", console::style("Welcome to Sapphire!").bold());
+    let base_dir = manager::get_sapphire_dir()?;
+    println!("This wizard sets up {} for you. Press Ctrl+C at any time to abort.\n", base_dir.display());
+
+    let modes = ["local", "managed"];
+    let mode_index = Select::new()
+        .with_prompt("How will this machine be managed? (local: configured here, managed: synced from a remote repository)")
+        .items(&modes)
+        .default(0)
+        .interact()
+        .context("Failed to read installation mode")?;
+    let mode = modes[mode_index];
+
+    create_directory_structure(&base_dir)?;
+    create_initial_config(&base_dir, mode)?;
+
+    #[cfg(feature = "shard")]
+    setup_shards()?;
+
+    #[cfg(feature = "fragment")]
+    setup_fragments(&base_dir)?;
+
+    setup_git_sync(&base_dir)?;
+
+    println!();
+    println!("Sapphire initialized successfully in {} mode", mode);
+    println!("Sapphire directory: {}", base_dir.display());
+
+    Ok(())
+}
+
+/// Create the default shards and optionally snapshot installed packages
+#[cfg(feature = "shard")]
+fn setup_shards() -> Result<()> {
+    shard::init_shards(false)?;
+
+    let snapshot = Confirm::new()
+        .with_prompt("Snapshot currently installed Homebrew packages into a shard? (keeps them installed on 'shard apply all')")
+        .default(true)
+        .interact()
+        .context("Failed to read confirmation")?;
+
+    if !snapshot {
+        return Ok(());
+    }
+
+    let name: String = Input::new()
+        .with_prompt("Shard name")
+        .default("snapshot".to_string())
+        .interact_text()
+        .context("Failed to read shard name")?;
+
+    let brew_client = shard::brew::get_client(shard::brew::ClientOverrides::default());
+    let dependencies = brew_client.get_dependency_packages()?;
+
+    let mut manifest = shard::manifest::Manifest::new();
+    manifest.metadata.name = name.clone();
+    manifest.metadata.description = "Packages installed before Sapphire was set up".to_string();
+    manifest.taps = brew_client.get_installed_taps()?.into_iter().map(Into::into).collect();
+    manifest.formulae = brew_client.get_installed_formulae()?
+        .into_iter()
+        .filter(|formula| !dependencies.contains(formula))
+        .collect();
+    manifest.casks = brew_client.get_installed_casks()?;
+
+    let shard_path = manager::get_sapphire_dir()?.join("shards").join(format!("{}.toml", name));
+    if utils::file_exists(&shard_path) {
+        tracing::warn!("Shard already exists, not overwriting: {}", shard_path.display());
+        return Ok(());
+    }
+
+    manifest.to_file(&shard_path)?;
+    tracing::info!("Saved {} formulae and {} casks to shard '{}'",
+        manifest.formulae.len(), manifest.casks.len(), name);
+
+    Ok(())
+}
+
+/// Initialize the fragment types the user selects
+#[cfg(feature = "fragment")]
+fn setup_fragments(base_dir: &Path) -> Result<()> {
+    let types: Vec<String> = fragment::parser::FragmentType::ALL.iter()
+        .map(|fragment_type| format!("{} - {}", fragment_type, fragment_type.description()))
+        .collect();
+
+    let selected = dialoguer::MultiSelect::new()
+        .with_prompt("Which fragment types do you want to start with? (space to select, enter to confirm)")
+        .items(&types)
+        .defaults(&[true])
+        .interact()
+        .context("Failed to read fragment selection")?;
+
+    let fragments_dir = base_dir.join("fragments/user");
+    for index in selected {
+        let fragment_type = fragment::parser::FragmentType::ALL[index].to_string();
+        if let Err(e) = fragment::init::init(&fragment_type, &fragments_dir, false) {
+            tracing::warn!("Skipping {} fragment: {}", fragment_type, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Optionally turn the Sapphire directory into a git repository with a remote
+fn setup_git_sync(base_dir: &Path) -> Result<()> {
+    let configure = Confirm::new()
+        .with_prompt(format!("Sync {} with a git repository?", base_dir.display()))
+        .default(false)
+        .interact()
+        .context("Failed to read confirmation")?;
+
+    if !configure {
+        return Ok(());
+    }
+
+    let remote: String = Input::new()
+        .with_prompt("Git remote URL")
+        .interact_text()
+        .context("Failed to read git remote")?;
+
+    if !base_dir.join(".git").exists() {
+        run_git(base_dir, &["init"])?;
+    }
+
+    // Backups are machine specific and should not be synced
+    let gitignore = base_dir.join(".gitignore");
+    if !utils::file_exists(&gitignore) {
+        utils::write_file(&gitignore, "backups/\n")?;
+    }
+
+    if run_git(base_dir, &["remote", "add", "origin", &remote]).is_err() {
+        run_git(base_dir, &["remote", "set-url", "origin", &remote])?;
+    }
+
+    manager::set_config_value("sync.remote", &remote)?;
+    tracing::info!("Configured git sync with {}", remote);
+
+    Ok(())
+}
+
+fn run_git(dir: &Path, args: &[&str]) -> Result<()> {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .status()
+        .context("Failed to run git")?;
+
+    if !status.success() {
+        anyhow::bail!("git {} failed", args.join(" "));
+    }
+
+    Ok(())
+}
+
 fn create_directory_structure(base_dir: &Path) -> Result<()> {
     // Create main directories
     let dirs = [
@@ -72,17 +236,72 @@ fn create_initial_config(config_dir: &Path, mode: &str) -> Result<()> {
     let config_content = format!(r#"# Sapphire Configuration
 version = "0.1.0"
 mode = "{}"
+# Refuse every command that changes the system, e.g. where changes go through
+# review. Also turned on by SAPPHIRE_READ_ONLY=1
+read_only = false
 
 [paths]
-fragments = "~/.sapphire/fragments"
-scripts = "~/.sapphire/scripts"
-manifests = "~/.sapphire/manifests"
-dotfiles = "~/.sapphire/dotfiles"
+fragments = "{root}/fragments"
+scripts = "{root}/scripts"
+manifests = "{root}/manifests"
+dotfiles = "{root}/dotfiles"
 
 [features]
 package_management = true
 configuration_management = true
-"#, mode);
+
+[shard]
+# Send a macOS notification summarizing unattended (scheduled) runs
+notify = false
+# "changes" only notifies when something changed, "always" after every run
+notify_threshold = "changes"
+# Backups kept per shard and their maximum age in days (0 keeps all)
+backup_max_count = 10
+backup_max_age_days = 90
+# Type `shard add` picks for names that exist as formula and cask: "formula", "cask" or "ask"
+prefer = "ask"
+# Brew executable, detected in /opt/homebrew and /usr/local when unset
+# brew_path = "/opt/homebrew/bin/brew"
+# Skip Homebrew's auto update and environment hints for faster, repeatable applies
+no_auto_update = true
+no_env_hints = true
+# Run `brew update` before apply and upgrade when the last update is older (0 disables)
+update_interval_hours = 24
+# Packages per brew invocation of batch installs and upgrades (0 for one batch),
+# so a bad name only fails its batch. Batches run in parallel when true
+batch_size = 10
+parallel_batches = false
+# Seconds brew may take to install, upgrade, fetch or uninstall, and to run any
+# other command, before it is stopped (0 waits forever). `--timeout` overrides both
+install_timeout_secs = 1800
+query_timeout_secs = 60
+# Times an install, upgrade or fetch that timed out or failed to download is
+# run again. `--retries` overrides it
+retries = 0
+# Brew options for every cask, e.g. ["--no-quarantine", "--appdir=~/Applications"]
+cask_options = []
+# Packages managed by other tools, never installed, upgraded or uninstalled.
+# Names or globs, e.g. ["python@*", "*-beta"]
+ignore = []
+# Mark messages with ✓ and →, or with ASCII like "ok" and "->" when false
+glyphs = true
+# Let `shard apply all` pick packages to keep when it would uninstall more (0 never asks)
+uninstall_prompt_threshold = 5
+# Post a JSON summary of every apply (host, changes, failures, duration) to
+# this URL, e.g. for a dashboard of many Macs
+# webhook_url = "https://example.com/sapphire/applies"
+
+# Environment passed to brew, e.g. credentials for private taps. Values are
+# literals, secrets stored with `sapphire secret set` or other keychain items:
+# {{ keychain = "service", account = "name" }}
+# [shard.env]
+# HOMEBREW_GITHUB_API_TOKEN = {{ secret = "github-token" }}
+
+# Package nicknames for `shard add`, `shard del` and `shard search`, in
+# addition to built-in ones such as vscode and chrome
+# [shard.aliases]
+# code = "visual-studio-code"
+"#, mode, root = config_dir.display());
     
     std::fs::write(&config_path, config_content)
         .context(format!("Failed to write configuration file: {}", config_path.display()))?;
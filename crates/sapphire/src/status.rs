@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::UNIX_EPOCH;
+use anyhow::{Context, Result};
+use console::style;
+use sapphire_core::history::{self, Applied, Kind};
+
+/// Show when shards and fragments were last applied and how it went
+///
+/// Enabled shards that were never applied are listed too, so stale shards
+/// stand out.
+pub fn status() -> Result<()> {
+    let applied = history::load().context("Failed to read apply history")?;
+
+    println!("{}", style("Sapphire Status").bold());
+
+    let mut shards = enabled_shards()?;
+    shards.extend(applied.iter().filter(|entry| entry.kind == Kind::Shard).map(|entry| entry.name.clone()));
+    shards.sort();
+    shards.dedup();
+    print_section("Shards", Kind::Shard, &shards, &applied);
+
+    let fragments: Vec<String> = applied.iter()
+        .filter(|entry| entry.kind == Kind::Fragment)
+        .map(|entry| entry.name.clone())
+        .collect();
+    print_section("Fragments", Kind::Fragment, &fragments, &applied);
+
+    Ok(())
+}
+
+fn print_section(title: &str, kind: Kind, names: &[String], applied: &[Applied]) {
+    println!();
+    println!("{}", style(title).bold());
+    if names.is_empty() {
+        println!("  Nothing applied yet");
+        return;
+    }
+
+    for name in names {
+        let Some(entry) = applied.iter().find(|entry| entry.kind == kind && &entry.name == name) else {
+            println!("  {:<32} {}", name, style("never applied").yellow());
+            continue;
+        };
+
+        let mut details = entry.describe_age();
+        if !entry.user.is_empty() {
+            details.push_str(&format!(" by {}", entry.user));
+        }
+        if let Some(outcome) = entry.outcome {
+            details.push_str(&format!(" ({})", outcome.describe()));
+        }
+        if entry.succeeded() {
+            println!("  {:<32} {}", name, details);
+        } else {
+            println!("  {:<32} {}", name, style(details).red());
+        }
+    }
+}
+
+/// Machine state in the Prometheus text exposition format
+///
+/// Written to `output` if given, replacing the file at once so a scraper
+/// never reads half of it, and printed otherwise.
+pub fn metrics(output: Option<&str>) -> Result<()> {
+    let applied = history::load().context("Failed to read apply history")?;
+    let mut text = String::new();
+
+    let shards = shard_metrics();
+    metric(&mut text, "sapphire_shards_enabled", "Number of enabled shards",
+        [(String::new(), shards.enabled as f64)]);
+    metric(&mut text, "sapphire_managed_packages", "Packages declared by the enabled shards",
+        shards.packages.iter().map(|(kind, count)| (format!("type=\"{}\"", kind), *count as f64)));
+    if let Some(drift) = &shards.drift {
+        metric(&mut text, "sapphire_drift_actions", "Changes applying all enabled shards would make",
+            drift.iter().map(|(change, count)| (format!("change=\"{}\"", change), *count as f64)));
+    }
+
+    let labels = |entry: &Applied| format!("kind=\"{}\",name=\"{}\"", entry.kind.as_str(), escape_label(&entry.name));
+    metric(&mut text, "sapphire_last_apply_timestamp_seconds", "Unix time of the last apply",
+        applied.iter().map(|entry| {
+            (labels(entry), entry.at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as f64)
+        }));
+    metric(&mut text, "sapphire_last_apply_success", "Whether the last apply succeeded",
+        applied.iter().map(|entry| (labels(entry), if entry.succeeded() { 1.0 } else { 0.0 })));
+    metric(&mut text, "sapphire_last_apply_failed_actions", "Actions that failed in the last apply",
+        applied.iter().filter_map(|entry| Some((labels(entry), entry.outcome?.failed as f64))));
+
+    let Some(path) = output else {
+        print!("{}", text);
+        return Ok(());
+    };
+    let path = std::path::PathBuf::from(shellexpand::tilde(path).into_owned());
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, text)
+        .with_context(|| format!("Failed to write {}", partial.display()))?;
+    std::fs::rename(&partial, &path)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Append a gauge with its help text and one sample per set of labels
+///
+/// Metrics without samples are left out.
+fn metric(text: &mut String, name: &str, help: &str, samples: impl IntoIterator<Item = (String, f64)>) {
+    let samples: Vec<(String, f64)> = samples.into_iter().collect();
+    if samples.is_empty() {
+        return;
+    }
+
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} gauge", name);
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(text, "{} {}", name, value);
+        } else {
+            let _ = writeln!(text, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+/// Escape a label value of the exposition format
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Shard state reported by [`metrics`]
+#[derive(Default)]
+struct ShardMetrics {
+    enabled: usize,
+    /// Declared packages by type
+    packages: BTreeMap<&'static str, usize>,
+    /// Pending changes by kind, `None` if planning failed
+    drift: Option<BTreeMap<&'static str, usize>>,
+}
+
+#[cfg(feature = "shard")]
+fn shard_metrics() -> ShardMetrics {
+    use std::collections::BTreeSet;
+    use shard::brew::ClientOverrides;
+    use shard::engine::{Action, ShardEngine};
+
+    let manifests = shard::shard::manager::ShardManager::new()
+        .and_then(|manager| manager.load_active_manifests())
+        .unwrap_or_else(|e| {
+            tracing::warn!("Could not read the enabled shards: {}", e);
+            Vec::new()
+        });
+    let formulae: BTreeSet<&String> = manifests.iter().flat_map(|(_, manifest)| &manifest.formulae).collect();
+    let casks: BTreeSet<&String> = manifests.iter().flat_map(|(_, manifest)| &manifest.casks).collect();
+
+    let drift = ShardEngine::new(ClientOverrides::default())
+        .and_then(|engine| engine.plan("all"))
+        .map(|plan| {
+            let mut drift = BTreeMap::from([("install", 0), ("upgrade", 0), ("remove", 0)]);
+            for action in shard::shard::apply::pending_actions(&plan) {
+                let change = match action {
+                    Action::AddTap { .. } => "tap",
+                    Action::Install { .. } | Action::InstallExtension { .. } => "install",
+                    Action::Upgrade { .. } => "upgrade",
+                    Action::Uninstall { .. } => "remove",
+                    Action::Link { .. } => "link",
+                };
+                *drift.entry(change).or_default() += 1;
+            }
+            drift
+        });
+    let drift = match drift {
+        Ok(drift) => Some(drift),
+        Err(e) => {
+            tracing::warn!("Leaving out drift metrics, the enabled shards could not be planned: {}", e);
+            None
+        }
+    };
+
+    ShardMetrics {
+        enabled: manifests.len(),
+        packages: BTreeMap::from([("formula", formulae.len()), ("cask", casks.len())]),
+        drift,
+    }
+}
+
+#[cfg(not(feature = "shard"))]
+fn shard_metrics() -> ShardMetrics {
+    ShardMetrics::default()
+}
+
+#[cfg(feature = "shard")]
+fn enabled_shards() -> Result<Vec<String>> {
+    let manager = shard::shard::manager::ShardManager::new()?;
+    Ok(manager.list_shards()?)
+}
+
+#[cfg(not(feature = "shard"))]
+fn enabled_shards() -> Result<Vec<String>> {
+    Ok(Vec::new())
+}
@@ -0,0 +1,33 @@
+//! Names of every formula and cask, as cached by Homebrew.
+//!
+//! Homebrew downloads its API index to `$HOMEBREW_CACHE/api` and keeps plain
+//! lists of the names next to it, `formula_names.txt` and `cask_names.txt`.
+//! Reading those is instant and needs neither brew nor the network, which
+//! makes them fit for shell completion. The lists are as fresh as the last
+//! `brew update` and missing on installations that never used the API.
+
+use std::path::PathBuf;
+
+/// Name lists in the API cache of Homebrew
+const FORMULA_NAMES: &str = "formula_names.txt";
+const CASK_NAMES: &str = "cask_names.txt";
+
+/// Names of all formulae and casks Homebrew knows, empty if not cached
+pub fn cached_names() -> Vec<String> {
+    let dir = api_cache_dir();
+    [FORMULA_NAMES, CASK_NAMES].iter()
+        .filter_map(|file| std::fs::read_to_string(dir.join(file)).ok())
+        .flat_map(|content| content.lines().map(|line| line.trim().to_string()).collect::<Vec<_>>())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// `$HOMEBREW_CACHE/api`, by default in the user's cache directory
+fn api_cache_dir() -> PathBuf {
+    let cache = match std::env::var_os("HOMEBREW_CACHE") {
+        Some(cache) => PathBuf::from(cache),
+        None if cfg!(target_os = "macos") => PathBuf::from(shellexpand::tilde("~/Library/Caches/Homebrew").into_owned()),
+        None => PathBuf::from(shellexpand::tilde("~/.cache/Homebrew").into_owned()),
+    };
+    cache.join("api")
+}
@@ -8,12 +8,15 @@
 //! ensuring backward compatibility while supporting proper separation of concerns.
 //! All operations enforce proper input validation to prevent command injection.
 
-use crate::utils::ShardResult;
+use crate::utils::{ShardError, ShardResult};
 use crate::brew::core::BrewCore;
-use crate::brew::installer::BrewInstaller;
+use crate::brew::installer::{BatchResults, BrewInstaller};
 use crate::brew::search::BrewSearcher;
+use crate::brew::validate;
+use crate::brew::diagnostics::{AuditProblem, BrewDiagnostics, DoctorWarning};
 
 /// Homebrew client for interacting with brew CLI
+#[derive(Clone)]
 pub struct BrewClient {
     /// Core execution engine
     core: BrewCore,
@@ -21,15 +24,18 @@ pub struct BrewClient {
     installer: BrewInstaller,
     /// Package searcher
     searcher: BrewSearcher,
+    /// Environment diagnostics
+    diagnostics: BrewDiagnostics,
 }
 
 impl BrewClient {
-    /// Create a new client with the default brew path
+    /// Create a new client with the detected brew path
     pub fn new() -> Self {
         let core = BrewCore::new();
         Self {
             installer: BrewInstaller::with_core(core.clone()),
             searcher: BrewSearcher::with_core(core.clone()),
+            diagnostics: BrewDiagnostics::with_core(core.clone()),
             core,
         }
     }
@@ -40,6 +46,7 @@ impl BrewClient {
         Self {
             installer: BrewInstaller::with_core(core.clone()),
             searcher: BrewSearcher::with_core(core.clone()),
+            diagnostics: BrewDiagnostics::with_core(core.clone()),
             core,
         }
     }
@@ -49,6 +56,7 @@ impl BrewClient {
         self.core = self.core.with_debug(debug);
         self.installer = BrewInstaller::with_core(self.core.clone());
         self.searcher = BrewSearcher::with_core(self.core.clone());
+        self.diagnostics = BrewDiagnostics::with_core(self.core.clone());
         self
     }
     
@@ -57,14 +65,66 @@ impl BrewClient {
         self.core = self.core.with_timeout(seconds);
         self.installer = BrewInstaller::with_core(self.core.clone());
         self.searcher = BrewSearcher::with_core(self.core.clone());
+        self.diagnostics = BrewDiagnostics::with_core(self.core.clone());
+        self
+    }
+    
+    /// Run installs, upgrades and fetches up to `retries` more times when they
+    /// time out or fail to download
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.core = self.core.with_retries(retries);
+        self.installer = BrewInstaller::with_core(self.core.clone());
+        self.searcher = BrewSearcher::with_core(self.core.clone());
+        self.diagnostics = BrewDiagnostics::with_core(self.core.clone());
+        self
+    }
+    
+    /// Set the timeouts of installs and of all other commands in seconds, 0 for none
+    pub fn with_timeouts(mut self, install_secs: u64, query_secs: u64) -> Self {
+        self.core = self.core.with_timeouts(install_secs, query_secs);
+        self.installer = BrewInstaller::with_core(self.core.clone());
+        self.searcher = BrewSearcher::with_core(self.core.clone());
+        self.diagnostics = BrewDiagnostics::with_core(self.core.clone());
+        self
+    }
+    
+    /// Control Homebrew's auto update and environment hints
+    pub fn with_homebrew_flags(mut self, no_auto_update: bool, no_env_hints: bool) -> Self {
+        self.core = self.core.with_homebrew_flags(no_auto_update, no_env_hints);
+        self.installer = BrewInstaller::with_core(self.core.clone());
+        self.searcher = BrewSearcher::with_core(self.core.clone());
+        self.diagnostics = BrewDiagnostics::with_core(self.core.clone());
+        self
+    }
+    
+    /// Split batch installs and upgrades into batches of `size` packages
+    pub fn with_batching(mut self, size: usize, parallel: bool) -> Self {
+        self.core = self.core.with_batching(size, parallel);
+        self.installer = BrewInstaller::with_core(self.core.clone());
+        self.searcher = BrewSearcher::with_core(self.core.clone());
+        self.diagnostics = BrewDiagnostics::with_core(self.core.clone());
+        self
+    }
+    
+    /// Path of the brew executable
+    pub fn brew_path(&self) -> &str {
+        self.core.brew_path()
+    }
+    
+    /// Pass resolved environment variables, e.g. credentials, to every brew process
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.core = self.core.with_env(env);
+        self.installer = BrewInstaller::with_core(self.core.clone());
+        self.searcher = BrewSearcher::with_core(self.core.clone());
+        self.diagnostics = BrewDiagnostics::with_core(self.core.clone());
         self
     }
 
     // Installer delegated methods
     
-    /// Add a Homebrew tap
-    pub fn add_tap(&self, tap: &str) -> ShardResult<()> {
-        self.installer.add_tap(tap)
+    /// Add a Homebrew tap, optionally from a custom remote URL
+    pub fn add_tap(&self, tap: &str, url: Option<&str>) -> ShardResult<()> {
+        self.installer.add_tap(tap, url)
     }
     
     /// Install a Homebrew formula
@@ -92,23 +152,23 @@ impl BrewClient {
         self.installer.get_installed_taps()
     }
 
-    /// Perform a batch install of multiple formulae at once
-    pub fn batch_install_formulae(&self, formulae: &[String]) -> ShardResult<()> {
+    /// Install multiple formulae in batches, returning the result of each
+    pub fn batch_install_formulae(&self, formulae: &[String]) -> ShardResult<BatchResults> {
         self.installer.batch_install_formulae(formulae)
     }
 
-    /// Perform a batch install of multiple casks at once
-    pub fn batch_install_casks(&self, casks: &[String]) -> ShardResult<()> {
+    /// Install multiple casks in batches, returning the result of each
+    pub fn batch_install_casks(&self, casks: &[String]) -> ShardResult<BatchResults> {
         self.installer.batch_install_casks(casks)
     }
 
-    /// Perform a batch upgrade of multiple formulae at once
-    pub fn batch_upgrade_formulae(&self, formulae: &[String]) -> ShardResult<()> {
+    /// Upgrade multiple formulae in batches, returning the result of each
+    pub fn batch_upgrade_formulae(&self, formulae: &[String]) -> ShardResult<BatchResults> {
         self.installer.batch_upgrade_formulae(formulae)
     }
 
-    /// Perform a batch upgrade of multiple casks at once
-    pub fn batch_upgrade_casks(&self, casks: &[String]) -> ShardResult<()> {
+    /// Upgrade multiple casks in batches, returning the result of each
+    pub fn batch_upgrade_casks(&self, casks: &[String]) -> ShardResult<BatchResults> {
         self.installer.batch_upgrade_casks(casks)
     }
 
@@ -122,21 +182,101 @@ impl BrewClient {
         self.installer.upgrade_cask_with_options(cask, options)
     }
 
+    /// Download a formula and its dependencies without installing them
+    pub fn fetch_formula(&self, formula: &str, options: &[String]) -> ShardResult<()> {
+        self.installer.fetch_formula(formula, options)
+    }
+
+    /// Download a cask without installing it
+    pub fn fetch_cask(&self, cask: &str) -> ShardResult<()> {
+        self.installer.fetch_cask(cask)
+    }
+
     /// Uninstall a formula
     pub fn uninstall_formula(&self, formula: &str, force: bool) -> ShardResult<()> {
         self.installer.uninstall_formula(formula, force)
     }
 
+    /// Get the link state of every installed formula
+    pub fn get_link_states(&self) -> ShardResult<Vec<crate::brew::installer::LinkState>> {
+        self.installer.get_link_states()
+    }
+
+    /// Link a formula into the Homebrew prefix, `force` is required for keg-only formulae
+    pub fn link_formula(&self, formula: &str, force: bool) -> ShardResult<()> {
+        self.installer.link_formula(formula, force)
+    }
+
+    /// Remove a formula's links from the Homebrew prefix
+    pub fn unlink_formula(&self, formula: &str) -> ShardResult<()> {
+        self.installer.unlink_formula(formula)
+    }
+
+    /// Uninstall several formulae with one brew call
+    pub fn uninstall_formulae(&self, formulae: &[String]) -> ShardResult<()> {
+        self.installer.uninstall_formulae(formulae)
+    }
+
     /// Uninstall a cask
     pub fn uninstall_cask(&self, cask: &str, force: bool) -> ShardResult<()> {
         self.installer.uninstall_cask(cask, force)
     }
 
+    /// Get the installed formulae and casks that directly depend on a formula
+    pub fn get_installed_dependents(&self, formula: &str) -> ShardResult<Vec<String>> {
+        self.installer.get_installed_dependents(formula)
+    }
+
     /// Get a list of all packages installed as dependencies
     pub fn get_dependency_packages(&self) -> ShardResult<Vec<String>> {
         self.installer.get_dependency_packages()
     }
 
+    /// Get what every installed formula and cask depends on
+    pub fn get_installed_dependencies(&self) -> ShardResult<Vec<crate::brew::installer::InstalledDependencies>> {
+        self.installer.get_installed_dependencies()
+    }
+
+    /// Get all outdated formulae and casks
+    pub fn get_outdated(&self, greedy: bool) -> ShardResult<crate::brew::installer::OutdatedReport> {
+        self.installer.get_outdated(greedy)
+    }
+
+    /// Get the installed casks that are only outdated with `--greedy`
+    pub fn get_greedy_casks(&self) -> ShardResult<std::collections::HashSet<String>> {
+        self.installer.get_greedy_casks()
+    }
+
+    /// Get the installed version of every formula, or every cask with `cask`
+    pub fn get_installed_versions(&self, cask: bool) -> ShardResult<std::collections::BTreeMap<String, String>> {
+        self.installer.get_installed_versions(cask)
+    }
+    
+    /// Get how every installed formula was built
+    pub fn get_installed_builds(&self) -> ShardResult<Vec<crate::brew::installer::InstalledBuild>> {
+        self.installer.get_installed_builds()
+    }
+
+    /// Get the Homebrew Cellar directory
+    pub fn get_cellar_path(&self) -> ShardResult<String> {
+        self.installer.get_cellar_path()
+    }
+
+    /// Get the Homebrew Caskroom directory
+    pub fn get_caskroom_path(&self) -> ShardResult<String> {
+        self.installer.get_caskroom_path()
+    }
+
+    /// Get the Homebrew repository directory
+    pub fn get_repository_path(&self) -> ShardResult<String> {
+        self.installer.get_repository_path()
+    }
+
+    /// Fetch the newest Homebrew and package index
+    pub fn update(&self) -> ShardResult<()> {
+        self.installer.update()
+    }
+    
     /// Run cleanup
     pub fn cleanup(&self, prune_all: bool) -> ShardResult<()> {
         self.installer.cleanup(prune_all)
@@ -159,8 +299,86 @@ impl BrewClient {
         self.searcher.get_cask_info(cask)
     }
 
+    /// Get information about several formulae at once
+    pub fn get_formulae_info(&self, formulae: &[String]) -> ShardResult<Vec<crate::brew::search::FormulaInfo>> {
+        self.searcher.get_formulae_info(formulae)
+    }
+
+    /// Get information about several casks at once
+    pub fn get_casks_info(&self, casks: &[String]) -> ShardResult<Vec<crate::brew::search::CaskInfo>> {
+        self.searcher.get_casks_info(casks)
+    }
+
+    /// Get the descriptions of formulae or casks by name
+    pub fn get_descriptions(&self, names: &[String], cask: bool) -> ShardResult<std::collections::HashMap<String, String>> {
+        self.searcher.get_descriptions(names, cask)
+    }
+
+    /// List the formulae and casks a tap provides
+    pub fn get_tap_packages(&self, tap: &str) -> ShardResult<crate::brew::search::TapPackages> {
+        self.searcher.get_tap_packages(tap)
+    }
+
+    /// Find the deprecated and disabled packages among formulae and casks
+    pub fn get_deprecations(&self, formulae: &[String], casks: &[String]) -> ShardResult<crate::brew::search::DeprecationReport> {
+        self.searcher.get_deprecations(formulae, casks)
+    }
+
+    /// Get the build options a formula offers
+    pub fn get_formula_options(&self, formula: &str) -> ShardResult<Vec<String>> {
+        self.searcher.get_formula_options(formula)
+    }
+
+    /// Get the app bundles casks install
+    pub fn get_cask_apps(&self, casks: &[String]) -> ShardResult<Vec<crate::brew::search::CaskApps>> {
+        self.searcher.get_cask_apps(casks)
+    }
+
+    /// Get the license and installed version of formulae
+    pub fn get_licenses(&self, formulae: &[String]) -> ShardResult<Vec<crate::brew::search::FormulaLicense>> {
+        self.searcher.get_licenses(formulae)
+    }
+
     /// Check if a package is available as brew formula and/or cask
     pub fn check_package_availability(&self, package_name: &str) -> ShardResult<crate::brew::search::PackageAvailability> {
         self.searcher.check_package_availability(package_name)
     }
-}
\ No newline at end of file
+    
+    // Diagnostics delegated methods
+    
+    /// Run `brew doctor` and return its warnings
+    pub fn doctor(&self) -> ShardResult<Vec<DoctorWarning>> {
+        self.diagnostics.doctor()
+    }
+    
+    /// Run `brew audit` on formulae and return the problems it finds
+    pub fn audit(&self, formulae: &[String]) -> ShardResult<Vec<AuditProblem>> {
+        self.diagnostics.audit(formulae)
+    }
+    
+    /// Get the output of `brew config` as key/value pairs
+    pub fn get_config(&self) -> ShardResult<std::collections::BTreeMap<String, String>> {
+        self.diagnostics.config()
+    }
+    
+    /// Get the local git repository of a tap
+    pub fn get_tap_repository(&self, tap: &str) -> ShardResult<std::path::PathBuf> {
+        self.diagnostics.get_tap_repository(tap)
+    }
+    
+    // Passthrough
+    
+    /// Run an arbitrary brew command attached to the terminal
+    ///
+    /// Only the command itself is validated, its arguments are passed to brew
+    /// as they are and never reach a shell.
+    pub fn passthrough(&self, args: &[String]) -> ShardResult<std::process::ExitStatus> {
+        let Some(command) = args.first() else {
+            return Err(ShardError::ValidationError("No brew command given".to_string()));
+        };
+        validate::validate_command(command)?;
+        
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.core.execute_passthrough(&args)
+    }
+}
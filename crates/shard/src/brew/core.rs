@@ -5,10 +5,14 @@
 //! input validation. Callers are responsible for validating all inputs before passing
 //! them to methods in this module.
 
-use crate::utils::ShardResult;
+use crate::brew::failure::BrewFailure;
+use crate::utils::{ShardError, ShardResult, log_trace, log_warning};
 use anyhow::Context;
-use std::process::{Command, Child, Stdio};
+use regex::Regex;
+use lazy_static::lazy_static;
+use std::process::{Command, ExitStatus, Stdio};
 use std::fmt::Write;
+use std::sync::{Arc, Mutex, PoisonError};
 use std::time::{Duration, Instant};
 use std::thread;
 use std::io::Read;
@@ -20,18 +24,67 @@ pub struct BrewCore {
     brew_path: String,
     /// Whether to enable debug output
     debug: bool,
-    /// Command timeout in seconds (None means no timeout)
+    /// Command timeout in seconds for every command, overriding the ones below
     timeout: Option<u64>,
+    /// Timeout of installs, upgrades, fetches and uninstalls (None means no timeout)
+    install_timeout: Option<u64>,
+    /// Timeout of all other commands (None means no timeout)
+    query_timeout: Option<u64>,
+    /// Times a command under the install timeout is run again after it timed
+    /// out or failed to download
+    retries: u32,
+    /// Extra environment variables, may contain secrets and is never logged
+    env: Vec<(String, String)>,
+    /// Set `HOMEBREW_NO_AUTO_UPDATE`
+    no_auto_update: bool,
+    /// Set `HOMEBREW_NO_ENV_HINTS`
+    no_env_hints: bool,
+    /// Packages per brew invocation of batch operations, 0 for a single batch
+    batch_size: usize,
+    /// Run the batches of an operation at the same time
+    parallel_batches: bool,
+}
+
+/// Brew commands that download or build and fall under the install timeout
+/// Commands that run as long as the user works with them, `shard brew` never stops them on the configured timeouts
+const INTERACTIVE_COMMANDS: &[&str] = &["edit", "sh", "irb", "ruby", "create"];
+
+const INSTALL_COMMANDS: &[&str] = &["install", "reinstall", "upgrade", "fetch", "uninstall", "update", "cleanup", "tap", "bundle"];
+
+/// Signs in brew's stderr that a download failed and may work on another try
+const DOWNLOAD_FAILURES: &[&str] = &["Failed to download resource", "Download failed", "curl: ("];
+
+/// Time to wait before running a command again
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    // `==> Installing wget`, `==> Fetching wget`, `==> Installing Cask firefox`
+    // or `==> Installing wget dependency: openssl@3` in brew's progress
+    static ref PROGRESS_REGEX: Regex = Regex::new(
+        r"(?m)^==> (?:Fetching|Installing|Upgrading|Reinstalling|Uninstalling) (?:Cask )?(?:\S+ dependency: )?([^\s:]+)$"
+    ).unwrap();
+}
+
+/// Output a child wrote so far, shared with the thread reading it
+type Buffer = Arc<Mutex<Vec<u8>>>;
+
+/// A thread reading a child pipe into its buffer
+type Reader = (Buffer, thread::JoinHandle<std::io::Result<()>>);
+
+/// Standard Homebrew locations on Apple silicon and Intel Macs, in order of preference
+const BREW_LOCATIONS: &[&str] = &["/opt/homebrew/bin/brew", "/usr/local/bin/brew"];
+
+/// Find the brew executable in the standard locations, falling back to PATH
+pub fn detect_brew_path() -> String {
+    BREW_LOCATIONS.iter()
+        .find(|path| std::path::Path::new(path).is_file())
+        .map_or_else(|| "brew".to_string(), |path| path.to_string())
 }
 
 impl BrewCore {
-    /// Create a new core with the default brew path
+    /// Create a new core with the detected brew path
     pub fn new() -> Self {
-        Self {
-            brew_path: "brew".to_string(),
-            debug: false,
-            timeout: None,
-        }
+        Self::with_path(detect_brew_path())
     }
     
     /// Create a new core with a custom brew path
@@ -40,6 +93,14 @@ impl BrewCore {
             brew_path,
             debug: false,
             timeout: None,
+            install_timeout: None,
+            query_timeout: None,
+            retries: 0,
+            env: Vec::new(),
+            no_auto_update: false,
+            no_env_hints: false,
+            batch_size: 0,
+            parallel_batches: false,
         }
     }
     
@@ -55,37 +116,188 @@ impl BrewCore {
         self
     }
     
-    /// Execute a brew command and return its output if successful
-    pub fn execute_brew_command(&self, args: &[&str]) -> ShardResult<std::process::Output> {
+    /// Set the timeouts of installs and of all other commands in seconds, 0 for none
+    ///
+    /// A timeout set with [`with_timeout`](Self::with_timeout) takes precedence.
+    pub fn with_timeouts(mut self, install_secs: u64, query_secs: u64) -> Self {
+        self.install_timeout = (install_secs > 0).then_some(install_secs);
+        self.query_timeout = (query_secs > 0).then_some(query_secs);
+        self
+    }
+    
+    /// Run installs, upgrades and fetches up to `retries` more times when they
+    /// time out or fail to download
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+    
+    /// Pass extra environment variables to every brew process
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+    
+    /// Control Homebrew's auto update and environment hints
+    pub fn with_homebrew_flags(mut self, no_auto_update: bool, no_env_hints: bool) -> Self {
+        self.no_auto_update = no_auto_update;
+        self.no_env_hints = no_env_hints;
+        self
+    }
+    
+    /// Split batch operations into batches of `size` packages, run one after
+    /// another or with `parallel` at the same time
+    pub fn with_batching(mut self, size: usize, parallel: bool) -> Self {
+        self.batch_size = size;
+        self.parallel_batches = parallel;
+        self
+    }
+    
+    /// Path of the brew executable
+    pub fn brew_path(&self) -> &str {
+        &self.brew_path
+    }
+    
+    /// Packages per batch, 0 for a single batch
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+    
+    /// Whether the batches of an operation run at the same time
+    pub fn parallel_batches(&self) -> bool {
+        self.parallel_batches
+    }
+    
+    /// Whether a brew command with these arguments falls under the install timeout
+    fn is_install(args: &[&str]) -> bool {
+        args.first().is_some_and(|command| INSTALL_COMMANDS.contains(command))
+    }
+    
+    /// Timeout of a brew command with these arguments
+    fn timeout_for(&self, args: &[&str]) -> Option<u64> {
+        self.timeout.or(if Self::is_install(args) { self.install_timeout } else { self.query_timeout })
+    }
+    
+    /// Timeout of a brew command run attached to the terminal
+    ///
+    /// Interactive commands only stop on a timeout set with [`with_timeout`](Self::with_timeout).
+    fn passthrough_timeout_for(&self, args: &[&str]) -> Option<u64> {
+        match args.first() {
+            Some(command) if INTERACTIVE_COMMANDS.contains(command) => self.timeout,
+            _ => self.timeout_for(args),
+        }
+    }
+    
+    /// Error for a command that exited with an error
+    ///
+    /// Common failures get a concise message, anything else keeps brew's stderr.
+    /// The full stderr is still logged with `-vv`.
+    fn failed(command: &str, output: &std::process::Output) -> ShardError {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match BrewFailure::parse(&stderr) {
+            Some(failure) => ShardError::BrewFailure { command: command.to_string(), failure, stderr: stderr.into_owned() },
+            None => ShardError::BrewError(
+                format!("Error executing brew command {}: {}", command, stderr)
+            ),
+        }
+    }
+    
+    /// Error for a command that was stopped after `timeout_secs`
+    ///
+    /// Names the package brew was working on according to the progress it
+    /// wrote to `stdout`, all packages of the command if that is unknown, and
+    /// the setting that raises the limit.
+    fn timed_out(&self, cmd: &Command, timeout_secs: u64, stdout: &[u8]) -> ShardError {
+        let args: Vec<String> = cmd.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let hint = match (self.timeout, Self::is_install(&args)) {
+            (Some(_), _) => "Pass a larger `--timeout`".to_string(),
+            (None, install) => format!("Raise `{}` in the [shard] config or pass `--timeout <seconds>`",
+                if install { "install_timeout_secs" } else { "query_timeout_secs" }),
+        };
+        let progress = String::from_utf8_lossy(stdout);
+        let packages = match PROGRESS_REGEX.captures_iter(&progress).last() {
+            Some(captures) => vec![captures[1].to_string()],
+            None => args.iter().skip(1).filter(|arg| !arg.starts_with('-')).map(|arg| arg.to_string()).collect(),
+        };
+        ShardError::BrewTimeout { command: args.join(" "), packages, seconds: timeout_secs, hint }
+    }
+    
+    /// Whether a failed command may succeed when run again
+    fn is_transient(result: &ShardResult<std::process::Output>) -> bool {
+        match result {
+            Ok(output) if !output.status.success() => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                DOWNLOAD_FAILURES.iter().any(|marker| stderr.contains(marker))
+            }
+            Ok(_) => false,
+            Err(e) => matches!(e, ShardError::BrewTimeout { .. }),
+        }
+    }
+    
+    /// Run a brew command with the timeout that applies to it
+    ///
+    /// Commands under the install timeout that timed out or failed to download
+    /// are run again up to the configured number of retries.
+    fn run(&self, args: &[&str]) -> ShardResult<std::process::Output> {
+        let mut attempt = 0;
+        loop {
+            let mut cmd = self.command();
+            cmd.args(args);
+            let result = match self.timeout_for(args) {
+                Some(timeout_secs) => self.execute_with_timeout(&mut cmd, timeout_secs),
+                None => cmd.output()
+                    .with_context(|| format!("Failed to execute brew command: {:?}", args))
+                    .map_err(ShardError::from),
+            };
+            
+            if attempt >= self.retries || !Self::is_install(args) || !Self::is_transient(&result) {
+                return result;
+            }
+            attempt += 1;
+            let reason = if result.is_err() { "timed out" } else { "failed to download" };
+            log_warning(&format!("`brew {}` {}, retrying ({}/{})", args.join(" "), reason, attempt, self.retries));
+            thread::sleep(RETRY_DELAY);
+        }
+    }
+    
+    /// Create a brew command with the extra environment applied
+    fn command(&self) -> Command {
         let mut cmd = Command::new(&self.brew_path);
-        for arg in args {
-            cmd.arg(arg);
+        if self.no_auto_update {
+            cmd.env("HOMEBREW_NO_AUTO_UPDATE", "1");
+        }
+        if self.no_env_hints {
+            cmd.env("HOMEBREW_NO_ENV_HINTS", "1");
+        }
+        // Explicit variables may still override the flags
+        cmd.envs(self.env.iter().map(|(name, value)| (name, value)));
+        
+        if self.debug && !self.env.is_empty() {
+            // Only names, values may be credentials
+            let names: Vec<&str> = self.env.iter().map(|(name, _)| name.as_str()).collect();
+            log_trace(&format!("Injecting environment: {}", names.join(", ")));
         }
         
+        cmd
+    }
+    
+    /// Execute a brew command and return its output if successful
+    pub fn execute_brew_command(&self, args: &[&str]) -> ShardResult<std::process::Output> {
         if self.debug {
             let cmd_str = format!("{} {}", self.brew_path, args.join(" "));
-            eprintln!("Executing: {}", cmd_str);
-        }
-        
-        // If timeout is set, use the timeout approach
-        if let Some(timeout_secs) = self.timeout {
-            self.execute_with_timeout(&mut cmd, timeout_secs)
-        } else {
-            // Otherwise use the standard approach
-            let output = cmd.output()
-                .context(format!("Failed to execute brew command: {:?}", args))?;
-                
-            self.process_output(&output, args);
-            
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(crate::utils::ShardError::BrewError(
-                    format!("Error executing brew command {:?}: {}", args, stderr)
-                ));
-            }
-            
-            Ok(output)
+            log_trace(&format!("Executing: {}", cmd_str));
+        }
+        
+        let output = self.run(args)?;
+        
+        self.process_output(&output, args);
+        
+        if !output.status.success() {
+            return Err(Self::failed(&args.join(" "), &output));
         }
+        
+        Ok(output)
     }
     
     /// Process and optionally log command output
@@ -104,7 +316,7 @@ impl BrewCore {
             }
             
             if !debug_output.is_empty() {
-                eprintln!("Command output:\n{}", debug_output);
+                log_trace(&format!("Command output:\n{}", debug_output));
             }
         }
         
@@ -120,6 +332,11 @@ impl BrewCore {
         // Start the child process
         let mut child = cmd.spawn()
             .context("Failed to spawn command")?;
+
+        // Drain both pipes while the child runs. A child writing more than the
+        // pipe buffer would otherwise block forever and never exit.
+        let stdout = Self::spawn_reader(child.stdout.take());
+        let stderr = Self::spawn_reader(child.stderr.take());
             
         // Track the start time
         let start = Instant::now();
@@ -131,54 +348,66 @@ impl BrewCore {
             match child.try_wait() {
                 Ok(Some(status)) => {
                     // Process finished, collect output
-                    let output = self.collect_child_output(child, status)?;
-                    return Ok(output);
+                    return Ok(std::process::Output {
+                        status,
+                        stdout: Self::join_reader(stdout, "stdout")?,
+                        stderr: Self::join_reader(stderr, "stderr")?,
+                    });
                 }
                 Ok(None) => {
                     // Still running, check for timeout
                     if start.elapsed() > timeout {
                         if self.debug {
-                            eprintln!("Command timed out after {} seconds", timeout_secs);
+                            log_trace(&format!("Command timed out after {} seconds", timeout_secs));
                         }
                         
-                        // Kill the process
+                        // Kill and reap the process. The readers are detached since
+                        // processes spawned by the child may still hold the pipes.
                         let _ = child.kill();
-                        return Err(crate::utils::ShardError::BrewError(
-                            format!("Command timed out after {} seconds", timeout_secs)
-                        ));
+                        let _ = child.wait();
+                        let progress = stdout.0.lock().unwrap_or_else(PoisonError::into_inner).clone();
+                        return Err(self.timed_out(cmd, timeout_secs, &progress));
                     }
                     
                     // Sleep briefly to avoid high CPU usage
                     thread::sleep(Duration::from_millis(100));
                 }
-                Err(e) => return Err(crate::utils::ShardError::BrewError(
+                Err(e) => return Err(ShardError::BrewError(
                     format!("Error waiting for process: {}", e)
                 )),
             }
         }
     }
     
-    /// Collect output from a child process
-    pub fn collect_child_output(&self, mut child: Child, status: std::process::ExitStatus) -> ShardResult<std::process::Output> {
-        // Read stdout and stderr
-        let mut stdout = Vec::new();
-        let mut stderr = Vec::new();
-        
-        if let Some(mut stdout_pipe) = child.stdout.take() {
-            stdout_pipe.read_to_end(&mut stdout)
-                .context("Failed to read stdout")?;
-        }
-        
-        if let Some(mut stderr_pipe) = child.stderr.take() {
-            stderr_pipe.read_to_end(&mut stderr)
-                .context("Failed to read stderr")?;
-        }
-        
-        Ok(std::process::Output {
-            status,
-            stdout,
-            stderr,
-        })
+    /// Read a child pipe to the end on a separate thread
+    ///
+    /// What was read so far is in the buffer while the child runs.
+    fn spawn_reader<R: Read + Send + 'static>(pipe: Option<R>) -> Reader {
+        let buffer = Buffer::default();
+        let shared = Arc::clone(&buffer);
+        let handle = thread::spawn(move || {
+            let Some(mut pipe) = pipe else {
+                return Ok(());
+            };
+            let mut chunk = [0; 8192];
+            loop {
+                match pipe.read(&mut chunk) {
+                    Ok(0) => return Ok(()),
+                    Ok(read) => shared.lock().unwrap_or_else(PoisonError::into_inner).extend_from_slice(&chunk[..read]),
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        });
+        (buffer, handle)
+    }
+
+    /// Wait for a reader started by `spawn_reader` and return what it read
+    fn join_reader((buffer, reader): Reader, name: &str) -> ShardResult<Vec<u8>> {
+        reader.join()
+            .map_err(|_| ShardError::BrewError(format!("Reader thread for {} panicked", name)))?
+            .with_context(|| format!("Failed to read {}", name))?;
+        Ok(std::mem::take(&mut *buffer.lock().unwrap_or_else(PoisonError::into_inner)))
     }
     
     /// Execute a brew command with custom arguments and return its output if successful
@@ -189,49 +418,84 @@ impl BrewCore {
     /// properly validated by the caller. Unvalidated user input should never be passed
     /// directly to this method as it could lead to command injection vulnerabilities.
     pub fn execute_brew_command_with_args(&self, base_args: &[&str], extra_args: &[&str]) -> ShardResult<std::process::Output> {
-        let mut cmd = Command::new(&self.brew_path);
-        
-        // Add base arguments
-        for arg in base_args {
-            cmd.arg(arg);
-        }
-        
-        // Add extra arguments
-        for arg in extra_args {
-            cmd.arg(arg);
-        }
-        
         if self.debug {
             let mut cmd_str = format!("{} {}", self.brew_path, base_args.join(" "));
             for arg in extra_args {
                 write!(cmd_str, " {}", arg).unwrap();
             }
-            eprintln!("Executing: {}", cmd_str);
+            log_trace(&format!("Executing: {}", cmd_str));
         }
         
         let cmd_str = format!("{} {}", self.brew_path, base_args.join(" "));
-        
-        // If timeout is set, use the timeout approach
-        let output = if let Some(timeout_secs) = self.timeout {
-            self.execute_with_timeout(&mut cmd, timeout_secs)?
-        } else {
-            // Otherwise use the standard approach
-            cmd.output()
-                .context(format!("Failed to execute command: {}", cmd_str))?
-        };
+        let output = self.run(&[base_args, extra_args].concat())?;
         
         self.process_output(&output, &cmd_str);
         
         if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(crate::utils::ShardError::BrewError(
-                format!("Error executing command {}: {}", cmd_str, stderr)
-            ));
+            let mut command = base_args.join(" ");
+            for arg in extra_args {
+                write!(command, " {}", arg).unwrap();
+            }
+            return Err(Self::failed(&command, &output));
+        }
+        
+        Ok(output)
+    }
+    
+    /// Execute a brew command and return its output regardless of exit status
+    ///
+    /// Used for commands like `brew doctor` that report findings through a
+    /// non-zero exit code.
+    pub fn execute_brew_command_unchecked(&self, args: &[&str]) -> ShardResult<std::process::Output> {
+        if self.debug {
+            log_trace(&format!("Executing: {} {}", self.brew_path, args.join(" ")));
         }
         
+        let output = self.run(args)?;
+        
+        self.process_output(&output, args);
         Ok(output)
     }
     
+    /// Execute a brew command attached to the terminal and return its exit status
+    ///
+    /// Output is not captured, brew writes straight to the terminal. The
+    /// configured timeouts apply as for captured commands, except to
+    /// interactive ones like `brew edit` or `brew sh`, which run as long as
+    /// the user works with them and only stop on a timeout set with
+    /// [`with_timeout`](Self::with_timeout).
+    pub fn execute_passthrough(&self, args: &[&str]) -> ShardResult<ExitStatus> {
+        let mut cmd = self.command();
+        cmd.args(args);
+        
+        if self.debug {
+            log_trace(&format!("Executing: {} {}", self.brew_path, args.join(" ")));
+        }
+        
+        let mut child = cmd.spawn()
+            .context(format!("Failed to execute brew command: {:?}", args))?;
+        let Some(timeout_secs) = self.passthrough_timeout_for(args) else {
+            return Ok(child.wait().context("Failed to wait for brew")?);
+        };
+        
+        let start = Instant::now();
+        let timeout = Duration::from_secs(timeout_secs);
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) => return Ok(status),
+                Ok(None) if start.elapsed() > timeout => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(self.timed_out(&cmd, timeout_secs, &[]));
+                }
+                Ok(None) => thread::sleep(Duration::from_millis(100)),
+                Err(e) => return Err(ShardError::BrewError(
+                    format!("Error waiting for process: {}", e)
+                )),
+            }
+        }
+    }
+    
     /// Parse command output into a list of strings
     pub fn parse_list_output(&self, output: std::process::Output) -> Vec<String> {
         let stdout = String::from_utf8_lossy(&output.stdout);
@@ -246,4 +510,107 @@ impl BrewCore {
 /// Utility function to get a default brew core instance
 pub fn get_core() -> BrewCore {
     BrewCore::new()
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// More than the 64KB a pipe buffers on macOS and Linux
+    const LARGE_OUTPUT: usize = 200_000;
+
+    fn shell(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        cmd
+    }
+
+    #[test]
+    fn large_output_does_not_deadlock_under_timeout() {
+        let core = BrewCore::with_path("sh".to_string());
+        let script = format!("head -c {0} /dev/zero; head -c {0} /dev/zero >&2", LARGE_OUTPUT);
+
+        let started = Instant::now();
+        let output = core.execute_with_timeout(&mut shell(&script), 10).unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout.len(), LARGE_OUTPUT);
+        assert_eq!(output.stderr.len(), LARGE_OUTPUT);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn child_is_stopped_after_timeout() {
+        let core = BrewCore::with_path("sh".to_string());
+
+        let started = Instant::now();
+        let result = core.execute_with_timeout(&mut shell("sleep 30"), 1);
+
+        assert!(result.unwrap_err().to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn timeout_names_the_package_in_progress() {
+        let core = BrewCore::with_path("sh".to_string());
+        let script = "echo '==> Fetching wget'; echo '==> Installing jq dependency: oniguruma'; sleep 30";
+
+        let error = core.execute_with_timeout(&mut shell(script), 1).unwrap_err();
+
+        match error {
+            ShardError::BrewTimeout { packages, .. } => assert_eq!(packages, ["oniguruma"]),
+            other => panic!("expected a timeout, got {}", other),
+        }
+    }
+
+    #[test]
+    fn timeout_without_progress_names_the_packages_of_the_command() {
+        let core = BrewCore::new();
+        let mut cmd = Command::new("brew");
+        cmd.args(["install", "--formula", "wget", "jq"]);
+
+        let error = core.timed_out(&cmd, 1800, b"==> Downloading https://ghcr.io/v2/homebrew/core/wget\n");
+
+        assert_eq!(error.to_string(),
+            "`brew install --formula wget jq` timed out after 1800 seconds on wget, jq. \
+             Raise `install_timeout_secs` in the [shard] config or pass `--timeout <seconds>`");
+    }
+
+    #[test]
+    fn passthrough_stops_on_the_configured_timeout() {
+        // `sh -c` stands in for a brew command that is not interactive
+        let core = BrewCore::with_path("sh".to_string()).with_timeouts(0, 1);
+
+        let started = Instant::now();
+        let result = core.execute_passthrough(&["-c", "sleep 30"]);
+
+        assert!(matches!(result, Err(ShardError::BrewTimeout { seconds: 1, .. })));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn interactive_passthrough_only_stops_on_an_explicit_timeout() {
+        let core = BrewCore::new().with_timeouts(1800, 60);
+
+        assert_eq!(core.passthrough_timeout_for(&["edit", "wget"]), None);
+        assert_eq!(core.passthrough_timeout_for(&["services", "list"]), Some(60));
+        assert_eq!(core.passthrough_timeout_for(&["install", "wget"]), Some(1800));
+        assert_eq!(core.with_timeout(5).passthrough_timeout_for(&["sh"]), Some(5));
+    }
+
+    #[test]
+    fn only_timeouts_and_failed_downloads_are_retried() {
+        use std::os::unix::process::ExitStatusExt;
+        let failed = |stderr: &str| Ok(std::process::Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        });
+        let timeout = ShardError::BrewTimeout { command: "install wget".to_string(), packages: Vec::new(), seconds: 1, hint: String::new() };
+
+        assert!(BrewCore::is_transient(&failed("curl: (6) Could not resolve host: ghcr.io\nError: wget: Failed to download resource \"wget\"")));
+        assert!(BrewCore::is_transient(&Err(timeout)));
+        assert!(!BrewCore::is_transient(&failed("Error: No available formula with the name \"wgett\".")));
+        assert!(!BrewCore::is_transient(&Err(ShardError::BrewError("spawn failed".to_string()))));
+    }
+}
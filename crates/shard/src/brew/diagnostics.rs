@@ -0,0 +1,134 @@
+//! Homebrew environment diagnostics.
+//!
+//! This module wraps `brew doctor` and `brew config` and parses their output into
+//! structured data. Like searching, these operations only inspect the local system
+//! and never modify it.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use crate::ShardResult;
+use crate::brew::core::BrewCore;
+use crate::brew::validate as validation;
+
+/// A single warning reported by `brew doctor`
+#[derive(Debug, Clone)]
+pub struct DoctorWarning {
+    /// First line of the warning, without the "Warning:" prefix
+    pub title: String,
+    /// Remaining lines of the warning, trimmed
+    pub details: Vec<String>,
+}
+
+/// A problem reported by `brew audit`
+#[derive(Debug, Clone)]
+pub struct AuditProblem {
+    /// Formula the problem was found in
+    pub name: String,
+    pub message: String,
+}
+
+/// Runs read-only diagnostics against the Homebrew installation
+#[derive(Clone)]
+pub struct BrewDiagnostics {
+    core: BrewCore,
+}
+
+impl Default for BrewDiagnostics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BrewDiagnostics {
+    /// Create new diagnostics with default brew core
+    pub fn new() -> Self {
+        Self {
+            core: BrewCore::new(),
+        }
+    }
+
+    /// Create new diagnostics with a custom brew core
+    pub fn with_core(core: BrewCore) -> Self {
+        Self { core }
+    }
+
+    /// Run `brew doctor` and return its warnings
+    ///
+    /// `brew doctor` exits with a non-zero status whenever it has warnings, so
+    /// the exit status is ignored and the output is parsed instead.
+    pub fn doctor(&self) -> ShardResult<Vec<DoctorWarning>> {
+        let output = self.core.execute_brew_command_unchecked(&["doctor"])?;
+        let text = format!("{}\n{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr));
+
+        let mut warnings: Vec<DoctorWarning> = Vec::new();
+        for line in text.lines() {
+            if let Some(title) = line.strip_prefix("Warning:") {
+                warnings.push(DoctorWarning {
+                    title: title.trim().to_string(),
+                    details: Vec::new(),
+                });
+            } else if let Some(current) = warnings.last_mut() {
+                let detail = line.trim();
+                if !detail.is_empty() {
+                    current.details.push(detail.to_string());
+                }
+            }
+        }
+
+        Ok(warnings)
+    }
+
+    /// Run `brew audit` on formulae and return the problems it finds
+    ///
+    /// Like `brew doctor`, `brew audit` exits with a non-zero status when it
+    /// finds problems, so the output is parsed instead. Problems are listed
+    /// as `* message` below a `name:` line.
+    pub fn audit(&self, formulae: &[String]) -> ShardResult<Vec<AuditProblem>> {
+        let mut args = vec!["audit", "--formula"];
+        for formula in formulae {
+            args.push(validation::validate_package_name(formula)?);
+        }
+        if args.len() == 2 {
+            return Ok(Vec::new());
+        }
+
+        let output = self.core.execute_brew_command_unchecked(&args)?;
+        let text = format!("{}\n{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr));
+
+        let mut problems = Vec::new();
+        let mut current: Option<&str> = None;
+        for line in text.lines() {
+            if let Some(message) = line.trim().strip_prefix("* ") {
+                if let Some(name) = current {
+                    problems.push(AuditProblem { name: name.to_string(), message: message.to_string() });
+                }
+            } else if !line.starts_with(char::is_whitespace) && let Some(name) = line.strip_suffix(':') {
+                current = formulae.iter().any(|formula| formula == name).then_some(name);
+            }
+        }
+
+        Ok(problems)
+    }
+
+    /// Run `brew config` and return its key/value pairs
+    pub fn config(&self) -> ShardResult<BTreeMap<String, String>> {
+        let output = self.core.execute_brew_command(&["config"])?;
+        let config = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect();
+        Ok(config)
+    }
+
+    /// Get the local git repository of a tap
+    pub fn get_tap_repository(&self, tap: &str) -> ShardResult<PathBuf> {
+        let validated_tap = validation::validate_tap_name(tap)?;
+        let output = self.core.execute_brew_command(&["--repository", validated_tap])?;
+        Ok(PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+    }
+}
@@ -0,0 +1,252 @@
+//! Common brew failures recognised from stderr.
+//!
+//! brew reports failures as free text, often many lines of it. The patterns
+//! here pick out the failures users run into most and turn them into a short
+//! message with a way out. Anything not recognised is reported with the full
+//! stderr, as before.
+//!
+//! Only `Error:` messages are looked at. brew prints warnings next to the
+//! error that failed a command, e.g. that a dependency is already installed,
+//! and those must not be mistaken for the failure.
+
+use std::fmt;
+use regex::Regex;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    // `No available formula with the name "foo".`, also for casks and both
+    static ref NO_SUCH_PACKAGE_REGEX: Regex = Regex::new(r#"No available (?:formula|cask)(?: or cask)? with the name "([^"]+)""#).unwrap();
+
+    // `No formulae or casks found for foo.` after brew searched for similar names
+    static ref NOTHING_FOUND_REGEX: Regex = Regex::new(r"(?m)No formulae or casks found for (\S+?)\.?$").unwrap();
+
+    // `Cask 'foo' is unavailable: No Cask with this name exists.`
+    static ref NO_SUCH_CASK_REGEX: Regex = Regex::new(r"Cask '([^']+)' is unavailable: No Cask with this name exists").unwrap();
+
+    // `File: /path/to/download` below a `SHA256 mismatch`
+    static ref CHECKSUM_FILE_REGEX: Regex = Regex::new(r"(?m)^\s*File: (.+)$").unwrap();
+
+    // Ruby's `Permission denied @ dir_s_mkdir - /usr/local/Frameworks`, also
+    // below an error like `An exception occurred within a postinstall step:`
+    static ref PERMISSION_DENIED_REGEX: Regex = Regex::new(r"Permission denied @ \w+ - (\S+)").unwrap();
+
+    // First path below `The following directories are not writable by your user:`
+    static ref NOT_WRITABLE_REGEX: Regex = Regex::new(r"are not writable by your user:\s*\n\s*(\S+)").unwrap();
+
+    // `It seems there is already an App at '/Applications/Foo.app'.`
+    static ref CASK_CONFLICT_REGEX: Regex = Regex::new(r"It seems there is already an? (\w+) at '([^']+)'").unwrap();
+
+    // `Error: Cask 'foo' is already installed.` or `Error: foo 1.2 is already installed`
+    static ref ALREADY_INSTALLED_REGEX: Regex = Regex::new(r"(?m)^Error: (?:(?:Cask|Formula) '([^']+)' is already installed|(\S+) \S+ is already installed)").unwrap();
+}
+
+/// A brew failure recognised from its stderr
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BrewFailure {
+    /// No formula or cask has the name
+    NoSuchPackage { name: String },
+    /// A download does not match its checksum, `file` is the cached download
+    ChecksumMismatch { file: Option<String> },
+    /// brew cannot write to a path, usually a Homebrew directory owned by another user
+    PermissionDenied { path: String },
+    /// A cask artifact, e.g. an `App` or `Binary`, already exists where the cask installs it
+    CaskConflict { artifact: String, path: String },
+    /// The package is installed already
+    AlreadyInstalled { name: String },
+}
+
+impl BrewFailure {
+    /// Recognise a failure in the `Error:` messages of a brew command's stderr
+    pub fn parse(stderr: &str) -> Option<Self> {
+        let stderr = &errors(stderr);
+        if stderr.is_empty() {
+            return None;
+        }
+        
+        if let Some(captures) = NO_SUCH_PACKAGE_REGEX.captures(stderr)
+            .or_else(|| NO_SUCH_CASK_REGEX.captures(stderr))
+            .or_else(|| NOTHING_FOUND_REGEX.captures(stderr)) {
+            return Some(BrewFailure::NoSuchPackage { name: captures[1].to_string() });
+        }
+        if stderr.contains("SHA256 mismatch") || (stderr.contains("Checksum") && stderr.contains("does not match")) {
+            let file = CHECKSUM_FILE_REGEX.captures(stderr).map(|captures| captures[1].trim().to_string());
+            return Some(BrewFailure::ChecksumMismatch { file });
+        }
+        if let Some(captures) = PERMISSION_DENIED_REGEX.captures(stderr).or_else(|| NOT_WRITABLE_REGEX.captures(stderr)) {
+            return Some(BrewFailure::PermissionDenied { path: captures[1].to_string() });
+        }
+        if let Some(captures) = CASK_CONFLICT_REGEX.captures(stderr) {
+            return Some(BrewFailure::CaskConflict { artifact: captures[1].to_string(), path: captures[2].to_string() });
+        }
+        if let Some(captures) = ALREADY_INSTALLED_REGEX.captures(stderr) {
+            let name = captures.get(1).or_else(|| captures.get(2)).map_or("", |name| name.as_str());
+            return Some(BrewFailure::AlreadyInstalled { name: name.to_string() });
+        }
+        None
+    }
+
+    /// What to do about the failure
+    pub fn suggestion(&self) -> String {
+        match self {
+            BrewFailure::NoSuchPackage { name } => format!(
+                "Check the name with `shard search {}`, it may have been renamed or moved to a tap", name),
+            BrewFailure::ChecksumMismatch { file: Some(file) } => format!(
+                "Remove {} and retry, if it keeps failing the upstream download changed", file),
+            BrewFailure::ChecksumMismatch { file: None } =>
+                "Run `brew cleanup` and retry, if it keeps failing the upstream download changed".to_string(),
+            BrewFailure::PermissionDenied { path } => format!(
+                "Make {} writable by your user, e.g. `sudo chown -R $(whoami) {}`", path, path),
+            BrewFailure::CaskConflict { path, .. } => format!(
+                "Remove {} or adopt it with `shard brew install --cask --adopt <cask>`", path),
+            BrewFailure::AlreadyInstalled { .. } => "Nothing to do".to_string(),
+        }
+    }
+}
+
+/// The `Error:` messages of brew's stderr with the lines that follow them
+///
+/// A message ends at the next warning or `==>` heading, so details like the
+/// `File:` of a checksum mismatch stay with their error.
+fn errors(stderr: &str) -> String {
+    let mut errors = String::new();
+    let mut in_error = false;
+    for line in stderr.lines() {
+        if line.starts_with("Error:") {
+            in_error = true;
+        } else if line.starts_with("Warning:") || line.starts_with("==>") {
+            in_error = false;
+        }
+        if in_error {
+            errors.push_str(line);
+            errors.push('\n');
+        }
+    }
+    errors
+}
+
+impl fmt::Display for BrewFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrewFailure::NoSuchPackage { name } => write!(f, "no formula or cask named '{}'", name)?,
+            BrewFailure::ChecksumMismatch { .. } => write!(f, "the download does not match its checksum")?,
+            BrewFailure::PermissionDenied { path } => write!(f, "permission denied for {}", path)?,
+            BrewFailure::CaskConflict { artifact, path } => write!(f, "{} {} already exists", artifact, path)?,
+            BrewFailure::AlreadyInstalled { name } if name.is_empty() => write!(f, "already installed")?,
+            BrewFailure::AlreadyInstalled { name } => write!(f, "{} is already installed", name)?,
+        }
+        write!(f, ". {}", self.suggestion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_such_formula() {
+        let stderr = "Error: No available formula with the name \"wgett\". Did you mean wget?\n";
+        assert_eq!(BrewFailure::parse(stderr), Some(BrewFailure::NoSuchPackage { name: "wgett".to_string() }));
+    }
+
+    #[test]
+    fn nothing_found_after_searching() {
+        let stderr = "\
+Warning: No available formula with the name \"nosuchtool\".
+==> Searching for similarly named formulae and casks...
+Error: No formulae or casks found for nosuchtool.
+";
+        assert_eq!(BrewFailure::parse(stderr), Some(BrewFailure::NoSuchPackage { name: "nosuchtool".to_string() }));
+    }
+
+    #[test]
+    fn checksum_mismatch_keeps_the_file() {
+        let stderr = "\
+Error: wget: SHA256 mismatch
+Expected: 4a5e1ba3f3b5bd6ac2a41b96a5ad4c0a40fe2b4c56a5f1b4b23f4c2b8f1a4d1e
+  Actual: 9b1c0ee2f5b3a4f52d3c9d5e8b0b7a3f1e2d4c6b8a0f1e3d5c7b9a1f3e5d7c9b
+    File: /Users/me/Library/Caches/Homebrew/downloads/1f3e--wget--1.24.5.arm64_sonoma.bottle.tar.gz
+To retry an incomplete download, remove the file above.
+";
+        assert_eq!(BrewFailure::parse(stderr), Some(BrewFailure::ChecksumMismatch {
+            file: Some("/Users/me/Library/Caches/Homebrew/downloads/1f3e--wget--1.24.5.arm64_sonoma.bottle.tar.gz".to_string()),
+        }));
+    }
+
+    #[test]
+    fn directories_not_writable() {
+        let stderr = "\
+Error: The following directories are not writable by your user:
+/usr/local/share/man/man8
+
+You should change the ownership of these directories to your user.
+  sudo chown -R $(whoami) /usr/local/share/man/man8
+";
+        assert_eq!(BrewFailure::parse(stderr), Some(BrewFailure::PermissionDenied { path: "/usr/local/share/man/man8".to_string() }));
+    }
+
+    #[test]
+    fn permission_denied_below_an_error() {
+        let stderr = "\
+Error: An exception occurred within a postinstall step:
+  Errno::EACCES: Permission denied @ dir_s_mkdir - /usr/local/etc/openssl@3
+";
+        assert_eq!(BrewFailure::parse(stderr), Some(BrewFailure::PermissionDenied { path: "/usr/local/etc/openssl@3".to_string() }));
+    }
+
+    #[test]
+    fn cask_conflict() {
+        let stderr = "\
+==> Installing Cask visual-studio-code
+Error: It seems there is already an App at '/Applications/Visual Studio Code.app'.
+==> Purging files for version 1.89.1 of Cask visual-studio-code
+";
+        assert_eq!(BrewFailure::parse(stderr), Some(BrewFailure::CaskConflict {
+            artifact: "App".to_string(),
+            path: "/Applications/Visual Studio Code.app".to_string(),
+        }));
+    }
+
+    #[test]
+    fn already_installed_error() {
+        let stderr = "Error: Cask 'firefox' is already installed.\n";
+        assert_eq!(BrewFailure::parse(stderr), Some(BrewFailure::AlreadyInstalled { name: "firefox".to_string() }));
+    }
+
+    #[test]
+    fn warnings_alone_are_no_failure() {
+        let stderr = "\
+Warning: jq 1.7.1 is already installed and up-to-date.
+To reinstall 1.7.1, run:
+  brew reinstall jq
+Warning: Permission denied @ rb_file_s_rename - /usr/local/var/homebrew/locks
+";
+        assert_eq!(BrewFailure::parse(stderr), None);
+    }
+
+    #[test]
+    fn warnings_next_to_an_error_are_ignored() {
+        let stderr = "\
+Warning: dep 1.2 is already installed and up-to-date.
+To reinstall 1.2, run:
+  brew reinstall dep
+Error: No available formula with the name \"badpkg\".
+";
+        assert_eq!(BrewFailure::parse(stderr), Some(BrewFailure::NoSuchPackage { name: "badpkg".to_string() }));
+
+        let stderr = "\
+Warning: Permission denied @ rb_file_s_rename - /usr/local/var/homebrew/locks
+Warning: Cask 'iterm2' is already installed.
+Error: It seems there is already a Binary at '/usr/local/bin/code'.
+";
+        assert_eq!(BrewFailure::parse(stderr), Some(BrewFailure::CaskConflict {
+            artifact: "Binary".to_string(),
+            path: "/usr/local/bin/code".to_string(),
+        }));
+    }
+
+    #[test]
+    fn unrecognised_errors_are_left_to_the_caller() {
+        let stderr = "Error: Failure while executing; `/usr/bin/sudo -u root -- /bin/rm -rf /Applications/Foo.app` exited with 1.\n";
+        assert_eq!(BrewFailure::parse(stderr), None);
+    }
+}
@@ -4,17 +4,214 @@
 //! such as installing, uninstalling, updating, and upgrading packages. It ensures that
 //! all user inputs are properly validated before execution to prevent command injection.
 
-use crate::ShardResult;
+use std::collections::{BTreeMap, HashSet};
+use serde::Deserialize;
+use sapphire_core::version::{Change, Version};
+use crate::{ShardError, ShardResult};
 use crate::brew::core::BrewCore;
+use crate::brew::failure::BrewFailure;
 use crate::brew::validate as validation;
-use crate::utils::{log_warning, log_error};
+use crate::utils::{ResultExt, log_debug, log_warning, log_error};
 
 /// Handles installation, uninstallation, updates, and other operations
 /// that modify the local package state
+#[derive(Clone)]
 pub struct BrewInstaller {
     core: BrewCore,
 }
 
+/// A single outdated package as reported by `brew outdated --json=v2`
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutdatedPackage {
+    pub name: String,
+    #[serde(default)]
+    pub installed_versions: Vec<String>,
+    #[serde(default)]
+    pub current_version: String,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub pinned_version: Option<String>,
+}
+
+impl OutdatedPackage {
+    /// The most recent locally installed version
+    pub fn installed_version(&self) -> &str {
+        self.installed_versions.last().map(String::as_str).unwrap_or("unknown")
+    }
+
+    /// How big the upgrade from the installed to the current version is
+    pub fn change(&self) -> Change {
+        Version::parse(self.installed_version()).change_to(&Version::parse(&self.current_version))
+    }
+}
+
+/// Outdated formulae and casks, mirroring the layout of `brew outdated --json=v2`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OutdatedReport {
+    #[serde(default)]
+    pub formulae: Vec<OutdatedPackage>,
+    #[serde(default)]
+    pub casks: Vec<OutdatedPackage>,
+}
+
+impl OutdatedReport {
+    /// Names of the outdated packages, for lookups without scanning the report
+    pub fn to_set(&self) -> OutdatedSet {
+        let packages = || self.formulae.iter().chain(&self.casks);
+        OutdatedSet {
+            outdated: packages().map(|package| package.name.clone()).collect(),
+            pinned: packages().filter(|package| package.pinned).map(|package| package.name.clone()).collect(),
+        }
+    }
+}
+
+/// Outdated formulae and casks of one `brew outdated` call
+///
+/// Plans upgrade every installed package, asking brew per package would spawn
+/// a process for each of them.
+#[derive(Debug, Clone, Default)]
+pub struct OutdatedSet {
+    outdated: HashSet<String>,
+    pinned: HashSet<String>,
+}
+
+impl OutdatedSet {
+    /// Whether a newer version of the package is available
+    pub fn is_outdated(&self, name: &str) -> bool {
+        self.outdated.contains(name)
+    }
+
+    /// Whether upgrading the package would change it, outdated and not pinned
+    pub fn is_upgradable(&self, name: &str) -> bool {
+        self.is_outdated(name) && !self.pinned.contains(name)
+    }
+}
+
+/// Result of every package of a batch operation, in the order they were given
+pub type BatchResults = Vec<ShardResult<()>>;
+
+/// A package that still failed after a batch was retried package by package
+#[derive(Debug, Clone)]
+pub struct BatchFailure {
+    pub name: String,
+    pub error: String,
+}
+
+impl BatchFailure {
+    /// Packages of a batch operation that failed, logging each
+    ///
+    /// Packages brew reports as installed already, also as a binary of a
+    /// cask, are skipped rather than failed.
+    pub fn collect(command: &str, packages: &[String], results: BatchResults) -> Vec<Self> {
+        packages.iter()
+            .zip(results)
+            .filter_map(|(package, result)| {
+                let e = result.err()?;
+                let error = e.to_string();
+                let installed = match e.brew_failure() {
+                    Some(BrewFailure::AlreadyInstalled { .. }) => true,
+                    Some(BrewFailure::CaskConflict { artifact, .. }) => artifact == "Binary",
+                    _ => false,
+                };
+                if installed {
+                    log_warning(&format!("Skipping {}: {}", package, error));
+                    None
+                } else {
+                    log_error(&format!("Error running brew {} {}: {}", command, package, error));
+                    Some(BatchFailure { name: package.clone(), error })
+                }
+            })
+            .collect()
+    }
+}
+
+/// How an installed formula was built
+#[derive(Debug, Clone)]
+pub struct InstalledBuild {
+    pub name: String,
+    /// Built from the HEAD of the repository
+    pub head: bool,
+    /// Compiled locally instead of poured from a bottle
+    pub from_source: bool,
+}
+
+/// An installed formula or cask and the formulae it needs at runtime
+#[derive(Debug, Clone)]
+pub struct InstalledDependencies {
+    pub name: String,
+    /// Formulae needed at runtime, including indirect ones
+    pub dependencies: Vec<String>,
+    /// Installed only to satisfy other packages, so `brew autoremove` removes
+    /// it once nothing needs it anymore
+    pub as_dependency: bool,
+}
+
+/// Whether an installed formula is linked into the Homebrew prefix
+#[derive(Debug, Clone)]
+pub struct LinkState {
+    pub name: String,
+    pub linked: bool,
+    /// Not linked on install since it would shadow software of macOS or
+    /// another formula, `brew link` needs `--force`
+    pub keg_only: bool,
+}
+
+/// Subset of `brew info --json=v2 --installed`
+#[derive(Deserialize)]
+struct InstalledInfo {
+    #[serde(default)]
+    formulae: Vec<InstalledFormulaInfo>,
+    #[serde(default)]
+    casks: Vec<InstalledCaskInfo>,
+}
+
+#[derive(Deserialize)]
+struct InstalledFormulaInfo {
+    name: String,
+    #[serde(default)]
+    installed: Vec<InstalledKeg>,
+    /// Version linked into the prefix, `None` if unlinked
+    #[serde(default)]
+    linked_keg: Option<String>,
+    #[serde(default)]
+    keg_only: bool,
+}
+
+#[derive(Deserialize)]
+struct InstalledCaskInfo {
+    token: String,
+    #[serde(default)]
+    depends_on: CaskDependsOn,
+    #[serde(default)]
+    version: String,
+    /// `null` for casks that do not declare it
+    #[serde(default)]
+    auto_updates: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+struct CaskDependsOn {
+    #[serde(default)]
+    formula: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RuntimeDependency {
+    full_name: String,
+}
+
+#[derive(Deserialize)]
+struct InstalledKeg {
+    version: String,
+    #[serde(default)]
+    poured_from_bottle: bool,
+    #[serde(default)]
+    installed_on_request: bool,
+    #[serde(default)]
+    runtime_dependencies: Vec<RuntimeDependency>,
+}
+
 impl BrewInstaller {
     /// Create a new installer with default brew core
     pub fn new() -> Self {
@@ -28,12 +225,20 @@ impl BrewInstaller {
         Self { core }
     }
     
-    /// Add a Homebrew tap
-    pub fn add_tap(&self, tap: &str) -> ShardResult<()> {
-        // Validate tap name before execution
+    /// Add a Homebrew tap, cloned from `url` instead of GitHub if given
+    pub fn add_tap(&self, tap: &str, url: Option<&str>) -> ShardResult<()> {
+        // Validate tap name and URL before execution
         let validated_tap = validation::validate_tap_name(tap)?;
         
-        self.core.execute_brew_command(&["tap", validated_tap])?;
+        match url {
+            Some(url) => {
+                let validated_url = validation::validate_tap_url(url)?;
+                self.core.execute_brew_command(&["tap", validated_tap, validated_url])?;
+            }
+            None => {
+                self.core.execute_brew_command(&["tap", validated_tap])?;
+            }
+        }
         Ok(())
     }
     
@@ -46,7 +251,7 @@ impl BrewInstaller {
         // Create a vector of &str for the options
         let option_strs: Vec<&str> = options.iter().map(AsRef::as_ref).collect();
         
-        self.core.execute_brew_command_with_args(&["install", validated_formula], &option_strs)?;
+        self.core.execute_brew_command_with_args(&["install", "--formula", validated_formula], &option_strs)?;
         Ok(())
     }
     
@@ -63,6 +268,24 @@ impl BrewInstaller {
         Ok(())
     }
 
+    /// Download a formula and its dependencies without installing them
+    pub fn fetch_formula(&self, formula: &str, options: &[String]) -> ShardResult<()> {
+        let validated_formula = validation::validate_package_name(formula)?;
+        validation::validate_options(options)?;
+        
+        let option_strs: Vec<&str> = options.iter().map(AsRef::as_ref).collect();
+        
+        self.core.execute_brew_command_with_args(&["fetch", "--formula", "--deps", validated_formula], &option_strs)?;
+        Ok(())
+    }
+    
+    /// Download a cask without installing it
+    pub fn fetch_cask(&self, cask: &str) -> ShardResult<()> {
+        let validated_cask = validation::validate_package_name(cask)?;
+        self.core.execute_brew_command(&["fetch", "--cask", validated_cask])?;
+        Ok(())
+    }
+
     /// Get a list of all currently installed formulae
     pub fn get_installed_formulae(&self) -> ShardResult<Vec<String>> {
         let output = self.core.execute_brew_command(&["list", "--formula"])?;
@@ -81,128 +304,99 @@ impl BrewInstaller {
         Ok(self.core.parse_list_output(output))
     }
 
-    /// Perform a batch install of multiple formulae at once
+    /// Install multiple formulae with one brew invocation per batch
+    ///
+    /// If a batch fails, every formula in it is retried on its own so one
+    /// broken package does not keep the rest from being installed. Returns
+    /// the result of every formula, in order.
     ///
     /// # Security
     ///
     /// All package names are validated individually before execution
-    pub fn batch_install_formulae(&self, formulae: &[String]) -> ShardResult<()> {
-        if formulae.is_empty() {
-            return Ok(());
-        }
-        
-        // Install formulae one by one for better error handling
-        for formula in formulae {
-            let validated_formula = validation::validate_package_name(formula)?;
-            
-            // Try to install each formula individually
-            let result = self.core.execute_brew_command(&["install", validated_formula]);
-            
-            if let Err(e) = result {
-                // Log the error but continue with other formulae
-                let error_str = e.to_string();
-                if error_str.contains("already installed") {
-                    log_warning(&format!("Skipping {}: {}", formula, error_str));
-                    continue;
-                } else {
-                    log_error(&format!("Error installing {}: {}", formula, error_str));
-                    // Don't fail the entire process for one formula
-                    continue;
-                }
-            }
-        }
-        
-        Ok(())
+    pub fn batch_install_formulae(&self, formulae: &[String]) -> ShardResult<BatchResults> {
+        self.run_batch("install", "--formula", formulae)
     }
 
-    /// Perform a batch install of multiple casks at once
+    /// Install multiple casks with one brew invocation per batch
     ///
-    /// # Security
+    /// Failed batches are retried cask by cask, see [`Self::batch_install_formulae`].
+    pub fn batch_install_casks(&self, casks: &[String]) -> ShardResult<BatchResults> {
+        self.run_batch("install", "--cask", casks)
+    }
+
+    /// Upgrade multiple formulae with one brew invocation per batch
     ///
-    /// All cask names are validated individually before execution
-    pub fn batch_install_casks(&self, casks: &[String]) -> ShardResult<()> {
-        if casks.is_empty() {
-            return Ok(());
-        }
-        
-        // Install casks one by one for better error handling
-        for cask in casks {
-            let validated_cask = validation::validate_package_name(cask)?;
-            
-            // Try to install each cask individually
-            let result = self.core.execute_brew_command(&["install", "--cask", validated_cask]);
-            
-            if let Err(e) = result {
-                // Log the error but continue with other casks
-                if e.to_string().contains("already a Binary at") || 
-                   e.to_string().contains("already installed") {
-                    // If it's already installed or there's a binary conflict, just skip it
-                    log_warning(&format!("Skipping {}: {}", cask, e));
-                    continue;
-                } else {
-                    // For other errors, log but continue
-                    log_error(&format!("Error installing {}: {}", cask, e));
-                    // Don't fail the entire process for one cask
-                    continue;
-                }
-            }
-        }
-        
-        Ok(())
+    /// Failed batches are retried formula by formula, see [`Self::batch_install_formulae`].
+    pub fn batch_upgrade_formulae(&self, formulae: &[String]) -> ShardResult<BatchResults> {
+        self.run_batch("upgrade", "--formula", formulae)
     }
 
-    /// Perform a batch upgrade of multiple formulae at once
+    /// Upgrade multiple casks with one brew invocation per batch
     ///
-    /// # Security
+    /// Failed batches are retried cask by cask, see [`Self::batch_install_formulae`].
+    pub fn batch_upgrade_casks(&self, casks: &[String]) -> ShardResult<BatchResults> {
+        self.run_batch("upgrade", "--cask", casks)
+    }
+
+    /// Run `brew <command> <type_flag>` for all packages in batches
     ///
-    /// All package names are validated individually before execution
-    pub fn batch_upgrade_formulae(&self, formulae: &[String]) -> ShardResult<()> {
-        if formulae.is_empty() {
-            return Ok(());
+    /// Batches hold as many packages as the core's batch size, so a long list
+    /// stays within command line limits and a bad name only fails its batch.
+    /// They run one after another, or at the same time if the core says so.
+    /// Returns the result of every package, in the order of `packages`.
+    fn run_batch(&self, command: &str, type_flag: &str, packages: &[String]) -> ShardResult<BatchResults> {
+        let mut validated = Vec::new();
+        for package in packages {
+            validated.push(validation::validate_package_name(package)?);
         }
-        
-        // Upgrade formulae one by one for better error handling
-        for formula in formulae {
-            let validated_formula = validation::validate_package_name(formula)?;
-            
-            // Attempt to upgrade each formula individually
-            let result = self.core.execute_brew_command(&["upgrade", validated_formula]);
-            
-            if let Err(e) = result {
-                // Log but continue with other formulae
-                log_warning(&format!("Error upgrading {}: {}", formula, e));
-                continue;
-            }
+        if validated.is_empty() {
+            return Ok(Vec::new());
         }
-        
-        Ok(())
-    }
 
-    /// Perform a batch upgrade of multiple casks at once
-    ///
-    /// # Security
-    ///
-    /// All cask names are validated individually before execution
-    pub fn batch_upgrade_casks(&self, casks: &[String]) -> ShardResult<()> {
-        if casks.is_empty() {
-            return Ok(());
+        let size = match self.core.batch_size() {
+            0 => validated.len(),
+            size => size,
+        };
+        let batches: Vec<&[&str]> = validated.chunks(size).collect();
+        if batches.len() > 1 {
+            log_debug(&format!("Running brew {} for {} package(s) in {} batches", command, validated.len(), batches.len()));
         }
-        
-        // Upgrade casks one by one for better error handling
-        for cask in casks {
-            let validated_cask = validation::validate_package_name(cask)?;
-            
-            // Attempt to upgrade each cask individually
-            let result = self.core.execute_brew_command(&["upgrade", "--cask", validated_cask]);
-            
-            if let Err(e) = result {
-                // Log but continue with other casks
-                log_warning(&format!("Error upgrading {}: {}", cask, e));
-                continue;
-            }
+
+        if !self.core.parallel_batches() || batches.len() == 1 {
+            return Ok(batches.into_iter().flat_map(|batch| self.run_single_batch(command, type_flag, batch)).collect());
         }
-        
-        Ok(())
+
+        // Every thread is joined before looking at the results, the scope
+        // would panic itself for a panicked thread left unjoined
+        let joined: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = batches.into_iter()
+                .map(|batch| scope.spawn(move || self.run_single_batch(command, type_flag, batch)))
+                .collect();
+            handles.into_iter().map(|handle| handle.join()).collect()
+        });
+
+        let mut results = Vec::new();
+        for batch in joined {
+            let batch = batch.map_err(|_| ShardError::BrewError(format!(
+                "A thread running brew {} panicked, the packages of its batch are in an unknown state", command)))?;
+            results.extend(batch);
+        }
+        Ok(results)
+    }
+
+    /// Run `brew <command> <type_flag>` for one batch, falling back to one at a time
+    fn run_single_batch(&self, command: &str, type_flag: &str, batch: &[&str]) -> BatchResults {
+        let mut args = vec![command, type_flag];
+        args.extend(batch);
+        if self.core.execute_brew_command(&args).is_ok() {
+            return batch.iter().map(|_| Ok(())).collect();
+        }
+
+        log_warning(&format!("Batch {} failed, retrying {} package(s) one by one", command, batch.len()));
+
+        batch.iter()
+            .map(|package| self.core.execute_brew_command(&[command, type_flag, package]).map(|_| ()))
+            .collect()
     }
 
     /// Upgrade a formula with custom options
@@ -214,7 +408,7 @@ impl BrewInstaller {
         // Create a vector of &str for the options
         let option_strs: Vec<&str> = options.iter().map(AsRef::as_ref).collect();
         
-        self.core.execute_brew_command_with_args(&["upgrade", validated_formula], &option_strs)?;
+        self.core.execute_brew_command_with_args(&["upgrade", "--formula", validated_formula], &option_strs)?;
         Ok(())
     }
 
@@ -261,12 +455,182 @@ impl BrewInstaller {
         Ok(())
     }
 
+    /// Get the installed formulae and casks that directly depend on a formula
+    pub fn get_installed_dependents(&self, formula: &str) -> ShardResult<Vec<String>> {
+        let validated_formula = validation::validate_package_name(formula)?;
+        let output = self.core.execute_brew_command(&["uses", "--installed", validated_formula])?;
+        Ok(self.core.parse_list_output(output))
+    }
+
     /// Get a list of all packages installed as dependencies
     pub fn get_dependency_packages(&self) -> ShardResult<Vec<String>> {
         let output = self.core.execute_brew_command(&["list", "--installed-as-dependency"])?;
         Ok(self.core.parse_list_output(output))
     }
 
+    /// Get all outdated formulae and casks with a single `brew outdated` call
+    ///
+    /// When `greedy` is set, casks that auto-update or use `version :latest`
+    /// are included as well.
+    pub fn get_outdated(&self, greedy: bool) -> ShardResult<OutdatedReport> {
+        let mut args = vec!["outdated", "--json=v2"];
+
+        if greedy {
+            args.push("--greedy");
+        }
+
+        let output = self.core.execute_brew_command(&args)?;
+        let report = serde_json::from_slice(&output.stdout)
+            .with_context(|| "Failed to parse output of brew outdated")?;
+        Ok(report)
+    }
+
+    /// Get the installed casks brew only reports as outdated with `--greedy`
+    ///
+    /// These are casks that update themselves or use `version :latest`.
+    pub fn get_greedy_casks(&self) -> ShardResult<HashSet<String>> {
+        let output = self.core.execute_brew_command(&["info", "--json=v2", "--installed"])?;
+        let info: InstalledInfo = serde_json::from_slice(&output.stdout)
+            .with_context(|| "Failed to parse output of brew info")?;
+
+        Ok(info.casks.into_iter()
+            .filter(|cask| cask.auto_updates == Some(true) || cask.version == "latest")
+            .map(|cask| cask.token)
+            .collect())
+    }
+
+    /// Get the installed version of every formula, or every cask with `cask`
+    pub fn get_installed_versions(&self, cask: bool) -> ShardResult<BTreeMap<String, String>> {
+        let type_flag = if cask { "--cask" } else { "--formula" };
+        let output = self.core.execute_brew_command(&["list", "--versions", type_flag])?;
+
+        // Each line is the name followed by all installed versions, newest last
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let name = fields.next()?.to_string();
+                Some((name, fields.last().unwrap_or_default().to_string()))
+            })
+            .collect())
+    }
+
+    /// Get how every installed formula was built
+    pub fn get_installed_builds(&self) -> ShardResult<Vec<InstalledBuild>> {
+        let output = self.core.execute_brew_command(&["info", "--json=v2", "--installed"])?;
+        let info: InstalledInfo = serde_json::from_slice(&output.stdout)
+            .with_context(|| "Failed to parse output of brew info")?;
+
+        Ok(info.formulae.into_iter()
+            .filter_map(|formula| {
+                let keg = formula.installed.last()?;
+                Some(InstalledBuild {
+                    head: keg.version.starts_with("HEAD"),
+                    from_source: !keg.poured_from_bottle,
+                    name: formula.name,
+                })
+            })
+            .collect())
+    }
+
+    /// Get what every installed formula and cask depends on
+    pub fn get_installed_dependencies(&self) -> ShardResult<Vec<InstalledDependencies>> {
+        let output = self.core.execute_brew_command(&["info", "--json=v2", "--installed"])?;
+        let info: InstalledInfo = serde_json::from_slice(&output.stdout)
+            .with_context(|| "Failed to parse output of brew info")?;
+
+        // Dependencies from taps are named `user/tap/name`, installed formulae by name only
+        let short_name = |name: &str| name.rsplit('/').next().unwrap_or(name).to_string();
+
+        let formulae = info.formulae.into_iter()
+            .filter_map(|formula| {
+                let keg = formula.installed.last()?;
+                Some(InstalledDependencies {
+                    dependencies: keg.runtime_dependencies.iter().map(|dependency| short_name(&dependency.full_name)).collect(),
+                    as_dependency: !keg.installed_on_request,
+                    name: formula.name,
+                })
+            });
+        let casks = info.casks.into_iter()
+            .map(|cask| InstalledDependencies {
+                name: cask.token,
+                dependencies: cask.depends_on.formula.iter().map(|formula| short_name(formula)).collect(),
+                as_dependency: false,
+            });
+        Ok(formulae.chain(casks).collect())
+    }
+
+    /// Get the link state of every installed formula
+    pub fn get_link_states(&self) -> ShardResult<Vec<LinkState>> {
+        let output = self.core.execute_brew_command(&["info", "--json=v2", "--installed"])?;
+        let info: InstalledInfo = serde_json::from_slice(&output.stdout)
+            .with_context(|| "Failed to parse output of brew info")?;
+
+        Ok(info.formulae.into_iter()
+            .map(|formula| LinkState {
+                linked: formula.linked_keg.is_some(),
+                keg_only: formula.keg_only,
+                name: formula.name,
+            })
+            .collect())
+    }
+
+    /// Link a formula into the Homebrew prefix, `force` is required for keg-only formulae
+    pub fn link_formula(&self, formula: &str, force: bool) -> ShardResult<()> {
+        let validated_formula = validation::validate_package_name(formula)?;
+        let mut args = vec!["link"];
+        if force {
+            args.push("--force");
+        }
+        args.push(validated_formula);
+        self.core.execute_brew_command(&args)?;
+        Ok(())
+    }
+
+    /// Remove a formula's links from the Homebrew prefix
+    pub fn unlink_formula(&self, formula: &str) -> ShardResult<()> {
+        let validated_formula = validation::validate_package_name(formula)?;
+        self.core.execute_brew_command(&["unlink", validated_formula])?;
+        Ok(())
+    }
+
+    /// Uninstall several formulae with one brew call
+    ///
+    /// Brew checks dependents against the whole set, so formulae depending on
+    /// each other can be removed together.
+    pub fn uninstall_formulae(&self, formulae: &[String]) -> ShardResult<()> {
+        let mut args = vec!["uninstall", "--formula"];
+        for formula in formulae {
+            args.push(validation::validate_package_name(formula)?);
+        }
+        self.core.execute_brew_command(&args)?;
+        Ok(())
+    }
+
+    /// Get the Homebrew Cellar directory where formulae are installed
+    pub fn get_cellar_path(&self) -> ShardResult<String> {
+        let output = self.core.execute_brew_command(&["--cellar"])?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Get the Homebrew Caskroom directory where casks are staged
+    pub fn get_caskroom_path(&self) -> ShardResult<String> {
+        let output = self.core.execute_brew_command(&["--caskroom"])?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Get the Homebrew repository, which contains `Library/Taps`
+    pub fn get_repository_path(&self) -> ShardResult<String> {
+        let output = self.core.execute_brew_command(&["--repository"])?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Fetch the newest Homebrew and package index
+    pub fn update(&self) -> ShardResult<()> {
+        self.core.execute_brew_command(&["update"])?;
+        Ok(())
+    }
+    
     /// Run cleanup
     pub fn cleanup(&self, prune_all: bool) -> ShardResult<()> {
         let mut args = vec!["cleanup"];
@@ -303,7 +667,7 @@ impl BrewInstaller {
             
             if let Err(e) = self.core.execute_brew_command(&cmd) {
                 let error_str = e.to_string();
-                if error_str.contains("already installed") {
+                if matches!(e.brew_failure(), Some(BrewFailure::AlreadyInstalled { .. })) {
                     log_warning(&format!("Skipping {}: {}", formula, error_str));
                     continue;
                 } else {
@@ -6,9 +6,14 @@
 //! a clean API.
 //!
 //! The module is organized into specialized components:
+//! - `catalog`: Names of all packages from Homebrew's API cache
 //! - `client`: Primary user-facing API and coordination
 //! - `core`: Low-level command execution
+//! - `diagnostics`: Environment checks via `brew doctor` and `brew config`
+//! - `failure`: Common failures recognised from brew's stderr
 //! - `installer`: Package installation and management
+//! - `options`: Validation of formula build options against `brew options`
+//! - `renames`: Formula renames from the Homebrew API
 //! - `search`: Package search and information
 //! - `validate`: Input validation and security
 //!
@@ -17,20 +22,53 @@
 //! All user inputs are validated to prevent command injection vulnerabilities.
 //! The validation module provides the security primitives used throughout.
 
+use crate::core::config::ShardConfig;
+use crate::utils::{Logger, LogLevel};
+
+pub mod catalog;
 pub mod client;
 pub mod core;
+pub mod diagnostics;
+pub mod failure;
 pub mod installer;
+pub mod options;
+pub mod renames;
 pub mod search;
 pub mod validate;
 
 // Re-export common types and functions
 pub use client::BrewClient;
 pub use core::BrewCore;
-pub use installer::BrewInstaller;
+pub use diagnostics::{AuditProblem, BrewDiagnostics, DoctorWarning};
+pub use failure::BrewFailure;
+pub use installer::{BatchFailure, BatchResults, BrewInstaller, InstalledBuild, OutdatedPackage, OutdatedReport, OutdatedSet};
+pub use renames::FormulaRenames;
 pub use search::BrewSearcher;
-pub use search::{FormulaInfo, CaskInfo, PackageAvailability};
+pub use search::{FormulaInfo, CaskApps, CaskInfo, Deprecation, DeprecationReport, FormulaLicense, PackageAvailability, SearchFilter, TapPackages};
+
+/// Brew settings from the command line, taking precedence over `[shard]` in the config file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientOverrides {
+    /// Timeout in seconds of every brew command, from `--timeout`
+    pub timeout: Option<u64>,
+    /// Retries of installs that timed out or failed to download, from `--retries`
+    pub retries: Option<u32>,
+}
 
-// Convenience function to get a brew client
-pub fn get_client() -> client::BrewClient {
-    client::BrewClient::new()
-} 
\ No newline at end of file
+// Convenience function to get a brew client configured by `[shard]` in the config file
+pub fn get_client(overrides: ClientOverrides) -> client::BrewClient {
+    let settings = ShardConfig::load().map(|config| config.brew).unwrap_or_default();
+    let client = match settings.path {
+        Some(path) => client::BrewClient::with_path(path),
+        None => client::BrewClient::new(),
+    };
+    let client = client.with_homebrew_flags(settings.no_auto_update, settings.no_env_hints)
+        .with_batching(settings.batch_size, settings.parallel_batches)
+        .with_timeouts(settings.install_timeout_secs, settings.query_timeout_secs)
+        .with_retries(overrides.retries.unwrap_or(settings.retries))
+        .with_debug(Logger::enabled(LogLevel::Trace));
+    match overrides.timeout {
+        Some(seconds) => client.with_timeout(seconds),
+        None => client,
+    }
+}
@@ -0,0 +1,145 @@
+//! Validation of formula build options against `brew options`.
+//!
+//! Options declared in shards are passed to `brew install` as they are, so a
+//! typo only shows when the install fails. The options a formula offers are
+//! listed by `brew options`, which is slow enough to cache the result per
+//! formula for a day.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use sapphire_core::paths::SapphirePaths;
+use crate::ShardResult;
+use crate::brew::BrewClient;
+use crate::utils::{ResultExt, log_debug, log_warning, ensure_parent_dir_exists};
+
+/// Cached options per formula
+const CACHE_FILE: &str = "formula_options.json";
+
+/// How long the options of a formula are used before brew is asked again
+const CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// `brew install` flags every formula accepts
+const INSTALL_FLAGS: &[&str] = &[
+    "--build-from-source", "--force-bottle", "--HEAD", "--fetch-HEAD", "--ignore-dependencies",
+    "--only-dependencies", "--include-test", "--keep-tmp", "--debug-symbols", "--bottle-arch",
+    "--cc", "--force", "--verbose", "--debug", "--quiet", "--overwrite",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedOptions {
+    /// Unix timestamp of the `brew options` run
+    fetched_at: u64,
+    options: Vec<String>,
+}
+
+/// An option a formula does not offer
+#[derive(Debug, Clone)]
+pub struct UnknownOption {
+    pub formula: String,
+    pub option: String,
+    /// Closest option the formula offers, if any is close
+    pub suggestion: Option<String>,
+}
+
+impl UnknownOption {
+    pub fn describe(&self) -> String {
+        let hint = self.suggestion.as_ref()
+            .map(|suggestion| format!(", did you mean '{}'?", suggestion))
+            .unwrap_or_default();
+        format!("Formula '{}' has no option '{}'{}", self.formula, self.option, hint)
+    }
+}
+
+/// Find declared formula options brew does not know
+///
+/// Formulae brew cannot list options for, e.g. because they do not exist,
+/// are skipped with a debug message.
+pub fn check(brew_client: &BrewClient, formula_options: &BTreeMap<String, Vec<String>>) -> Vec<UnknownOption> {
+    let path = cache_path();
+    let mut cache: BTreeMap<String, CachedOptions> = std::fs::read_to_string(&path).ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let mut fetched = false;
+
+    let mut unknown = Vec::new();
+    for (formula, declared) in formula_options {
+        if declared.is_empty() {
+            continue;
+        }
+
+        let fresh = cache.get(formula)
+            .is_some_and(|cached| now.saturating_sub(cached.fetched_at) < CACHE_MAX_AGE.as_secs());
+        if !fresh {
+            match brew_client.get_formula_options(formula) {
+                Ok(options) => {
+                    cache.insert(formula.clone(), CachedOptions { fetched_at: now, options });
+                    fetched = true;
+                }
+                Err(e) => {
+                    log_debug(&format!("Could not list the options of {}: {}", formula, e));
+                    continue;
+                }
+            }
+        }
+
+        let offered = &cache[formula].options;
+        for option in declared {
+            let flag = option.split('=').next().unwrap_or(option);
+            if offered.iter().any(|offered| offered == flag) || INSTALL_FLAGS.contains(&flag) {
+                continue;
+            }
+            unknown.push(UnknownOption {
+                formula: formula.clone(),
+                option: option.clone(),
+                suggestion: closest(flag, offered.iter().map(String::as_str).chain(INSTALL_FLAGS.iter().copied())),
+            });
+        }
+    }
+
+    if fetched && let Err(e) = store(&path, &cache) {
+        log_warning(&format!("Could not cache formula options: {}", e));
+    }
+
+    unknown
+}
+
+/// Candidate within a few edits of `flag`
+fn closest<'a>(flag: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let limit = (flag.len() / 3).max(2);
+    candidates
+        .map(|candidate| (edit_distance(flag, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn store(path: &Path, cache: &BTreeMap<String, CachedOptions>) -> ShardResult<()> {
+    ensure_parent_dir_exists(path)?;
+    let content = serde_json::to_string(cache)
+        .with_context(|| "Failed to serialize formula options")?;
+    std::fs::write(path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+fn cache_path() -> PathBuf {
+    SapphirePaths::resolve().cache_dir().join(CACHE_FILE)
+}
@@ -0,0 +1,124 @@
+//! Formula renames published by the Homebrew API.
+//!
+//! Homebrew occasionally renames formulae (e.g. `exa` became `eza`). The
+//! formula index at formulae.brew.sh lists the previous names of every
+//! formula, which is turned into a map from old to current names. The map is
+//! cached for a day, since the index is large and renames are rare.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use serde::Deserialize;
+use sapphire_core::paths::SapphirePaths;
+use crate::ShardResult;
+use crate::utils::{ShardError, ResultExt, log_debug, log_warning, ensure_parent_dir_exists};
+
+/// Formula index of the Homebrew API
+const FORMULA_API_URL: &str = "https://formulae.brew.sh/api/formula.json";
+
+/// Cached map of old to current formula names
+const CACHE_FILE: &str = "formula_renames.json";
+
+/// How long the cached map is used before it is fetched again
+const CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Subset of a formula in the API index
+#[derive(Deserialize)]
+struct ApiFormula {
+    name: String,
+    #[serde(default)]
+    oldnames: Vec<String>,
+    /// Older API versions only list the most recent previous name
+    #[serde(default)]
+    oldname: Option<String>,
+}
+
+/// Map of previous formula names to their current names
+#[derive(Debug, Clone, Default)]
+pub struct FormulaRenames {
+    renames: BTreeMap<String, String>,
+}
+
+impl FormulaRenames {
+    /// Load the renames, fetching them from the API if the cache is stale
+    ///
+    /// A stale cache is still used if the API cannot be reached.
+    pub fn load() -> ShardResult<Self> {
+        let path = cache_path();
+        let cached = std::fs::read_to_string(&path).ok()
+            .and_then(|content| serde_json::from_str::<BTreeMap<String, String>>(&content).ok());
+
+        let fresh = std::fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age < CACHE_MAX_AGE);
+
+        if fresh && let Some(renames) = cached {
+            log_debug(&format!("Using cached formula renames from {}", path.display()));
+            return Ok(Self { renames });
+        }
+
+        match fetch() {
+            Ok(renames) => {
+                if let Err(e) = store(&path, &renames) {
+                    log_warning(&format!("Could not cache formula renames: {}", e));
+                }
+                Ok(Self { renames })
+            }
+            Err(e) => match cached {
+                Some(renames) => {
+                    log_debug(&format!("Using stale formula renames: {}", e));
+                    Ok(Self { renames })
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Current name of a formula that was renamed, `None` if it was not
+    pub fn current_name(&self, name: &str) -> Option<&str> {
+        self.renames.get(name).map(String::as_str)
+    }
+}
+
+/// Download the API index and collect the previous names of every formula
+fn fetch() -> ShardResult<BTreeMap<String, String>> {
+    log_debug(&format!("Fetching formula renames from {}", FORMULA_API_URL));
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", "--compressed", FORMULA_API_URL])
+        .output()
+        .with_context(|| "Failed to run curl")?;
+
+    if !output.status.success() {
+        return Err(ShardError::BrewError(format!(
+            "Failed to fetch formula renames: {}", String::from_utf8_lossy(&output.stderr).trim())));
+    }
+
+    let formulae: Vec<ApiFormula> = serde_json::from_slice(&output.stdout)
+        .with_context(|| "Failed to parse the Homebrew formula index")?;
+
+    let mut renames = BTreeMap::new();
+    for formula in formulae {
+        for oldname in formula.oldnames.into_iter().chain(formula.oldname) {
+            if oldname != formula.name {
+                renames.insert(oldname, formula.name.clone());
+            }
+        }
+    }
+    Ok(renames)
+}
+
+fn store(path: &Path, renames: &BTreeMap<String, String>) -> ShardResult<()> {
+    ensure_parent_dir_exists(path)?;
+    let content = serde_json::to_string(renames)
+        .with_context(|| "Failed to serialize formula renames")?;
+    std::fs::write(path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+fn cache_path() -> PathBuf {
+    SapphirePaths::resolve().cache_dir().join(CACHE_FILE)
+}
@@ -5,28 +5,46 @@
 //! primarily focus on discovery and information retrieval. All user inputs are properly
 //! validated to prevent command injection.
 
+use std::collections::{HashMap, HashSet};
+use serde::Deserialize;
 use crate::ShardResult;
+use crate::utils::{ShardError, ResultExt, log_debug};
 use console::style;
 use crate::brew::core::BrewCore;
 use crate::brew::validate as validation;
 
 /// Searcher for Homebrew packages
+#[derive(Clone)]
 pub struct BrewSearcher {
     core: BrewCore,
 }
 
 /// Formula information structure
+#[derive(Debug, Clone)]
 pub struct FormulaInfo {
     pub name: String,
+    /// Current stable version
     pub version: String,
     pub description: String,
+    pub homepage: String,
+    /// Tap the formula comes from, e.g. `homebrew/core`
+    pub tap: String,
+    /// Installed version, `None` if not installed
+    pub installed: Option<String>,
 }
 
 /// Cask information structure
+#[derive(Debug, Clone)]
 pub struct CaskInfo {
     pub name: String,
+    /// Current version
     pub version: String,
     pub description: String,
+    pub homepage: String,
+    /// Tap the cask comes from, e.g. `homebrew/cask`
+    pub tap: String,
+    /// Installed version, `None` if not installed
+    pub installed: Option<String>,
 }
 
 /// Result of checking package availability
@@ -35,6 +53,290 @@ pub struct PackageAvailability {
     pub name: String,
     pub available_as_formula: bool,
     pub available_as_cask: bool,
+    /// Information about the formula, if there is one
+    pub formula: Option<FormulaInfo>,
+    /// Information about the cask, if there is one
+    pub cask: Option<CaskInfo>,
+}
+
+/// Formulae and casks a tap provides
+#[derive(Debug, Clone, Default)]
+pub struct TapPackages {
+    pub formulae: Vec<String>,
+    pub casks: Vec<String>,
+}
+
+/// Local state search results are filtered by and annotated with
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilter {
+    pub installed_formulae: HashSet<String>,
+    pub installed_casks: HashSet<String>,
+    /// Enabled shards declaring each formula
+    pub formula_shards: HashMap<String, Vec<String>>,
+    /// Enabled shards declaring each cask
+    pub cask_shards: HashMap<String, Vec<String>>,
+    /// Only show installed packages
+    pub installed_only: bool,
+    /// Only show packages declared by this shard
+    pub shard: Option<String>,
+}
+
+impl SearchFilter {
+    /// Whether a search result passes the filter
+    pub fn keep(&self, name: &str, cask: bool) -> bool {
+        let installed = if cask { &self.installed_casks } else { &self.installed_formulae };
+        if self.installed_only && !installed.contains(name) {
+            return false;
+        }
+        match &self.shard {
+            Some(shard) => self.shards(name, cask).iter().any(|s| s == shard),
+            None => true,
+        }
+    }
+
+    /// Suffix describing the local state, e.g. " [in shard dev]"
+    pub fn describe(&self, name: &str, cask: bool) -> String {
+        let shards = self.shards(name, cask);
+        let installed = if cask { &self.installed_casks } else { &self.installed_formulae };
+        if !shards.is_empty() {
+            format!(" {}", style(format!("[in shard {}]", shards.join(", "))).cyan())
+        } else if installed.contains(name) {
+            format!(" {}", style("[installed but unmanaged]").yellow())
+        } else {
+            String::new()
+        }
+    }
+
+    fn shards(&self, name: &str, cask: bool) -> &[String] {
+        let shards = if cask { &self.cask_shards } else { &self.formula_shards };
+        shards.get(name).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// A package Homebrew has deprecated or disabled
+#[derive(Debug, Clone)]
+pub struct Deprecation {
+    pub name: String,
+    /// Disabled packages can no longer be installed
+    pub disabled: bool,
+    /// Date the package was or will be disabled
+    pub disable_date: Option<String>,
+    pub reason: Option<String>,
+    /// Package suggested instead
+    pub replacement: Option<String>,
+}
+
+impl Deprecation {
+    /// One line summary, e.g. "deprecated (unmaintained), disabled from 2025-06-01, use eza"
+    pub fn describe(&self) -> String {
+        let mut text = if self.disabled { "disabled".to_string() } else { "deprecated".to_string() };
+        if let Some(reason) = &self.reason {
+            text.push_str(&format!(" ({})", reason.replace('_', " ")));
+        }
+        match (&self.disable_date, self.disabled) {
+            (Some(date), true) => text.push_str(&format!(" since {}", date)),
+            (Some(date), false) => text.push_str(&format!(", disabled from {}", date)),
+            (None, _) => {}
+        }
+        if let Some(replacement) = &self.replacement {
+            text.push_str(&format!(", use {}", replacement));
+        }
+        text
+    }
+}
+
+/// License of a formula
+#[derive(Debug, Clone)]
+pub struct FormulaLicense {
+    pub name: String,
+    /// Installed version, `None` if not installed
+    pub version: Option<String>,
+    /// SPDX license expression, `None` if the formula declares none
+    pub license: Option<String>,
+}
+
+/// App bundles a cask installs
+#[derive(Debug, Clone)]
+pub struct CaskApps {
+    pub token: String,
+    /// File names of the `.app` bundles, relative to the app directory
+    pub apps: Vec<String>,
+}
+
+/// Deprecated and disabled formulae and casks
+#[derive(Debug, Clone, Default)]
+pub struct DeprecationReport {
+    pub formulae: Vec<Deprecation>,
+    pub casks: Vec<Deprecation>,
+}
+
+/// Subset of `brew info --json=v2`
+#[derive(Deserialize)]
+struct PackageInfo {
+    #[serde(default)]
+    formulae: Vec<FormulaStatus>,
+    #[serde(default)]
+    casks: Vec<CaskStatus>,
+}
+
+#[derive(Deserialize)]
+struct FormulaStatus {
+    name: String,
+    #[serde(default)]
+    desc: Option<String>,
+    #[serde(default)]
+    homepage: Option<String>,
+    #[serde(default)]
+    tap: Option<String>,
+    #[serde(default)]
+    versions: FormulaVersions,
+    /// SPDX expression, missing for some older formulae
+    #[serde(default)]
+    license: Option<String>,
+    #[serde(default)]
+    installed: Vec<InstalledVersion>,
+    #[serde(flatten)]
+    status: PackageStatus,
+}
+
+#[derive(Deserialize)]
+struct InstalledVersion {
+    version: String,
+}
+
+#[derive(Deserialize, Default)]
+struct FormulaVersions {
+    #[serde(default)]
+    stable: Option<String>,
+}
+
+impl FormulaStatus {
+    fn info(&self) -> FormulaInfo {
+        FormulaInfo {
+            name: self.name.clone(),
+            version: self.versions.stable.clone().unwrap_or_default(),
+            description: self.desc.clone().unwrap_or_default(),
+            homepage: self.homepage.clone().unwrap_or_default(),
+            tap: self.tap.clone().unwrap_or_default(),
+            installed: self.installed.last().map(|keg| keg.version.clone()),
+        }
+    }
+}
+
+/// Casks are identified by their token, `name` lists display names
+#[derive(Deserialize)]
+struct CaskStatus {
+    token: String,
+    #[serde(default)]
+    desc: Option<String>,
+    #[serde(default)]
+    homepage: Option<String>,
+    #[serde(default)]
+    tap: Option<String>,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    installed: Option<String>,
+    /// One object per artifact kind, e.g. `{"app": ["Firefox.app"]}`
+    #[serde(default)]
+    artifacts: Vec<serde_json::Value>,
+    #[serde(flatten)]
+    status: PackageStatus,
+}
+
+impl CaskStatus {
+    fn info(&self) -> CaskInfo {
+        CaskInfo {
+            name: self.token.clone(),
+            version: self.version.clone().unwrap_or_default(),
+            description: self.desc.clone().unwrap_or_default(),
+            homepage: self.homepage.clone().unwrap_or_default(),
+            tap: self.tap.clone().unwrap_or_default(),
+            installed: self.installed.clone(),
+        }
+    }
+}
+
+/// Subset of `brew tap-info --json`
+#[derive(Deserialize)]
+struct TapStatus {
+    #[serde(default)]
+    formula_names: Vec<String>,
+    #[serde(default)]
+    cask_tokens: Vec<String>,
+}
+
+/// Deprecation fields shared by formulae and casks
+#[derive(Deserialize)]
+struct PackageStatus {
+    #[serde(default)]
+    deprecated: bool,
+    #[serde(default)]
+    deprecation_reason: Option<String>,
+    #[serde(default)]
+    deprecation_replacement: Option<String>,
+    #[serde(default)]
+    deprecation_replacement_formula: Option<String>,
+    #[serde(default)]
+    deprecation_replacement_cask: Option<String>,
+    #[serde(default)]
+    disabled: bool,
+    #[serde(default)]
+    disable_date: Option<String>,
+    #[serde(default)]
+    disable_reason: Option<String>,
+    #[serde(default)]
+    disable_replacement: Option<String>,
+    #[serde(default)]
+    disable_replacement_formula: Option<String>,
+    #[serde(default)]
+    disable_replacement_cask: Option<String>,
+}
+
+impl PackageStatus {
+    fn into_deprecation(self, name: String) -> Option<Deprecation> {
+        if self.disabled {
+            Some(Deprecation {
+                name,
+                disabled: true,
+                disable_date: self.disable_date,
+                reason: self.disable_reason,
+                replacement: self.disable_replacement
+                    .or(self.disable_replacement_formula)
+                    .or(self.disable_replacement_cask),
+            })
+        } else if self.deprecated {
+            Some(Deprecation {
+                name,
+                disabled: false,
+                disable_date: self.disable_date,
+                reason: self.deprecation_reason,
+                replacement: self.deprecation_replacement
+                    .or(self.deprecation_replacement_formula)
+                    .or(self.deprecation_replacement_cask),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Installed names of the apps in an `app` artifact
+///
+/// An app is followed by an object with a `target` if the cask renames it.
+fn app_names(apps: &[serde_json::Value]) -> Vec<String> {
+    let mut names: Vec<&str> = Vec::new();
+    for app in apps {
+        if let Some(name) = app.as_str() {
+            names.push(name);
+        } else if let Some(target) = app.get("target").and_then(|target| target.as_str())
+            && let Some(last) = names.last_mut() {
+            *last = target;
+        }
+    }
+    names.into_iter()
+        .map(|name| name.rsplit('/').next().unwrap_or(name).to_string())
+        .collect()
 }
 
 impl BrewSearcher {
@@ -75,101 +377,98 @@ impl BrewSearcher {
     
     /// Get detailed information about a formula
     pub fn get_formula_info(&self, formula: &str) -> ShardResult<FormulaInfo> {
-        // Validate formula name
-        let validated_formula = validation::validate_package_name(formula)?;
-        
-        let output = self.core.execute_brew_command(&["info", validated_formula])?;
-        let info_text = String::from_utf8_lossy(&output.stdout);
-        let lines: Vec<&str> = info_text.lines().collect();
-        
-        let mut version = String::new();
-        let mut description = String::new();
-        
-        if !lines.is_empty() {
-            // First line usually contains the formula name and version
-            let first_line = lines[0];
-            if let Some(version_index) = first_line.find(':') {
-                version = first_line[version_index+1..].trim().to_string();
-            }
-            
-            // Description is usually in the second line
-            if lines.len() > 1 {
-                description = lines[1].trim().to_string();
-            }
-        }
-        
-        Ok(FormulaInfo {
-            name: validated_formula.to_string(),
-            version: version.trim().to_string(),
-            description: description.trim().to_string(),
-        })
+        self.get_formulae_info(&[formula.to_string()])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ShardError::PackageError(format!("Formula '{}' not found", formula)))
     }
     
     /// Get detailed information about a cask
     pub fn get_cask_info(&self, cask: &str) -> ShardResult<CaskInfo> {
-        // Validate cask name
-        let validated_cask = validation::validate_package_name(cask)?;
-        
-        let output = self.core.execute_brew_command(&["info", "--cask", validated_cask])?;
-        let info_text = String::from_utf8_lossy(&output.stdout);
-        let lines: Vec<&str> = info_text.lines().collect();
-        
-        let mut version = String::new();
-        let mut description = String::new();
-        
-        if !lines.is_empty() {
-            // Process output to extract version and description
-            for line in lines {
-                if line.contains("version:") {
-                    let parts: Vec<&str> = line.split(':').collect();
-                    if parts.len() > 1 {
-                        version = parts[1].trim().to_string();
-                    }
-                } else if !line.starts_with(validated_cask) && !line.contains("==>") {
-                    description = line.trim().to_string();
-                    break;
-                }
-            }
-        }
+        self.get_casks_info(&[cask.to_string()])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ShardError::PackageError(format!("Cask '{}' not found", cask)))
+    }
+    
+    /// Get information about several formulae at once
+    ///
+    /// Formulae brew does not know are skipped.
+    pub fn get_formulae_info(&self, formulae: &[String]) -> ShardResult<Vec<FormulaInfo>> {
+        Ok(self.package_info("--formula", formulae)?
+            .iter()
+            .flat_map(|info| info.formulae.iter().map(FormulaStatus::info))
+            .collect())
+    }
+    
+    /// Get information about several casks at once
+    ///
+    /// Casks brew does not know are skipped.
+    pub fn get_casks_info(&self, casks: &[String]) -> ShardResult<Vec<CaskInfo>> {
+        Ok(self.package_info("--cask", casks)?
+            .iter()
+            .flat_map(|info| info.casks.iter().map(CaskStatus::info))
+            .collect())
+    }
+    
+    /// Get the descriptions of formulae or casks by name
+    ///
+    /// Packages brew does not know or without a description are left out.
+    pub fn get_descriptions(&self, names: &[String], cask: bool) -> ShardResult<HashMap<String, String>> {
+        let descriptions: Vec<(String, String)> = if cask {
+            self.get_casks_info(names)?.into_iter().map(|info| (info.name, info.description)).collect()
+        } else {
+            self.get_formulae_info(names)?.into_iter().map(|info| (info.name, info.description)).collect()
+        };
+        Ok(descriptions.into_iter().filter(|(_, description)| !description.is_empty()).collect())
+    }
+    
+    /// List the formulae and casks a tap provides
+    pub fn get_tap_packages(&self, tap: &str) -> ShardResult<TapPackages> {
+        let validated_tap = validation::validate_tap_name(tap)?;
+        let output = self.core.execute_brew_command(&["tap-info", "--json", validated_tap])?;
+        let taps: Vec<TapStatus> = serde_json::from_slice(&output.stdout)
+            .with_context(|| "Failed to parse output of brew tap-info")?;
         
-        Ok(CaskInfo {
-            name: validated_cask.to_string(),
-            version: version.trim().to_string(),
-            description: description.trim().to_string(),
-        })
+        Ok(taps.into_iter().fold(TapPackages::default(), |mut packages, tap| {
+            packages.formulae.extend(tap.formula_names);
+            packages.casks.extend(tap.cask_tokens);
+            packages
+        }))
     }
     
     /// Search homebrew formulas and display results
-    pub fn search_and_display_homebrew(&self, query: &str, deep: bool) -> ShardResult<usize> {
+    pub fn search_and_display_homebrew(&self, query: &str, deep: bool, filter: &SearchFilter) -> ShardResult<usize> {
         // Validate query
         let validated_query = validation::validate_search_query(query)?.to_string();
         
         let results = self.search(&validated_query, true, false)?;
         
-        if results.is_empty() {
-            return Ok(0);
-        }
+        let results: Vec<String> = results.into_iter().filter(|name| filter.keep(name, false)).collect();
+        
+        // Get additional info if deep search requested, in a single brew call
+        let infos: HashMap<String, FormulaInfo> = if deep {
+            self.get_formulae_info(&results).unwrap_or_default()
+                .into_iter()
+                .map(|info| (info.name.clone(), info))
+                .collect()
+        } else {
+            HashMap::new()
+        };
         
         let mut count = 0;
         
         for formula_name in &results {
             count += 1;
+            let note = filter.describe(formula_name, false);
             
-            // Get additional info if deep search requested
-            if deep {
-                match self.get_formula_info(formula_name) {
-                    Ok(formula_info) => {
-                        println!("  {} ({})", style(&formula_info.name).bold(), formula_info.version);
-                        if !formula_info.description.is_empty() {
-                            println!("    {}", formula_info.description);
-                        }
-                    },
-                    Err(_) => {
-                        println!("  {}", formula_name);
-                    }
+            if let Some(formula_info) = infos.get(formula_name) {
+                println!("  {} ({}){}", style(&formula_info.name).bold(), formula_info.version, note);
+                if !formula_info.description.is_empty() {
+                    println!("    {}", formula_info.description);
                 }
             } else {
-                println!("  {}", formula_name);
+                println!("  {}{}", formula_name, note);
             }
         }
         
@@ -177,36 +476,37 @@ impl BrewSearcher {
     }
     
     /// Search homebrew casks and display results
-    pub fn search_and_display_casks(&self, query: &str, deep: bool) -> ShardResult<usize> {
+    pub fn search_and_display_casks(&self, query: &str, deep: bool, filter: &SearchFilter) -> ShardResult<usize> {
         // Validate query
         let validated_query = validation::validate_search_query(query)?.to_string();
         
         let results = self.search(&validated_query, false, true)?;
         
-        if results.is_empty() {
-            return Ok(0);
-        }
+        let results: Vec<String> = results.into_iter().filter(|name| filter.keep(name, true)).collect();
+        
+        // Get additional info if deep search requested, in a single brew call
+        let infos: HashMap<String, CaskInfo> = if deep {
+            self.get_casks_info(&results).unwrap_or_default()
+                .into_iter()
+                .map(|info| (info.name.clone(), info))
+                .collect()
+        } else {
+            HashMap::new()
+        };
         
         let mut count = 0;
         
         for cask_name in &results {
             count += 1;
+            let note = filter.describe(cask_name, true);
             
-            // Get additional info if deep search requested
-            if deep {
-                match self.get_cask_info(cask_name) {
-                    Ok(cask_info) => {
-                        println!("  {} ({})", style(&cask_info.name).bold(), cask_info.version);
-                        if !cask_info.description.is_empty() {
-                            println!("    {}", cask_info.description);
-                        }
-                    },
-                    Err(_) => {
-                        println!("  {}", cask_name);
-                    }
+            if let Some(cask_info) = infos.get(cask_name) {
+                println!("  {} ({}){}", style(&cask_info.name).bold(), cask_info.version, note);
+                if !cask_info.description.is_empty() {
+                    println!("    {}", cask_info.description);
                 }
             } else {
-                println!("  {}", cask_name);
+                println!("  {}{}", cask_name, note);
             }
         }
         
@@ -214,17 +514,17 @@ impl BrewSearcher {
     }
     
     /// Search both Homebrew formulas and casks and display results
-    pub fn search_and_display_all(&self, query: &str, deep: bool) -> ShardResult<(usize, usize)> {
+    pub fn search_and_display_all(&self, query: &str, deep: bool, filter: &SearchFilter) -> ShardResult<(usize, usize)> {
         // Search formulas
         println!("\n::: 🍺 BREW FORMULAS :::\n");
-        let formula_count = self.search_and_display_homebrew(query, deep)?;
+        let formula_count = self.search_and_display_homebrew(query, deep, filter)?;
         if formula_count == 0 {
             println!("!!!result empty:::");
         }
         
         // Search casks
         println!("\n::: 🍻 BREW CASKS :::\n");
-        let cask_count = self.search_and_display_casks(query, deep)?;
+        let cask_count = self.search_and_display_casks(query, deep, filter)?;
         if cask_count == 0 {
             println!("!!!result empty:::");
         }
@@ -232,38 +532,133 @@ impl BrewSearcher {
         Ok((formula_count, cask_count))
     }
 
-    /// Check if a package is available as brew formula and/or cask
-    /// This involves calling the external 'brew' command.
-    pub fn check_package_availability(&self, package_name: &str) -> ShardResult<PackageAvailability> {
-        // Validate package name first for safety
-        let validated_name = validation::validate_package_name(package_name)?;
+    /// Find the deprecated and disabled packages among formulae and casks
+    ///
+    /// Packages brew does not know are skipped.
+    pub fn get_deprecations(&self, formulae: &[String], casks: &[String]) -> ShardResult<DeprecationReport> {
+        Ok(DeprecationReport {
+            formulae: self.package_statuses("--formula", formulae)?,
+            casks: self.package_statuses("--cask", casks)?,
+        })
+    }
 
-        // Check if it's a brew formula using the core executor
-        let brew_check_output = self.core.execute_brew_command(&["info", "--formula", validated_name]);
-        let available_as_formula = brew_check_output.is_ok(); // Success means it exists
+    fn package_statuses(&self, type_flag: &str, names: &[String]) -> ShardResult<Vec<Deprecation>> {
+        let mut deprecations = Vec::new();
+        for info in self.package_info(type_flag, names)? {
+            deprecations.extend(info.formulae.into_iter()
+                .filter_map(|formula| formula.status.into_deprecation(formula.name)));
+            deprecations.extend(info.casks.into_iter()
+                .filter_map(|cask| cask.status.into_deprecation(cask.token)));
+        }
+        Ok(deprecations)
+    }
+
+    /// Get the build options a formula offers, e.g. `--with-openssl`
+    pub fn get_formula_options(&self, formula: &str) -> ShardResult<Vec<String>> {
+        let validated_formula = validation::validate_package_name(formula)?;
+        let output = self.core.execute_brew_command(&["options", validated_formula])?;
+
+        // Each option is followed by its indented description
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| line.starts_with("--"))
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Get the app bundles casks install
+    ///
+    /// Casks brew does not know and casks without apps, e.g. fonts, are skipped.
+    pub fn get_cask_apps(&self, casks: &[String]) -> ShardResult<Vec<CaskApps>> {
+        Ok(self.package_info("--cask", casks)?
+            .into_iter()
+            .flat_map(|info| info.casks)
+            .map(|cask| CaskApps {
+                apps: cask.artifacts.iter()
+                    .filter_map(|artifact| artifact.get("app")?.as_array())
+                    .flat_map(|apps| app_names(apps))
+                    .collect(),
+                token: cask.token,
+            })
+            .filter(|cask| !cask.apps.is_empty())
+            .collect())
+    }
 
-        // Check if it's a cask using the core executor
-        let cask_check_output = self.core.execute_brew_command(&["info", "--cask", validated_name]);
-        let available_as_cask = cask_check_output.is_ok(); // Success means it exists
+    /// Get the license and installed version of formulae
+    ///
+    /// Formulae brew does not know are skipped.
+    pub fn get_licenses(&self, formulae: &[String]) -> ShardResult<Vec<FormulaLicense>> {
+        Ok(self.package_info("--formula", formulae)?
+            .into_iter()
+            .flat_map(|info| info.formulae)
+            .map(|formula| FormulaLicense {
+                version: formula.installed.last().map(|keg| keg.version.clone()),
+                name: formula.name,
+                license: formula.license,
+            })
+            .collect())
+    }
 
-        // Process output for debug logging
-        if let Ok(output) = &brew_check_output {
-            self.core.process_output(output, &["info", "--formula", validated_name]);
+    /// Run `brew info --json=v2` for packages of one type
+    fn package_info(&self, type_flag: &str, names: &[String]) -> ShardResult<Vec<PackageInfo>> {
+        let mut validated = Vec::new();
+        for name in names {
+            validated.push(validation::validate_package_name(name)?);
+        }
+        if validated.is_empty() {
+            return Ok(Vec::new());
         }
-        if let Ok(output) = &cask_check_output {
-            self.core.process_output(output, &["info", "--cask", validated_name]);
+
+        // A single unknown package fails the whole query, so retry one by one
+        let mut outputs = Vec::new();
+        let mut args = vec!["info", "--json=v2", type_flag];
+        args.extend(&validated);
+        match self.core.execute_brew_command(&args) {
+            Ok(output) => outputs.push(output),
+            Err(_) => {
+                for name in &validated {
+                    match self.core.execute_brew_command(&["info", "--json=v2", type_flag, name]) {
+                        Ok(output) => outputs.push(output),
+                        Err(e) => log_debug(&format!("Skipping {}: {}", name, e)),
+                    }
+                }
+            }
         }
 
+        outputs.iter()
+            .map(|output| serde_json::from_slice(&output.stdout)
+                .with_context(|| "Failed to parse output of brew info"))
+            .collect()
+    }
+
+    /// Check if a package is available as brew formula and/or cask
+    ///
+    /// The information brew has about either is returned along, so callers
+    /// need no further brew calls to describe the package.
+    pub fn check_package_availability(&self, package_name: &str) -> ShardResult<PackageAvailability> {
+        // Validate package name first for safety
+        validation::validate_package_name(package_name)?;
+        let name = [package_name.to_string()];
+
+        // Unknown packages are skipped, so an empty result means not available
+        let formula = self.get_formulae_info(&name)?.into_iter().next();
+        let cask = self.get_casks_info(&name)?.into_iter().next();
+
         Ok(PackageAvailability {
             name: package_name.to_string(),
-            available_as_formula,
-            available_as_cask,
+            available_as_formula: formula.is_some(),
+            available_as_cask: cask.is_some(),
+            formula,
+            cask,
         })
     }
 }
 
 /// Main search function, used by the CLI
-pub fn search(query: &str, search_type: &str, deep: bool) -> ShardResult<()> {
+///
+/// Results are filtered by and annotated with the local state in `filter`.
+pub fn search(query: &str, search_type: &str, deep: bool, filter: &SearchFilter) -> ShardResult<()> {
     let searcher = BrewSearcher::new();
     let query = query.to_lowercase();
     let search_type = search_type.to_lowercase();
@@ -272,7 +667,7 @@ pub fn search(query: &str, search_type: &str, deep: bool) -> ShardResult<()> {
     match search_type.as_str() {
         "brew" => {
             println!(":::searching homebrew packages for '{}' :::", query);
-            match searcher.search_and_display_homebrew(&query, deep) {
+            match searcher.search_and_display_homebrew(&query, deep, filter) {
                 Ok(count) => {
                     if count == 0 {
                         println!("!!!result empty:::");
@@ -283,7 +678,7 @@ pub fn search(query: &str, search_type: &str, deep: bool) -> ShardResult<()> {
         }
         "cask" => {
             println!(":::searching cask packages for '{}' :::", query);
-            match searcher.search_and_display_casks(&query, deep) {
+            match searcher.search_and_display_casks(&query, deep, filter) {
                 Ok(count) => {
                     if count == 0 {
                         println!("!!!result empty:::");
@@ -295,7 +690,7 @@ pub fn search(query: &str, search_type: &str, deep: bool) -> ShardResult<()> {
         "any" | _ => {
             println!(":::searching all package types for '{}' :::", query);
             
-            match searcher.search_and_display_all(&query, deep) {
+            match searcher.search_and_display_all(&query, deep, filter) {
                 Ok(_) => {
                     println!("\n:::query executed:::");
                 }
@@ -25,8 +25,16 @@ lazy_static! {
     // Valid Homebrew tap name regex (e.g., "user/repo" or "homebrew/core")
     static ref TAP_NAME_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9_\-]+/[a-zA-Z0-9_\-]+$").unwrap();
     
+    // Valid tap remote regex (https://, ssh:// or file:// URLs and scp-style git@host:path)
+    // Never starts with a dash, so a URL cannot be mistaken for a brew option
+    static ref TAP_URL_REGEX: Regex = Regex::new(r"^(https://|ssh://|file://|[a-zA-Z0-9_\-\.]+@[a-zA-Z0-9_\-\.]+:)[a-zA-Z0-9_\-\.~/:@%+]+$").unwrap();
+    
     // Valid option regex - more permissive, but still restricted
-    static ref OPTION_REGEX: Regex = Regex::new(r"^--?[a-zA-Z0-9_\-]+(=[a-zA-Z0-9_\-\.+/]+)?$").unwrap();
+    // Values may be paths (--appdir=~/Applications) or lists (--language=de,en)
+    static ref OPTION_REGEX: Regex = Regex::new(r"^--?[a-zA-Z0-9_\-]+(=[a-zA-Z0-9_\-\.+/~,]+)?$").unwrap();
+    
+    // Valid brew command regex (e.g., "info", "services", "bundle-dump")
+    static ref COMMAND_REGEX: Regex = Regex::new(r"^[a-z][a-z0-9\-]*$").unwrap();
 }
 
 /// Validate a Homebrew package name (formula or cask)
@@ -44,6 +52,21 @@ pub fn validate_package_name(name: &str) -> ShardResult<&str> {
     Ok(name)
 }
 
+/// Validate the remote URL of a tap
+pub fn validate_tap_url(url: &str) -> ShardResult<&str> {
+    if url.is_empty() {
+        return Err(ShardError::ValidationError("Tap URL cannot be empty".to_string()));
+    }
+    
+    if !TAP_URL_REGEX.is_match(url) {
+        return Err(ShardError::ValidationError(
+            format!("Invalid tap URL: '{}'. Use an https://, ssh:// or file:// URL or the form 'git@host:org/repo.git'", url)
+        ));
+    }
+    
+    Ok(url)
+}
+
 /// Validate a Homebrew tap name
 pub fn validate_tap_name(name: &str) -> ShardResult<&str> {
     if name.is_empty() {
@@ -74,6 +97,21 @@ pub fn validate_option(option: &str) -> ShardResult<&str> {
     Ok(option)
 }
 
+/// Validate the command of a brew invocation, e.g. `info`, or a global option like `--prefix`
+pub fn validate_command(command: &str) -> ShardResult<&str> {
+    if command.is_empty() {
+        return Err(ShardError::ValidationError("Brew command cannot be empty".to_string()));
+    }
+    
+    if !COMMAND_REGEX.is_match(command) && !OPTION_REGEX.is_match(command) {
+        return Err(ShardError::ValidationError(
+            format!("Invalid brew command: '{}'. Commands must contain only lowercase letters, numbers and dashes", command)
+        ));
+    }
+    
+    Ok(command)
+}
+
 /// Validate a search query - slightly more permissive than package names
 pub fn validate_search_query(query: &str) -> ShardResult<&str> {
     if query.is_empty() {
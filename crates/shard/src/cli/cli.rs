@@ -1,22 +1,56 @@
-use clap::{Parser, Subcommand};
-use crate::utils::ShardResult;
+use std::path::PathBuf;
+use clap::{ArgAction, Parser, Subcommand};
+use sapphire_core::{lock, read_only};
+use sapphire_core::paths::SapphirePaths;
+use crate::core::aliases;
+use crate::core::config::ShardConfig;
+use crate::core::manifest::ManifestSection;
+use crate::utils::{ShardResult, log_error};
 use crate::utils::observability::{Logger, LogLevel};
 
 use crate::{
-    brew::search,
+    brew::{search, ClientOverrides},
     package::operations as package,
     shard::{
-        apply, diff, init,
+        apply, apps, audit, backups, complete, context, dedupe, diff, doctor, edit, export, freeze, init, list,
         manager as manage,
+        outdated, passthrough, profile, renames, report, shellhook, size, snapshot, update, upgrade, validate,
     }
 };
 
 #[derive(Debug, Parser)]
 #[command(author, version = crate::VERSION, about = "Shard package management tool", long_about = None)]
 pub struct Cli {
-    /// Enable verbose output
-    #[arg(short, long)]
-    pub verbose: bool,
+    /// Enable verbose output, repeat (-vv) to also show brew command lines
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only print errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Disable colored output, also disabled by setting NO_COLOR
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// Wait for another running sapphire process instead of failing
+    #[arg(long, global = true)]
+    pub wait: bool,
+
+    /// Sapphire directory to use instead of ~/.sapphire, SAPPHIRE_HOME or the configured one
+    #[arg(long, global = true, value_name = "DIR")]
+    pub root: Option<PathBuf>,
+
+    /// Stop brew commands after this many seconds, instead of the configured
+    /// install_timeout_secs and query_timeout_secs. Interactive commands run
+    /// through `shard brew`, like edit or sh, only stop when this is given
+    #[arg(long, global = true, value_name = "SECONDS")]
+    pub timeout: Option<u64>,
+
+    /// Run installs, upgrades and fetches that timed out or failed to download
+    /// again up to this many times, instead of the configured retries
+    #[arg(long, global = true, value_name = "COUNT")]
+    pub retries: Option<u32>,
 
     #[command(subcommand)]
     pub command: Commands,
@@ -26,20 +60,61 @@ pub struct Cli {
 pub enum Commands {
     /// Apply a shard to install/remove packages
     Apply {
-        /// Path to shard file or "all" to apply all enabled shards
-        #[arg(default_value = "~/.sapphire/shards/user.toml")]
+        /// Shard name, path to a shard file, or "all" to apply all enabled shards
+        #[arg(default_value = "user")]
         shard: String,
         
         /// Skip cleanup after applying
         #[arg(long)]
         skip_cleanup: bool,
+        
+        /// Fail if applying again would still change something
+        #[arg(long)]
+        check: bool,
+        
+        /// Apply all shards even if nothing changed since the last apply
+        #[arg(long)]
+        force: bool,
+        
+        /// Only apply these sections: taps, formulae, casks or vscode_extensions
+        #[arg(long, value_delimiter = ',')]
+        only: Vec<String>,
+        
+        /// Only download what would be installed or upgraded
+        #[arg(long, conflicts_with_all = ["check", "force", "dequarantine"])]
+        fetch_only: bool,
+        
+        /// Remove the macOS quarantine from the apps of installed casks
+        #[arg(long)]
+        dequarantine: bool,
+        
+        /// Only install casks whose installers asked for input in an unattended run
+        #[arg(long, conflicts_with_all = ["check", "force", "only", "fetch_only"])]
+        interactive_only: bool,
+        
+        /// Run brew update first, even if it ran recently
+        #[arg(long, conflicts_with = "no_update")]
+        update: bool,
+        
+        /// Do not run brew update first
+        #[arg(long)]
+        no_update: bool,
     },
     
     /// Check what would change if a shard was applied
     Diff {
-        /// Path to shard file or "all" to check all enabled shards
-        #[arg(default_value = "~/.sapphire/shards/user.toml")]
+        /// Shard name, path to a shard file, or "all" to check all enabled shards
+        #[arg(default_value = "user")]
         shard: String,
+        
+        /// Also list packages that are already up to date
+        #[arg(short, long)]
+        verbose: bool,
+        
+        /// Compare installed packages with a snapshot of another machine
+        /// instead of with shards, see `shard snapshot`
+        #[arg(long, value_name = "SNAPSHOT", conflicts_with = "verbose")]
+        against: Option<String>,
     },
     
     /// Initialize default system and user shards
@@ -57,6 +132,10 @@ pub enum Commands {
         /// Description of the shard's purpose
         #[arg(short, long)]
         description: Option<String>,
+        
+        /// Populate the shard from a template (web-dev, rust, data-science, media or one in ~/.sapphire/templates)
+        #[arg(short, long)]
+        template: Option<String>,
     },
     
     /// Delete a shard permanently
@@ -67,12 +146,70 @@ pub enum Commands {
         /// Skip confirmation prompt
         #[arg(short, long)]
         force: bool,
+        
+        /// Also uninstall the installed packages no other enabled shard declares
+        #[arg(long)]
+        purge: bool,
+    },
+    
+    /// List shards with their status and package counts
+    List {
+        /// Also list the packages of every shard with their comments, and when
+        /// and by whom it was created and last modified
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    
+    /// Show the details and packages of a shard
+    Info {
+        /// Name of the shard
+        name: String,
+    },
+    
+    /// Rename a shard, active or disabled
+    Rename {
+        /// Current name of the shard
+        old: String,
+        
+        /// New name of the shard
+        new: String,
+        
+        /// Replace an existing shard named NEW (a backup is kept)
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Copy a shard under a new name
+    Copy {
+        /// Name of the shard to copy
+        source: String,
+        
+        /// Name of the copy
+        dest: String,
+        
+        /// Replace an existing shard named DEST (a backup is kept)
+        #[arg(short, long)]
+        force: bool,
+    },
+    
+    /// Open a shard in $EDITOR and save it once it is valid
+    Edit {
+        /// Name of the shard to edit
+        name: String,
+        
+        /// Apply all enabled shards after saving
+        #[arg(long)]
+        apply: bool,
     },
     
     /// Disable a shard without deleting it (moves to disabled directory)
     Disable {
         /// Name of the shard to disable
         name: String,
+        
+        /// Also uninstall the installed packages no remaining enabled shard declares
+        #[arg(long)]
+        apply: bool,
     },
     
     /// Enable a previously disabled shard
@@ -81,6 +218,37 @@ pub enum Commands {
         name: String,
     },
     
+    /// Change the owner of a shard
+    Chown {
+        /// Name of the shard
+        name: String,
+        
+        /// New owner, or a group with --shared
+        owner: String,
+        
+        /// Let every member of the group OWNER modify the shard
+        #[arg(long)]
+        shared: bool,
+    },
+    
+    /// List, inspect, prune or restore shard backups
+    Backups {
+        /// Only show backups of this shard (required for --inspect and --restore)
+        name: Option<String>,
+        
+        /// Compare a backup (timestamp or "latest") with the current shard
+        #[arg(long, value_name = "BACKUP", requires = "name", conflicts_with_all = ["prune", "restore"])]
+        inspect: Option<String>,
+        
+        /// Delete backups exceeding the retention policy
+        #[arg(long, conflicts_with = "restore")]
+        prune: bool,
+        
+        /// Replace the shard with a backup (timestamp or "latest")
+        #[arg(long, value_name = "BACKUP", requires = "name")]
+        restore: Option<String>,
+    },
+    
     /// Search for packages
     Search {
         /// Search query
@@ -93,6 +261,132 @@ pub enum Commands {
         /// Show more details
         #[arg(short, long)]
         deep: bool,
+        
+        /// Only show installed packages
+        #[arg(long)]
+        installed: bool,
+        
+        /// Only show packages declared by this shard
+        #[arg(short = 's', long = "shard")]
+        shard: Option<String>,
+    },
+    
+    /// List managed packages that have an upgrade available
+    Outdated {
+        /// Only report packages from this shard (default: all enabled shards)
+        #[arg(short = 's', long = "shard")]
+        shard: Option<String>,
+
+        /// Include casks that auto-update or use version :latest
+        #[arg(long)]
+        greedy: bool,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Report licenses and known problems of managed packages
+    Audit {
+        /// Only audit packages from this shard (default: all enabled shards)
+        #[arg(short = 's', long = "shard")]
+        shard: Option<String>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Upgrade managed packages without installing or removing anything else
+    Upgrade {
+        /// Packages or shard names to upgrade, or "all" for every enabled shard
+        #[arg(default_value = "all")]
+        targets: Vec<String>,
+
+        /// Also upgrade casks that auto-update or use version :latest
+        #[arg(long)]
+        greedy: bool,
+
+        /// Show what would be upgraded without making changes
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Run brew update first, even if it ran recently
+        #[arg(long, conflicts_with = "no_update")]
+        update: bool,
+
+        /// Do not run brew update first
+        #[arg(long)]
+        no_update: bool,
+    },
+    
+    /// Check shards for invalid entries and deprecated or disabled packages
+    Validate {
+        /// Name of the shard to check, or "all" for every enabled shard
+        #[arg(default_value = "all")]
+        shard: String,
+    },
+    
+    /// Replace formulae Homebrew has renamed with their current names
+    FixRenames {
+        /// Name of the shard to update, or "all" for every enabled shard
+        #[arg(default_value = "all")]
+        shard: String,
+
+        /// Show the renames without changing any shard
+        #[arg(long)]
+        dry_run: bool,
+    },
+    
+    /// Choose a single shard for packages declared in several
+    Dedupe {
+        /// List the duplicates without changing any shard
+        #[arg(long)]
+        dry_run: bool,
+    },
+    
+    /// Export shards for another package manager
+    Export {
+        /// Name of the shard to export, or "all" for every enabled shard
+        #[arg(default_value = "all")]
+        shard: String,
+
+        /// Export format (nix)
+        #[arg(short, long, default_value = "nix")]
+        format: String,
+
+        /// Write the export to this file instead of printing it
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    
+    /// Record the installed packages of this machine for `diff --against`
+    Snapshot {
+        /// Snapshot format (json)
+        #[arg(short, long, default_value = "json")]
+        format: String,
+
+        /// Write the snapshot to this file instead of printing it
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    
+    /// Write a document of the managed state of this machine
+    Report {
+        /// Document format (markdown, html)
+        #[arg(short, long, default_value = "markdown")]
+        format: String,
+
+        /// Write the report to this file instead of printing it
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    
+    /// Show disk usage of installed packages per shard
+    Size {
+        /// Name of the shard to measure, or "all" for every enabled shard
+        #[arg(default_value = "all")]
+        shard: String,
     },
     
     /// Add packages to a shard and install them
@@ -126,7 +420,74 @@ pub enum Commands {
         apply: bool,
     },
     
+    /// Make `apply all` a no-op until thawed, e.g. while bandwidth is scarce
+    Freeze {
+        /// Note shown while frozen
+        #[arg(short, long)]
+        reason: Option<String>,
+    },
+    
+    /// Resume reconciliation after a freeze
+    Thaw,
+    
+    /// Print a shell function that suggests `shard add` after `brew install`
+    ///
+    /// Add `eval "$(shard shellhook zsh)"` to ~/.zshrc, or the same for bash.
+    /// For fish, add `shard shellhook fish | source` to config.fish.
+    Shellhook {
+        /// Shell to print the hook for (bash, zsh, fish)
+        #[arg(required_unless_present = "installed")]
+        shell: Option<String>,
+
+        /// Offer to add installed packages right away instead of printing a reminder
+        #[arg(long)]
+        prompt: bool,
+
+        /// Arguments of a succeeded `brew install`, passed by the hook
+        #[arg(long, hide = true, num_args = 1.., allow_hyphen_values = true)]
+        installed: Vec<String>,
+    },
+    
+    /// Save and load sets of enabled shards
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
+    },
+    
+    /// Choose which context-specific packages apply, e.g. work or personal
+    Context {
+        #[command(subcommand)]
+        command: ContextCommands,
+    },
+    
+    /// Check shards and the environment for common problems
+    Doctor {
+        /// Also run Homebrew diagnostics (brew doctor, brew config, taps)
+        #[arg(long)]
+        brew: bool,
+    },
+    
+    /// Run a brew command with shard's brew settings and record it in the history
+    ///
+    /// The configured timeouts apply, except to interactive commands like
+    /// edit, sh, irb, ruby and create, which only stop on --timeout.
+    ///
+    /// Example: `shard brew -- services restart postgresql@16`
+    Brew {
+        /// Brew command and its arguments
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    
+    /// Print completion candidates, one per line
+    #[command(name = "_complete", hide = true)]
+    Complete {
+        #[command(subcommand)]
+        command: CompleteCommands,
+    },
+    
     /// Remove packages from a shard
+    #[command(visible_aliases = ["remove", "rm"])]
     Del {
         /// Packages to remove
         #[arg(required = true)]
@@ -158,47 +519,302 @@ pub enum Commands {
     },
 }
 
+impl Commands {
+    /// Whether the command changes packages, shards or shard state
+    ///
+    /// These commands are refused in read-only mode and hold the sapphire
+    /// lock while they run. The others only read shards, saving a manifest
+    /// is refused in read-only mode whichever command tries it.
+    fn is_mutating(&self) -> bool {
+        match self {
+            Commands::Apply { .. }
+            | Commands::Init { .. }
+            | Commands::Grow { .. }
+            | Commands::Shatter { .. }
+            | Commands::Rename { .. }
+            | Commands::Copy { .. }
+            | Commands::Edit { .. }
+            | Commands::Disable { .. }
+            | Commands::Enable { .. }
+            | Commands::Chown { .. }
+            | Commands::Freeze { .. }
+            | Commands::Brew { .. }
+            | Commands::Thaw => true,
+            Commands::Backups { prune, restore, .. } => *prune || restore.is_some(),
+            Commands::Upgrade { dry_run, .. }
+            | Commands::FixRenames { dry_run, .. }
+            | Commands::Dedupe { dry_run }
+            | Commands::Add { dry_run, .. }
+            | Commands::Del { dry_run, .. } => !dry_run,
+            Commands::Shellhook { prompt, installed, .. } => *prompt && !installed.is_empty(),
+            Commands::Profile { command } => !matches!(command, ProfileCommands::List),
+            Commands::Context { command } => !matches!(command, ContextCommands::Show),
+            Commands::Diff { .. }
+            | Commands::List { .. }
+            | Commands::Info { .. }
+            | Commands::Search { .. }
+            | Commands::Outdated { .. }
+            | Commands::Audit { .. }
+            | Commands::Validate { .. }
+            | Commands::Export { .. }
+            | Commands::Snapshot { .. }
+            | Commands::Report { .. }
+            | Commands::Size { .. }
+            | Commands::Doctor { .. }
+            | Commands::Complete { .. } => false,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommands {
+    /// Save which shards are enabled and disabled
+    Save {
+        /// Name of the profile, e.g. "work"
+        name: String,
+    },
+    
+    /// Enable and disable shards to match a profile
+    Load {
+        /// Name of the profile
+        name: String,
+    },
+    
+    /// List saved profiles
+    List,
+    
+    /// Delete a profile
+    Rm {
+        /// Name of the profile
+        name: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CompleteCommands {
+    /// Names of all shards
+    Shards,
+    
+    /// Declared and available packages
+    Packages {
+        /// Only print packages starting with this
+        #[arg(default_value = "")]
+        prefix: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ContextCommands {
+    /// Apply the packages declared for a context, e.g. "work"
+    Set {
+        /// Name of the context
+        name: String,
+    },
+    
+    /// Print the active context
+    Show,
+    
+    /// Only apply packages that are declared for no context
+    Clear,
+}
+
 pub fn run() -> ShardResult<()> {
     let cli = Cli::parse();
     
+    // Everything below resolves its paths from the root
+    if let Some(root) = &cli.root {
+        SapphirePaths::set_root(root);
+    }
+    
     // Set log level based on verbosity
-    let log_level = if cli.verbose { LogLevel::Debug } else { LogLevel::Info }; // Default to Info
-    Logger::init(log_level);
+    if cli.no_color {
+        Logger::disable_colors();
+    }
+    Logger::init(LogLevel::from_flags(cli.quiet, cli.verbose));
+    if let Ok(config) = ShardConfig::load() {
+        Logger::set_glyphs(config.output.glyphs);
+    }
+    
+    let overrides = ClientOverrides { timeout: cli.timeout, retries: cli.retries };
+    
+    // Held until the command finished
+    let _lock = if cli.command.is_mutating() {
+        read_only::ensure_writable().inspect_err(|e| log_error(&e.to_string()))?;
+        Some(lock::acquire(cli.wait).inspect_err(|e| log_error(&e.to_string()))?)
+    } else {
+        None
+    };
     
     match cli.command {
-        Commands::Apply { shard, skip_cleanup } => {
-            if shard.to_lowercase() == "all" {
-                apply::apply_all_enabled_shards(skip_cleanup)
+        Commands::Apply { shard, skip_cleanup, check, force, only, fetch_only, dequarantine, interactive_only, update: force_update, no_update } => {
+            let only = only.iter()
+                .map(|section| ManifestSection::parse(section))
+                .collect::<ShardResult<Vec<_>>>()?;
+            update::refresh(update::UpdatePolicy::from_flags(force_update, no_update), overrides)?;
+            if fetch_only {
+                return apply::fetch_only(&shard, &only, overrides);
+            }
+            if interactive_only {
+                return apply::apply_interactive_only(&shard, skip_cleanup, overrides);
+            }
+            let applied = if shard.to_lowercase() == "all" {
+                apply::apply_all_enabled_shards(skip_cleanup, force, &only, overrides)
             } else {
-                apply::apply(&shard, skip_cleanup)
+                apply::apply_single_shard(&shard, skip_cleanup, &only, overrides)
+            };
+            // Apps installed before a failed action are still worth clearing
+            if dequarantine {
+                apps::dequarantine(&shard, overrides)?;
+            }
+            applied?;
+            if check {
+                apply::check_converged(&shard, overrides)?;
             }
+            Ok(())
         },
-        Commands::Diff { shard } => {
-            diff::diff(&shard)
+        Commands::Diff { shard, verbose, against } => match against {
+            Some(path) => snapshot::diff_against(&path, overrides),
+            None => diff::diff(&shard, verbose, overrides),
         },
         Commands::Init { force } => {
             init::init_shards(force)
         },
-        Commands::Grow { name, description } => {
-            manage::grow_shard(&name, description.as_deref())
+        Commands::Grow { name, description, template } => {
+            manage::grow_shard(&name, description.as_deref(), template.as_deref())
+        },
+        Commands::Shatter { name, force, purge } => {
+            manage::shatter_shard(&name, force, purge, overrides)
         },
-        Commands::Shatter { name, force } => {
-            manage::shatter_shard(&name, force)
+        Commands::List { verbose } => {
+            list::list(verbose)
         },
-        Commands::Disable { name } => {
-            manage::disable_shard(&name)
+        Commands::Info { name } => {
+            list::info(&name)
+        },
+        Commands::Rename { old, new, force } => {
+            manage::rename_shard(&old, &new, force)
+        },
+        Commands::Copy { source, dest, force } => {
+            manage::copy_shard(&source, &dest, force)
+        },
+        Commands::Edit { name, apply } => {
+            edit::edit(&name, apply, overrides)
+        },
+        Commands::Disable { name, apply } => {
+            manage::disable_shard(&name, apply, overrides)
         },
         Commands::Enable { name } => {
             manage::enable_shard(&name)
         },
-        Commands::Search { query, r#type, deep } => {
-            search::search(&query, &r#type, deep)
+        Commands::Chown { name, owner, shared } => {
+            manage::chown_shard(&name, &owner, shared)
+        },
+        Commands::Backups { name, inspect, prune, restore } => {
+            backups::backups(name.as_deref(), inspect.as_deref(), prune, restore.as_deref())
+        },
+        Commands::Search { query, r#type, deep, installed, shard } => {
+            let filter = package::search_filter(installed, shard.as_deref(), overrides)?;
+            let query = aliases::resolve_all(std::slice::from_ref(&query)).remove(0);
+            search::search(&query, &r#type, deep, &filter)
+        },
+        Commands::Outdated { shard, greedy, json } => {
+            outdated::outdated(shard.as_deref(), greedy, json, overrides)
+        },
+        Commands::Audit { shard, json } => {
+            audit::audit(shard.as_deref(), json, overrides)
+        },
+        Commands::Upgrade { targets, greedy, dry_run, update: force_update, no_update } => {
+            update::refresh(update::UpdatePolicy::from_flags(force_update, no_update), overrides)?;
+            upgrade::upgrade(&targets, greedy, dry_run, overrides)
+        },
+        Commands::Validate { shard } => {
+            validate::validate(&shard, overrides)
+        },
+        Commands::FixRenames { shard, dry_run } => {
+            renames::fix_renames(&shard, dry_run)
+        },
+        Commands::Dedupe { dry_run } => {
+            dedupe::dedupe(dry_run)
+        },
+        Commands::Export { shard, format, output } => {
+            export::export(&shard, &format, output.as_deref())
+        },
+        Commands::Snapshot { format, output } => {
+            snapshot::snapshot(&format, output.as_deref(), overrides)
+        },
+        Commands::Report { format, output } => {
+            report::report(&format, output.as_deref(), overrides)
+        },
+        Commands::Size { shard } => {
+            size::size(&shard, overrides)
+        },
+        Commands::Freeze { reason } => {
+            freeze::freeze(reason.as_deref())
+        },
+        Commands::Thaw => {
+            freeze::thaw()
+        },
+        Commands::Shellhook { shell, prompt, installed } => match shell {
+            Some(shell) if installed.is_empty() => shellhook::print_hook(&shell, prompt),
+            _ => shellhook::installed(&installed, prompt, overrides),
+        },
+        Commands::Profile { command } => match command {
+            ProfileCommands::Save { name } => profile::save(&name),
+            ProfileCommands::Load { name } => profile::load(&name),
+            ProfileCommands::List => profile::list(),
+            ProfileCommands::Rm { name } => profile::remove(&name),
+        },
+        Commands::Context { command } => match command {
+            ContextCommands::Set { name } => context::set(&name),
+            ContextCommands::Show => context::show(),
+            ContextCommands::Clear => context::clear(),
+        },
+        Commands::Complete { command } => match command {
+            CompleteCommands::Shards => complete::shards(),
+            CompleteCommands::Packages { prefix } => complete::packages(&prefix),
+        },
+        Commands::Doctor { brew } => {
+            doctor::doctor(brew, overrides)
+        },
+        Commands::Brew { args } => {
+            passthrough::brew(&args, overrides)
         },
         Commands::Add { packages, formula, cask, shard, dry_run, exec, apply } => {
-            package::add_packages(&packages, formula, cask, &shard, dry_run, exec, apply)
+            package::add_packages(&packages, formula, cask, &shard, dry_run, exec, apply, overrides)
         },
         Commands::Del { packages, formula, cask, shard, dry_run, exec, apply } => {
-            package::remove_packages(&packages, formula, cask, &shard, dry_run, exec, apply)
+            package::remove_packages(&packages, formula, cask, &shard, dry_run, exec, apply, overrides)
         },
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_mutating(args: &[&str]) -> bool {
+        Cli::try_parse_from([&["shard"], args].concat()).unwrap().command.is_mutating()
+    }
+
+    #[test]
+    fn commands_changing_the_system_are_gated() {
+        assert!(is_mutating(&["apply", "all"]));
+        assert!(is_mutating(&["add", "wget"]));
+        assert!(is_mutating(&["del", "wget"]));
+        assert!(is_mutating(&["shatter", "work"]));
+        assert!(is_mutating(&["upgrade"]));
+        assert!(is_mutating(&["brew", "--", "edit", "wget"]));
+        assert!(is_mutating(&["context", "set", "work"]));
+    }
+
+    #[test]
+    fn previews_and_queries_are_not_gated() {
+        assert!(!is_mutating(&["add", "wget", "--dry-run"]));
+        assert!(!is_mutating(&["del", "wget", "--dry-run"]));
+        assert!(!is_mutating(&["upgrade", "--dry-run"]));
+        assert!(!is_mutating(&["diff", "all"]));
+        assert!(!is_mutating(&["list"]));
+        assert!(!is_mutating(&["search", "wget"]));
+        assert!(!is_mutating(&["report"]));
+        assert!(!is_mutating(&["context", "show"]));
+    }
+}
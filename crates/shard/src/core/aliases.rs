@@ -0,0 +1,50 @@
+//! Package nicknames such as `vscode` for `visual-studio-code`.
+//!
+//! A small built-in table covers common casks with long names. Users add or
+//! override nicknames in the `[shard.aliases]` section of the configuration,
+//! e.g. `code = "visual-studio-code"`. Names without an alias are unchanged.
+
+use std::collections::BTreeMap;
+use crate::core::config::ShardConfig;
+use crate::utils::log_step;
+
+/// Nicknames shipped with shard, none of them is a Homebrew package itself
+pub const BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("chrome", "google-chrome"),
+    ("edge", "microsoft-edge"),
+    ("excel", "microsoft-excel"),
+    ("intellij", "intellij-idea"),
+    ("iterm", "iterm2"),
+    ("outlook", "microsoft-outlook"),
+    ("powerpoint", "microsoft-powerpoint"),
+    ("sublime", "sublime-text"),
+    ("teams", "microsoft-teams"),
+    ("vscode", "visual-studio-code"),
+    ("word", "microsoft-word"),
+];
+
+/// Resolve a nickname to its Homebrew name
+///
+/// Configured aliases win over the built-in ones.
+pub fn resolve<'a>(name: &'a str, configured: &'a BTreeMap<String, String>) -> &'a str {
+    if let Some(target) = configured.get(name) {
+        return target;
+    }
+    BUILTIN_ALIASES.iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, target)| target)
+}
+
+/// Resolve nicknames given on the command line, reporting each one
+pub fn resolve_all(names: &[String]) -> Vec<String> {
+    let configured = ShardConfig::load().map(|config| config.aliases).unwrap_or_default();
+    names.iter()
+        .map(|name| {
+            let resolved = resolve(name, &configured);
+            if resolved != name {
+                log_step(&format!("Using {} for {}", resolved, name));
+            }
+            resolved.to_string()
+        })
+        .collect()
+}
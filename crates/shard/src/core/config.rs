@@ -0,0 +1,314 @@
+//! Shard settings stored in the shared Sapphire configuration file.
+//!
+//! Shard reads the top-level `mode` and the `[shard]` section of
+//! `~/.sapphire/config.toml`. Missing files, sections and keys fall back to
+//! defaults, so shard works without any configuration. Values written by
+//! `sapphire config set` are always strings, so booleans are also accepted as
+//! `"true"`/`"false"`.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use sapphire_core::paths::SapphirePaths;
+use crate::core::ignore::IgnoreList;
+use crate::core::env::EnvVars;
+use crate::utils::{ShardError, ShardResult, ResultExt, path_exists};
+
+/// When unattended runs send a notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NotifyThreshold {
+    /// After every run
+    Always,
+    /// Only if packages changed, drift was detected or an action failed
+    #[default]
+    Changes,
+}
+
+/// Desktop notification settings
+#[derive(Debug, Clone, Default)]
+pub struct NotificationConfig {
+    /// `notify`: send notifications when running without a terminal
+    pub enabled: bool,
+    /// `notify_threshold`: `"always"` or `"changes"`
+    pub threshold: NotifyThreshold,
+}
+
+/// Package type `shard add` picks for names that exist as formula and cask
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PackagePreference {
+    Formula,
+    Cask,
+    /// Prompt, falling back to the formula without a terminal
+    #[default]
+    Ask,
+}
+
+/// How many shard backups are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupRetention {
+    /// `backup_max_count`: backups kept per shard, 0 keeps all
+    pub max_count: usize,
+    /// `backup_max_age_days`: older backups are deleted, 0 keeps all
+    pub max_age_days: u64,
+}
+
+impl Default for BackupRetention {
+    fn default() -> Self {
+        Self {
+            max_count: 10,
+            max_age_days: 90,
+        }
+    }
+}
+
+/// Which brew shard runs and how
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrewSettings {
+    /// `brew_path`: brew executable, detected if unset
+    pub path: Option<String>,
+    /// `no_auto_update`: set `HOMEBREW_NO_AUTO_UPDATE` for every brew process
+    pub no_auto_update: bool,
+    /// `no_env_hints`: set `HOMEBREW_NO_ENV_HINTS` for every brew process
+    pub no_env_hints: bool,
+    /// `update_interval_hours`: age after which apply and upgrade run
+    /// `brew update` first, 0 never updates automatically
+    pub update_interval_hours: u64,
+    /// `batch_size`: packages per brew invocation of batch installs and
+    /// upgrades, 0 puts all of them in one
+    pub batch_size: usize,
+    /// `parallel_batches`: run the batches at the same time instead of one after another
+    pub parallel_batches: bool,
+    /// `install_timeout_secs`: time brew may take to install, upgrade, fetch or
+    /// uninstall before it is stopped, 0 waits forever
+    pub install_timeout_secs: u64,
+    /// `query_timeout_secs`: time brew may take for any other command, 0 waits forever
+    pub query_timeout_secs: u64,
+    /// `retries`: times an install, upgrade or fetch that timed out or failed
+    /// to download is run again
+    pub retries: u32,
+}
+
+impl Default for BrewSettings {
+    fn default() -> Self {
+        Self {
+            path: None,
+            no_auto_update: true,
+            no_env_hints: true,
+            update_interval_hours: 24,
+            batch_size: 10,
+            parallel_batches: false,
+            install_timeout_secs: 30 * 60,
+            query_timeout_secs: 60,
+            retries: 0,
+        }
+    }
+}
+
+/// How console output looks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputSettings {
+    /// `glyphs`: mark messages with ✓ and →, or ASCII like `ok` and `->` if false
+    pub glyphs: bool,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self { glyphs: true }
+    }
+}
+
+/// When `apply all` asks before changing things
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptSettings {
+    /// `uninstall_prompt_threshold`: uninstalls above which a checklist lets
+    /// the user keep packages, 0 never asks
+    pub uninstall_threshold: usize,
+}
+
+impl Default for PromptSettings {
+    fn default() -> Self {
+        Self { uninstall_threshold: 5 }
+    }
+}
+
+/// Settings relevant to shard
+#[derive(Debug, Clone, Default)]
+pub struct ShardConfig {
+    /// `mode = "managed"`: system shards are shared by all users
+    pub managed: bool,
+    pub notifications: NotificationConfig,
+    pub backups: BackupRetention,
+    /// `cask_options`: brew options for every cask, e.g. `["--no-quarantine"]`
+    pub cask_options: Vec<String>,
+    /// `[shard.env]`: variables passed to brew, e.g. tokens for private taps
+    pub env: EnvVars,
+    /// `prefer`: `"formula"`, `"cask"` or `"ask"`
+    pub prefer: PackagePreference,
+    pub brew: BrewSettings,
+    /// `[shard.aliases]`: package nicknames, e.g. `code = "visual-studio-code"`
+    pub aliases: BTreeMap<String, String>,
+    /// `ignore`: names and globs of packages shard leaves alone, e.g. `["python@*"]`
+    pub ignore: IgnoreList,
+    pub output: OutputSettings,
+    pub prompts: PromptSettings,
+    /// `webhook_url`: URL the summary of every apply is posted to as JSON
+    pub webhook_url: Option<String>,
+}
+
+impl ShardConfig {
+    /// Load the configuration, using defaults if the file does not exist
+    pub fn load() -> ShardResult<Self> {
+        let path = config_path();
+        if !path_exists(&path) {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read configuration file: {}", path.display()))?;
+        let config: toml::Table = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse configuration file: {}", path.display()))?;
+
+        Self::from_table(&config)
+    }
+
+    /// Extract the shard settings from a parsed configuration file
+    pub fn from_table(config: &toml::Table) -> ShardResult<Self> {
+        let managed = config.get("mode").and_then(toml::Value::as_str) == Some("managed");
+
+        let Some(section) = config.get("shard").and_then(toml::Value::as_table) else {
+            return Ok(Self { managed, ..Self::default() });
+        };
+
+        let threshold = match section.get("notify_threshold").and_then(toml::Value::as_str) {
+            None | Some("changes") => NotifyThreshold::Changes,
+            Some("always") => NotifyThreshold::Always,
+            Some(other) => return Err(ShardError::ValidationError(format!(
+                "Invalid shard.notify_threshold '{}', expected \"always\" or \"changes\"", other))),
+        };
+
+        let prefer = match section.get("prefer").and_then(toml::Value::as_str) {
+            None | Some("ask") => PackagePreference::Ask,
+            Some("formula") => PackagePreference::Formula,
+            Some("cask") => PackagePreference::Cask,
+            Some(other) => return Err(ShardError::ValidationError(format!(
+                "Invalid shard.prefer '{}', expected \"formula\", \"cask\" or \"ask\"", other))),
+        };
+
+        let defaults = BackupRetention::default();
+        let brew_defaults = BrewSettings::default();
+
+        let brew_path = match section.get("brew_path") {
+            None => None,
+            Some(toml::Value::String(path)) if path.is_empty() => None,
+            Some(toml::Value::String(path)) => Some(shellexpand::tilde(path).into_owned()),
+            Some(other) => return Err(ShardError::ValidationError(format!(
+                "Invalid shard.brew_path {}, expected a path", other))),
+        };
+
+        let webhook_url = match section.get("webhook_url") {
+            None => None,
+            Some(toml::Value::String(url)) if url.is_empty() => None,
+            Some(toml::Value::String(url)) if url.starts_with("https://") || url.starts_with("http://") => Some(url.clone()),
+            Some(other) => return Err(ShardError::ValidationError(format!(
+                "Invalid shard.webhook_url {}, expected an http:// or https:// URL", other))),
+        };
+
+        Ok(Self {
+            managed,
+            notifications: NotificationConfig {
+                enabled: get_bool(section, "notify")?.unwrap_or(false),
+                threshold,
+            },
+            backups: BackupRetention {
+                max_count: get_u64(section, "backup_max_count")?.map_or(defaults.max_count, |count| count as usize),
+                max_age_days: get_u64(section, "backup_max_age_days")?.unwrap_or(defaults.max_age_days),
+            },
+            cask_options: get_list(section, "cask_options")?,
+            aliases: get_aliases(section)?,
+            ignore: IgnoreList::new(get_list(section, "ignore")?),
+            env: get_env(section)?,
+            prefer,
+            brew: BrewSettings {
+                path: brew_path,
+                no_auto_update: get_bool(section, "no_auto_update")?.unwrap_or(brew_defaults.no_auto_update),
+                no_env_hints: get_bool(section, "no_env_hints")?.unwrap_or(brew_defaults.no_env_hints),
+                update_interval_hours: get_u64(section, "update_interval_hours")?.unwrap_or(brew_defaults.update_interval_hours),
+                batch_size: get_u64(section, "batch_size")?.map_or(brew_defaults.batch_size, |size| size as usize),
+                parallel_batches: get_bool(section, "parallel_batches")?.unwrap_or(brew_defaults.parallel_batches),
+                install_timeout_secs: get_u64(section, "install_timeout_secs")?.unwrap_or(brew_defaults.install_timeout_secs),
+                query_timeout_secs: get_u64(section, "query_timeout_secs")?.unwrap_or(brew_defaults.query_timeout_secs),
+                retries: get_u64(section, "retries")?.map_or(brew_defaults.retries, |retries| retries as u32),
+            },
+            output: OutputSettings {
+                glyphs: get_bool(section, "glyphs")?.unwrap_or(true),
+            },
+            prompts: PromptSettings {
+                uninstall_threshold: get_u64(section, "uninstall_prompt_threshold")?
+                    .map_or(PromptSettings::default().uninstall_threshold, |count| count as usize),
+            },
+            webhook_url,
+        })
+    }
+}
+
+fn config_path() -> PathBuf {
+    SapphirePaths::resolve().config_file()
+}
+
+/// Read a boolean that may also be stored as a string
+fn get_bool(section: &toml::Table, key: &str) -> ShardResult<Option<bool>> {
+    match section.get(key) {
+        None => Ok(None),
+        Some(toml::Value::Boolean(value)) => Ok(Some(*value)),
+        Some(toml::Value::String(value)) => value.parse().map(Some).map_err(|_| {
+            ShardError::ValidationError(format!("Invalid shard.{} '{}', expected true or false", key, value))
+        }),
+        Some(other) => Err(ShardError::ValidationError(format!(
+            "Invalid shard.{} {}, expected true or false", key, other))),
+    }
+}
+
+/// Read a list of strings, a single string is split on whitespace
+fn get_list(section: &toml::Table, key: &str) -> ShardResult<Vec<String>> {
+    match section.get(key) {
+        None => Ok(Vec::new()),
+        Some(toml::Value::String(value)) => Ok(value.split_whitespace().map(str::to_string).collect()),
+        Some(toml::Value::Array(values)) => values.iter()
+            .map(|value| value.as_str().map(str::to_string).ok_or_else(|| ShardError::ValidationError(
+                format!("Invalid shard.{}: {} is not a string", key, value))))
+            .collect(),
+        Some(other) => Err(ShardError::ValidationError(format!(
+            "Invalid shard.{} {}, expected a list of strings", key, other))),
+    }
+}
+
+/// Read the `aliases` table of nicknames and package names
+fn get_aliases(section: &toml::Table) -> ShardResult<BTreeMap<String, String>> {
+    match section.get("aliases") {
+        None => Ok(BTreeMap::new()),
+        Some(aliases) => aliases.clone().try_into().map_err(|e| ShardError::ValidationError(format!(
+            "Invalid shard.aliases: {}", e))),
+    }
+}
+
+/// Read the `env` table of literal values and keychain references
+fn get_env(section: &toml::Table) -> ShardResult<EnvVars> {
+    match section.get("env") {
+        None => Ok(EnvVars::new()),
+        Some(env) => env.clone().try_into().map_err(|e| ShardError::ValidationError(format!(
+            "Invalid shard.env: {}", e))),
+    }
+}
+
+/// Read a non-negative integer that may also be stored as a string
+fn get_u64(section: &toml::Table, key: &str) -> ShardResult<Option<u64>> {
+    let invalid = |value: &dyn std::fmt::Display| {
+        ShardError::ValidationError(format!("Invalid shard.{} '{}', expected a non-negative number", key, value))
+    };
+
+    match section.get(key) {
+        None => Ok(None),
+        Some(toml::Value::Integer(value)) => u64::try_from(*value).map(Some).map_err(|_| invalid(value)),
+        Some(toml::Value::String(value)) => value.parse().map(Some).map_err(|_| invalid(value)),
+        Some(other) => Err(invalid(other)),
+    }
+}
@@ -0,0 +1,96 @@
+//! Environment variables injected into spawned brew processes.
+//!
+//! Private taps and casks often need credentials such as
+//! `HOMEBREW_GITHUB_API_TOKEN`. They are declared in the `[shard.env]` section
+//! of the configuration file or the `[env]` table of a manifest, either as a
+//! literal value, a secret stored with `sapphire secret set` or a reference to
+//! any macOS keychain item:
+//!
+//! ```toml
+//! [env]
+//! HOMEBREW_GITHUB_API_TOKEN = { secret = "github-token" }
+//! ARTIFACTORY_TOKEN = { keychain = "artifactory", account = "me" }
+//! ARTIFACTORY_HOST = "artifacts.example.com"
+//! ```
+//!
+//! Values are only resolved right before brew runs and are never logged.
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use sapphire_core::secrets;
+use crate::utils::{ShardError, ShardResult};
+
+/// Environment variables by name
+pub type EnvVars = BTreeMap<String, EnvValue>;
+
+/// Value of an injected environment variable
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EnvValue {
+    /// Literal value
+    Value(String),
+    /// Secret stored with `sapphire secret set`
+    Secret { secret: String },
+    /// Password of a generic keychain item, looked up with `security`
+    Keychain {
+        keychain: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account: Option<String>,
+    },
+}
+
+// Literal values may be secrets, so they are never printed
+impl std::fmt::Debug for EnvValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvValue::Value(_) => write!(f, "Value(<redacted>)"),
+            EnvValue::Secret { secret } => f.debug_struct("Secret").field("secret", secret).finish(),
+            EnvValue::Keychain { keychain, account } => f.debug_struct("Keychain")
+                .field("keychain", keychain)
+                .field("account", account)
+                .finish(),
+        }
+    }
+}
+
+impl EnvValue {
+    /// Resolve the value, reading keychain items as needed
+    pub fn resolve(&self) -> ShardResult<String> {
+        match self {
+            EnvValue::Value(value) => Ok(value.clone()),
+            EnvValue::Secret { secret } => Ok(secrets::get(secret)?),
+            EnvValue::Keychain { keychain, account } => Ok(secrets::read_keychain_item(keychain, account.as_deref())?),
+        }
+    }
+}
+
+/// Layer environment tables, later tables override earlier ones
+pub fn merge<'a>(layers: impl IntoIterator<Item = &'a EnvVars>) -> EnvVars {
+    layers.into_iter()
+        .flat_map(|vars| vars.iter().map(|(name, value)| (name.clone(), value.clone())))
+        .collect()
+}
+
+/// Resolve all variables into name/value pairs for a child process
+pub fn resolve(vars: &EnvVars) -> ShardResult<Vec<(String, String)>> {
+    vars.iter()
+        .map(|(name, value)| {
+            validate_name(name)?;
+            let value = value.resolve().map_err(|e| ShardError::ValidationError(format!(
+                "Cannot set {}: {}", name, e)))?;
+            Ok((name.clone(), value))
+        })
+        .collect()
+}
+
+/// Only allow conventional variable names
+fn validate_name(name: &str) -> ShardResult<()> {
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ShardError::ValidationError(format!("Invalid environment variable name: '{}'", name)))
+    }
+}
@@ -1,39 +1,193 @@
-use serde::{Deserialize, Serialize};
-use crate::utils::ShardResult;
+use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::core::env::EnvVars;
+use sapphire_core::requirements::Requirements;
+use crate::utils::{ShardResult, ShardError};
 use std::path::Path;
 use anyhow::Context;
 use crate::utils::filesystem;
-use crate::utils::log_debug;
+use crate::utils::{log_debug, log_warning};
+
+/// Current manifest schema version
+///
+/// Bump this and register a migration in [`MIGRATIONS`] whenever the on-disk
+/// format changes.
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
 
 /// Package manifest for Shard
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Manifest {
-    /// Array of formula names - preferred over structured formulas
+    /// Array of formula names
     #[serde(default)]
     pub formulae: Vec<String>,
     
-    /// Array of cask names - preferred over structured casks
+    /// Array of cask names
     #[serde(default)]
     pub casks: Vec<String>,
     
-    /// Array of tap names - preferred over structured taps
+    /// Array of tap names or `{ name, url }` tables for taps outside GitHub
     #[serde(default)]
-    pub taps: Vec<String>,
+    pub taps: Vec<Tap>,
+    
+    /// VS Code extension IDs, e.g. `rust-lang.rust-analyzer`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vscode_extensions: Vec<String>,
+    
+    /// Extra brew options per cask, e.g. `--appdir=/Applications/Utilities`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cask_options: BTreeMap<String, Vec<String>>,
+    
+    /// Build options per formula, e.g. `--with-openssl` for formulae of taps
+    /// that still offer options (see `brew options <formula>`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub formula_options: BTreeMap<String, Vec<String>>,
     
-    /// Legacy structured formulas representation
-    #[serde(default, skip_serializing)]
-    pub formulas: Vec<Formula>,
+    /// How formulae are built when not installed from a bottle
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub formula_builds: BTreeMap<String, FormulaBuild>,
     
-    /// Legacy structured casks representation
-    #[serde(default, skip_serializing)]
-    pub casks_structured: Vec<Cask>,
+    /// Environment passed to brew while applying, e.g. credentials for private taps
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: EnvVars,
     
-    /// Legacy structured taps representation
-    #[serde(default, skip_serializing)]
-    pub taps_structured: Vec<Tap>,
+    /// Why a package is declared, e.g. `jq = "needed for deploy scripts"`
+    ///
+    /// Formulae and casks may also be written as `{ name = "jq", comment = "..." }`,
+    /// their comments are read into this table.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub comments: BTreeMap<String, String>,
+    
+    /// Contexts a package is declared for, e.g. `slack = ["work"]`
+    ///
+    /// Packages without contexts apply everywhere, the others only while one
+    /// of their contexts is active (see `shard context`). Entries may also be
+    /// written as `{ name = "slack", context = "work" }` or with a list of
+    /// contexts, which are read into this table.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub contexts: BTreeMap<String, Vec<String>>,
+    
+    /// Whether formulae are linked into the Homebrew prefix, e.g.
+    /// `"python@3.11" = true` or `curl = "force"` for keg-only formulae
+    ///
+    /// Only one version of a formula can be linked, linking one unlinks the
+    /// others. Entries may also be written as `{ name = "python@3.11", link = true }`.
+    /// Formulae without an entry keep whatever brew decided.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: BTreeMap<String, FormulaLink>,
+    
+    /// Checks the machine has to pass before the shard is applied, e.g.
+    /// `macos = "14.0"` (see [`sapphire_core::requirements`])
+    #[serde(default, skip_serializing_if = "Requirements::is_empty")]
+    pub requires: Requirements,
     
     #[serde(default)]
     pub metadata: Metadata,
+    
+    /// Other sections, e.g. `[npm]`, handed as they are to the plugin that
+    /// claims them (see [`crate::plugins`])
+    #[serde(flatten)]
+    pub custom_sections: BTreeMap<String, toml::Value>,
+}
+
+/// Requested build mode of a formula, e.g. `neovim = { head = true }`
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FormulaBuild {
+    /// Compile from source instead of pouring a bottle
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub build_from_source: bool,
+    
+    /// Install the development version from the HEAD of the repository
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub head: bool,
+}
+
+impl FormulaBuild {
+    /// Arguments passed to `brew install`
+    pub fn install_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.build_from_source {
+            args.push("--build-from-source".to_string());
+        }
+        if self.head {
+            args.push("--HEAD".to_string());
+        }
+        args
+    }
+    
+    /// Arguments passed to `brew upgrade`, which only refetches HEAD builds
+    pub fn upgrade_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.build_from_source {
+            args.push("--build-from-source".to_string());
+        }
+        if self.head {
+            args.push("--fetch-HEAD".to_string());
+        }
+        args
+    }
+    
+    /// Short description, `bottle` for the default
+    pub fn describe(&self) -> &'static str {
+        match (self.head, self.build_from_source) {
+            (true, _) => "HEAD",
+            (false, true) => "source",
+            (false, false) => "bottle",
+        }
+    }
+}
+
+/// Requested link state of a formula, written as `true`, `false` or `"force"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormulaLink {
+    Unlinked,
+    Linked,
+    /// Linked even if keg-only, which brew only does with `--force`
+    Forced,
+}
+
+impl FormulaLink {
+    pub fn is_linked(&self) -> bool {
+        !matches!(self, FormulaLink::Unlinked)
+    }
+    
+    /// Short description, e.g. `force link`
+    pub fn describe(&self) -> &'static str {
+        match self {
+            FormulaLink::Unlinked => "unlink",
+            FormulaLink::Linked => "link",
+            FormulaLink::Forced => "force link",
+        }
+    }
+}
+
+impl Serialize for FormulaLink {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            FormulaLink::Unlinked => serializer.serialize_bool(false),
+            FormulaLink::Linked => serializer.serialize_bool(true),
+            FormulaLink::Forced => serializer.serialize_str("force"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FormulaLink {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bool(bool),
+            Text(String),
+        }
+        
+        match Raw::deserialize(deserializer)? {
+            Raw::Bool(true) => Ok(FormulaLink::Linked),
+            Raw::Bool(false) => Ok(FormulaLink::Unlinked),
+            Raw::Text(text) if text == "force" => Ok(FormulaLink::Forced),
+            Raw::Text(other) => Err(serde::de::Error::custom(format!(
+                "invalid link '{}', expected true, false or \"force\"", other))),
+        }
+    }
 }
 
 /// Metadata for the manifest
@@ -51,21 +205,81 @@ pub struct Metadata {
     #[serde(default)]
     pub owner: String,
     
+    /// Whether `owner` names a group whose members all own the shard
+    #[serde(default)]
+    pub shared: bool,
+    
     /// Whether this shard is protected from modifications
     #[serde(default)]
     pub protected: bool,
     
-    /// Shard schema version
+    /// Shard version
     #[serde(default)]
     pub version: String,
     
+    /// Manifest schema version, used to migrate older formats on load
+    #[serde(default)]
+    pub schema_version: u32,
+    
     /// List of users allowed to modify this shard even if protected
     #[serde(default)]
     pub allowed_users: Vec<String>,
     
-    /// DEPRECATED: Protection level (use 'protected' boolean instead)
-    #[serde(default, skip_serializing)]
-    pub protection_level: u8,
+    /// Sections applied from this shard, e.g. `["formulae"]`, all if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub apply: Vec<ManifestSection>,
+    
+    /// When shard first wrote the manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    
+    /// When shard last wrote the manifest, hand edits are not tracked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<DateTime<Utc>>,
+    
+    /// User who ran the command that last wrote the manifest
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub modified_by: String,
+}
+
+/// Part of a manifest that can be applied on its own
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestSection {
+    Taps,
+    #[serde(alias = "formulas")]
+    Formulae,
+    Casks,
+    #[serde(rename = "vscode_extensions")]
+    VscodeExtensions,
+}
+
+impl ManifestSection {
+    /// Parse a section name as given on the command line
+    pub fn parse(value: &str) -> ShardResult<Self> {
+        match value.to_lowercase().as_str() {
+            "taps" | "tap" => Ok(ManifestSection::Taps),
+            "formulae" | "formulas" | "formula" => Ok(ManifestSection::Formulae),
+            "casks" | "cask" => Ok(ManifestSection::Casks),
+            "vscode_extensions" | "vscode" | "extensions" => Ok(ManifestSection::VscodeExtensions),
+            other => Err(ShardError::ValidationError(format!(
+                "Unknown section '{}', expected \"taps\", \"formulae\", \"casks\" or \"vscode_extensions\"", other))),
+        }
+    }
+    
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ManifestSection::Taps => "taps",
+            ManifestSection::Formulae => "formulae",
+            ManifestSection::Casks => "casks",
+            ManifestSection::VscodeExtensions => "vscode_extensions",
+        }
+    }
+    
+    /// Whether a section is part of a selection, an empty one selects all
+    pub fn selected(self, selection: &[ManifestSection]) -> bool {
+        selection.is_empty() || selection.contains(&self)
+    }
 }
 
 /// Package state (present, absent, latest) - kept for compatibility
@@ -107,10 +321,68 @@ pub struct Cask {
     pub state: PackageState,
 }
 
-/// Homebrew tap - legacy format
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Homebrew tap
+///
+/// Written as a plain name unless it has a custom remote, e.g.
+/// `{ name = "org/tap", url = "git@github.com:org/homebrew-tap.git" }`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(from = "TapEntry", into = "TapEntry")]
 pub struct Tap {
     pub name: String,
+    
+    /// Git remote to clone instead of `github.com/<user>/homebrew-<repo>`
+    pub url: Option<String>,
+}
+
+impl From<String> for Tap {
+    fn from(name: String) -> Self {
+        Self { name, url: None }
+    }
+}
+
+impl From<&str> for Tap {
+    fn from(name: &str) -> Self {
+        Self::from(name.to_string())
+    }
+}
+
+impl std::fmt::Display for Tap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.url {
+            Some(url) => write!(f, "{} ({})", self.name, url),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// On-disk form of a [`Tap`]
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum TapEntry {
+    Name(String),
+    Remote {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+}
+
+impl From<TapEntry> for Tap {
+    fn from(entry: TapEntry) -> Self {
+        match entry {
+            TapEntry::Name(name) => Self { name, url: None },
+            TapEntry::Remote { name, url } => Self { name, url },
+        }
+    }
+}
+
+impl From<Tap> for TapEntry {
+    fn from(tap: Tap) -> Self {
+        match tap.url {
+            Some(url) => TapEntry::Remote { name: tap.name, url: Some(url) },
+            None => TapEntry::Name(tap.name),
+        }
+    }
 }
 
 fn default_version() -> String {
@@ -129,128 +401,599 @@ impl Manifest {
                 name: String::new(),
                 description: "Package manifest".to_string(),
                 owner: String::new(),
+                shared: false,
                 protected: false,
                 version: "0.1.0".to_string(),
+                schema_version: CURRENT_SCHEMA_VERSION,
                 allowed_users: Vec::new(),
-                protection_level: 0,
+                apply: Vec::new(),
+                created_at: None,
+                modified_at: None,
+                modified_by: String::new(),
             },
             formulae: Vec::new(),
             casks: Vec::new(),
             taps: Vec::new(),
-            formulas: Vec::new(),
-            casks_structured: Vec::new(),
-            taps_structured: Vec::new(),
+            vscode_extensions: Vec::new(),
+            cask_options: BTreeMap::new(),
+            formula_options: BTreeMap::new(),
+            formula_builds: BTreeMap::new(),
+            env: BTreeMap::new(),
+            comments: BTreeMap::new(),
+            contexts: BTreeMap::new(),
+            links: BTreeMap::new(),
+            requires: Requirements::default(),
+            custom_sections: BTreeMap::new(),
         }
     }
     
+    /// Check if a user owns this manifest
+    ///
+    /// Manifests without an owner belong to everyone. For shared manifests the
+    /// owner is a group, and `groups` (the user's groups) must contain it.
+    pub fn is_owned_by(&self, username: &str, groups: &[String]) -> bool {
+        let owner = &self.metadata.owner;
+        
+        owner.is_empty()
+            || self.metadata.allowed_users.iter().any(|user| user == username)
+            || if self.metadata.shared { groups.contains(owner) } else { owner == username }
+    }
+    
     /// Check if a user is allowed to modify this manifest
-    pub fn can_modify(&self, _username: &str) -> bool {
-        // If not protected, anyone can modify
-        !self.metadata.protected
+    ///
+    /// Protected manifests can only be modified by `allowed_users`, all others
+    /// by their owners.
+    pub fn can_modify(&self, username: &str, groups: &[String]) -> bool {
+        self.metadata.allowed_users.iter().any(|user| user == username)
+            || (!self.metadata.protected && self.is_owned_by(username, groups))
     }
     
     /// Load a manifest from a file
+    ///
+    /// The format follows the file extension, see [`ManifestFormat`].
+    /// Manifests written with an older schema are migrated to the current
+    /// schema in memory only. Loading never writes, the file keeps its old
+    /// form and comments until a command changes the shard and saves it.
     pub fn from_file<P: AsRef<Path>>(path: P) -> ShardResult<Self> {
-        log_debug(&format!("Loading manifest from: {}", path.as_ref().display()));
-        let content = std::fs::read_to_string(path.as_ref())
-            .with_context(|| format!("Failed to read manifest file: {}", path.as_ref().display()))?;
+        let path = path.as_ref();
+        log_debug(&format!("Loading manifest from: {}", path.display()));
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest file: {}", path.display()))?;
         
-        // Parse the TOML content
-        let mut parsed: Manifest = toml::from_str(&content)
-            .with_context(|| format!("Failed to parse manifest file: {}", path.as_ref().display()))?;
-            
-        // Handle migration between the different formats
-        
-        // 1. Check if we have structured formulas but no simple formulae array
-        if !parsed.formulas.is_empty() && parsed.formulae.is_empty() {
-            // Migrate to simple format
-            for formula in &parsed.formulas {
-                if !parsed.formulae.contains(&formula.name) {
-                    parsed.formulae.push(formula.name.clone());
-                }
-            }
-        }
-        
-        // 2. Check if we have structured casks but no simple casks array
-        if !parsed.casks_structured.is_empty() && parsed.casks.is_empty() {
-            // Migrate to simple format
-            for cask in &parsed.casks_structured {
-                if !parsed.casks.contains(&cask.name) {
-                    parsed.casks.push(cask.name.clone());
-                }
-            }
-        }
+        // Parse into a raw value first so migrations can rewrite old layouts
+        let mut raw: toml::Value = match ManifestFormat::from_path(path) {
+            ManifestFormat::Toml => toml::from_str(&content)
+                .with_context(|| format!("Failed to parse manifest file: {}", path.display()))?,
+            ManifestFormat::Yaml => serde_yaml::from_str(&content)
+                .with_context(|| format!("Failed to parse manifest file: {}", path.display()))?,
+            ManifestFormat::Json => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse manifest file: {}", path.display()))?,
+        };
         
-        // 3. Check if we have structured taps but no simple taps array
-        if !parsed.taps_structured.is_empty() && parsed.taps.is_empty() {
-            // Migrate to simple format
-            for tap in &parsed.taps_structured {
-                if !parsed.taps.contains(&tap.name) {
-                    parsed.taps.push(tap.name.clone());
-                }
-            }
+        migrate(&mut raw, path)?;
+        if let Some(table) = raw.as_table_mut() {
+            lift_inline_entries(table);
         }
         
-        // 4. Migrate from 'brews' field if it exists in the raw TOML (backward compatibility)
-        if let Ok(raw_value) = toml::from_str::<toml::Value>(&content) {
-            // Process legacy 'brews' field for casks
-            if let Some(brews) = raw_value.get("brews").and_then(|v| v.as_array()) {
-                for brew in brews {
-                    if let Some(name) = brew.as_str() {
-                        let name_string = name.to_string();
-                        if !parsed.casks.contains(&name_string) {
-                            parsed.casks.push(name_string);
-                        }
-                    }
-                }
-            }
-        }
+        let parsed: Manifest = raw.try_into()
+            .with_context(|| format!("Failed to parse manifest file: {}", path.display()))?;
         
         Ok(parsed)
     }
     
-    /// Save a manifest to a file - outputs simplified format
+    /// Save a manifest to a file, in the format of its extension
+    ///
+    /// Refused in read-only mode (see [`sapphire_core::read_only`]), whichever
+    /// command tries it.
     pub fn to_file<P: AsRef<Path>>(&self, path: P) -> ShardResult<()> {
         log_debug(&format!("Saving manifest to: {}", path.as_ref().display()));
+        sapphire_core::read_only::ensure_writable()?;
         
-        // Create a simplified representation for serialization
-        let simplified = SimplifiedManifest {
-            formulae: self.formulae.clone(),
-            casks: self.casks.clone(),
-            taps: self.taps.clone(),
-            metadata: self.metadata.clone(),
+        let content = match ManifestFormat::from_path(path.as_ref()) {
+            ManifestFormat::Toml => toml::to_string_pretty(self)
+                .with_context(|| "Failed to serialize manifest to TOML")?,
+            ManifestFormat::Yaml => serde_yaml::to_string(self)
+                .with_context(|| "Failed to serialize manifest to YAML")?,
+            ManifestFormat::Json => serde_json::to_string_pretty(self)
+                .with_context(|| "Failed to serialize manifest to JSON")? + "\n",
         };
         
-        // Serialize to TOML
-        let toml_content = toml::to_string_pretty(&simplified)
-            .with_context(|| "Failed to serialize manifest to TOML")?;
-        
         // Ensure parent directory exists
         filesystem::ensure_parent_dir_exists(path.as_ref())?;
         
         // Write to file
-        std::fs::write(path.as_ref(), &toml_content)
+        std::fs::write(path.as_ref(), &content)
             .with_context(|| format!("Failed to write manifest to file: {}", path.as_ref().display()))?;
         
         Ok(())
     }
     
-    /// Update modification info - placeholder for future use
+    /// Record that the current user is writing the manifest now
+    ///
+    /// Called before every write shard makes, the creation time is set on the
+    /// first one. Manifests from before these fields get it on their next write.
     pub fn update_modification_info(&mut self) {
-        // Currently a placeholder, will be updated with user/timestamp later
+        let now = Utc::now();
+        self.metadata.created_at.get_or_insert(now);
+        self.metadata.modified_at = Some(now);
+        self.metadata.modified_by = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
     }
     
     /// Check if the manifest is protected
     pub fn is_protected(&self) -> bool {
         self.metadata.protected
     }
+    
+    /// Brew options declared for a cask in this manifest
+    pub fn cask_options_for(&self, cask: &str) -> &[String] {
+        self.cask_options.get(cask).map(Vec::as_slice).unwrap_or_default()
+    }
+    
+    /// Build options declared for a formula in this manifest
+    pub fn formula_options_for(&self, formula: &str) -> &[String] {
+        self.formula_options.get(formula).map(Vec::as_slice).unwrap_or_default()
+    }
+    
+    /// Comment explaining why a package is declared
+    pub fn comment_for(&self, package: &str) -> Option<&str> {
+        self.comments.get(package).map(String::as_str)
+    }
+    
+    /// Contexts a package is declared for, empty if it applies everywhere
+    pub fn contexts_for(&self, package: &str) -> &[String] {
+        self.contexts.get(package).map(Vec::as_slice).unwrap_or_default()
+    }
+    
+    /// Whether a package applies while `active` is the active context
+    pub fn applies_in(&self, package: &str, active: Option<&str>) -> bool {
+        in_context(self.contexts_for(package), active)
+    }
+    
+    /// Remove the packages that do not apply in `active` and return them
+    pub fn retain_context(&mut self, active: Option<&str>) -> Vec<(ManifestSection, String)> {
+        let mut removed = Vec::new();
+        for (section, packages) in [
+            (ManifestSection::Formulae, &mut self.formulae),
+            (ManifestSection::Casks, &mut self.casks),
+            (ManifestSection::VscodeExtensions, &mut self.vscode_extensions),
+        ] {
+            packages.retain(|package| {
+                let contexts = self.contexts.get(package).map(Vec::as_slice).unwrap_or_default();
+                let applies = in_context(contexts, active);
+                if !applies {
+                    removed.push((section, package.clone()));
+                }
+                applies
+            });
+        }
+        removed
+    }
+    
+    /// Requested build mode of a formula in this manifest
+    pub fn formula_build(&self, formula: &str) -> FormulaBuild {
+        self.formula_builds.get(formula).copied().unwrap_or_default()
+    }
+}
+
+/// Whether a package declared for `contexts` applies in `active`
+fn in_context(contexts: &[String], active: Option<&str>) -> bool {
+    contexts.is_empty() || active.is_some_and(|active| contexts.iter().any(|context| context == active))
+}
+
+/// Formula name without its version, `python` for `python@3.11`
+pub fn versioned_base(formula: &str) -> &str {
+    formula.split_once('@').map_or(formula, |(base, _)| base)
+}
+
+/// Groups of versions of the same formula that all request linking
+pub fn link_conflicts<'a>(linked: impl IntoIterator<Item = &'a String>) -> Vec<Vec<String>> {
+    let mut by_base: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for formula in linked {
+        by_base.entry(versioned_base(formula)).or_default().push(formula.clone());
+    }
+    by_base.into_values()
+        .filter(|versions| versions.len() > 1)
+        .collect()
+}
+
+/// Combine default brew options with package specific ones
+///
+/// A specific option replaces a default with the same flag, so a cask's
+/// `--appdir=...` wins over a configured default `--appdir`.
+pub fn merge_options(defaults: &[String], specific: &[String]) -> Vec<String> {
+    let flag = |option: &String| option.split('=').next().unwrap_or_default().to_string();
+    let overridden: Vec<String> = specific.iter().map(flag).collect();
+    
+    defaults.iter()
+        .filter(|option| !overridden.contains(&flag(option)))
+        .chain(specific)
+        .cloned()
+        .collect()
+}
+
+/// Replace `{ name, comment, context, link }` entries of formulae, casks
+/// and VS Code extensions by their name
+///
+/// The values move to the `comments`, `contexts` and `links` tables, where a
+/// value given there takes precedence.
+fn lift_inline_entries(table: &mut toml::Table) {
+    let mut comments = Vec::new();
+    let mut contexts = Vec::new();
+    let mut links = Vec::new();
+    for key in ["formulae", "casks", "vscode_extensions"] {
+        let Some(entries) = table.get_mut(key).and_then(|entries| entries.as_array_mut()) else {
+            continue;
+        };
+        for entry in entries.iter_mut() {
+            let toml::Value::Table(inline) = entry else {
+                continue;
+            };
+            let Some(name) = inline.get("name").and_then(|name| name.as_str()).map(str::to_string) else {
+                continue;
+            };
+            if let Some(comment) = inline.get("comment").and_then(|comment| comment.as_str()) {
+                comments.push((name.clone(), toml::Value::String(comment.to_string())));
+            }
+            // A single context or a list of them
+            match inline.get("context") {
+                Some(toml::Value::String(context)) => {
+                    contexts.push((name.clone(), toml::Value::Array(vec![toml::Value::String(context.clone())])));
+                }
+                Some(list @ toml::Value::Array(_)) => contexts.push((name.clone(), list.clone())),
+                _ => {}
+            }
+            if let Some(link) = inline.get("link") {
+                links.push((name.clone(), link.clone()));
+            }
+            *entry = toml::Value::String(name);
+        }
+    }
+    
+    for (key, lifted) in [("comments", comments), ("contexts", contexts), ("links", links)] {
+        if lifted.is_empty() {
+            continue;
+        }
+        let target = table.entry(key)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        if let Some(target) = target.as_table_mut() {
+            for (name, value) in lifted {
+                target.entry(name).or_insert(value);
+            }
+        }
+    }
+}
+
+/// File format of a manifest
+///
+/// All formats share the same model, the format only decides how a shard is
+/// read and written. New shards are created as TOML.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ManifestFormat {
+    #[default]
+    Toml,
+    Yaml,
+    Json,
 }
 
-/// Simplified manifest structure for serialization
-#[derive(Serialize)]
-struct SimplifiedManifest {
-    formulae: Vec<String>,
-    casks: Vec<String>,
-    taps: Vec<String>,
-    metadata: Metadata,
+impl ManifestFormat {
+    /// Extensions of manifest files, in lookup order
+    pub const EXTENSIONS: &[&str] = &["toml", "yaml", "yml", "json"];
+    
+    /// Format for a file extension, `None` if it is not a manifest extension
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "toml" => Some(ManifestFormat::Toml),
+            "yaml" | "yml" => Some(ManifestFormat::Yaml),
+            "json" => Some(ManifestFormat::Json),
+            _ => None,
+        }
+    }
+    
+    /// Format of a manifest path, TOML if the extension is unknown
+    pub fn from_path(path: &Path) -> Self {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_extension)
+            .unwrap_or_default()
+    }
+    
+    /// Whether a path has a manifest extension
+    pub fn is_manifest(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| Self::from_extension(ext).is_some())
+    }
+}
+
+//-------------------------------------------------------------------------------
+// Schema migrations
+//-------------------------------------------------------------------------------
+
+/// A single migration step from one schema version to the next
+struct Migration {
+    /// Schema version this migration upgrades from
+    from: u32,
+    /// Short description for debug output
+    description: &'static str,
+    /// Rewrites the raw manifest in place
+    apply: fn(&mut toml::Table) -> ShardResult<()>,
+}
+
+/// Registry of all migrations, ordered by source version
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "fold legacy structured entries into simple lists",
+        apply: migrate_v0_to_v1,
+    },
+];
+
+/// Run all migrations needed to bring a raw manifest to the current schema
+fn migrate(raw: &mut toml::Value, path: &Path) -> ShardResult<()> {
+    let table = raw.as_table_mut()
+        .ok_or_else(|| ShardError::ManifestError("Manifest must be a table".to_string()))?;
+    
+    let from_version = table.get("metadata")
+        .and_then(|m| m.get("schema_version"))
+        .and_then(|v| v.as_integer())
+        .unwrap_or(0) as u32;
+    
+    if from_version > CURRENT_SCHEMA_VERSION {
+        return Err(ShardError::ManifestError(format!(
+            "Manifest schema v{} is newer than supported v{}", from_version, CURRENT_SCHEMA_VERSION)));
+    }
+    
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from_version) {
+        log_debug(&format!("Applying manifest migration v{}: {}", migration.from, migration.description));
+        (migration.apply)(table)?;
+    }
+    
+    // `brews` held casks despite its name; accept it at any schema version
+    if table.contains_key("brews") {
+        log_warning(&format!("Shard {} uses the deprecated 'brews' field, moving its entries to 'casks'", path.display()));
+        fold_legacy_entries(table, "brews", "casks");
+    }
+    
+    if from_version < CURRENT_SCHEMA_VERSION {
+        log_debug(&format!("Migrated manifest {} from schema v{} to v{}",
+            path.display(), from_version, CURRENT_SCHEMA_VERSION));
+        
+        let metadata = table.entry("metadata")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        if let Some(metadata) = metadata.as_table_mut() {
+            metadata.insert("schema_version".to_string(), toml::Value::Integer(CURRENT_SCHEMA_VERSION as i64));
+        }
+    }
+    
+    Ok(())
+}
+
+/// v0 -> v1: unversioned manifests
+///
+/// - `[[formulas]]`, `[[casks_structured]]` and `[[taps_structured]]` tables are
+///   folded into the simple `formulae`, `casks` and `taps` lists (entries with
+///   `state = "absent"` are dropped)
+/// - options of structured casks move to `[cask_options]`
+/// - `metadata.protection_level` is replaced by `metadata.protected`
+fn migrate_v0_to_v1(table: &mut toml::Table) -> ShardResult<()> {
+    fold_legacy_entries(table, "formulas", "formulae");
+    keep_legacy_cask_options(table);
+    fold_legacy_entries(table, "casks_structured", "casks");
+    fold_legacy_entries(table, "taps_structured", "taps");
+    
+    if let Some(metadata) = table.get_mut("metadata").and_then(|m| m.as_table_mut()) {
+        let level = metadata.remove("protection_level").and_then(|l| l.as_integer()).unwrap_or(0);
+        if level > 0 {
+            metadata.insert("protected".to_string(), toml::Value::Boolean(true));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Copy the options of legacy structured casks into `cask_options`
+fn keep_legacy_cask_options(table: &mut toml::Table) {
+    let options: Vec<(String, toml::Value)> = table.get("casks_structured")
+        .and_then(|legacy| legacy.as_array())
+        .map(|entries| entries.iter()
+            .filter_map(|entry| entry.as_table())
+            .filter_map(|t| Some((t.get("name")?.as_str()?.to_string(), t.get("options")?.clone())))
+            .filter(|(_, options)| options.as_array().is_some_and(|o| !o.is_empty()))
+            .collect())
+        .unwrap_or_default();
+    
+    if options.is_empty() {
+        return;
+    }
+    
+    let target = table.entry("cask_options")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    if let Some(target) = target.as_table_mut() {
+        target.extend(options);
+    }
+}
+
+/// Move the names from a legacy list (of strings or `{ name = ... }` tables)
+/// into a simple string list, skipping duplicates and absent entries
+fn fold_legacy_entries(table: &mut toml::Table, legacy_key: &str, target_key: &str) {
+    let Some(legacy) = table.remove(legacy_key) else {
+        return;
+    };
+    
+    let names: Vec<String> = legacy.as_array()
+        .map(|entries| entries.iter()
+            .filter_map(|entry| match entry {
+                toml::Value::String(name) => Some(name.clone()),
+                toml::Value::Table(t) => {
+                    let absent = t.get("state").and_then(|s| s.as_str()) == Some("absent");
+                    let name = t.get("name").and_then(|n| n.as_str());
+                    name.filter(|_| !absent).map(str::to_string)
+                }
+                _ => None,
+            })
+            .collect())
+        .unwrap_or_default();
+    
+    let target = table.entry(target_key)
+        .or_insert_with(|| toml::Value::Array(Vec::new()));
+    if let Some(target) = target.as_array_mut() {
+        for name in names {
+            let value = toml::Value::String(name);
+            if !target.contains(&value) {
+                target.push(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(owner: &str, shared: bool, protected: bool, allowed_users: &[&str]) -> Manifest {
+        let mut manifest = Manifest::new();
+        manifest.metadata.owner = owner.to_string();
+        manifest.metadata.shared = shared;
+        manifest.metadata.protected = protected;
+        manifest.metadata.allowed_users = allowed_users.iter().map(|user| user.to_string()).collect();
+        manifest
+    }
+
+    fn groups(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// A commented manifest without schema version, written to its own directory
+    fn v0_manifest(test: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("shard-manifest-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("legacy.toml");
+        std::fs::write(&path, V0_MANIFEST).unwrap();
+        path
+    }
+
+    const V0_MANIFEST: &str = "\
+# Kept by hand, do not reformat
+formulae = [{ name = \"jq\", comment = \"JSON on the command line\" }]
+
+[[formulas]]
+name = \"wget\"
+
+[[formulas]]
+name = \"curl\"
+state = \"absent\"
+
+[metadata]
+name = \"legacy\"
+protection_level = 1
+";
+
+    #[test]
+    fn old_manifests_are_migrated_in_memory_only() {
+        let path = v0_manifest("migrate");
+        let manifest = Manifest::from_file(&path).unwrap();
+
+        assert_eq!(manifest.formulae, ["jq", "wget"]);
+        assert!(manifest.metadata.protected);
+        assert_eq!(manifest.metadata.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), V0_MANIFEST);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn manifests_in_directories_users_cannot_write_load() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Like the system layer for users who are not administrators
+        let path = v0_manifest("system-layer");
+        let dir = path.parent().unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o444)).unwrap();
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        let loaded = Manifest::from_file(&path);
+        let content = std::fs::read(&path).unwrap();
+
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert_eq!(loaded.unwrap().formulae, ["jq", "wget"]);
+        assert_eq!(content, V0_MANIFEST.as_bytes());
+    }
+
+    #[test]
+    fn read_only_mode_leaves_manifests_untouched() {
+        let path = v0_manifest("read-only");
+        // SAFETY: no other test reads or writes the read-only variable
+        unsafe { std::env::set_var(sapphire_core::read_only::READ_ONLY_ENV, "1") };
+
+        let manifest = Manifest::from_file(&path).unwrap();
+        let saved = manifest.to_file(&path);
+
+        unsafe { std::env::remove_var(sapphire_core::read_only::READ_ONLY_ENV) };
+        assert!(matches!(saved, Err(ShardError::ReadOnly(_))));
+        assert_eq!(std::fs::read(&path).unwrap(), V0_MANIFEST.as_bytes());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn manifest_without_owner_belongs_to_everyone() {
+        let manifest = manifest("", false, false, &[]);
+        for user in ["alice", "bob", "root"] {
+            assert!(manifest.is_owned_by(user, &[]));
+            assert!(manifest.can_modify(user, &[]));
+        }
+    }
+
+    #[test]
+    fn user_owned_manifest() {
+        let manifest = manifest("alice", false, false, &[]);
+
+        // Owner
+        assert!(manifest.is_owned_by("alice", &groups(&["staff"])));
+        assert!(manifest.can_modify("alice", &groups(&["staff"])));
+        // A group named like the owner does not count for a user-owned shard
+        assert!(!manifest.is_owned_by("bob", &groups(&["staff", "alice"])));
+        assert!(!manifest.can_modify("bob", &groups(&["staff", "alice"])));
+        // Other users
+        assert!(!manifest.can_modify("carol", &groups(&["staff"])));
+        // Root gets no implicit ownership here, the manager lets administrators
+        // change owners and edit the system layer
+        assert!(!manifest.is_owned_by("root", &groups(&["wheel", "admin"])));
+        assert!(!manifest.can_modify("root", &groups(&["wheel", "admin"])));
+    }
+
+    #[test]
+    fn shared_manifest_belongs_to_group_members() {
+        let manifest = manifest("devs", true, false, &[]);
+
+        // Group member
+        assert!(manifest.is_owned_by("bob", &groups(&["staff", "devs"])));
+        assert!(manifest.can_modify("bob", &groups(&["staff", "devs"])));
+        // A user named like the group is not a member
+        assert!(!manifest.is_owned_by("devs", &groups(&["staff"])));
+        // Other users and root outside the group
+        assert!(!manifest.can_modify("carol", &groups(&["staff"])));
+        assert!(!manifest.can_modify("root", &groups(&["wheel", "admin"])));
+    }
+
+    #[test]
+    fn protected_manifest_only_allowed_users_modify() {
+        let manifest = manifest("alice", false, true, &["dave"]);
+
+        // The owner still owns it but cannot modify it
+        assert!(manifest.is_owned_by("alice", &[]));
+        assert!(!manifest.can_modify("alice", &[]));
+        // Allowed users can
+        assert!(manifest.can_modify("dave", &[]));
+        // Everyone else cannot
+        assert!(!manifest.can_modify("carol", &[]));
+        assert!(!manifest.can_modify("root", &groups(&["admin"])));
+    }
+
+    #[test]
+    fn allowed_users_own_unprotected_manifest() {
+        let manifest = manifest("devs", true, false, &["dave"]);
+
+        assert!(manifest.is_owned_by("dave", &groups(&["staff"])));
+        assert!(manifest.can_modify("dave", &groups(&["staff"])));
+    }
 }
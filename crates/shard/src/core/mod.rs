@@ -1,3 +1,7 @@
+pub mod aliases;
 pub mod manifest;
+pub mod config;
+pub mod env;
+pub mod ignore;
 
 // Common types that might be moved here in future refactoring 
\ No newline at end of file
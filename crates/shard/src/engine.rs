@@ -0,0 +1,1039 @@
+//! Programmatic entry point for embedding shard functionality.
+//!
+//! The CLI commands are thin wrappers that print their progress to stdout.
+//! `ShardEngine` exposes the same plan/apply workflow as a library API that
+//! returns structured results and reports progress through a [`Reporter`],
+//! so other tools can drive shard without capturing console output.
+//!
+//! ```no_run
+//! use shard::brew::ClientOverrides;
+//! use shard::engine::{ShardEngine, ApplyOptions};
+//! use shard::reporter::Event;
+//!
+//! let engine = ShardEngine::new(ClientOverrides::default())?.with_reporter(|event: &Event| eprintln!("{:?}", event));
+//! let plan = engine.plan("all")?;
+//! let report = engine.apply(&plan, &ApplyOptions::default())?;
+//! println!("{} action(s) failed", report.failed.len());
+//! # Ok::<(), shard::ShardError>(())
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use crate::brew::{get_client, BatchResults, BrewClient, ClientOverrides, BrewFailure, OutdatedReport};
+use crate::core::config::ShardConfig;
+use crate::core::env::{self, EnvVars};
+use crate::core::ignore::IgnoreList;
+use crate::core::manifest::{link_conflicts as link_conflicts_of, merge_options, versioned_base, FormulaBuild, FormulaLink, Manifest, ManifestSection, Tap};
+use crate::mdm::{ManagedCask, MdmInventory};
+use crate::package::processor::{PackageProcessor, PackageType};
+use crate::plugins::{self, Plugin, PluginPlan};
+use crate::reporter::{Event, Reporter, NullReporter};
+use crate::shard::context;
+use crate::shard::manager::ShardManager;
+use crate::utils::{ShardError, ShardResult, ResultExt};
+use crate::utils::filesystem::{path_exists, resolve_manifest_path};
+use crate::vscode;
+
+/// Packages that are never uninstalled when synchronizing
+const CRITICAL_PACKAGES: &[&str] = &["git", "brew", "curl", "openssl", "python", "fish", "bash", "zsh"];
+
+/// Seconds a cask install or upgrade may take in unattended runs before it
+/// is assumed to wait for input
+const UNATTENDED_CASK_TIMEOUT_SECS: u64 = 1800;
+
+/// Errors of installers that asked for a password or other input
+const PROMPT_ERRORS: &[&str] = &[
+    "a terminal is required to read the password",
+    "a password is required",
+    "no tty present",
+    "timed out after",
+];
+
+/// How a plan treats packages that are not declared in the loaded shards
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApplyMode {
+    /// Only install and upgrade, never uninstall (single shard)
+    #[default]
+    Additive,
+    /// Uninstall packages not declared in any enabled shard ("all")
+    Synchronize,
+}
+
+/// A single change to the system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    AddTap { name: String, url: Option<String> },
+    Install { package_type: PackageType, name: String },
+    Upgrade { package_type: PackageType, name: String },
+    Uninstall { package_type: PackageType, name: String },
+    /// Extensions are only installed, never uninstalled, since VS Code
+    /// installs its own and users add others from the editor
+    InstallExtension { name: String },
+    /// Link a formula into the Homebrew prefix, or unlink it
+    Link { name: String, link: FormulaLink },
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::AddTap { name, .. } => write!(f, "add tap {}", name),
+            Action::Install { package_type, name } => write!(f, "install {} {}", package_type.as_str(), name),
+            Action::Upgrade { package_type, name } => write!(f, "upgrade {} {}", package_type.as_str(), name),
+            Action::Uninstall { package_type, name } => write!(f, "uninstall {} {}", package_type.as_str(), name),
+            Action::InstallExtension { name } => write!(f, "install extension {}", name),
+            Action::Link { name, link } => write!(f, "{} formula {}", link.describe(), name),
+        }
+    }
+}
+
+impl Action {
+    /// Name of the tap or package this action targets
+    pub fn name(&self) -> &str {
+        match self {
+            Action::AddTap { name, .. }
+            | Action::Install { name, .. }
+            | Action::Upgrade { name, .. }
+            | Action::Uninstall { name, .. }
+            | Action::InstallExtension { name }
+            | Action::Link { name, .. } => name,
+        }
+    }
+}
+
+/// A declared package left out because none of its contexts is active
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextSkip {
+    pub section: ManifestSection,
+    pub name: String,
+    /// Contexts the package is declared for
+    pub contexts: Vec<String>,
+}
+
+/// An undeclared formula left installed because a package that stays depends on it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyHold {
+    pub formula: String,
+    /// Dependents from the one requiring `formula` directly up to the package
+    /// that stays installed, e.g. `["ffmpeg", "yt-dlp"]`
+    pub chain: Vec<String>,
+}
+
+impl DependencyHold {
+    /// The chain from the package that stays down to the formula, e.g. `yt-dlp → ffmpeg → x264`
+    pub fn describe(&self) -> String {
+        let mut names: Vec<&str> = self.chain.iter().rev().map(String::as_str).collect();
+        names.push(&self.formula);
+        names.join(" → ")
+    }
+}
+
+/// A `requires` check of a shard this machine does not pass
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnmetRequirement {
+    pub shard: String,
+    /// What is missing, e.g. `command 'xcode-select' is not installed or not on the PATH`
+    pub problem: String,
+}
+
+/// Changes required to bring the system in line with a set of shards
+///
+/// The default is an empty additive plan.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub mode: ApplyMode,
+    /// Names of the shards this plan was built from
+    pub shards: Vec<String>,
+    /// Actions in execution order: taps, formulae, casks, VS Code extensions,
+    /// uninstalls of casks and formulae, dependents first, then link changes
+    ///
+    /// Installed packages are only upgraded if `outdated` lists them and they
+    /// are not pinned.
+    pub actions: Vec<Action>,
+    /// Formulae the planned shards declare, installed or not
+    pub formulae: Vec<String>,
+    /// Casks the planned shards declare, installed or not
+    pub casks: Vec<String>,
+    /// Outdated packages from the single `brew outdated` call made while planning
+    pub outdated: OutdatedReport,
+    /// Brew options for casks that have any, defaults already merged in
+    pub cask_options: BTreeMap<String, Vec<String>>,
+    /// Build options for formulae that have any
+    pub formula_options: BTreeMap<String, Vec<String>>,
+    /// Requested build modes of formulae not installed from a bottle
+    pub formula_builds: BTreeMap<String, FormulaBuild>,
+    /// Environment for brew, shard values override configured ones
+    pub env: EnvVars,
+    /// Context the plan was built for, see [`ShardEngine::with_context`]
+    pub context: Option<String>,
+    /// Declared packages skipped because they belong to other contexts
+    pub skipped_by_context: Vec<ContextSkip>,
+    /// Undeclared casks left installed because device management controls them
+    pub mdm_managed: Vec<ManagedCask>,
+    /// Undeclared formulae left installed because packages that stay depend on them
+    pub held_by_dependents: Vec<DependencyHold>,
+    /// Formulae installed as dependencies that nothing needs after the
+    /// uninstalls, removed after them like `brew autoremove` would
+    pub orphans: Vec<String>,
+    /// Declared or installed packages left alone because the `ignore` list matches them
+    pub ignored: Vec<String>,
+    /// Versions of the same formula that all request linking, left as they are
+    pub link_conflicts: Vec<Vec<String>>,
+    /// Keg-only formulae set to `link = true`, which brew only links with
+    /// `link = "force"`
+    pub keg_only_links: Vec<String>,
+    /// Changes plugins would make to the custom sections they claim
+    pub plugins: Vec<PluginPlan>,
+    /// Custom sections of the shards no plugin claims, left alone
+    pub unclaimed_sections: Vec<String>,
+    /// Failed `requires` checks, applying the plan is refused while there are any
+    pub unmet_requirements: Vec<UnmetRequirement>,
+}
+
+impl Plan {
+    /// True if applying the plan would not change anything
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty() && self.plugins.iter().all(|plugin| plugin.changes.is_empty())
+    }
+
+    /// Count the planned actions matching a predicate
+    pub fn count(&self, predicate: impl Fn(&Action) -> bool) -> usize {
+        self.actions.iter().filter(|action| predicate(action)).count()
+    }
+
+    /// Packages of a type declared by the planned shards
+    pub fn declared(&self, package_type: PackageType) -> Vec<String> {
+        match package_type {
+            PackageType::Formula => self.formulae.clone(),
+            PackageType::Cask => self.casks.clone(),
+        }
+    }
+
+    /// Brew options to install or upgrade the package of an action with
+    pub fn options_for(&self, action: &Action) -> Vec<String> {
+        let build = |name: &String| self.formula_builds.get(name).copied().unwrap_or_default();
+        match action {
+            Action::Install { package_type: PackageType::Cask, name }
+            | Action::Upgrade { package_type: PackageType::Cask, name } => {
+                self.cask_options.get(name).cloned().unwrap_or_default()
+            }
+            Action::Install { package_type: PackageType::Formula, name } => {
+                let mut args = build(name).install_args();
+                args.extend(self.formula_options.get(name).cloned().unwrap_or_default());
+                args
+            }
+            Action::Upgrade { package_type: PackageType::Formula, name } => build(name).upgrade_args(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Options for [`ShardEngine::apply`]
+#[derive(Debug, Default, Clone)]
+pub struct ApplyOptions {
+    /// If true, skip the final `brew cleanup`.
+    pub skip_cleanup: bool,
+    /// No one can answer prompts, so cask installs and upgrades time out
+    /// instead of waiting forever
+    pub unattended: bool,
+}
+
+/// An action that could not be completed
+#[derive(Debug, Clone)]
+pub struct FailedAction {
+    pub action: Action,
+    pub error: String,
+}
+
+/// Outcome of applying a plan
+#[derive(Debug, Clone, Default)]
+pub struct ApplyReport {
+    pub succeeded: Vec<Action>,
+    /// Actions brew reported as already satisfied
+    pub skipped: Vec<Action>,
+    pub failed: Vec<FailedAction>,
+    /// Cask installs and upgrades that waited for a password or other input
+    pub interactive: Vec<Action>,
+    /// Every executed action in order, with how long it took
+    pub timings: Vec<ActionTiming>,
+    /// Orphaned dependencies removed after the uninstalls
+    pub orphans_removed: Vec<String>,
+    /// Changes plugins made, with the failed ones
+    pub plugins: Vec<PluginPlan>,
+    /// Wall-clock time of the whole apply, including cleanup
+    pub elapsed: Duration,
+}
+
+/// How long a single action took
+#[derive(Debug, Clone)]
+pub struct ActionTiming {
+    pub action: Action,
+    pub outcome: ActionOutcome,
+    pub duration: Duration,
+}
+
+impl ApplyReport {
+    /// True if every action and plugin change succeeded or was skipped
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty() && self.plugins.iter().all(|plugin| plugin.failures() == 0)
+    }
+}
+
+/// Result of running a single action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActionOutcome {
+    Succeeded,
+    /// Brew reported the action as already satisfied
+    Skipped(String),
+    /// The installer asked for a password or other input no one could give
+    Interactive(String),
+    Failed(String),
+}
+
+/// Library facade for planning and applying shards
+pub struct ShardEngine {
+    manager: ShardManager,
+    brew_client: BrewClient,
+    reporter: Box<dyn Reporter>,
+    default_cask_options: Vec<String>,
+    default_env: EnvVars,
+    /// Sections to apply, all if empty
+    sections: Vec<ManifestSection>,
+    /// Active context, packages of other contexts are skipped
+    context: Option<String>,
+    /// Software controlled by device management, never uninstalled
+    mdm: Option<MdmInventory>,
+    /// Packages left to other tools, see [`crate::core::ignore`]
+    ignore: IgnoreList,
+    /// Plugins managing custom manifest sections
+    plugins: Vec<Plugin>,
+}
+
+impl ShardEngine {
+    /// Create an engine using the default shard directories and brew client,
+    /// with `overrides` from the command line
+    pub fn new(overrides: ClientOverrides) -> ShardResult<Self> {
+        // The shard manager already warns about an unreadable configuration
+        let config = ShardConfig::load().unwrap_or_default();
+        Ok(Self::with_parts(ShardManager::new()?, get_client(overrides))
+            .with_default_cask_options(config.cask_options)
+            .with_default_env(config.env)
+            .with_context(context::active()?)
+            .with_mdm(config.managed.then(MdmInventory::detect))
+            .with_ignore(config.ignore)
+            .with_plugins(Plugin::load_all()?))
+    }
+
+    /// Create an engine with a custom shard manager and brew client
+    pub fn with_parts(manager: ShardManager, brew_client: BrewClient) -> Self {
+        Self {
+            manager,
+            brew_client,
+            reporter: Box::new(NullReporter),
+            default_cask_options: Vec::new(),
+            default_env: EnvVars::new(),
+            sections: Vec::new(),
+            context: None,
+            mdm: None,
+            ignore: IgnoreList::default(),
+            plugins: Vec::new(),
+        }
+    }
+
+    /// Pass these variables to brew, unless a shard overrides them
+    pub fn with_default_env(mut self, env: EnvVars) -> Self {
+        self.default_env = env;
+        self
+    }
+
+    /// Pass these options to brew for every cask, unless a shard overrides them
+    pub fn with_default_cask_options(mut self, options: Vec<String>) -> Self {
+        self.default_cask_options = options;
+        self
+    }
+
+    /// Only plan these sections, e.g. formulae without casks
+    ///
+    /// Shards can narrow this further with `metadata.apply`.
+    pub fn with_sections(mut self, sections: Vec<ManifestSection>) -> Self {
+        self.sections = sections;
+        self
+    }
+
+    /// Plan for a context, e.g. "work"
+    ///
+    /// Packages declared for other contexts are skipped and never uninstalled.
+    /// Without a context only packages declared for none apply.
+    pub fn with_context(mut self, context: Option<String>) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Never uninstall casks this device management inventory controls
+    ///
+    /// Detected in managed mode by [`ShardEngine::new`].
+    pub fn with_mdm(mut self, inventory: Option<MdmInventory>) -> Self {
+        self.mdm = inventory;
+        self
+    }
+
+    /// Never install, upgrade or uninstall packages matching these patterns
+    pub fn with_ignore(mut self, ignore: IgnoreList) -> Self {
+        self.ignore = ignore;
+        self
+    }
+
+    /// Hand custom manifest sections to these plugins
+    ///
+    /// Loaded from the plugins directory by [`ShardEngine::new`].
+    pub fn with_plugins(mut self, plugins: Vec<Plugin>) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// Brew client the engine plans and applies with
+    pub fn brew_client(&self) -> &BrewClient {
+        &self.brew_client
+    }
+
+    /// Send events to a reporter instead of discarding them
+    pub fn with_reporter<R: Reporter + 'static>(mut self, reporter: R) -> Self {
+        self.reporter = Box::new(reporter);
+        self
+    }
+
+    /// The reporter events are sent to
+    pub fn reporter(&self) -> &dyn Reporter {
+        self.reporter.as_ref()
+    }
+
+    fn emit(&self, event: Event) {
+        self.reporter.report(&event);
+    }
+
+    /// Build a plan for a target without changing anything
+    ///
+    /// `"all"` synchronizes against every enabled shard. Any other target is
+    /// a shard name or manifest path and is planned in additive mode.
+    pub fn plan(&self, target: &str) -> ShardResult<Plan> {
+        if target.eq_ignore_ascii_case("all") {
+            let mut manifests = Vec::new();
+            let mut names = self.manager.list_shards()?;
+            names.sort();
+
+            for name in names {
+                match Manifest::from_file(self.manager.get_shard_path(&name)) {
+                    Ok(manifest) => {
+                        self.emit(Event::ShardLoaded { name: name.clone() });
+                        manifests.push((name, manifest));
+                    }
+                    Err(e) => self.emit(Event::ShardSkipped { name, error: e.to_string() }),
+                }
+            }
+
+            self.plan_manifests(manifests, ApplyMode::Synchronize)
+        } else {
+            let path = resolve_manifest_path(target)?;
+            if !path_exists(Path::new(&path)) {
+                return Err(ShardError::NotFound(target.to_string()));
+            }
+
+            let manifest = Manifest::from_file(&path)
+                .with_context(|| format!("Failed to load manifest: {}", path))?;
+            self.emit(Event::ShardLoaded { name: target.to_string() });
+
+            self.plan_manifests(vec![(target.to_string(), manifest)], ApplyMode::Additive)
+        }
+    }
+
+    /// Build a plan for already loaded manifests
+    pub fn plan_manifests(&self, manifests: Vec<(String, Manifest)>, mode: ApplyMode) -> ShardResult<Plan> {
+        let mut taps = Vec::new();
+        let mut formulae = Vec::new();
+        let mut casks = Vec::new();
+        let mut extensions = Vec::new();
+        let mut shards = Vec::new();
+        let mut cask_options = BTreeMap::new();
+        let mut formula_options = BTreeMap::new();
+        let mut formula_builds = BTreeMap::new();
+        let mut env = self.default_env.clone();
+        // Declared in a section a shard skips, so never uninstalled
+        let mut kept_formulae = Vec::new();
+        let mut kept_casks = Vec::new();
+        let mut skipped_by_context = Vec::new();
+        let mut links = BTreeMap::new();
+        let mut custom_sections: BTreeMap<String, BTreeMap<String, toml::Value>> = BTreeMap::new();
+        let mut unmet_requirements = Vec::new();
+
+        for (name, mut manifest) in manifests {
+            unmet_requirements.extend(manifest.requires.unmet().into_iter()
+                .map(|problem| UnmetRequirement { shard: name.clone(), problem }));
+            for (section, package) in manifest.retain_context(self.context.as_deref()) {
+                // Out of context, but still declared and so never uninstalled
+                match section {
+                    ManifestSection::Formulae => kept_formulae.push(package.clone()),
+                    ManifestSection::Casks => kept_casks.push(package.clone()),
+                    _ => {}
+                }
+                let contexts = manifest.contexts_for(&package).to_vec();
+                skipped_by_context.push(ContextSkip { section, name: package, contexts });
+            }
+
+
+            let applies = |section: ManifestSection| {
+                section.selected(&self.sections) && section.selected(&manifest.metadata.apply)
+            };
+            let (apply_taps, apply_formulae, apply_casks) = (
+                applies(ManifestSection::Taps),
+                applies(ManifestSection::Formulae),
+                applies(ManifestSection::Casks),
+            );
+
+            for cask in &manifest.casks {
+                let options = merge_options(&self.default_cask_options, manifest.cask_options_for(cask));
+                if !options.is_empty() {
+                    cask_options.insert(cask.clone(), options);
+                }
+            }
+            formula_options.extend(manifest.formula_options.into_iter().filter(|(_, options)| !options.is_empty()));
+            for (formula, build) in manifest.formula_builds {
+                if build != FormulaBuild::default() {
+                    formula_builds.insert(formula, build);
+                }
+            }
+            env.extend(manifest.env);
+            if apply_taps {
+                taps.extend(manifest.taps);
+            }
+            if apply_formulae {
+                links.extend(manifest.links.into_iter().filter(|(formula, _)| manifest.formulae.contains(formula)));
+                formulae.extend(manifest.formulae);
+            } else {
+                kept_formulae.extend(manifest.formulae);
+            }
+            if apply_casks {
+                casks.extend(manifest.casks);
+            } else {
+                kept_casks.extend(manifest.casks);
+            }
+            if applies(ManifestSection::VscodeExtensions) {
+                extensions.extend(manifest.vscode_extensions.iter().map(|id| id.to_lowercase()));
+            }
+            // Custom sections are only planned when no section selection narrows the apply
+            if self.sections.is_empty() && manifest.metadata.apply.is_empty() {
+                for (section, content) in manifest.custom_sections {
+                    custom_sections.entry(section).or_default().insert(name.clone(), content);
+                }
+            }
+            shards.push(name);
+        }
+
+        let mut ignored: Vec<String> = formulae.iter().chain(&casks)
+            .filter(|name| self.ignore.is_ignored(name))
+            .cloned()
+            .collect();
+        formulae.retain(|name| !self.ignore.is_ignored(name));
+        casks.retain(|name| !self.ignore.is_ignored(name));
+        links.retain(|name, _| !self.ignore.is_ignored(name));
+
+        for list in [&mut formulae, &mut casks, &mut extensions] {
+            list.sort();
+            list.dedup();
+        }
+
+        // A tap declared with a custom remote wins over the same tap by name only
+        taps.sort_by(|a: &Tap, b: &Tap| a.name.cmp(&b.name).then(a.url.is_none().cmp(&b.url.is_none())));
+        taps.dedup_by(|later, earlier| later.name == earlier.name);
+
+        let mut actions = Vec::new();
+
+        if !taps.is_empty() {
+            let installed_taps: HashSet<String> = self.brew_client.get_installed_taps()?.into_iter().collect();
+            actions.extend(taps.iter()
+                .filter(|tap| !installed_taps.contains(&tap.name))
+                .map(|tap| Action::AddTap { name: tap.name.clone(), url: tap.url.clone() }));
+        }
+
+        let installed_formulae = self.brew_client.get_installed_formulae()?;
+        let installed_casks = self.brew_client.get_installed_casks()?;
+
+        let mut processed = Vec::new();
+        for (package_type, declared, installed) in [
+            (PackageType::Formula, &formulae, &installed_formulae),
+            (PackageType::Cask, &casks, &installed_casks),
+        ] {
+            let processor = PackageProcessor::new(package_type, installed.clone(), true);
+            processed.push((package_type, processor.process_packages(declared)?));
+        }
+
+        // One `brew outdated` call decides which installed packages to upgrade,
+        // running `brew upgrade` for each would spawn brew once per package
+        let outdated = if processed.iter().any(|(_, ops)| !ops.to_upgrade.is_empty()) {
+            self.brew_client.get_outdated(false)?
+        } else {
+            OutdatedReport::default()
+        };
+        let upgradable = outdated.to_set();
+        for (package_type, ops) in processed {
+            actions.extend(ops.to_install.into_iter().map(|name| Action::Install { package_type, name }));
+            actions.extend(ops.to_upgrade.into_iter()
+                .filter(|name| upgradable.is_upgradable(name))
+                .map(|name| Action::Upgrade { package_type, name }));
+        }
+
+        if !extensions.is_empty() {
+            if vscode::is_available() {
+                let installed: HashSet<String> = vscode::installed_extensions()?.into_iter().collect();
+                actions.extend(extensions.into_iter()
+                    .filter(|id| !installed.contains(id))
+                    .map(|name| Action::InstallExtension { name }));
+            } else {
+                self.emit(Event::ExtensionsSkipped { count: extensions.len(), reason: "the `code` command was not found".to_string() });
+            }
+        }
+
+        let mut mdm_managed = Vec::new();
+        let mut held_by_dependents = Vec::new();
+        let mut orphans = Vec::new();
+        if mode == ApplyMode::Synchronize {
+            let dependencies: HashSet<String> = self.brew_client.get_dependency_packages()?.into_iter().collect();
+            let is_removable = |name: &String, declared: &[String], kept: &[String]| {
+                !declared.contains(name) && !kept.contains(name) && !CRITICAL_PACKAGES.contains(&name.as_str())
+            };
+            ignored.extend(installed_formulae.iter()
+                .filter(|name| !dependencies.contains(*name))
+                .chain(&installed_casks)
+                .filter(|name| self.ignore.is_ignored(name))
+                .cloned());
+
+            // Only packages installed on request are candidates, never dependencies.
+            // Sections left out of the engine selection are not synchronized at all.
+            let mut removable_formulae: Vec<String> = Vec::new();
+            if ManifestSection::Formulae.selected(&self.sections) {
+                removable_formulae = installed_formulae.iter()
+                    .filter(|name| !dependencies.contains(*name) && is_removable(name, &formulae, &kept_formulae))
+                    .filter(|name| !self.ignore.is_ignored(name))
+                    .cloned()
+                    .collect();
+            }
+            let mut removable_casks: Vec<String> = Vec::new();
+            if ManifestSection::Casks.selected(&self.sections) {
+                removable_casks = installed_casks.iter()
+                    .filter(|name| is_removable(name, &casks, &kept_casks) && !self.ignore.is_ignored(name))
+                    .cloned()
+                    .collect();
+                if let Some(mdm) = &self.mdm {
+                    mdm_managed = mdm.managed_casks(&self.brew_client, &removable_casks)?;
+                }
+                removable_casks.retain(|name| !mdm_managed.iter().any(|managed| &managed.cask == name));
+            }
+
+            held_by_dependents = self.hold_dependencies(&mut removable_formulae, &removable_casks)?;
+            if !removable_formulae.is_empty() || !removable_casks.is_empty() {
+                let removed: Vec<String> = removable_formulae.iter().chain(&removable_casks).cloned().collect();
+                let staying: Vec<String> = formulae.iter().chain(&kept_formulae).cloned().collect();
+                orphans = self.find_orphans(&removed, &staying)?;
+            }
+            // Casks may depend on formulae, so they go first
+            actions.extend(removable_casks.into_iter()
+                .map(|name| Action::Uninstall { package_type: PackageType::Cask, name }));
+            actions.extend(removable_formulae.into_iter()
+                .map(|name| Action::Uninstall { package_type: PackageType::Formula, name }));
+        }
+
+        let mut link_conflicts = Vec::new();
+        let mut keg_only_links = Vec::new();
+        if !links.is_empty() {
+            let linked: Vec<&String> = links.iter().filter(|(_, link)| link.is_linked()).map(|(name, _)| name).collect();
+            link_conflicts = link_conflicts_of(linked);
+            let links: BTreeMap<String, FormulaLink> = links.into_iter()
+                .filter(|(name, _)| !link_conflicts.iter().any(|versions| versions.contains(name)))
+                .collect();
+            let (link_actions, keg_only) = self.plan_links(&links, &installed_formulae, &actions)?;
+            actions.extend(link_actions);
+            keg_only_links = keg_only;
+        }
+
+        let (plugins, unclaimed_sections) = plugins::plan(&self.plugins, custom_sections);
+
+        ignored.sort();
+        ignored.dedup();
+        skipped_by_context.sort_by(|a, b| a.name.cmp(&b.name));
+        skipped_by_context.dedup_by(|later, earlier| later.name == earlier.name && later.section == earlier.section);
+
+        Ok(Plan {
+            mode,
+            shards,
+            actions,
+            formulae,
+            casks,
+            outdated,
+            cask_options,
+            formula_options,
+            formula_builds,
+            env,
+            context: self.context.clone(),
+            skipped_by_context,
+            mdm_managed,
+            held_by_dependents,
+            orphans,
+            ignored,
+            link_conflicts,
+            keg_only_links,
+            plugins,
+            unclaimed_sections,
+            unmet_requirements,
+        })
+    }
+
+    /// Link and unlink actions bringing formulae to their requested link state
+    ///
+    /// Linking a formula first unlinks the other linked versions of it.
+    /// Formulae about to be installed are included, since brew links those
+    /// that are not keg-only on install. Installed keg-only formulae set to
+    /// `link = true` are returned separately and left unlinked.
+    fn plan_links(&self, links: &BTreeMap<String, FormulaLink>, installed: &[String], planned: &[Action]) -> ShardResult<(Vec<Action>, Vec<String>)> {
+        let states = self.brew_client.get_link_states()?;
+        let linked_now: BTreeSet<&str> = states.iter().filter(|state| state.linked).map(|state| state.name.as_str()).collect();
+        let keg_only = |name: &str| states.iter().any(|state| state.name == name && state.keg_only);
+        let uninstalled = |name: &str| planned.iter()
+            .any(|action| matches!(action, Action::Uninstall { name: removed, .. } if removed == name));
+
+        let mut actions = Vec::new();
+        let mut keg_only_links = Vec::new();
+        for (formula, &link) in links {
+            if link == FormulaLink::Linked && keg_only(formula) {
+                keg_only_links.push(formula.clone());
+            } else if link.is_linked() {
+                actions.extend(linked_now.iter()
+                    .filter(|other| **other != formula && versioned_base(other) == versioned_base(formula) && !uninstalled(other))
+                    .map(|other| Action::Link { name: other.to_string(), link: FormulaLink::Unlinked }));
+                if !linked_now.contains(formula.as_str()) {
+                    actions.push(Action::Link { name: formula.clone(), link });
+                }
+            } else if linked_now.contains(formula.as_str()) || !installed.contains(formula) {
+                actions.push(Action::Link { name: formula.clone(), link: FormulaLink::Unlinked });
+            }
+        }
+
+        // Unlink first, a version can only be linked once the others are not
+        actions.sort_by_key(|action| matches!(action, Action::Link { link, .. } if link.is_linked()));
+        actions.dedup();
+        Ok((actions, keg_only_links))
+    }
+
+    /// Formulae `brew autoremove` would remove once `removed` is uninstalled
+    ///
+    /// Declared formulae in `staying` are never orphans, even if brew
+    /// installed them as a dependency first.
+    fn find_orphans(&self, removed: &[String], staying: &[String]) -> ShardResult<Vec<String>> {
+        let installed = self.brew_client.get_installed_dependencies()?;
+        let mut gone: HashSet<&str> = removed.iter().map(String::as_str).collect();
+        let mut orphans = Vec::new();
+
+        loop {
+            let next: Vec<&str> = installed.iter()
+                .filter(|package| package.as_dependency && !gone.contains(package.name.as_str()))
+                .filter(|package| !staying.contains(&package.name) && !CRITICAL_PACKAGES.contains(&package.name.as_str()))
+                .filter(|package| !self.ignore.is_ignored(&package.name))
+                .filter(|package| !installed.iter()
+                    .any(|other| !gone.contains(other.name.as_str()) && other.dependencies.contains(&package.name)))
+                .map(|package| package.name.as_str())
+                .collect();
+            if next.is_empty() {
+                break;
+            }
+            gone.extend(&next);
+            orphans.extend(next.into_iter().map(str::to_string));
+        }
+
+        orphans.sort();
+        Ok(orphans)
+    }
+
+    /// Keep formulae that packages staying installed depend on
+    ///
+    /// Brew refuses to uninstall a formula something else requires, so these
+    /// are taken out of `formulae` and returned with the chain of dependents.
+    /// Keeping a formula can keep the ones it depends on in turn. The rest is
+    /// ordered so dependents are uninstalled before their dependencies.
+    fn hold_dependencies(&self, formulae: &mut Vec<String>, casks: &[String]) -> ShardResult<Vec<DependencyHold>> {
+        let mut dependents: HashMap<String, Vec<String>> = HashMap::new();
+        for formula in formulae.iter() {
+            dependents.insert(formula.clone(), self.brew_client.get_installed_dependents(formula)?);
+        }
+
+        let mut held: Vec<DependencyHold> = Vec::new();
+        loop {
+            let removed = |name: &String| formulae.contains(name) || casks.contains(name);
+            let next = formulae.iter().find_map(|formula| {
+                let dependent = dependents[formula].iter().find(|dependent| !removed(dependent))?;
+                let mut chain = vec![dependent.clone()];
+                if let Some(hold) = held.iter().find(|hold| &hold.formula == dependent) {
+                    chain.extend(hold.chain.iter().cloned());
+                }
+                Some(DependencyHold { formula: formula.clone(), chain })
+            });
+            let Some(hold) = next else {
+                break;
+            };
+            formulae.retain(|formula| formula != &hold.formula);
+            held.push(hold);
+        }
+
+        let mut ordered: Vec<String> = Vec::with_capacity(formulae.len());
+        while ordered.len() < formulae.len() {
+            let ready: Vec<String> = formulae.iter()
+                .filter(|formula| !ordered.contains(formula))
+                .filter(|formula| dependents[*formula].iter()
+                    .all(|dependent| !formulae.contains(dependent) || ordered.contains(dependent)))
+                .cloned()
+                .collect();
+            if ready.is_empty() {
+                // A dependency cycle, leave the remaining order to brew
+                ordered.extend(formulae.iter().filter(|formula| !ordered.contains(formula)).cloned().collect::<Vec<_>>());
+                break;
+            }
+            ordered.extend(ready);
+        }
+        *formulae = ordered;
+
+        held.sort_by(|a, b| a.formula.cmp(&b.formula));
+        Ok(held)
+    }
+
+    /// Execute a plan
+    ///
+    /// Consecutive installs and upgrades without options run as batches of the configured
+    /// size, see [`execute_batch`]. Individual failures are collected in the
+    /// report rather than aborting the run. Only unmet `requires` checks, which refuse the whole plan
+    /// before anything changes, secrets in the plan's environment that cannot be resolved and a
+    /// failing cleanup return an error.
+    pub fn apply(&self, plan: &Plan, options: &ApplyOptions) -> ShardResult<ApplyReport> {
+        if let Some(unmet) = plan.unmet_requirements.first() {
+            return Err(ShardError::ValidationError(format!(
+                "Shard '{}' cannot be applied on this machine: {}", unmet.shard, unmet.problem)));
+        }
+
+        let started = Instant::now();
+        let mut report = ApplyReport::default();
+
+        // Secrets are read only now, so planning never touches the keychain
+        let brew_client = if plan.env.is_empty() {
+            self.brew_client.clone()
+        } else {
+            self.brew_client.clone().with_env(env::resolve(&plan.env)?)
+        };
+
+        // A cask that timed out waits for input, running it again would only wait longer
+        let cask_client = if options.unattended {
+            brew_client.clone().with_timeout(UNATTENDED_CASK_TIMEOUT_SECS).with_retries(0)
+        } else {
+            brew_client.clone()
+        };
+
+        let mut remaining = plan.actions.as_slice();
+        while let Some(action) = remaining.first() {
+            // Consecutive installs or upgrades without options go to brew together.
+            // Casks prompting in unattended runs would hold up the whole batch.
+            let batchable = |next: &Action| batches_with(action, next) && plan.options_for(next).is_empty()
+                && !(options.unattended && matches!(next,
+                    Action::Install { package_type: PackageType::Cask, .. } | Action::Upgrade { package_type: PackageType::Cask, .. }));
+            let size = remaining.iter().take_while(|next| batchable(next)).count().max(1);
+            let (batch, rest) = remaining.split_at(size);
+            remaining = rest;
+
+            let client = match action {
+                Action::Install { package_type: PackageType::Cask, .. }
+                | Action::Upgrade { package_type: PackageType::Cask, .. } => &cask_client,
+                _ => &brew_client,
+            };
+            let batch_started = Instant::now();
+            let outcomes = if batch.len() > 1 {
+                execute_batch(client, batch, self.reporter.as_ref())
+            } else {
+                vec![execute_action(client, action, &plan.options_for(action), self.reporter.as_ref())]
+            };
+            // Packages handled by one brew call share its duration
+            let duration = batch_started.elapsed() / batch.len() as u32;
+
+            for (action, outcome) in batch.iter().zip(outcomes) {
+                report.timings.push(ActionTiming {
+                    action: action.clone(),
+                    outcome: outcome.clone(),
+                    duration,
+                });
+
+                match outcome {
+                    ActionOutcome::Succeeded => report.succeeded.push(action.clone()),
+                    ActionOutcome::Skipped(_) => report.skipped.push(action.clone()),
+                    ActionOutcome::Interactive(_) => report.interactive.push(action.clone()),
+                    ActionOutcome::Failed(error) => report.failed.push(FailedAction { action: action.clone(), error }),
+                }
+            }
+        }
+
+        if !plan.orphans.is_empty() {
+            self.remove_orphans(&brew_client, plan, &mut report);
+        }
+
+        for planned in plan.plugins.iter().filter(|planned| planned.error.is_none() && !planned.changes.is_empty()) {
+            report.plugins.push(self.run_plugin(planned));
+        }
+
+        if !options.skip_cleanup {
+            self.emit(Event::CleanupStarted);
+            self.brew_client.cleanup(true)?; // true for prune_all
+            self.emit(Event::CleanupFinished);
+        }
+
+        report.elapsed = started.elapsed();
+        Ok(report)
+    }
+
+    /// Uninstall the planned orphans once the uninstalls that free them succeeded
+    ///
+    /// Only the planned formulae are removed rather than running `brew
+    /// autoremove`, which would also take declared formulae brew installed as
+    /// dependencies.
+    fn remove_orphans(&self, brew_client: &BrewClient, plan: &Plan, report: &mut ApplyReport) {
+        let names = plan.orphans.clone();
+        if report.failed.iter().any(|failed| matches!(failed.action, Action::Uninstall { .. })) {
+            self.emit(Event::OrphansKept { names, reason: "not every package was uninstalled".to_string() });
+            return;
+        }
+
+        match brew_client.uninstall_formulae(&names) {
+            Ok(()) => {
+                report.orphans_removed = names.clone();
+                self.emit(Event::OrphansRemoved { names });
+            }
+            Err(e) => self.emit(Event::OrphansKept { names, reason: e.to_string() }),
+        }
+    }
+
+    /// Let a plugin apply the changes it planned for its section
+    fn run_plugin(&self, planned: &PluginPlan) -> PluginPlan {
+        let mut applied = PluginPlan { changes: Vec::new(), ..planned.clone() };
+        self.emit(Event::PluginStarted { plugin: planned.plugin.clone(), section: planned.section.clone() });
+
+        let Some(plugin) = self.plugins.iter().find(|plugin| plugin.name == planned.plugin) else {
+            applied.error = Some("the plugin is no longer registered".to_string());
+            self.emit(Event::PluginFailed { plugin: planned.plugin.clone(), error: applied.error.clone().unwrap_or_default() });
+            return applied;
+        };
+
+        match plugin.run(&planned.section, &planned.shards, false) {
+            Ok(changes) => {
+                for change in &changes {
+                    self.emit(Event::PluginChanged { plugin: plugin.name.clone(), change: change.clone() });
+                }
+                applied.changes = changes;
+            }
+            Err(e) => {
+                applied.error = Some(e.to_string());
+                self.emit(Event::PluginFailed { plugin: plugin.name.clone(), error: e.to_string() });
+            }
+        }
+        applied
+    }
+}
+
+/// Run a single action through the brew client, reporting its progress
+///
+/// `options` are passed to brew for installs and upgrades.
+pub fn execute_action(brew_client: &BrewClient, action: &Action, options: &[String], reporter: &dyn Reporter) -> ActionOutcome {
+    reporter.report(&Event::started(action));
+
+    let result = match action {
+        Action::AddTap { name, url } => brew_client.add_tap(name, url.as_deref()),
+        Action::Install { package_type: PackageType::Formula, name } => brew_client.install_formula(name, options),
+        Action::Install { package_type: PackageType::Cask, name } => brew_client.install_cask(name, options),
+        Action::Upgrade { package_type: PackageType::Formula, name } => brew_client.upgrade_formula_with_options(name, options),
+        Action::Upgrade { package_type: PackageType::Cask, name } => brew_client.upgrade_cask_with_options(name, options),
+        Action::Uninstall { package_type: PackageType::Formula, name } => brew_client.uninstall_formula(name, true),
+        Action::Uninstall { package_type: PackageType::Cask, name } => brew_client.uninstall_cask(name, true),
+        Action::InstallExtension { name } => vscode::install_extension(name),
+        Action::Link { name, link: FormulaLink::Unlinked } => brew_client.unlink_formula(name),
+        Action::Link { name, link } => brew_client.link_formula(name, *link == FormulaLink::Forced),
+    };
+
+    finish_action(action, result, reporter)
+}
+
+/// Run installs or upgrades of one package type through brew in batches, reporting each
+///
+/// All actions must be installs, or upgrades, of the same package type
+/// without options, others are run one by one. A failed batch is retried package by package,
+/// so every action still gets its own outcome, in order.
+pub fn execute_batch(brew_client: &BrewClient, actions: &[Action], reporter: &dyn Reporter) -> Vec<ActionOutcome> {
+    let batch: fn(&BrewClient, &[String]) -> ShardResult<BatchResults> = match actions.first() {
+        Some(Action::Install { package_type: PackageType::Formula, .. }) => BrewClient::batch_install_formulae,
+        Some(Action::Install { package_type: PackageType::Cask, .. }) => BrewClient::batch_install_casks,
+        Some(Action::Upgrade { package_type: PackageType::Formula, .. }) => BrewClient::batch_upgrade_formulae,
+        Some(Action::Upgrade { package_type: PackageType::Cask, .. }) => BrewClient::batch_upgrade_casks,
+        _ => return actions.iter().map(|action| execute_action(brew_client, action, &[], reporter)).collect(),
+    };
+
+    for action in actions {
+        reporter.report(&Event::started(action));
+    }
+    let names: Vec<String> = actions.iter().map(|action| action.name().to_string()).collect();
+    match batch(brew_client, &names) {
+        Ok(results) => actions.iter()
+            .zip(results)
+            .map(|(action, result)| finish_action(action, result, reporter))
+            .collect(),
+        // Nothing ran, e.g. a name did not validate
+        Err(e) => actions.iter()
+            .map(|action| {
+                reporter.report(&Event::failed(action, e.to_string()));
+                ActionOutcome::Failed(e.to_string())
+            })
+            .collect(),
+    }
+}
+
+/// Whether `next` can run in the same brew call as `first`
+fn batches_with(first: &Action, next: &Action) -> bool {
+    match (first, next) {
+        (Action::Install { package_type: a, .. }, Action::Install { package_type: b, .. })
+        | (Action::Upgrade { package_type: a, .. }, Action::Upgrade { package_type: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+/// Report the result of an action and turn it into its outcome
+fn finish_action(action: &Action, result: ShardResult<()>, reporter: &dyn Reporter) -> ActionOutcome {
+    match result {
+        Ok(()) => {
+            reporter.report(&Event::succeeded(action));
+            ActionOutcome::Succeeded
+        }
+        Err(e) => {
+            let error = e.to_string();
+            match action {
+                Action::Install { package_type, name } if matches!(e.brew_failure(), Some(BrewFailure::AlreadyInstalled { .. })) => {
+                    reporter.report(&Event::PackageSkipped { package_type: *package_type, name: name.clone(), reason: error.clone() });
+                    ActionOutcome::Skipped(error)
+                }
+                Action::Install { package_type: PackageType::Cask, name }
+                | Action::Upgrade { package_type: PackageType::Cask, name } if needs_interaction(&error) => {
+                    let reason = "requires interactive install".to_string();
+                    reporter.report(&Event::PackageSkipped { package_type: PackageType::Cask, name: name.clone(), reason: reason.clone() });
+                    ActionOutcome::Interactive(error)
+                }
+                _ => {
+                    reporter.report(&Event::failed(action, error.clone()));
+                    ActionOutcome::Failed(error)
+                }
+            }
+        }
+    }
+}
+
+/// Whether an installer failed because it waited for input, e.g. a sudo password
+fn needs_interaction(error: &str) -> bool {
+    let error = error.to_lowercase();
+    PROMPT_ERRORS.iter().any(|pattern| error.contains(pattern))
+}
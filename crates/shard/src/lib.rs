@@ -6,6 +6,13 @@ pub mod package;
 pub mod brew;
 pub mod shard;
 pub mod utils;
+pub mod engine;
+pub mod reporter;
+pub mod notify;
+pub mod webhook;
+pub mod vscode;
+pub mod mdm;
+pub mod plugins;
 
 // CLI handling
 pub mod cli;
@@ -14,12 +21,14 @@ pub mod cli;
 pub use utils::{
     ShardError, ShardResult, ResultExt,
     log_success, log_warning, log_error, log_step,
-    Logger, LogLevel
+    Logger, LogLevel, Glyph
 };
 
 // Re-export common types and functions for convenience
 pub use core::manifest;
 pub use brew::BrewClient;
+pub use engine::{ShardEngine, Plan, ApplyReport};
+pub use reporter::{Event, Reporter, ConsoleReporter};
 pub use shard::{
     apply::{apply, apply_all_enabled_shards},
     diff::diff,
@@ -1,10 +1,7 @@
 // Shard binary entry point
-use shard::{ShardResult, Logger, LogLevel};
+use shard::ShardResult;
 
 fn main() -> ShardResult<()> {
-    // Initialize logging with warn level
-    Logger::init(LogLevel::Warn);
-    
-    // Run the CLI
+    // Run the CLI, which initializes logging from its flags
     shard::cli::run()
 } 
\ No newline at end of file
@@ -0,0 +1,202 @@
+//! Software controlled by device management.
+//!
+//! Company Macs receive apps from IT through an MDM server or Munki, and
+//! `apply all` must never uninstall those even if no shard declares them. In
+//! managed mode (`mode = "managed"`) shard detects them from:
+//!
+//! - `/Library/Managed Installs`: items Munki and tools built on it install,
+//!   listed in `ManagedInstallReport.plist`
+//! - `/Library/Managed Preferences`: one plist per app whose settings a
+//!   configuration profile enforces, named after the app's bundle ID
+//!
+//! A cask is managed if its token or one of its apps matches the name of a
+//! managed install, or one of its apps has the bundle ID of a profile.
+
+use std::path::Path;
+use std::process::Command;
+use crate::brew::BrewClient;
+use crate::utils::{ShardResult, log_debug};
+
+/// Munki's report of the items it manages
+const MANAGED_INSTALLS_REPORT: &str = "/Library/Managed Installs/ManagedInstallReport.plist";
+
+/// Preferences enforced by configuration profiles, with a subdirectory per user
+const MANAGED_PREFERENCES_DIR: &str = "/Library/Managed Preferences";
+
+/// Where casks put apps unless given `--appdir`
+const DEFAULT_APPDIR: &str = "/Applications";
+
+/// How an item is managed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MdmSource {
+    /// Installed from `/Library/Managed Installs`
+    ManagedInstalls,
+    /// Settings enforced by a configuration profile
+    Profile,
+}
+
+impl MdmSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MdmSource::ManagedInstalls => "managed installs",
+            MdmSource::Profile => "configuration profile",
+        }
+    }
+}
+
+/// Something device management controls
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ManagedItem {
+    pub source: MdmSource,
+    /// Item name, or the bundle ID for configuration profiles
+    pub name: String,
+    /// Name shown to users, e.g. "Google Chrome"
+    pub display_name: Option<String>,
+}
+
+/// An installed cask that device management controls
+#[derive(Debug, Clone)]
+pub struct ManagedCask {
+    pub cask: String,
+    pub item: ManagedItem,
+}
+
+/// Everything detected as controlled by device management
+#[derive(Debug, Clone, Default)]
+pub struct MdmInventory {
+    pub items: Vec<ManagedItem>,
+}
+
+impl MdmInventory {
+    /// Read the managed installs and profiles of this Mac
+    ///
+    /// Sources that do not exist or cannot be read are skipped.
+    pub fn detect() -> Self {
+        let mut items = managed_installs(Path::new(MANAGED_INSTALLS_REPORT));
+        items.extend(profile_domains(Path::new(MANAGED_PREFERENCES_DIR)));
+        items.sort();
+        items.dedup();
+        log_debug(&format!("Found {} item(s) controlled by device management", items.len()));
+        Self { items }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The casks among `casks` that device management controls
+    pub fn managed_casks(&self, brew_client: &BrewClient, casks: &[String]) -> ShardResult<Vec<ManagedCask>> {
+        if self.items.is_empty() || casks.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut managed: Vec<ManagedCask> = casks.iter()
+            .filter_map(|cask| Some(ManagedCask { cask: cask.clone(), item: self.item_named(cask)?.clone() }))
+            .collect();
+
+        let remaining: Vec<String> = casks.iter()
+            .filter(|cask| !managed.iter().any(|m| &m.cask == *cask))
+            .cloned()
+            .collect();
+        if remaining.is_empty() {
+            return Ok(managed);
+        }
+        for cask in brew_client.get_cask_apps(&remaining)? {
+            if let Some(item) = cask.apps.iter().find_map(|app| self.item_for_app(app)) {
+                managed.push(ManagedCask { cask: cask.token, item: item.clone() });
+            }
+        }
+
+        managed.sort_by(|a, b| a.cask.cmp(&b.cask));
+        Ok(managed)
+    }
+
+    /// The managed install named like a cask token, e.g. `GoogleChrome` for `google-chrome`
+    fn item_named(&self, token: &str) -> Option<&ManagedItem> {
+        let token = normalize(token);
+        self.items.iter()
+            .filter(|item| item.source == MdmSource::ManagedInstalls)
+            .find(|item| normalize(&item.name) == token)
+    }
+
+    /// The item controlling an app bundle such as `Slack.app`
+    fn item_for_app(&self, app: &str) -> Option<&ManagedItem> {
+        let stem = normalize(app.trim_end_matches(".app"));
+        let bundle_id = bundle_id(&Path::new(DEFAULT_APPDIR).join(app));
+
+        self.items.iter().find(|item| match item.source {
+            MdmSource::ManagedInstalls => normalize(&item.name) == stem
+                || item.display_name.as_deref().is_some_and(|name| normalize(name) == stem),
+            MdmSource::Profile => bundle_id.as_deref().is_some_and(|id| id.eq_ignore_ascii_case(&item.name)),
+        })
+    }
+}
+
+/// Items of Munki's install report
+fn managed_installs(report: &Path) -> Vec<ManagedItem> {
+    if !report.exists() {
+        return Vec::new();
+    }
+
+    // Only this key, other parts of the report hold dates JSON cannot represent
+    let output = Command::new("plutil")
+        .args(["-extract", "ManagedInstalls", "json", "-o", "-"])
+        .arg(report)
+        .output();
+    let entries: Vec<serde_json::Value> = match output {
+        Ok(output) if output.status.success() => serde_json::from_slice(&output.stdout).unwrap_or_default(),
+        _ => {
+            log_debug(&format!("Could not read {}", report.display()));
+            return Vec::new();
+        }
+    };
+
+    entries.iter()
+        .filter_map(|entry| Some(ManagedItem {
+            source: MdmSource::ManagedInstalls,
+            name: entry.get("name")?.as_str()?.to_string(),
+            display_name: entry.get("display_name").and_then(|name| name.as_str()).map(str::to_string),
+        }))
+        .collect()
+}
+
+/// Preference domains of third party apps enforced for the machine and the current user
+fn profile_domains(dir: &Path) -> Vec<ManagedItem> {
+    let mut dirs = vec![dir.to_path_buf()];
+    if let Ok(user) = std::env::var("USER") {
+        dirs.push(dir.join(user));
+    }
+
+    dirs.iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "plist"))
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        // Apple's own settings never belong to a cask
+        .filter(|domain| !domain.starts_with("com.apple.") && !domain.starts_with('.'))
+        .map(|domain| ManagedItem { source: MdmSource::Profile, name: domain, display_name: None })
+        .collect()
+}
+
+/// Bundle ID of an installed app, `None` if it is not installed
+fn bundle_id(app: &Path) -> Option<String> {
+    let info = app.join("Contents/Info.plist");
+    if !info.exists() {
+        return None;
+    }
+    let output = Command::new("plutil")
+        .args(["-extract", "CFBundleIdentifier", "raw", "-o", "-"])
+        .arg(&info)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!id.is_empty()).then_some(id)
+}
+
+/// Lowercase letters and digits only, so `Google Chrome`, `GoogleChrome` and `google-chrome` match
+fn normalize(name: &str) -> String {
+    name.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_lowercase()).collect()
+}
@@ -0,0 +1,131 @@
+//! macOS user notifications for unattended runs.
+//!
+//! When shard runs without a terminal, for example from a launch agent on a
+//! schedule, nobody sees its console output. If `notify` is enabled in the
+//! `[shard]` configuration, `apply` and `diff` then summarize their result in
+//! a notification, sent with `terminal-notifier` if it is installed and with
+//! `osascript` otherwise.
+
+use std::process::Command;
+use crate::core::config::{NotificationConfig, NotifyThreshold, ShardConfig};
+use crate::utils::{ShardError, ShardResult, log_debug, log_warning};
+
+/// Title shown on every notification
+const TITLE: &str = "Shard";
+
+/// Counts of what an apply changed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApplySummary {
+    pub installed: usize,
+    pub upgraded: usize,
+    pub removed: usize,
+    pub failed: usize,
+}
+
+/// Counts of what applying would change
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DriftSummary {
+    pub to_install: usize,
+    pub to_upgrade: usize,
+    pub to_remove: usize,
+}
+
+/// Notification settings if notifications should be sent for this run
+///
+/// Returns `None` when attached to a terminal, when notifications are
+/// disabled, or when the configuration cannot be read.
+pub fn unattended_config() -> Option<NotificationConfig> {
+    if console::user_attended() {
+        return None;
+    }
+
+    match ShardConfig::load() {
+        Ok(config) if config.notifications.enabled => Some(config.notifications),
+        Ok(_) => None,
+        Err(e) => {
+            log_warning(&format!("Not sending notification: {}", e));
+            None
+        }
+    }
+}
+
+/// Notify about the result of an apply
+pub fn notify_applied(config: &NotificationConfig, shard: &str, summary: ApplySummary) {
+    let changed = summary.installed + summary.upgraded + summary.removed;
+    if changed == 0 && summary.failed == 0 && config.threshold == NotifyThreshold::Changes {
+        log_debug("Nothing changed, skipping notification");
+        return;
+    }
+
+    let mut message = if changed == 0 {
+        format!("{} is up to date", shard)
+    } else {
+        format!("{}: installed {}, upgraded {}, removed {}", shard, summary.installed, summary.upgraded, summary.removed)
+    };
+    if summary.failed > 0 {
+        message.push_str(&format!(" ({} failed)", summary.failed));
+    }
+
+    send_logged(&message);
+}
+
+/// Notify about drift between the shards and the system
+pub fn notify_drift(config: &NotificationConfig, shard: &str, summary: DriftSummary) {
+    let drift = summary.to_install + summary.to_upgrade + summary.to_remove;
+    if drift == 0 && config.threshold == NotifyThreshold::Changes {
+        log_debug("No drift detected, skipping notification");
+        return;
+    }
+
+    let message = if drift == 0 {
+        format!("No drift detected for {}", shard)
+    } else {
+        format!("Drift detected for {}: {} to install, {} to upgrade, {} to remove",
+            shard, summary.to_install, summary.to_upgrade, summary.to_remove)
+    };
+
+    send_logged(&message);
+}
+
+/// Send a notification, logging instead of failing the run
+fn send_logged(message: &str) {
+    if let Err(e) = send(TITLE, message) {
+        log_warning(&format!("Failed to send notification: {}", e));
+    }
+}
+
+/// Show a user notification
+pub fn send(title: &str, message: &str) -> ShardResult<()> {
+    if !cfg!(target_os = "macos") {
+        log_debug(&format!("Notifications are only supported on macOS: {}", message));
+        return Ok(());
+    }
+
+    let status = if has_terminal_notifier() {
+        Command::new("terminal-notifier")
+            .args(["-title", title, "-message", message, "-group", "shard"])
+            .status()?
+    } else {
+        let script = format!("display notification \"{}\" with title \"{}\"",
+            escape_applescript(message), escape_applescript(title));
+        Command::new("osascript").args(["-e", &script]).status()?
+    };
+
+    if !status.success() {
+        return Err(ShardError::Other(format!("Notification command exited with {}", status)));
+    }
+
+    Ok(())
+}
+
+fn has_terminal_notifier() -> bool {
+    Command::new("sh")
+        .args(["-c", "command -v terminal-notifier"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Escape a string for use inside an AppleScript string literal
+fn escape_applescript(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -2,11 +2,14 @@ use crate::utils::ShardResult;
 use std::path::PathBuf;
 use crate::utils::filesystem as fs_utils;
 use crate::brew::validate as validation;
-use crate::core::manifest::Manifest;
+use crate::core::config::{PackagePreference, ShardConfig};
+use crate::core::{aliases, env};
+use crate::core::manifest::{merge_options, Manifest};
 use crate::shard::{apply, manager as shard_manager};
 use crate::package::processor::PackageType;
-use crate::brew::get_client;
-use crate::brew::search::PackageAvailability;
+use crate::brew::{get_client, BrewClient, ClientOverrides};
+use crate::brew::search::{PackageAvailability, SearchFilter};
+use dialoguer::{MultiSelect, Select};
 use std::collections::HashMap;
 use crate::utils::{ShardError, ResultExt, log_step, log_warning, log_error, log_debug, log_success};
 use std::hash::Hash;
@@ -26,7 +29,37 @@ impl From<PackageType> for PackageTypeWrapper {
     }
 }
 
+/// Local state for filtering and annotating `shard search` results
+///
+/// `shard` restricts results to packages of that enabled shard.
+pub fn search_filter(installed_only: bool, shard: Option<&str>, overrides: ClientOverrides) -> ShardResult<SearchFilter> {
+    let manager = shard_manager::ShardManager::new()?;
+    if let Some(name) = shard && !manager.shard_is_active(name) {
+        return Err(ShardError::NotFound(name.to_string()));
+    }
+
+    let mut filter = SearchFilter {
+        installed_only,
+        shard: shard.map(str::to_string),
+        ..SearchFilter::default()
+    };
+    for (name, manifest) in manager.load_active_manifests()? {
+        for formula in manifest.formulae {
+            filter.formula_shards.entry(formula).or_default().push(name.clone());
+        }
+        for cask in manifest.casks {
+            filter.cask_shards.entry(cask).or_default().push(name.clone());
+        }
+    }
+
+    let brew_client = get_client(overrides);
+    filter.installed_formulae = brew_client.get_installed_formulae()?.into_iter().collect();
+    filter.installed_casks = brew_client.get_installed_casks()?.into_iter().collect();
+    Ok(filter)
+}
+
 /// Add packages to manifest and potentially install/apply
+#[allow(clippy::too_many_arguments)]
 pub fn add_packages(
     packages: &[String],
     force_formula: bool, // Renamed from force_brew
@@ -35,13 +68,17 @@ pub fn add_packages(
     dry_run: bool,
     exec: bool,          // New flag
     apply_all: bool,     // New flag (renamed from apply)
+    overrides: ClientOverrides,
 ) -> ShardResult<()> {
     log_step(&format!("Adding packages to shard '{}': {}", manifest_name, packages.join(", ")));
     if dry_run { log_debug("Dry run enabled"); }
     if exec { log_debug("Exec flag enabled: will install added packages immediately"); }
     if apply_all { log_debug("Apply flag enabled: will run 'apply all' after adding"); }
 
-    let brew_client = get_client();
+    let packages = &aliases::resolve_all(packages);
+
+    let brew_client = get_client(overrides);
+    let prefer = ShardConfig::load().map(|config| config.prefer).unwrap_or_default();
 
     // Validate all package names first
     for package in packages {
@@ -79,12 +116,8 @@ pub fn add_packages(
     // Check protection
     let shard_name_for_check = manifest_path_obj.file_stem().unwrap_or_default().to_string_lossy();
     let manager = shard_manager::ShardManager::new()?; // Use manager for checks
-    if manager.shard_is_protected(&shard_name_for_check) {
-         // Allow modification only if owned or allowed (implement stricter check if needed)
-         // For now, simple protection check:
-        log_error(&format!("Cannot modify protected shard: {}", shard_name_for_check));
-        return Err(ShardError::Protected(shard_name_for_check.to_string()));
-    }
+    manager.ensure_editable(&shard_name_for_check)
+        .inspect_err(|_| log_error(&format!("Cannot modify protected shard: {}", shard_name_for_check)))?;
 
     let mut added_packages_map: HashMap<String, PackageType> = HashMap::new(); // Track what was added and its type
 
@@ -106,23 +139,33 @@ pub fn add_packages(
         log_debug(&format!("Checking availability for '{}'", package_name));
         let availability = brew_client.check_package_availability(package_name)?;
 
-        let determined_type = determine_package_type(package_name, &availability, force_formula, force_cask)?;
+        let determined_type = determine_package_type(package_name, &availability, force_formula, force_cask, prefer)?;
+        let searchable = !force_formula && !force_cask && console::user_attended();
 
-        if let Some(package_type) = determined_type {
-             log_debug(&format!("Adding '{}' as {} to shard '{}'", package_name, package_type.as_str(), manifest_name));
-
-             // Add to the appropriate list
-             match package_type {
-                 PackageType::Formula => {
-                      manifest.formulae.push(package_name.clone());
-                 }
-                 PackageType::Cask => {
-                      manifest.casks.push(package_name.clone());
-                 }
-             }
-            added_packages_map.insert(package_name.clone(), package_type);
-        } else {
+        let chosen = match determined_type {
+            // Versions or variants of the package may be meant as well, let the user pick
+            Some(package_type) if searchable => select_variants(&brew_client, package_name, package_type)?,
+            Some(package_type) => vec![(package_name.clone(), package_type)],
+            // Not an exact match - let the user pick from search results instead
+            None if searchable && !availability.available_as_formula && !availability.available_as_cask => {
+                select_from_search(&brew_client, package_name)?
+            }
             // determine_package_type already printed error/skip message
+            None => Vec::new(),
+        };
+
+        for (name, package_type) in chosen {
+            if manifest.formulae.contains(&name) || manifest.casks.contains(&name) {
+                log_warning(&format!("Package '{}' already exists in shard. Skipping.", name));
+                continue;
+            }
+
+            log_debug(&format!("Adding '{}' as {} to shard '{}'", name, package_type.as_str(), manifest_name));
+            match package_type {
+                PackageType::Formula => manifest.formulae.push(name.clone()),
+                PackageType::Cask => manifest.casks.push(name.clone()),
+            }
+            added_packages_map.insert(name, package_type);
         }
     }
 
@@ -130,6 +173,7 @@ pub fn add_packages(
     if !added_packages_map.is_empty() {
         if !dry_run {
             log_step(&format!("Saving updated manifest: {}", manifest_path));
+            manifest.update_modification_info();
             manifest.to_file(&manifest_path_obj)?;
             log_success("Manifest saved.");
         } else {
@@ -139,16 +183,21 @@ pub fn add_packages(
         // --- Handle --exec and --apply ---
         if exec && !dry_run {
             log_step("Executing immediate install for added packages...");
+            let config = ShardConfig::load().unwrap_or_default();
+            let brew_client = brew_client.clone().with_env(env::resolve(&env::merge([&config.env, &manifest.env]))?);
             for (name, pkg_type) in &added_packages_map {
                  match pkg_type {
-                      PackageType::Formula => brew_client.install_formula(name, &[])?,
-                      PackageType::Cask => brew_client.install_cask(name, &[])?,
+                      PackageType::Formula => brew_client.install_formula(name, &manifest.formula_build(name).install_args())?,
+                      PackageType::Cask => {
+                          let options = merge_options(&config.cask_options, manifest.cask_options_for(name));
+                          brew_client.install_cask(name, &options)?
+                      }
                  }
             }
             log_success("Immediate installation complete.");
         } else if apply_all && !dry_run {
             log_step("Running 'apply all'...");
-            apply::apply_all_enabled_shards(false)?; // Don't skip cleanup
+            apply::apply_all_enabled_shards(false, false, &[], overrides)?; // Don't skip cleanup
             log_success("'apply all' complete.");
         } else if exec && dry_run {
              log_debug("Dry run: Would execute immediate install for added packages.");
@@ -169,6 +218,7 @@ fn determine_package_type(
     availability: &PackageAvailability,
     force_formula: bool,
     force_cask: bool,
+    prefer: PackagePreference,
 ) -> ShardResult<Option<PackageType>> {
     if force_formula {
         if availability.available_as_formula {
@@ -193,9 +243,24 @@ fn determine_package_type(
             Ok(None) // Don't automatically switch if forced
         }
     } else {
-        // Auto-detect: Prefer Cask if available, otherwise Formula
-        if availability.available_as_cask {
-             log_debug(&format!("Package '{}' found as cask (preferred).", package_name));
+        // Auto-detect: the configured preference decides when both exist
+        if availability.available_as_cask && availability.available_as_formula {
+            let package_type = match prefer {
+                PackagePreference::Formula => PackageType::Formula,
+                PackagePreference::Cask => PackageType::Cask,
+                PackagePreference::Ask if console::user_attended() => choose_package_type(availability)?,
+                PackagePreference::Ask => {
+                    log_warning(&format!(
+                        "Package '{}' exists as formula and cask, adding the formula. Use --cask to add the cask.",
+                        package_name
+                    ));
+                    PackageType::Formula
+                }
+            };
+            log_debug(&format!("Package '{}' found as formula and cask, using {}.", package_name, package_type.as_str()));
+            Ok(Some(package_type))
+        } else if availability.available_as_cask {
+             log_debug(&format!("Package '{}' found as cask.", package_name));
             Ok(Some(PackageType::Cask))
         } else if availability.available_as_formula {
              log_debug(&format!("Package '{}' found as formula.", package_name));
@@ -207,7 +272,135 @@ fn determine_package_type(
     }
 }
 
+/// Ask whether to add the formula or the cask of a name that exists as both
+fn choose_package_type(availability: &PackageAvailability) -> ShardResult<PackageType> {
+    let package_name = &availability.name;
+    let formula_description = availability.formula.as_ref().map(|info| info.description.as_str()).unwrap_or_default();
+    let cask_description = availability.cask.as_ref().map(|info| info.description.as_str()).unwrap_or_default();
+
+    let labels = [
+        package_label(package_name, PackageType::Formula, formula_description),
+        package_label(package_name, PackageType::Cask, cask_description),
+    ];
+
+    let selection = Select::new()
+        .with_prompt(format!("'{}' exists as formula and cask. Which one should be added?", package_name))
+        .items(&labels)
+        .default(0)
+        .interact()?;
+
+    Ok(if selection == 0 { PackageType::Formula } else { PackageType::Cask })
+}
+
+/// Prompt label with the package type and description
+fn package_label(name: &str, package_type: PackageType, description: &str) -> String {
+    if description.is_empty() {
+        format!("{} ({})", name, package_type.as_str())
+    } else {
+        format!("{} ({}) - {}", name, package_type.as_str(), description)
+    }
+}
+
+/// Maximum number of search results per package type offered for selection
+const MAX_SEARCH_CANDIDATES: usize = 10;
+
+/// Search brew for a query and let the user pick packages to add
+///
+/// Shows formula and cask candidates with their descriptions in a multi-select
+/// prompt and returns the chosen packages with their types.
+fn select_from_search(brew_client: &BrewClient, query: &str) -> ShardResult<Vec<(String, PackageType)>> {
+    log_step(&format!("Searching for packages matching '{}'...", query));
+
+    let candidates = search_candidates(brew_client, query, |_, _| true);
+    if candidates.is_empty() {
+        log_warning(&format!("No packages found matching '{}'.", query));
+        return Ok(Vec::new());
+    }
+
+    pick_candidates(&candidates, &format!("'{}' was not found", query), &[])
+}
+
+/// Let the user pick between an exact match and its versions or variants
+///
+/// `python` may mean `python@3.12` as well, `firefox` may mean `firefox@beta`.
+/// Without such search hits the exact match is returned right away, otherwise
+/// all are offered with the exact match selected.
+fn select_variants(brew_client: &BrewClient, name: &str, package_type: PackageType) -> ShardResult<Vec<(String, PackageType)>> {
+    let variant = format!("{}@", name);
+    let candidates = search_candidates(brew_client, name, |candidate, candidate_type| {
+        candidate.starts_with(&variant) || (candidate == name && candidate_type == package_type)
+    });
+    if !candidates.iter().any(|(candidate, _, _)| candidate.starts_with(&variant)) {
+        return Ok(vec![(name.to_string(), package_type)]);
+    }
+
+    let selected: Vec<bool> = candidates.iter()
+        .map(|(candidate, candidate_type, _)| candidate == name && *candidate_type == package_type)
+        .collect();
+    pick_candidates(&candidates, &format!("'{}' has several versions", name), &selected)
+}
+
+/// Search results accepted by `keep` with their descriptions, at most
+/// [`MAX_SEARCH_CANDIDATES`] per package type
+fn search_candidates(
+    brew_client: &BrewClient,
+    query: &str,
+    keep: impl Fn(&str, PackageType) -> bool,
+) -> Vec<(String, PackageType, String)> {
+    let mut candidates = Vec::new();
+
+    for package_type in [PackageType::Formula, PackageType::Cask] {
+        let cask = package_type == PackageType::Cask;
+        let mut names: Vec<String> = brew_client.search(query, !cask, cask).unwrap_or_default()
+            .into_iter()
+            .filter(|name| keep(name, package_type))
+            .collect();
+        names.truncate(MAX_SEARCH_CANDIDATES);
+
+        let mut descriptions = brew_client.get_descriptions(&names, cask).unwrap_or_default();
+        for name in names {
+            let description = descriptions.remove(&name).unwrap_or_default();
+            candidates.push((name, package_type, description));
+        }
+    }
+
+    candidates
+}
+
+/// Multi-select prompt over search candidates, `selected` marks those chosen up front
+fn pick_candidates(
+    candidates: &[(String, PackageType, String)],
+    reason: &str,
+    selected: &[bool],
+) -> ShardResult<Vec<(String, PackageType)>> {
+    let labels: Vec<String> = candidates.iter()
+        .map(|(name, package_type, description)| package_label(name, *package_type, description))
+        .collect();
+
+    let selection = MultiSelect::new()
+        .with_prompt(format!("{}. Select packages to add (space to select, enter to confirm)", reason))
+        .items(&labels)
+        .defaults(selected)
+        .interact()?;
+
+    Ok(selection.into_iter()
+        .map(|index| (candidates[index].0.clone(), candidates[index].1))
+        .collect())
+}
+
+/// Uninstall a formula unless installed packages depend on it
+///
+/// Brew would refuse anyway, this names the dependents up front.
+fn uninstall_unless_required(brew_client: &BrewClient, formula: &str) -> ShardResult<()> {
+    let dependents = brew_client.get_installed_dependents(formula)?;
+    if !dependents.is_empty() {
+        return Err(ShardError::PackageError(format!("required by {}", dependents.join(", "))));
+    }
+    brew_client.uninstall_formula(formula, true)
+}
+
 /// Remove packages from manifest and potentially uninstall/apply
+#[allow(clippy::too_many_arguments)]
 pub fn remove_packages(
     packages: &[String],
     force_formula: bool, // Renamed from force_brew
@@ -216,7 +409,9 @@ pub fn remove_packages(
     dry_run: bool,
     exec: bool,          // New flag
     apply_all: bool,     // New flag
+    overrides: ClientOverrides,
 ) -> ShardResult<()> {
+    let packages = &aliases::resolve_all(packages);
     log_step(&format!("Removing packages from shard '{}': {}", manifest_target, packages.join(", ")));
     if dry_run { log_debug("Dry run enabled"); }
     if exec { log_debug("Exec flag enabled: will uninstall removed packages immediately"); }
@@ -224,10 +419,10 @@ pub fn remove_packages(
 
     // Handle "all" differently - need to process multiple manifests
     if manifest_target.eq_ignore_ascii_case("all") {
-        return remove_packages_from_all(packages, force_formula, force_cask, dry_run, exec, apply_all);
+        return remove_packages_from_all(packages, force_formula, force_cask, dry_run, exec, apply_all, overrides);
     }
 
-    let brew_client = get_client();
+    let brew_client = get_client(overrides);
 
     // Validate all package names first
     for package in packages {
@@ -251,11 +446,9 @@ pub fn remove_packages(
     
     // Check protection
     let shard_name_for_check = manifest_path_obj.file_stem().unwrap_or_default().to_string_lossy();
-    let manager = shard_manager::ShardManager::new()?;
-    if manager.shard_is_protected(&shard_name_for_check) {
-        log_error(&format!("Cannot modify protected shard: {}", shard_name_for_check));
-        return Err(ShardError::Protected(shard_name_for_check.to_string()));
-    }
+    let manager = shard_manager::ShardManager::new()?; // Use manager for checks
+    manager.ensure_editable(&shard_name_for_check)
+        .inspect_err(|_| log_error(&format!("Cannot modify protected shard: {}", shard_name_for_check)))?;
 
     // Track removed packages for --exec option
     let mut removed_packages: HashMap<String, PackageTypeWrapper> = HashMap::new();
@@ -293,6 +486,11 @@ pub fn remove_packages(
         
         // If found and removed, track for potential uninstall
         if package_found {
+            if !dry_run && !manifest.formulae.contains(package_name) && !manifest.casks.contains(package_name) {
+                manifest.comments.remove(package_name);
+                manifest.contexts.remove(package_name);
+                manifest.links.remove(package_name);
+            }
             if let Some(pkg_type) = package_type {
                 removed_packages.insert(package_name.clone(), pkg_type);
             }
@@ -305,6 +503,7 @@ pub fn remove_packages(
     if !removed_packages.is_empty() {
         if !dry_run {
             log_step(&format!("Saving updated manifest: {}", manifest_path));
+            manifest.update_modification_info();
             manifest.to_file(&manifest_path_obj)?;
             log_success("Manifest saved.");
         } else {
@@ -319,7 +518,7 @@ pub fn remove_packages(
                 match pkg_type {
                     PackageTypeWrapper::Formula => {
                         log_debug(&format!("Uninstalling formula: {}", name));
-                        brew_client.uninstall_formula(name, true)
+                        uninstall_unless_required(&brew_client, name)
                             .unwrap_or_else(|e| log_error(&format!("Failed to uninstall formula {}: {}", name, e)));
                     }
                     PackageTypeWrapper::Cask => {
@@ -333,7 +532,7 @@ pub fn remove_packages(
             log_success("Immediate uninstallation complete.");
         } else if apply_all && !dry_run {
             log_step("Running 'apply all'...");
-            apply::apply_all_enabled_shards(false)?;
+            apply::apply_all_enabled_shards(false, false, &[], overrides)?;
             log_success("'apply all' complete.");
         } else if exec && dry_run {
             log_debug("Dry run: Would execute immediate uninstall for removed packages.");
@@ -355,6 +554,7 @@ fn remove_packages_from_all(
     dry_run: bool,
     exec: bool,
     apply_all: bool,
+    overrides: ClientOverrides,
 ) -> ShardResult<()> {
     log_step(&format!("Removing packages from all non-protected shards: {}", packages.join(", ")));
     
@@ -364,10 +564,10 @@ fn remove_packages_from_all(
     // Collect all non-protected shard paths
     let mut non_protected_shards = Vec::new();
     for shard_name in active_shards {
-        if !manager.shard_is_protected(&shard_name) {
+        if manager.ensure_editable(&shard_name).is_ok() {
             non_protected_shards.push(shard_name);
         } else {
-            log_debug(&format!("Skipping protected shard: {}", shard_name));
+            log_debug(&format!("Skipping protected or foreign shard: {}", shard_name));
         }
     }
     
@@ -383,7 +583,7 @@ fn remove_packages_from_all(
     for shard_name in non_protected_shards {
         // Use the individual shard version with exec=false to avoid multiple uninstalls
         // We'll handle exec once at the end if needed
-        match remove_packages(packages, force_formula, force_cask, &shard_name, dry_run, false, false) {
+        match remove_packages(packages, force_formula, force_cask, &shard_name, dry_run, false, false, overrides) {
             Ok(_) => {
                 any_changes = true;
                 log_debug(&format!("Successfully processed shard: {}", shard_name));
@@ -397,7 +597,7 @@ fn remove_packages_from_all(
     
     // After processing all shards, handle --exec and --apply if requested
     if any_changes {
-        let brew_client = get_client();
+        let brew_client = get_client(overrides);
         
         if exec && !dry_run {
             log_step("Executing immediate uninstall for removed packages...");
@@ -406,7 +606,7 @@ fn remove_packages_from_all(
                 // try to uninstall both types (formula and cask) if the flags allow
                 if !force_cask {
                     log_debug(&format!("Attempting to uninstall formula: {}", package_name));
-                    brew_client.uninstall_formula(package_name, true)
+                    uninstall_unless_required(&brew_client, package_name)
                         .unwrap_or_else(|e| log_debug(&format!("Formula {} uninstall skipped: {}", package_name, e)));
                 }
                 
@@ -419,7 +619,7 @@ fn remove_packages_from_all(
             log_success("Immediate uninstallation attempts complete.");
         } else if apply_all && !dry_run {
             log_step("Running 'apply all'...");
-            apply::apply_all_enabled_shards(false)?;
+            apply::apply_all_enabled_shards(false, false, &[], overrides)?;
             log_success("'apply all' complete.");
         } else if exec && dry_run {
             log_debug("Dry run: Would execute immediate uninstall for removed packages.");
@@ -1,7 +1,9 @@
 use crate::ShardResult;
 use crate::core::manifest::{PackageState, Formula, Cask};
-use crate::brew::{BrewClient, get_client};
-use crate::utils::{log_step, log_success, log_error, log_warning};
+use crate::brew::{BrewClient, ClientOverrides, get_client};
+use crate::engine::{execute_action, Action, Plan};
+use crate::reporter::{Event, Reporter};
+use crate::utils::{log_step, log_success, log_error};
 
 /// Represents the type of package being managed
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -125,7 +127,7 @@ impl PackageProcessor {
             package_type,
             installed_packages,
             suppress_messages,
-            brew_client: get_client(),
+            brew_client: get_client(ClientOverrides::default()),
         }
     }
     
@@ -189,110 +191,44 @@ impl PackageProcessor {
     }
     
     /// Execute operations on the packages based on the processed results
-    pub fn execute_operations(&self, result: &PackageProcessResult, dry_run: bool) -> ShardResult<()> {
-        let pkg_type_str = self.package_type.as_str();
+    ///
+    /// Progress is sent to `reporter`. In dry-run mode nothing is executed and
+    /// the pending changes are reported as a plan instead.
+    pub fn execute_operations(&self, result: &PackageProcessResult, dry_run: bool, reporter: &dyn Reporter) -> ShardResult<()> {
+        let package_type = self.package_type;
+        let install = |name: &String| Action::Install { package_type, name: name.clone() };
+        let upgrade = |name: &String| Action::Upgrade { package_type, name: name.clone() };
+        let uninstall = |name: &String| Action::Uninstall { package_type, name: name.clone() };
 
         // --- Dry Run Handling ---
         if dry_run {
-            if !result.to_install.is_empty() {
-                 log_step(&format!("Would install {} {}(s): {}", result.to_install.len(), pkg_type_str, result.to_install.join(", ")));
-            }
-            // Skip upgrade information in dry-run mode as it's not relevant for manifests
-            // Only show packages that would be newly installed or uninstalled
-            for (name, options) in &result.with_options {
-                 // Only show installation messages, not upgrades
-                 if !self.is_installed(name) {
-                     log_step(&format!("Would install {} {} with options: {}", pkg_type_str, name, options.join(" ")));
-                 }
-            }
-            if !result.to_uninstall.is_empty() {
-                 log_step(&format!("Would uninstall {} {}(s): {}", result.to_uninstall.len(), pkg_type_str, result.to_uninstall.join(", ")));
-            }
+            // Upgrades are not relevant for manifests, only show what would be
+            // newly installed or uninstalled
+            let actions = result.to_install.iter().map(install)
+                .chain(result.with_options.iter()
+                    .filter(|(name, _)| !self.is_installed(name))
+                    .map(|(name, _)| install(name)))
+                .chain(result.to_uninstall.iter().map(uninstall))
+                .collect();
+            reporter.report(&Event::PlanReady(Box::new(Plan { actions, ..Plan::default() })));
             return Ok(());
         }
 
         // --- Actual Execution ---
-
-        // Process installations (batch)
-        if !result.to_install.is_empty() {
-            match self.package_type {
-                PackageType::Formula => {
-                    // Use our improved method with better error handling
-                    if let Err(e) = self.brew_client.batch_install_formulae(&result.to_install) {
-                        log_warning(&format!("Some formula installations may have failed: {}", e));
-                    }
-                },
-                PackageType::Cask => {
-                    // This already has improved error handling
-                    if let Err(e) = self.brew_client.batch_install_casks(&result.to_install) {
-                        log_warning(&format!("Some cask installations may have failed: {}", e));
-                    }
-                },
-            }
+        let actions = result.to_install.iter().map(install)
+            .chain(result.to_upgrade.iter().map(upgrade));
+        for action in actions {
+            execute_action(&self.brew_client, &action, &[], reporter);
         }
 
-        // Process upgrades (batch) - with improved error handling
-        if !result.to_upgrade.is_empty() {
-            match self.package_type {
-                PackageType::Formula => {
-                    if let Err(e) = self.brew_client.batch_upgrade_formulae(&result.to_upgrade) {
-                        log_warning(&format!("Some formula upgrades may have failed: {}", e));
-                    }
-                },
-                PackageType::Cask => {
-                    if let Err(e) = self.brew_client.batch_upgrade_casks(&result.to_upgrade) {
-                        log_warning(&format!("Some cask upgrades may have failed: {}", e));
-                    }
-                },
-            }
-        }
-
-        // Process packages with options (individual)
+        // Packages with options are installed or upgraded individually
         for (name, options) in &result.with_options {
-            let is_installed = self.is_installed(name);
-            match self.package_type {
-                PackageType::Formula => {
-                    if is_installed {
-                        if let Err(e) = self.brew_client.upgrade_formula_with_options(name, options) {
-                            log_warning(&format!("Failed to upgrade formula {} with options: {}", name, e));
-                        }
-                    } else {
-                        if let Err(e) = self.brew_client.install_formula(name, options) {
-                            log_warning(&format!("Failed to install formula {} with options: {}", name, e));
-                        }
-                    }
-                }
-                PackageType::Cask => {
-                    if is_installed {
-                        if let Err(e) = self.brew_client.upgrade_cask_with_options(name, options) {
-                            log_warning(&format!("Failed to upgrade cask {} with options: {}", name, e));
-                        } 
-                    } else {
-                        if let Err(e) = self.brew_client.install_cask(name, options) {
-                            log_warning(&format!("Failed to install cask {} with options: {}", name, e));
-                        }
-                    }
-                }
-            }
+            let action = if self.is_installed(name) { upgrade(name) } else { install(name) };
+            execute_action(&self.brew_client, &action, options, reporter);
         }
 
-        // Process uninstallations (individual)
-        if !result.to_uninstall.is_empty() {
-             log_step(&format!("Processing {} {} uninstalls...", result.to_uninstall.len(), pkg_type_str));
-            for name in &result.to_uninstall {
-                match self.package_type {
-                     PackageType::Formula => {
-                         if let Err(e) = self.brew_client.uninstall_formula(name, true) {
-                              log_warning(&format!("Failed to uninstall formula {}: {}", name, e));
-                         }
-                     },
-                     PackageType::Cask => {
-                         if let Err(e) = self.brew_client.uninstall_cask(name, true) {
-                              log_warning(&format!("Failed to uninstall cask {}: {}", name, e));
-                         }
-                     },
-                }
-            }
+        for name in &result.to_uninstall {
+            execute_action(&self.brew_client, &uninstall(name), &[], reporter);
         }
 
         Ok(())
@@ -300,7 +236,7 @@ impl PackageProcessor {
     
     /// Create a new processor for formulae
     pub fn for_formulae(suppress_messages: bool) -> ShardResult<Self> {
-        let brew_client = get_client();
+        let brew_client = get_client(ClientOverrides::default());
         let installed_packages = brew_client.get_installed_formulae()?;
         Ok(Self {
             package_type: PackageType::Formula,
@@ -312,7 +248,7 @@ impl PackageProcessor {
     
     /// Create a new processor for casks
     pub fn for_casks(suppress_messages: bool) -> ShardResult<Self> {
-        let brew_client = get_client();
+        let brew_client = get_client(ClientOverrides::default());
         let installed_packages = brew_client.get_installed_casks()?;
         Ok(Self {
             package_type: PackageType::Cask,
@@ -332,32 +268,32 @@ impl PackageProcessor {
 }
 
 // The following are convenience functions that directly use the BrewClient
-// These could be imported directly from brew::get_client() in consuming code
+// These could be imported directly from brew::get_client(ClientOverrides::default()) in consuming code
 // but are kept for backward compatibility
 
 /// Get a list of all currently installed formulae
 pub fn get_installed_formulae() -> ShardResult<Vec<String>> {
-    get_client().get_installed_formulae()
+    get_client(ClientOverrides::default()).get_installed_formulae()
 }
 
 /// Get a list of all currently installed casks
 pub fn get_installed_casks() -> ShardResult<Vec<String>> {
-    get_client().get_installed_casks()
+    get_client(ClientOverrides::default()).get_installed_casks()
 }
 
 /// Get a list of all currently installed taps
 pub fn get_installed_taps() -> ShardResult<Vec<String>> {
-    get_client().get_installed_taps()
+    get_client(ClientOverrides::default()).get_installed_taps()
 }
 
 /// Get a list of all packages installed as dependencies
 pub fn get_dependency_packages() -> ShardResult<Vec<String>> {
-    get_client().get_dependency_packages()
+    get_client(ClientOverrides::default()).get_dependency_packages()
 }
 
 /// Get a list of explicitly installed packages (both formulae and casks, excluding dependencies)
 pub fn get_all_main_packages() -> ShardResult<(Vec<String>, Vec<String>)> {
-    let brew_client = get_client();
+    let brew_client = get_client(ClientOverrides::default());
     let main_formulae = brew_client.get_installed_formulae()?;
     let main_casks = brew_client.get_installed_casks()?;
     let dependency_packages = brew_client.get_dependency_packages()?;
@@ -373,13 +309,13 @@ pub fn get_all_main_packages() -> ShardResult<(Vec<String>, Vec<String>)> {
 
 /// Add a tap to Homebrew
 pub fn add_tap(name: &str) -> ShardResult<()> {
-    get_client().add_tap(name)
+    get_client(ClientOverrides::default()).add_tap(name, None)
 }
 
 /// Run Homebrew cleanup
 pub fn run_cleanup() -> ShardResult<()> {
     log_step("Running final cleanup...");
-    match get_client().cleanup(true) {
+    match get_client(ClientOverrides::default()).cleanup(true) {
         Ok(_) => {
             log_success("Final cleanup completed");
             Ok(())
@@ -0,0 +1,199 @@
+//! External executables that manage custom manifest sections.
+//!
+//! A plugin is registered with a TOML file in `~/.sapphire/plugins`, named
+//! after the plugin:
+//!
+//! ```toml
+//! # ~/.sapphire/plugins/npm.toml
+//! command = "~/bin/sapphire-npm"
+//! args = []
+//! sections = ["npm"]
+//! ```
+//!
+//! Manifests may then contain an `[npm]` section. The plugin is run once per
+//! claimed section with a JSON request on stdin:
+//!
+//! ```json
+//! { "protocol": 1, "section": "npm", "dry_run": true,
+//!   "shards": { "user": { "packages": ["typescript"] } } }
+//! ```
+//!
+//! and answers on stdout with the changes it would make, or made when
+//! `dry_run` is false:
+//!
+//! ```json
+//! { "changes": [{ "action": "install", "target": "typescript", "detail": "5.4.5" }] }
+//! ```
+//!
+//! A change with an `error` failed. A plugin exiting with a non-zero status
+//! fails as a whole. Plans run the plugin with `dry_run`, applies run it again
+//! without, so the changes show up in `diff` and `apply` output like the
+//! packages do.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use serde::{Deserialize, Serialize};
+use sapphire_core::paths::SapphirePaths;
+use crate::utils::{ShardError, ShardResult, ResultExt, path_exists};
+
+/// Version of the request format, sent along so plugins can reject newer ones
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A registered plugin
+#[derive(Debug, Clone, Deserialize)]
+pub struct Plugin {
+    /// File name of the definition without `.toml`
+    #[serde(skip)]
+    pub name: String,
+    /// Executable, `~` is expanded
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Manifest sections the plugin manages
+    pub sections: Vec<String>,
+}
+
+/// A change reported by a plugin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginChange {
+    /// What happens, e.g. `install`
+    pub action: String,
+    /// What it happens to, e.g. a package name
+    pub target: String,
+    /// Extra information, e.g. a version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Why the change failed, only set when applying
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl std::fmt::Display for PluginChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.action, self.target)?;
+        if let Some(detail) = &self.detail {
+            write!(f, " ({})", detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Changes of a plugin for one section, planned or applied
+#[derive(Debug, Clone)]
+pub struct PluginPlan {
+    pub plugin: String,
+    pub section: String,
+    /// Content of the section per shard, as passed to the plugin
+    pub shards: BTreeMap<String, toml::Value>,
+    pub changes: Vec<PluginChange>,
+    /// Why the plugin itself failed, in which case `changes` is empty
+    pub error: Option<String>,
+}
+
+impl PluginPlan {
+    /// Failed changes, counting a failed plugin as one
+    pub fn failures(&self) -> usize {
+        usize::from(self.error.is_some()) + self.changes.iter().filter(|change| change.error.is_some()).count()
+    }
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    protocol: u32,
+    section: &'a str,
+    dry_run: bool,
+    shards: &'a BTreeMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(default)]
+    changes: Vec<PluginChange>,
+}
+
+impl Plugin {
+    /// Every plugin in the plugins directory, by name
+    ///
+    /// Definitions that cannot be read fail the whole load, a plugin silently
+    /// missing would leave its sections unmanaged.
+    pub fn load_all() -> ShardResult<Vec<Plugin>> {
+        let dir = SapphirePaths::resolve().plugins_dir();
+        if !path_exists(&dir) {
+            return Ok(Vec::new());
+        }
+
+        let mut plugins = Vec::new();
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                continue;
+            }
+            plugins.push(Self::from_file(&path)?);
+        }
+        plugins.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(plugins)
+    }
+
+    fn from_file(path: &Path) -> ShardResult<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read plugin {}", path.display()))?;
+        let mut plugin: Plugin = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse plugin {}", path.display()))?;
+        plugin.name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        Ok(plugin)
+    }
+
+    /// Run the plugin for a section and return the changes it reports
+    pub fn run(&self, section: &str, shards: &BTreeMap<String, toml::Value>, dry_run: bool) -> ShardResult<Vec<PluginChange>> {
+        let request = serde_json::to_vec(&Request { protocol: PROTOCOL_VERSION, section, dry_run, shards })
+            .with_context(|| format!("Failed to serialize the {} section", section))?;
+
+        let command = shellexpand::tilde(&self.command).into_owned();
+        let mut child = Command::new(&command)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run plugin {} ({})", self.name, command))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&request)?;
+        }
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(ShardError::Other(format!("Plugin {} exited with {}: {}",
+                self.name, output.status, String::from_utf8_lossy(&output.stderr).trim())));
+        }
+        let response: Response = serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("Plugin {} returned invalid JSON", self.name))?;
+        Ok(response.changes)
+    }
+}
+
+/// Plan every custom section with the plugin that claims it
+///
+/// `sections` maps section names to their content per shard. Plugins that
+/// fail are recorded in their plan rather than failing the whole plan.
+/// Returns the plans and the sections no plugin claims.
+pub fn plan(plugins: &[Plugin], sections: BTreeMap<String, BTreeMap<String, toml::Value>>) -> (Vec<PluginPlan>, Vec<String>) {
+    let mut plans = Vec::new();
+    let mut unclaimed = Vec::new();
+
+    for (section, shards) in sections {
+        let Some(plugin) = plugins.iter().find(|plugin| plugin.sections.contains(&section)) else {
+            unclaimed.push(section);
+            continue;
+        };
+
+        let (changes, error) = match plugin.run(&section, &shards, true) {
+            Ok(changes) => (changes, None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+        plans.push(PluginPlan { plugin: plugin.name.clone(), section, shards, changes, error });
+    }
+
+    (plans, unclaimed)
+}
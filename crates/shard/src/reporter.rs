@@ -0,0 +1,251 @@
+//! Typed progress events and the reporters that present them.
+//!
+//! Operations describe what they are doing by emitting [`Event`]s through a
+//! [`Reporter`] instead of printing directly. [`ConsoleReporter`] renders them
+//! the way the CLI always has; other front-ends (JSON output, a TUI, a GUI)
+//! provide their own implementation. Any `Fn(&Event)` closure is a reporter.
+
+use crate::engine::{Action, Plan};
+use crate::package::processor::PackageType;
+use crate::plugins::PluginChange;
+use crate::utils::{Glyph, log_step, log_warning, log_error, log_debug};
+
+/// Something that happened while planning or applying shards
+#[derive(Debug, Clone)]
+pub enum Event {
+    ShardLoaded { name: String },
+    ShardSkipped { name: String, error: String },
+    /// A plan was computed and is about to be shown or applied
+    PlanReady(Box<Plan>),
+    TapAddStarted { name: String },
+    TapAdded { name: String },
+    TapAddFailed { name: String, error: String },
+    PackageInstallStarted { package_type: PackageType, name: String },
+    PackageInstalled { package_type: PackageType, name: String },
+    PackageInstallFailed { package_type: PackageType, name: String, error: String },
+    PackageUpgradeStarted { package_type: PackageType, name: String },
+    PackageUpgraded { package_type: PackageType, name: String },
+    PackageUpgradeFailed { package_type: PackageType, name: String, error: String },
+    PackageUninstallStarted { package_type: PackageType, name: String },
+    PackageUninstalled { package_type: PackageType, name: String },
+    PackageUninstallFailed { package_type: PackageType, name: String, error: String },
+    /// Brew reported the package as already in the desired state, or its
+    /// installer needs someone to answer a prompt
+    PackageSkipped { package_type: PackageType, name: String, reason: String },
+    ExtensionInstallStarted { name: String },
+    ExtensionInstalled { name: String },
+    ExtensionInstallFailed { name: String, error: String },
+    FormulaLinkStarted { name: String, linked: bool },
+    FormulaLinked { name: String, linked: bool },
+    FormulaLinkFailed { name: String, linked: bool, error: String },
+    /// Declared VS Code extensions were left out of the plan
+    ExtensionsSkipped { count: usize, reason: String },
+    /// Dependencies nothing needs after the uninstalls were removed
+    OrphansRemoved { names: Vec<String> },
+    /// Planned orphaned dependencies were left installed
+    OrphansKept { names: Vec<String>, reason: String },
+    PluginStarted { plugin: String, section: String },
+    /// A plugin reported a change, failed if the change has an error
+    PluginChanged { plugin: String, change: PluginChange },
+    PluginFailed { plugin: String, error: String },
+    CleanupStarted,
+    CleanupFinished,
+}
+
+impl Event {
+    /// Event announcing that an action is about to run
+    pub fn started(action: &Action) -> Self {
+        match action.clone() {
+            Action::AddTap { name, .. } => Event::TapAddStarted { name },
+            Action::Install { package_type, name } => Event::PackageInstallStarted { package_type, name },
+            Action::Upgrade { package_type, name } => Event::PackageUpgradeStarted { package_type, name },
+            Action::Uninstall { package_type, name } => Event::PackageUninstallStarted { package_type, name },
+            Action::InstallExtension { name } => Event::ExtensionInstallStarted { name },
+            Action::Link { name, link } => Event::FormulaLinkStarted { name, linked: link.is_linked() },
+        }
+    }
+
+    /// Event announcing that an action completed
+    pub fn succeeded(action: &Action) -> Self {
+        match action.clone() {
+            Action::AddTap { name, .. } => Event::TapAdded { name },
+            Action::Install { package_type, name } => Event::PackageInstalled { package_type, name },
+            Action::Upgrade { package_type, name } => Event::PackageUpgraded { package_type, name },
+            Action::Uninstall { package_type, name } => Event::PackageUninstalled { package_type, name },
+            Action::InstallExtension { name } => Event::ExtensionInstalled { name },
+            Action::Link { name, link } => Event::FormulaLinked { name, linked: link.is_linked() },
+        }
+    }
+
+    /// Event announcing that an action failed
+    pub fn failed(action: &Action, error: String) -> Self {
+        match action.clone() {
+            Action::AddTap { name, .. } => Event::TapAddFailed { name, error },
+            Action::Install { package_type, name } => Event::PackageInstallFailed { package_type, name, error },
+            Action::Upgrade { package_type, name } => Event::PackageUpgradeFailed { package_type, name, error },
+            Action::Uninstall { package_type, name } => Event::PackageUninstallFailed { package_type, name, error },
+            Action::InstallExtension { name } => Event::ExtensionInstallFailed { name, error },
+            Action::Link { name, link } => Event::FormulaLinkFailed { name, linked: link.is_linked(), error },
+        }
+    }
+}
+
+/// Receives events emitted by shard operations
+pub trait Reporter {
+    fn report(&self, event: &Event);
+}
+
+impl<F> Reporter for F
+where
+    F: Fn(&Event),
+{
+    fn report(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Reporter that discards every event
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullReporter;
+
+impl Reporter for NullReporter {
+    fn report(&self, _event: &Event) {}
+}
+
+/// Reporter that prints events with the standard log helpers
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConsoleReporter;
+
+impl Reporter for ConsoleReporter {
+    fn report(&self, event: &Event) {
+        match event {
+            Event::ShardLoaded { name } => log_debug(&format!("Loaded shard: {}", name)),
+            Event::ShardSkipped { name, error } => {
+                log_warning(&format!("Skipping invalid manifest file {}: {}", name, error))
+            }
+            Event::PlanReady(plan) => print_plan(plan),
+            Event::TapAddStarted { name } => log_debug(&format!("Adding tap: {}", name)),
+            Event::TapAddFailed { name, error } => log_error(&format!("Failed to add tap {}: {}", name, error)),
+            Event::PackageInstallStarted { package_type, name } => {
+                log_debug(&format!("Installing {}: {}", package_type.as_str(), name))
+            }
+            Event::PackageInstallFailed { name, error, .. } => {
+                log_error(&format!("Error installing {}: {}", name, error))
+            }
+            Event::PackageUpgradeStarted { package_type, name } => {
+                log_debug(&format!("Upgrading {}: {}", package_type.as_str(), name))
+            }
+            Event::PackageUpgradeFailed { name, error, .. } => {
+                log_warning(&format!("Error upgrading {}: {}", name, error))
+            }
+            Event::PackageUninstallStarted { package_type, name } => {
+                log_debug(&format!("Uninstalling {}: {}", package_type.as_str(), name))
+            }
+            Event::PackageUninstallFailed { package_type, name, error } => {
+                log_error(&format!("Failed uninstalling {} {}: {}", package_type.as_str(), name, error))
+            }
+            Event::PackageSkipped { name, reason, .. } => log_warning(&format!("Skipping {}: {}", name, reason)),
+            Event::ExtensionInstallStarted { name } => log_debug(&format!("Installing VS Code extension: {}", name)),
+            Event::ExtensionInstallFailed { name, error } => {
+                log_error(&format!("Error installing VS Code extension {}: {}", name, error))
+            }
+            Event::FormulaLinkStarted { name, linked } => {
+                log_debug(&format!("{} formula: {}", if *linked { "Linking" } else { "Unlinking" }, name))
+            }
+            Event::FormulaLinkFailed { name, linked, error } => {
+                log_error(&format!("Failed {} {}: {}", if *linked { "linking" } else { "unlinking" }, name, error))
+            }
+            Event::ExtensionsSkipped { count, reason } => {
+                log_warning(&format!("Skipping {} VS Code extension(s): {}", count, reason))
+            }
+            Event::OrphansRemoved { names } => {
+                log_step(&format!("Removed {} orphaned dependencie(s): {}", names.len(), names.join(", ")))
+            }
+            Event::OrphansKept { names, reason } => {
+                log_warning(&format!("Keeping {} orphaned dependencie(s), {}: {}", names.len(), reason, names.join(", ")))
+            }
+            Event::PluginStarted { plugin, section } => {
+                log_debug(&format!("Running plugin {} for section {}", plugin, section))
+            }
+            Event::PluginChanged { plugin, change } => match &change.error {
+                Some(error) => log_error(&format!("Plugin {} failed to {}: {}", plugin, change, error)),
+                None => log_debug(&format!("Plugin {}: {}", plugin, change)),
+            },
+            Event::PluginFailed { plugin, error } => log_error(&format!("Plugin {} failed: {}", plugin, error)),
+            Event::CleanupStarted => log_debug("Running brew cleanup..."),
+            Event::TapAdded { .. }
+            | Event::PackageInstalled { .. }
+            | Event::PackageUpgraded { .. }
+            | Event::PackageUninstalled { .. }
+            | Event::ExtensionInstalled { .. }
+            | Event::FormulaLinked { .. }
+            | Event::CleanupFinished => {}
+        }
+    }
+}
+
+/// Print the changes a plan would make, grouped by kind
+///
+/// Upgrades are left out since every installed package is upgraded on apply.
+fn print_plan(plan: &Plan) {
+    let groups = [
+        ("install", PackageType::Formula),
+        ("install", PackageType::Cask),
+        ("uninstall", PackageType::Formula),
+        ("uninstall", PackageType::Cask),
+    ];
+
+    let taps: Vec<&str> = plan.actions.iter()
+        .filter(|a| matches!(a, Action::AddTap { .. }))
+        .map(Action::name)
+        .collect();
+    for tap in taps {
+        log_step(&format!("{} Tap would be installed: {}", Glyph::Missing, tap));
+    }
+
+    for (verb, package_type) in groups {
+        let names: Vec<&str> = plan.actions.iter()
+            .filter_map(|action| match action {
+                Action::Install { package_type: t, name } if verb == "install" && *t == package_type => Some(name.as_str()),
+                Action::Uninstall { package_type: t, name } if verb == "uninstall" && *t == package_type => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        if names.is_empty() {
+            continue;
+        }
+
+        log_step(&format!("Would {} {} {}(s):", verb, names.len(), package_type.as_str()));
+        for name in names {
+            log_step(&format!("  {} {}", Glyph::Bullet, name));
+        }
+    }
+
+    for action in plan.actions.iter().filter(|a| matches!(a, Action::Link { .. })) {
+        log_step(&format!("Would {}", action));
+    }
+
+    if !plan.orphans.is_empty() {
+        log_step(&format!("Would uninstall {} orphaned dependencie(s):", plan.orphans.len()));
+        for name in &plan.orphans {
+            log_step(&format!("  {} {}", Glyph::Bullet, name));
+        }
+    }
+
+    for planned in &plan.plugins {
+        for change in &planned.changes {
+            log_step(&format!("Would {} ({} plugin)", change, planned.plugin));
+        }
+    }
+
+    let extensions: Vec<&str> = plan.actions.iter()
+        .filter(|a| matches!(a, Action::InstallExtension { .. }))
+        .map(Action::name)
+        .collect();
+    if !extensions.is_empty() {
+        log_step(&format!("Would install {} VS Code extension(s):", extensions.len()));
+        for name in extensions {
+            log_step(&format!("  {} {}", Glyph::Bullet, name));
+        }
+    }
+}
@@ -1,286 +1,717 @@
-use crate::utils::{ShardResult, ShardError, ResultExt, log_success, log_warning, log_error, log_step, log_debug};
-use crate::package::processor::{PackageProcessor, PackageType};
-use crate::core::manifest::{Manifest, PackageState};
-use crate::brew::{get_client, client::BrewClient};
-use std::path::{Path, PathBuf};
-use std::collections::HashSet;
-use std::fs;
-use shellexpand;
-use crate::utils::filesystem::{path_exists, resolve_manifest_path};
-
-/// Options for applying manifests - SIMPLIFIED
-#[derive(Debug, Default, Clone)]
-pub struct ApplyOptions {
-    /// If true, only install/upgrade, do not uninstall anything.
-    pub additive_only: bool,
-    /// If true, skip the final `brew cleanup`.
-    pub skip_cleanup: bool,
-}
+use crate::utils::{ShardError, ShardResult, log_success, log_warning, log_error, log_step, log_debug, log_plain};
+use std::time::Duration;
+use console::style;
+use dialoguer::MultiSelect;
+use crate::engine::{ShardEngine, ApplyOptions, ApplyMode, ApplyReport, Action, ActionOutcome, ActionTiming, Plan};
+use crate::reporter::ConsoleReporter;
+use crate::brew::{get_client, BrewClient, ClientOverrides, OutdatedSet};
+use crate::notify::{self, ApplySummary};
+use crate::webhook::{self, ApplyPayload};
+use crate::shard::{apps, context, freeze, interactive, renames, state, suggestions};
+use crate::shard::manager::ShardManager;
+use crate::core::config::ShardConfig;
+use crate::core::env;
+use crate::core::manifest::{Manifest, ManifestSection};
+use crate::package::processor::PackageType;
+use crate::plugins::{PluginChange, PluginPlan};
+use sapphire_core::history::{self, Kind, Outcome};
 
 /// Apply a *single* shard manifest file (ADDITIVE ONLY)
 /// Installs/upgrades packages defined in the shard, does NOT uninstall anything.
-pub fn apply_single_shard(shard_name: &str, skip_cleanup: bool) -> ShardResult<()> {
+///
+/// `only` limits the sections applied, all if empty.
+pub fn apply_single_shard(shard_name: &str, skip_cleanup: bool, only: &[ManifestSection], overrides: ClientOverrides) -> ShardResult<()> {
     log_step(&format!("Applying single shard (additive mode): {}", shard_name));
 
-    let manifest_path = resolve_manifest_path(shard_name)?;
-    let manifest_path_obj = Path::new(&manifest_path);
-
-    if !path_exists(manifest_path_obj) {
-        log_error(&format!("Shard manifest not found: {}", manifest_path));
-        return Err(ShardError::NotFound(shard_name.to_string()));
-    }
-
-    let manifest = Manifest::from_file(manifest_path_obj)
-        .with_context(|| format!("Failed to load manifest: {}", manifest_path))?;
-
-    let options = ApplyOptions {
-        additive_only: true, // Force additive mode for single shard apply
-        skip_cleanup,
-    };
+    let engine = console_engine(only, overrides)?;
+    let plan = engine.plan(shard_name)
+        .inspect_err(|_| log_error(&format!("Failed to load shard manifest: {}", shard_name)))?;
 
-    // Call the internal apply function
-    apply_manifest(&manifest, &options)
+    run_plan(&engine, &plan, shard_name, skip_cleanup)
 }
 
 /// Apply *all* enabled shards (SYNCHRONIZING)
 /// Installs/upgrades packages from all shards, uninstalls packages not in any enabled shard.
-pub fn apply_all_enabled_shards(skip_cleanup: bool) -> ShardResult<()> {
+///
+/// Returns early if neither the shards nor the installed packages changed
+/// since the last successful run, unless `force` is set. `only` limits the
+/// sections applied and synchronized, all if empty.
+pub fn apply_all_enabled_shards(skip_cleanup: bool, force: bool, only: &[ManifestSection], overrides: ClientOverrides) -> ShardResult<()> {
+    if let Some(state) = freeze::freeze_state()? {
+        log_warning(&format!("Shard is {}, skipping apply all. Run `shard thaw` to resume", state.describe()));
+        return Ok(());
+    }
+
+    let manifests = ShardManager::new()?.load_active_manifests()?;
+    let brew_client = get_client(overrides);
+    if !force && state::is_unchanged(&brew_client, &manifests) {
+        log_success("Already converged, nothing changed since the last apply. Use --force to apply anyway");
+        return Ok(());
+    }
+
     log_step("Applying all enabled shards (synchronizing)");
+    if !only.is_empty() {
+        let names: Vec<&str> = only.iter().map(|section| section.as_str()).collect();
+        log_step(&format!("Only applying {}", names.join(", ")));
+    }
 
-    let shards_dir_path = PathBuf::from(shellexpand::tilde("~/.sapphire/shards").into_owned());
+    let engine = console_engine(only, overrides)?;
+    let plan = engine.plan("all")?;
 
-    if !path_exists(&shards_dir_path) {
-        log_warning("Shards directory (~/.sapphire/shards) not found. Nothing to apply.");
+    if plan.shards.is_empty() {
+        log_warning("No valid manifests loaded. Nothing to apply.");
         return Ok(());
     }
 
-    // --- 1. Collect all manifests and desired state ---
-    let mut all_manifests = Vec::new();
-    let mut desired_taps = HashSet::new();
-    let mut desired_formulae = HashSet::new();
-    let mut desired_casks = HashSet::new();
+    let (plan, kept) = confirm_uninstalls(&brew_client, plan, &manifests)?;
+    run_plan(&engine, &plan, "all shards", skip_cleanup)?;
 
-    let entries = fs::read_dir(&shards_dir_path)
-        .with_context(|| format!("Failed to read shards directory: {}", shards_dir_path.display()))?;
+    // A partial apply leaves the other sections unconverged, and kept
+    // packages should be asked about again
+    if only.is_empty() && !kept && let Err(e) = state::record(&brew_client, &manifests) {
+        log_warning(&format!("Could not save the apply state: {}", e));
+    }
 
-    let mut shard_files = Vec::new();
-    for entry_res in entries {
-        if let Ok(entry) = entry_res {
-            let path = entry.path();
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "toml") {
-                shard_files.push(path);
-            }
-        }
+    log_success(&format!("Applied {} shards successfully.", plan.shards.len()));
+
+    Ok(())
+}
+
+/// Let the user keep packages when a plan would uninstall many of them
+///
+/// Shows a checklist of the uninstalls, all checked, once there are more than
+/// `uninstall_prompt_threshold`. Unchecked packages stay installed for this run
+/// and are remembered as suggestions to add to a shard. Returns the plan
+/// without them and whether any were kept.
+fn confirm_uninstalls(brew_client: &BrewClient, plan: Plan, manifests: &[(String, Manifest)]) -> ShardResult<(Plan, bool)> {
+    let declared: Vec<String> = manifests.iter()
+        .flat_map(|(_, manifest)| manifest.formulae.iter().chain(&manifest.casks).cloned())
+        .collect();
+    forget_suggestions(&declared);
+
+    let threshold = ShardConfig::load().unwrap_or_default().prompts.uninstall_threshold;
+    let uninstalls: Vec<(PackageType, String)> = plan.actions.iter()
+        .filter_map(|action| match action {
+            Action::Uninstall { package_type, name } => Some((*package_type, name.clone())),
+            _ => None,
+        })
+        .collect();
+    if threshold == 0 || uninstalls.len() <= threshold || !console::user_attended() {
+        return Ok((plan, false));
     }
-    shard_files.sort(); // Consistent order
 
-    if shard_files.is_empty() {
-        log_warning("No shard files (.toml) found in shards directory. Nothing to apply.");
-        return Ok(());
+    let labels = uninstall_labels(brew_client, &uninstalls);
+    let selection = MultiSelect::new()
+        .with_prompt(format!("Uninstall {} package(s) not present in any shard? Uncheck those to keep (space to toggle, enter to confirm)",
+            uninstalls.len()))
+        .items(&labels)
+        .defaults(&vec![true; uninstalls.len()])
+        .interact()?;
+
+    let (removed, kept): (Vec<_>, Vec<_>) = uninstalls.into_iter()
+        .enumerate()
+        .partition(|(index, _)| selection.contains(index));
+    let removed: Vec<String> = removed.into_iter().map(|(_, (_, name))| name).collect();
+    let kept: Vec<(PackageType, String)> = kept.into_iter().map(|(_, package)| package).collect();
+
+    // Packages the user removes now are no longer worth suggesting
+    forget_suggestions(&removed);
+    if kept.is_empty() {
+        return Ok((plan, false));
+    }
+
+    if let Err(e) = suggestions::record(&kept) {
+        log_warning(&format!("Could not save the kept packages: {}", e));
     }
+    let names: Vec<&str> = kept.iter().map(|(_, name)| name.as_str()).collect();
+    log_step(&format!("Keeping {} package(s) this time: {}", names.len(), names.join(", ")));
+    log_plain("  Add them to a shard with `shard add`, or apply again to be asked once more");
+
+    let mut remaining = plan;
+    remaining.actions.retain(|action| !matches!(action,
+        Action::Uninstall { name, .. } if kept.iter().any(|(_, kept)| kept == name)));
+    Ok((remaining, true))
+}
 
-    log_debug(&format!("Found {} shard file(s). Loading manifests...", shard_files.len()));
+/// Checklist entries such as `wget (formula 1.24.5)`
+fn uninstall_labels(brew_client: &BrewClient, uninstalls: &[(PackageType, String)]) -> Vec<String> {
+    let formula_versions = brew_client.get_installed_versions(false).unwrap_or_default();
+    let cask_versions = brew_client.get_installed_versions(true).unwrap_or_default();
+
+    uninstalls.iter()
+        .map(|(package_type, name)| {
+            let versions = match package_type {
+                PackageType::Formula => &formula_versions,
+                PackageType::Cask => &cask_versions,
+            };
+            match versions.get(name) {
+                Some(version) => format!("{} ({} {})", name, package_type.as_str(), version),
+                None => format!("{} ({})", name, package_type.as_str()),
+            }
+        })
+        .collect()
+}
 
-    for path in &shard_files {
-        match Manifest::from_file(path) {
-            Ok(manifest) => {
-                log_debug(&format!("Loaded shard: {}", path.display()));
-                
-                // Collect taps (from both simple and structured formats)
-                manifest.taps.iter().for_each(|tap_name| { desired_taps.insert(tap_name.clone()); });
-                manifest.taps_structured.iter().for_each(|tap| { desired_taps.insert(tap.name.clone()); });
+fn forget_suggestions(names: &[String]) {
+    if let Err(e) = suggestions::forget(names) {
+        log_warning(&format!("Could not update the kept packages: {}", e));
+    }
+}
 
-                // Collect formulae (from both simple and structured formats)
-                manifest.formulae.iter().for_each(|formula_name| { desired_formulae.insert(formula_name.clone()); });
-                manifest.formulas.iter()
-                    .filter(|f| f.state != PackageState::Absent) // Skip explicitly absent packages
-                    .for_each(|f| { desired_formulae.insert(f.name.clone()); });
+/// Engine that reports progress on the console
+fn console_engine(only: &[ManifestSection], overrides: ClientOverrides) -> ShardResult<ShardEngine> {
+    Ok(ShardEngine::new(overrides)?
+        .with_sections(only.to_vec())
+        .with_reporter(ConsoleReporter))
+}
 
-                // Collect casks (from both simple and structured formats)
-                manifest.casks.iter().for_each(|cask_name| { desired_casks.insert(cask_name.clone()); });
-                manifest.casks_structured.iter()
-                    .filter(|c| c.state != PackageState::Absent) // Skip explicitly absent packages
-                    .for_each(|c| { desired_casks.insert(c.name.clone()); });
+/// Summarize and execute a plan
+///
+/// `label` names what is applied in notifications sent for unattended runs.
+fn run_plan(engine: &ShardEngine, plan: &Plan, label: &str, skip_cleanup: bool) -> ShardResult<()> {
+    // Scheduled runs would only wait for prompts again
+    let unattended = !console::user_attended();
+    let deferred = if unattended { interactive::pending() } else { Default::default() };
+    let is_deferred = |action: &Action| match action {
+        Action::Install { package_type: PackageType::Cask, name }
+        | Action::Upgrade { package_type: PackageType::Cask, name } => deferred.contains(name),
+        _ => false,
+    };
+    let skipped: Vec<&str> = plan.actions.iter().filter(|a| is_deferred(a)).map(Action::name).collect();
+    let filtered;
+    let plan = if skipped.is_empty() {
+        plan
+    } else {
+        log_warning(&format!("Skipping {} package(s) that require an interactive install: {}",
+            skipped.len(), skipped.join(", ")));
+        let mut remaining = plan.clone();
+        remaining.actions.retain(|a| !is_deferred(a));
+        filtered = remaining;
+        &filtered
+    };
 
-                all_manifests.push(manifest);
-            }
-            Err(e) => {
-                log_warning(&format!("Skipping invalid manifest file {}: {}", path.display(), e));
-            }
+    if !plan.unmet_requirements.is_empty() {
+        log_error(&format!("{} requirement(s) of the shard(s) are not met on this machine:", plan.unmet_requirements.len()));
+        for unmet in &plan.unmet_requirements {
+            log_error(&format!("  {}: {}", unmet.shard, unmet.problem));
         }
+        return Err(ShardError::ValidationError("Not applying, fix the unmet requirements first".to_string()));
     }
 
-    if all_manifests.is_empty() {
-        log_warning("No valid manifests loaded. Nothing to apply.");
-        return Ok(());
+    let taps = plan.count(|a| matches!(a, Action::AddTap { .. }));
+    let installs = plan.count(|a| matches!(a, Action::Install { .. }));
+    let upgrades = plan.count(|a| matches!(a, Action::Upgrade { .. }));
+    let uninstalls = plan.count(|a| matches!(a, Action::Uninstall { .. }));
+    let extensions = plan.count(|a| matches!(a, Action::InstallExtension { .. }));
+    let links = plan.count(|a| matches!(a, Action::Link { .. }));
+
+    renames::warn_renamed(plan);
+
+    if !plan.skipped_by_context.is_empty() {
+        let names: Vec<String> = plan.skipped_by_context.iter()
+            .map(|skip| format!("{} ({})", skip.name, skip.contexts.join(", ")))
+            .collect();
+        log_step(&format!("Skipping {} package(s) of other contexts in {}: {}",
+            names.len(), context::describe(plan.context.as_deref()), names.join(", ")));
+    }
+    if !plan.mdm_managed.is_empty() {
+        let names: Vec<&str> = plan.mdm_managed.iter().map(|managed| managed.cask.as_str()).collect();
+        log_step(&format!("Keeping {} cask(s) managed by MDM: {}", names.len(), names.join(", ")));
+    }
+    if !plan.ignored.is_empty() {
+        log_debug(&format!("Leaving {} package(s) matched by shard.ignore alone: {}", plan.ignored.len(), plan.ignored.join(", ")));
+    }
+    for formula in &plan.keg_only_links {
+        log_warning(&format!("{} is keg-only and stays unlinked, set link = \"force\" to link it anyway", formula));
+    }
+    for versions in &plan.link_conflicts {
+        log_warning(&format!("{} all request linking, leaving their links unchanged", versions.join(" and ")));
+    }
+    if !plan.held_by_dependents.is_empty() {
+        log_warning(&format!("Keeping {} formula(e) other packages depend on:", plan.held_by_dependents.len()));
+        for hold in &plan.held_by_dependents {
+            log_plain(&format!("  {}", hold.describe()));
+        }
+    }
+    if taps > 0 {
+        log_step(&format!("Adding {} tap(s)...", taps));
+    }
+    log_step(&format!("Processing {} install(s) and {} upgrade(s)...", installs, upgrades));
+    if extensions > 0 {
+        log_step(&format!("Installing {} VS Code extension(s)...", extensions));
+    }
+    if links > 0 {
+        log_step(&format!("Changing {} formula link(s)...", links));
+    }
+    for planned in plan.plugins.iter().filter(|planned| !planned.changes.is_empty()) {
+        log_step(&format!("Applying {} change(s) to [{}] with plugin {}...", planned.changes.len(), planned.section, planned.plugin));
+    }
+    for planned in &plan.plugins {
+        if let Some(error) = &planned.error {
+            log_warning(&format!("Skipping plugin {}, it could not plan the [{}] section: {}", planned.plugin, planned.section, error));
+        }
+    }
+    for section in &plan.unclaimed_sections {
+        log_warning(&format!("No plugin claims the [{}] section, leaving it alone", section));
+    }
+    if plan.mode == ApplyMode::Synchronize {
+        log_step(&format!("Uninstalling {} package(s) not present in any shard...", uninstalls));
+        if !plan.orphans.is_empty() {
+            log_step(&format!("Uninstalling {} orphaned dependencie(s) afterwards...", plan.orphans.len()));
+        }
+    } else {
+        log_debug("Additive mode: Skipping uninstallation of packages not in manifest.");
     }
 
-    // --- 2. Create a single "virtual" manifest representing the combined desired state ---
-    let mut combined_manifest = Manifest::new();
-    combined_manifest.taps = desired_taps.into_iter().collect();
-    combined_manifest.taps.sort(); // Sort for consistent output
-    
-    combined_manifest.formulae = desired_formulae.into_iter().collect();
-    combined_manifest.formulae.sort(); // Sort for consistent output
-    
-    combined_manifest.casks = desired_casks.into_iter().collect();
-    combined_manifest.casks.sort(); // Sort for consistent output
-
-    // --- 3. Apply the combined manifest ---
-    let options = ApplyOptions {
-        additive_only: false, // Allow uninstalls for 'apply all'
-        skip_cleanup,
+    // Plans only upgrade outdated packages, this still holds for plans built elsewhere
+    let outdated = plan.outdated.to_set();
+    let was_outdated = |name: &str| outdated.is_outdated(name);
+
+    let report = engine.apply(plan, &ApplyOptions { skip_cleanup, unattended })?;
+    let summary = ApplySummary {
+        installed: report.succeeded.iter()
+            .filter(|a| matches!(a, Action::AddTap { .. } | Action::Install { .. } | Action::InstallExtension { .. }))
+            .count(),
+        upgraded: report.succeeded.iter().filter(|a| matches!(a, Action::Upgrade { .. }) && was_outdated(a.name())).count(),
+        removed: report.succeeded.iter().filter(|a| matches!(a, Action::Uninstall { .. })).count() + report.orphans_removed.len(),
+        failed: report.failed.len() + report.plugins.iter().map(PluginPlan::failures).sum::<usize>(),
+    };
+    print_summary(&report, &summary, was_outdated);
+    apps::verify_applied(engine.brew_client(), plan, &report);
+    track_interactive(&report);
+
+    let outcome = Outcome {
+        success: report.is_success(),
+        installed: summary.installed,
+        upgraded: summary.upgraded,
+        removed: summary.removed,
+        failed: summary.failed,
     };
-    apply_manifest(&combined_manifest, &options)?;
+    for shard in &plan.shards {
+        if let Err(e) = history::record(Kind::Shard, shard, outcome) {
+            log_warning(&format!("Could not record apply of shard '{}': {}", shard, e));
+        }
+    }
 
-    log_success(&format!("Applied {} shards successfully.", all_manifests.len()));
+    if let Some(config) = notify::unattended_config() {
+        notify::notify_applied(&config, label, summary);
+    }
+    if let Some(url) = ShardConfig::load().ok().and_then(|config| config.webhook_url) {
+        webhook::post_applied(&url, &ApplyPayload::new(label, &report, &summary, was_outdated));
+    }
 
-    Ok(())
-}
+    if report.is_success() {
+        return Ok(());
+    }
 
-/// Internal function to apply a given manifest state (can be combined or single)
-fn apply_manifest(manifest: &Manifest, options: &ApplyOptions) -> ShardResult<()> {
-    let brew_client = get_client();
-
-    // --- 1. Process Taps ---
-    if !manifest.taps.is_empty() {
-        log_step(&format!("Processing {} taps...", manifest.taps.len()));
-        let installed_taps = brew_client.get_installed_taps()?.into_iter().collect::<HashSet<_>>();
-        
-        for tap in &manifest.taps {
-            if !installed_taps.contains(tap) {
-                brew_client.add_tap(tap)?;
+    let plugin_failures: usize = report.plugins.iter().map(PluginPlan::failures).sum();
+    if !report.failed.is_empty() {
+        log_error(&format!("{} action(s) failed:", report.failed.len()));
+        for failed in &report.failed {
+            log_error(&format!("  {}: {}", failed.action, failed.error));
+        }
+    }
+    if plugin_failures > 0 {
+        log_error(&format!("{} plugin change(s) failed:", plugin_failures));
+        for applied in &report.plugins {
+            if let Some(error) = &applied.error {
+                log_error(&format!("  {}: {}", applied.plugin, error));
+            }
+            for change in applied.changes.iter().filter(|change| change.error.is_some()) {
+                log_error(&format!("  {} ({}): {}", change, applied.plugin, change.error.as_deref().unwrap_or_default()));
             }
         }
     }
+    Err(ShardError::PackageError(format!("{} action(s) failed", report.failed.len() + plugin_failures)))
+}
+
+/// Remember casks that waited for input and forget those installed since
+fn track_interactive(report: &ApplyReport) {
+    let installed: Vec<String> = report.succeeded.iter().map(|a| a.name().to_string()).collect();
+    if let Err(e) = interactive::clear(&installed) {
+        log_warning(&format!("Could not update the list of interactive installs: {}", e));
+    }
 
-    // --- 2. Process Formulas & Casks ---
-    log_debug("Gathering current system state...");
-    let installed_formulae = brew_client.get_installed_formulae()?;
-    let installed_casks = brew_client.get_installed_casks()?;
-
-    // Create processors
-    let formula_processor = PackageProcessor::new(PackageType::Formula, installed_formulae.clone(), true);
-    let cask_processor = PackageProcessor::new(PackageType::Cask, installed_casks.clone(), true);
-
-    // Process packages using the processors
-    log_step(&format!("Processing {} formulae...", manifest.formulae.len()));
-    let formula_ops = formula_processor.process_packages(&manifest.formulae)?;
-    formula_processor.execute_operations(&formula_ops, false)?; // false = no dry run
-
-    log_step(&format!("Processing {} casks...", manifest.casks.len()));
-    let cask_ops = cask_processor.process_packages(&manifest.casks)?;
-    cask_processor.execute_operations(&cask_ops, false)?; // false = no dry run
-
-    // --- 3. Process Implied Uninstalls (only if not additive) ---
-    if !options.additive_only {
-        log_step("Checking for packages to uninstall (not present in any shard)...");
-
-        // Get all *main* packages currently installed (exclude dependencies)
-        let (main_formulae, main_casks) = get_all_main_packages(&brew_client)?;
-
-        // Identify formulae defined in the manifest - consider all forms
-        let mut desired_formulae_names = HashSet::new();
-        desired_formulae_names.extend(manifest.formulae.iter().map(|s| s.as_str()));
-        desired_formulae_names.extend(manifest.formulas.iter().filter_map(|f| {
-            if f.state != PackageState::Absent { Some(f.name.as_str()) } else { None }
-        }));
-
-        // Identify casks defined in the manifest - consider all forms
-        let mut desired_casks_names = HashSet::new();
-        desired_casks_names.extend(manifest.casks.iter().map(|s| s.as_str()));
-        desired_casks_names.extend(manifest.casks_structured.iter().filter_map(|c| {
-            if c.state != PackageState::Absent { Some(c.name.as_str()) } else { None }
-        }));
-
-        // Get system dependencies to protect them
-        let dependency_packages = brew_client.get_dependency_packages()?;
-        let dependency_set: HashSet<&str> = dependency_packages.iter().map(|s| s.as_str()).collect();
-
-        // Create a safe list of packages that shouldn't be uninstalled
-        let critical_packages = vec!["git", "brew", "curl", "openssl", "python", "fish", "bash", "zsh"];
-        let critical_set: HashSet<&str> = critical_packages.iter().copied().collect();
-
-        // Find formulae to uninstall: not in manifest, not a dependency, not critical
-        let formulae_to_uninstall: Vec<_> = main_formulae.iter()
-            .filter(|name| {
-                !desired_formulae_names.contains(name.as_str()) && 
-                !dependency_set.contains(name.as_str()) &&
-                !critical_set.contains(name.as_str())
-            })
-            .cloned()
-            .collect();
+    if report.interactive.is_empty() {
+        return;
+    }
 
-        // Find casks to uninstall: not in manifest, not critical
-        let casks_to_uninstall: Vec<_> = main_casks.iter()
-            .filter(|name| {
-                !desired_casks_names.contains(name.as_str()) && 
-                !critical_set.contains(name.as_str())
-            })
-            .cloned()
-            .collect();
+    let names: Vec<String> = report.interactive.iter().map(|a| a.name().to_string()).collect();
+    if let Err(e) = interactive::mark(&names) {
+        log_warning(&format!("Could not update the list of interactive installs: {}", e));
+    }
+    log_warning(&format!("{} package(s) require an interactive install: {}", names.len(), names.join(", ")));
+    log_step("Run `shard apply --interactive-only` in a terminal to install them");
+}
 
-        if !formulae_to_uninstall.is_empty() {
-            log_debug(&format!("Found {} formulae to uninstall: {}", formulae_to_uninstall.len(), formulae_to_uninstall.join(", ")));
-            for name in formulae_to_uninstall {
-                log_debug(&format!("Uninstalling formula: {}", name));
-                // Use BrewClient directly
-                brew_client.uninstall_formula(&name, true).unwrap_or_else(|e| 
-                    log_error(&format!("Failed uninstalling formula {}: {}", name, e))
-                );
-            }
-        } else {
-            log_debug("No extra formulae found to uninstall.");
-        }
+/// Most operations listed in the summary table, slowest first
+const MAX_SUMMARY_ROWS: usize = 15;
 
-        if !casks_to_uninstall.is_empty() {
-            log_debug(&format!("Found {} casks to uninstall: {}", casks_to_uninstall.len(), casks_to_uninstall.join(", ")));
-            for name in casks_to_uninstall {
-                log_debug(&format!("Uninstalling cask: {}", name));
-                brew_client.uninstall_cask(&name, true).unwrap_or_else(|e| 
-                    log_error(&format!("Failed uninstalling cask {}: {}", name, e))
-                );
-            }
-        } else {
-            log_debug("No extra casks found to uninstall.");
+/// Print the operations that changed something with their duration
+///
+/// Upgrades of packages that were already up to date are left out of the
+/// table but count towards the total time. Every operation is logged.
+fn print_summary(report: &ApplyReport, summary: &ApplySummary, was_outdated: impl Fn(&str) -> bool) {
+    for timing in &report.timings {
+        log_debug(&format!("{} took {}", timing.action, format_duration(timing.duration)));
+    }
+
+    let mut rows: Vec<&ActionTiming> = report.timings.iter()
+        .filter(|timing| match (&timing.action, &timing.outcome) {
+            (_, ActionOutcome::Failed(_)) => true,
+            (Action::Upgrade { name, .. }, _) => was_outdated(name),
+            _ => true,
+        })
+        .collect();
+    rows.sort_by_key(|timing| std::cmp::Reverse(timing.duration));
+
+    if !rows.is_empty() {
+        log_plain("");
+        log_plain(&format!("{:<10} {:<40} {:<8} {:<8} {:>8}", style("Operation").bold(), "Package", "Type", "Result", "Time"));
+        for timing in rows.iter().take(MAX_SUMMARY_ROWS) {
+            let (operation, kind) = match &timing.action {
+                Action::AddTap { .. } => ("tap", "tap"),
+                Action::Install { package_type, .. } => ("install", package_type.as_str()),
+                Action::Upgrade { package_type, .. } => ("upgrade", package_type.as_str()),
+                Action::Uninstall { package_type, .. } => ("remove", package_type.as_str()),
+                Action::InstallExtension { .. } => ("install", "vscode"),
+                Action::Link { link, .. } => (link.describe(), "formula"),
+            };
+            let result = match timing.outcome {
+                ActionOutcome::Succeeded => style("ok").green(),
+                ActionOutcome::Skipped(_) => style("skipped").dim(),
+                ActionOutcome::Interactive(_) => style("deferred").yellow(),
+                ActionOutcome::Failed(_) => style("failed").red(),
+            };
+            log_plain(&format!("{:<10} {:<40} {:<8} {:<8} {:>8}",
+                operation, timing.action.name(), kind, result, format_duration(timing.duration)));
         }
-    } else {
-        log_debug("Additive mode: Skipping uninstallation of packages not in manifest.");
+        if rows.len() > MAX_SUMMARY_ROWS {
+            log_plain(&style(format!("... and {} faster operation(s)", rows.len() - MAX_SUMMARY_ROWS)).dim().to_string());
+        }
+        log_plain("");
     }
 
-    // --- 4. Cleanup ---
-    if !options.skip_cleanup {
-        brew_client.cleanup(true)?; // true for prune_all
+    log_step(&format!("Installed {}, upgraded {}, removed {}, failed {} in {}",
+        summary.installed, summary.upgraded, summary.removed, summary.failed, format_duration(report.elapsed)));
+}
+
+/// Format a duration as e.g. "4.2s" or "3m 05s"
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
     } else {
-        log_debug("Skipping cleanup step.");
+        format!("{}m {:02}s", secs / 60, secs % 60)
     }
+}
 
-    Ok(())
+/// Install the casks an unattended run found to require input, from a terminal
+///
+/// Casks that are no longer declared by the target are forgotten.
+pub fn apply_interactive_only(shard: &str, skip_cleanup: bool, overrides: ClientOverrides) -> ShardResult<()> {
+    if !console::user_attended() {
+        return Err(ShardError::ValidationError(
+            "--interactive-only needs a terminal to answer installer prompts".to_string()));
+    }
+
+    let pending = interactive::pending();
+    if pending.is_empty() {
+        log_success("No packages are waiting for an interactive install");
+        return Ok(());
+    }
+
+    let target = if shard.eq_ignore_ascii_case("all") { "all" } else { shard };
+    let engine = console_engine(&[], overrides)?;
+    let mut plan = engine.plan(target)?;
+    plan.actions.retain(|action| matches!(action,
+        Action::Install { package_type: PackageType::Cask, .. } | Action::Upgrade { package_type: PackageType::Cask, .. })
+        && pending.contains(action.name()));
+    plan.plugins.clear();
+
+    let stale: Vec<String> = pending.iter()
+        .filter(|cask| !plan.actions.iter().any(|action| action.name() == cask.as_str()))
+        .cloned()
+        .collect();
+    if !stale.is_empty() && target == "all" {
+        log_debug(&format!("Forgetting casks no longer declared: {}", stale.join(", ")));
+        interactive::clear(&stale)?;
+    }
+
+    if plan.is_empty() {
+        log_success("No declared packages are waiting for an interactive install");
+        return Ok(());
+    }
+
+    log_step(&format!("Installing {} package(s) that require input, answer their prompts when asked", plan.actions.len()));
+    run_plan(&engine, &plan, "interactive installs", skip_cleanup)
 }
 
-/// Helper function to get main packages (non-dependencies)
-fn get_all_main_packages(brew_client: &BrewClient) -> ShardResult<(Vec<String>, Vec<String>)> {
-    let installed_formulae = brew_client.get_installed_formulae()?;
-    let installed_casks = brew_client.get_installed_casks()?;
-    let dependency_packages = brew_client.get_dependency_packages()?;
-    
-    // Filter out dependencies from installed formulae
-    let main_formulae: Vec<String> = installed_formulae
+/// Plan again after applying and fail if anything would still change
+///
+/// Used by `apply --check` to verify that applying converges, e.g. in CI.
+pub fn check_converged(shard: &str, overrides: ClientOverrides) -> ShardResult<()> {
+    let target = if shard.eq_ignore_ascii_case("all") { "all" } else { shard };
+    if target == "all" && let Some(state) = freeze::freeze_state()? {
+        log_warning(&format!("Shard is {}, skipping the convergence check", state.describe()));
+        return Ok(());
+    }
+
+    log_step("Checking that applying again would not change anything...");
+    let plan = ShardEngine::new(overrides)?.plan(target)?;
+    let pending = pending_actions(&plan);
+    let plugin_changes: Vec<(&str, &PluginChange)> = plan.plugins.iter()
+        .flat_map(|planned| planned.changes.iter().map(move |change| (planned.plugin.as_str(), change)))
+        .collect();
+
+    if pending.is_empty() && plugin_changes.is_empty() {
+        log_success("Converged: applying again would not change anything");
+        return Ok(());
+    }
+
+    for action in &pending {
+        log_error(&format!("Still pending: {}", action));
+    }
+    for (plugin, change) in &plugin_changes {
+        log_error(&format!("Still pending: {} ({} plugin)", change, plugin));
+    }
+    Err(ShardError::ValidationError(format!(
+        "Not converged, {} change(s) still pending after apply", pending.len() + plugin_changes.len())))
+}
+
+/// Actions of a plan that would actually change something
+///
+/// Upgrades only count if the plan's `brew outdated` snapshot reports the
+/// package as outdated and not pinned.
+pub fn pending_actions(plan: &Plan) -> Vec<&Action> {
+    pending_with(plan, &plan.outdated.to_set())
+}
+
+/// Actions of a plan that would change something, given a `brew outdated` snapshot
+pub fn pending_with<'a>(plan: &'a Plan, outdated: &OutdatedSet) -> Vec<&'a Action> {
+    plan.actions.iter()
+        .filter(|action| match action {
+            Action::Upgrade { name, .. } => outdated.is_upgradable(name),
+            _ => true,
+        })
+        .collect()
+}
+
+/// Download everything applying would install or upgrade, without installing
+///
+/// Lets large downloads happen on a fast network and the apply itself later,
+/// offline. Taps and uninstalls are left for the apply.
+pub fn fetch_only(shard: &str, only: &[ManifestSection], overrides: ClientOverrides) -> ShardResult<()> {
+    let target = if shard.eq_ignore_ascii_case("all") { "all" } else { shard };
+    let plan = ShardEngine::new(overrides)?.with_sections(only.to_vec()).plan(target)?;
+    let downloads: Vec<&Action> = pending_actions(&plan)
         .into_iter()
-        .filter(|name| !dependency_packages.contains(name))
+        .filter(|action| matches!(action, Action::Install { .. } | Action::Upgrade { .. }))
         .collect();
-    
-    // Casks are never dependencies
-    let main_casks = installed_casks;
-    
-    Ok((main_formulae, main_casks))
+
+    if downloads.is_empty() {
+        log_success("Nothing to download, all packages are installed and up to date");
+        return Ok(());
+    }
+
+    log_step(&format!("Downloading {} package(s)...", downloads.len()));
+    let brew_client = if plan.env.is_empty() {
+        get_client(overrides)
+    } else {
+        get_client(overrides).with_env(env::resolve(&plan.env)?)
+    };
+
+    let mut failed = Vec::new();
+    for action in downloads {
+        let name = action.name();
+        log_step(&format!("Fetching {}", name));
+        let result = match action {
+            Action::Install { package_type: PackageType::Cask, .. }
+            | Action::Upgrade { package_type: PackageType::Cask, .. } => brew_client.fetch_cask(name),
+            // HEAD and source builds need their sources rather than a bottle
+            _ => brew_client.fetch_formula(name, &plan.formula_builds.get(name).copied().unwrap_or_default().install_args()),
+        };
+        if let Err(e) = result {
+            log_error(&format!("Failed to fetch {}: {}", name, e));
+            failed.push(name.to_string());
+        }
+    }
+
+    if failed.is_empty() {
+        log_success("All packages downloaded, run `shard apply` to install them");
+        return Ok(());
+    }
+    Err(ShardError::PackageError(format!("Failed to fetch {} package(s): {}", failed.len(), failed.join(", "))))
 }
 
 /// Apply a manifest (backwards compatibility function)
 pub fn apply(shard: &str, skip_cleanup: bool) -> ShardResult<()> {
     if shard.eq_ignore_ascii_case("all") {
-        apply_all_enabled_shards(skip_cleanup)
+        apply_all_enabled_shards(skip_cleanup, false, &[], ClientOverrides::default())
     } else {
-        apply_single_shard(shard, skip_cleanup)
+        apply_single_shard(shard, skip_cleanup, &[], ClientOverrides::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brew::OutdatedReport;
+
+    /// `brew outdated --json=v2` output with `wget` outdated and `node` outdated but pinned
+    const OUTDATED: &str = r#"{
+        "formulae": [
+            {"name": "wget", "installed_versions": ["1.24.5"], "current_version": "1.25.0", "pinned": false, "pinned_version": null},
+            {"name": "node", "installed_versions": ["20.11.0"], "current_version": "22.1.0", "pinned": true, "pinned_version": "20.11.0"}
+        ],
+        "casks": []
+    }"#;
+
+    /// `brew outdated --json=v2` output once everything is up to date
+    const UP_TO_DATE: &str = r#"{"formulae": [], "casks": []}"#;
+
+    fn snapshot(json: &str) -> OutdatedSet {
+        serde_json::from_str::<OutdatedReport>(json).unwrap().to_set()
+    }
+
+    fn install(package_type: PackageType, name: &str) -> Action {
+        Action::Install { package_type, name: name.to_string() }
+    }
+
+    fn upgrade(package_type: PackageType, name: &str) -> Action {
+        Action::Upgrade { package_type, name: name.to_string() }
+    }
+
+    /// Plan for `wget`, `node` and `jq` installed and `ripgrep` and `firefox` missing
+    fn first_plan() -> Plan {
+        Plan {
+            actions: vec![
+                Action::AddTap { name: "homebrew/services".to_string(), url: None },
+                install(PackageType::Formula, "ripgrep"),
+                upgrade(PackageType::Formula, "jq"),
+                upgrade(PackageType::Formula, "node"),
+                upgrade(PackageType::Formula, "wget"),
+                install(PackageType::Cask, "firefox"),
+            ],
+            ..Plan::default()
+        }
+    }
+
+    /// Plan of the same shard once the first one was applied, every package is installed
+    fn second_plan() -> Plan {
+        Plan {
+            actions: ["jq", "node", "ripgrep", "wget"].into_iter()
+                .map(|name| upgrade(PackageType::Formula, name))
+                .chain([upgrade(PackageType::Cask, "firefox")])
+                .collect(),
+            ..Plan::default()
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn only_outdated_unpinned_upgrades_are_pending() {
+        let plan = first_plan();
+        let pending = pending_with(&plan, &snapshot(OUTDATED));
+
+        assert_eq!(pending, vec![
+            &plan.actions[0],
+            &install(PackageType::Formula, "ripgrep"),
+            &upgrade(PackageType::Formula, "wget"),
+            &install(PackageType::Cask, "firefox"),
+        ]);
+    }
+
+    #[test]
+    fn up_to_date_upgrades_are_not_pending() {
+        let plan = second_plan();
+
+        assert!(pending_with(&plan, &snapshot(UP_TO_DATE)).is_empty());
+    }
+
+    /// Stand-in for brew keeping the installed packages, taps and outdated
+    /// formulae in files next to it
+    const FAKE_BREW: &str = r#"#!/bin/sh
+dir=$(dirname "$0")
+command=$1
+shift
+names=$(for arg in "$@"; do case $arg in -*) ;; *) echo "$arg" ;; esac; done)
+case "$command" in
+    list) case $1 in --cask) cat "$dir/casks" ;; *) cat "$dir/formulae" ;; esac ;;
+    tap) if [ -z "$names" ]; then cat "$dir/taps"; else echo "$names" >> "$dir/taps"; fi ;;
+    outdated)
+        printf '{"formulae": ['
+        sep=""
+        for name in $(cat "$dir/outdated"); do
+            printf '%s{"name": "%s", "installed_versions": ["1.0"], "current_version": "2.0", "pinned": false}' "$sep" "$name"
+            sep=", "
+        done
+        printf '], "casks": []}\n' ;;
+    install) case $1 in --cask) echo "$names" >> "$dir/casks" ;; *) echo "$names" >> "$dir/formulae" ;; esac ;;
+    upgrade) for name in $names; do grep -vx "$name" "$dir/outdated" > "$dir/still"; mv "$dir/still" "$dir/outdated"; done ;;
+    cleanup) ;;
+    *) echo "Error: unexpected brew $command" >&2; exit 1 ;;
+esac
+"#;
+
+    /// Engine whose brew has `jq` and an outdated `wget` installed
+    fn fake_engine(test: &str) -> (ShardEngine, std::path::PathBuf) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("shard-apply-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, content) in [("formulae", "jq\nwget\n"), ("casks", ""), ("taps", ""), ("outdated", "wget\n")] {
+            std::fs::write(dir.join(file), content).unwrap();
+        }
+        let brew = dir.join("brew");
+        std::fs::write(&brew, FAKE_BREW).unwrap();
+        std::fs::set_permissions(&brew, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let manager = ShardManager::with_paths(dir.join("shards"), dir.join("disabled"));
+        let client = BrewClient::with_path(brew.display().to_string());
+        (ShardEngine::with_parts(manager, client), dir)
+    }
+
+    fn user_shard() -> Vec<(String, Manifest)> {
+        let mut manifest = Manifest::new();
+        manifest.taps = vec!["homebrew/services".into()];
+        manifest.formulae = ["jq", "ripgrep", "wget"].map(String::from).to_vec();
+        manifest.casks = vec!["firefox".to_string()];
+        vec![("user".to_string(), manifest)]
+    }
+
+    #[test]
+    fn second_plan_after_a_converged_apply_is_empty() {
+        let (engine, dir) = fake_engine("converge");
+
+        let first = engine.plan_manifests(user_shard(), ApplyMode::Additive).unwrap();
+        assert_eq!(first.actions, vec![
+            Action::AddTap { name: "homebrew/services".to_string(), url: None },
+            install(PackageType::Formula, "ripgrep"),
+            upgrade(PackageType::Formula, "wget"),
+            install(PackageType::Cask, "firefox"),
+        ]);
+        let report = engine.apply(&first, &ApplyOptions::default()).unwrap();
+        assert!(report.is_success(), "{:?}", report.failed);
+
+        let second = engine.plan_manifests(user_shard(), ApplyMode::Additive).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert!(second.actions.is_empty(), "{:?}", second.actions);
+    }
+
+    #[test]
+    fn pinned_packages_never_keep_apply_from_converging() {
+        // `node` stays outdated since it is pinned, which is not a pending change
+        let plan = second_plan();
+
+        assert!(pending_with(&plan, &snapshot(r#"{"formulae": [{"name": "node", "pinned": true}]}"#)).is_empty());
+    }
+
+    #[test]
+    fn installs_are_pending_until_applied() {
+        let plan = Plan { actions: vec![install(PackageType::Cask, "firefox")], ..Plan::default() };
+
+        assert_eq!(pending_with(&plan, &snapshot(UP_TO_DATE)).len(), 1);
+        assert!(!plan.is_empty());
+        assert!(Plan::default().is_empty());
+    }
+}
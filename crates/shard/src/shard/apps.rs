@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::brew::{get_client, BrewClient, ClientOverrides};
+use crate::engine::{Action, ApplyReport, Plan, ShardEngine};
+use crate::package::processor::PackageType;
+use crate::utils::{ShardError, ShardResult, log_debug, log_step, log_success, log_warning};
+
+/// Extended attribute macOS sets on downloaded files until the user opens them
+const QUARANTINE_ATTR: &str = "com.apple.quarantine";
+
+/// Where casks put apps unless given `--appdir`
+const DEFAULT_APPDIR: &str = "/Applications";
+
+/// Why an installed cask's app is not usable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppProblem {
+    /// The app bundle does not exist
+    Missing,
+    /// macOS asks before opening the app, or refuses to if it is unsigned
+    Quarantined,
+}
+
+/// An app bundle of a cask that is missing or quarantined
+#[derive(Debug, Clone)]
+pub struct AppIssue {
+    pub cask: String,
+    pub path: PathBuf,
+    pub problem: AppProblem,
+}
+
+impl AppIssue {
+    pub fn describe(&self) -> String {
+        match self.problem {
+            AppProblem::Missing => format!("{}: {} is missing", self.cask, self.path.display()),
+            AppProblem::Quarantined => format!("{}: {} is quarantined", self.cask, self.path.display()),
+        }
+    }
+}
+
+/// Check that the apps of casks exist and are not quarantined
+///
+/// Apps are looked up in the `--appdir` of the cask's options in the plan,
+/// `/Applications` by default.
+pub fn find_issues(brew_client: &BrewClient, casks: &[String], plan: &Plan) -> ShardResult<Vec<AppIssue>> {
+    let mut issues = Vec::new();
+    for cask in brew_client.get_cask_apps(casks)? {
+        let appdir = appdir(plan.cask_options.get(&cask.token).map(Vec::as_slice).unwrap_or_default());
+        for app in &cask.apps {
+            let path = appdir.join(app);
+            let problem = if !path.exists() {
+                AppProblem::Missing
+            } else if is_quarantined(&path) {
+                AppProblem::Quarantined
+            } else {
+                continue;
+            };
+            issues.push(AppIssue { cask: cask.token.clone(), path, problem });
+        }
+    }
+    Ok(issues)
+}
+
+/// Warn about apps of casks an apply installed or upgraded that are missing or quarantined
+pub fn verify_applied(brew_client: &BrewClient, plan: &Plan, report: &ApplyReport) {
+    let casks: Vec<String> = report.succeeded.iter()
+        .filter_map(|action| match action {
+            Action::Install { package_type: PackageType::Cask, name }
+            | Action::Upgrade { package_type: PackageType::Cask, name } => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    if casks.is_empty() {
+        return;
+    }
+
+    let issues = match find_issues(brew_client, &casks, plan) {
+        Ok(issues) => issues,
+        Err(e) => {
+            log_warning(&format!("Could not verify the apps of installed casks: {}", e));
+            return;
+        }
+    };
+
+    for issue in &issues {
+        log_warning(&issue.describe());
+    }
+    if issues.iter().any(|issue| issue.problem == AppProblem::Missing) {
+        log_warning("Reinstall casks with missing apps using `brew reinstall --cask <cask>`");
+    }
+    if issues.iter().any(|issue| issue.problem == AppProblem::Quarantined) {
+        log_warning("Run `shard apply --dequarantine` to remove the quarantine from these apps");
+    }
+}
+
+/// Remove the quarantine from the apps of installed casks declared by a shard or `all`
+///
+/// Fails if any app is missing.
+pub fn dequarantine(shard: &str, overrides: ClientOverrides) -> ShardResult<()> {
+    let target = if shard.eq_ignore_ascii_case("all") { "all" } else { shard };
+    let plan = ShardEngine::new(overrides)?.plan(target)?;
+    let brew_client = get_client(overrides);
+    let installed = brew_client.get_installed_casks()?;
+    let casks: Vec<String> = plan.declared(PackageType::Cask)
+        .into_iter()
+        .filter(|cask| installed.contains(cask))
+        .collect();
+
+    log_step(&format!("Checking the apps of {} cask(s)...", casks.len()));
+    let issues = find_issues(&brew_client, &casks, &plan)?;
+
+    let mut missing = 0;
+    let mut cleared = 0;
+    for issue in &issues {
+        match issue.problem {
+            AppProblem::Missing => {
+                log_warning(&issue.describe());
+                missing += 1;
+            }
+            AppProblem::Quarantined => {
+                remove_quarantine(&issue.path)?;
+                log_debug(&format!("Removed the quarantine from {}", issue.path.display()));
+                cleared += 1;
+            }
+        }
+    }
+
+    if cleared > 0 {
+        log_success(&format!("Removed the quarantine from {} app(s)", cleared));
+    }
+    if missing > 0 {
+        return Err(ShardError::PackageError(format!(
+            "{} app(s) of installed casks are missing, reinstall them with `brew reinstall --cask <cask>`", missing)));
+    }
+    if cleared == 0 {
+        log_success("All apps of installed casks are present and not quarantined");
+    }
+    Ok(())
+}
+
+/// App directory from cask options such as `--appdir=~/Applications`
+pub fn appdir(options: &[String]) -> PathBuf {
+    let dir = options.iter()
+        .find_map(|option| option.strip_prefix("--appdir="))
+        .unwrap_or(DEFAULT_APPDIR);
+    PathBuf::from(shellexpand::tilde(dir).into_owned())
+}
+
+fn is_quarantined(path: &Path) -> bool {
+    Command::new("xattr")
+        .args(["-p", QUARANTINE_ATTR])
+        .arg(path)
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+fn remove_quarantine(path: &Path) -> ShardResult<()> {
+    let output = Command::new("xattr")
+        .args(["-dr", QUARANTINE_ATTR])
+        .arg(path)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(ShardError::ApplicationError(format!("Failed to remove the quarantine from {}: {}",
+            path.display(), String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(())
+}
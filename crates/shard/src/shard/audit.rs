@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use console::{style, StyledObject};
+use serde::Serialize;
+use crate::brew::{get_client, ClientOverrides};
+use crate::shard::manager::ShardManager;
+use crate::utils::{ShardResult, ShardError, ResultExt, log_step, log_success, log_warning};
+
+/// How urgently a finding needs attention
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Disabled, the package no longer receives fixes
+    Critical,
+    /// Deprecated, the package will stop receiving fixes
+    High,
+    /// Reported by `brew audit`
+    Medium,
+    /// Compliance gaps such as a missing license
+    Low,
+}
+
+impl Severity {
+    const ALL: [Severity; 4] = [Severity::Critical, Severity::High, Severity::Medium, Severity::Low];
+
+    fn title(&self) -> &'static str {
+        match self {
+            Severity::Critical => "Critical",
+            Severity::High => "High",
+            Severity::Medium => "Medium",
+            Severity::Low => "Low",
+        }
+    }
+
+    fn paint<D>(&self, text: D) -> StyledObject<D> {
+        match self {
+            Severity::Critical => style(text).red().bold(),
+            Severity::High => style(text).red(),
+            Severity::Medium => style(text).yellow(),
+            Severity::Low => style(text).dim(),
+        }
+    }
+}
+
+/// A problem found in a managed package
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditFinding {
+    pub severity: Severity,
+    pub name: String,
+    /// Package type ("formula" or "cask")
+    pub package_type: String,
+    pub message: String,
+    /// Shards that declare this package
+    pub shards: Vec<String>,
+}
+
+/// License of a managed formula
+#[derive(Debug, Clone, Serialize)]
+pub struct LicenseEntry {
+    pub name: String,
+    /// Installed version, `None` if not installed
+    pub version: Option<String>,
+    /// SPDX license expression
+    pub license: Option<String>,
+    /// Shards that declare this formula
+    pub shards: Vec<String>,
+}
+
+/// Licenses and findings of all managed packages
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditReport {
+    pub licenses: Vec<LicenseEntry>,
+    pub findings: Vec<AuditFinding>,
+}
+
+/// Collect licenses and check managed packages for known problems
+///
+/// Homebrew does not publish vulnerability data, so findings come from the
+/// deprecation status of packages and from `brew audit`. Licenses are only
+/// available for formulae.
+pub fn audit(shard: Option<&str>, json: bool, overrides: ClientOverrides) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+
+    let manifests: Vec<_> = match shard {
+        Some(name) if !name.eq_ignore_ascii_case("all") => {
+            if !manager.shard_is_active(name) {
+                return Err(ShardError::NotFound(name.to_string()));
+            }
+            manager.load_active_manifests()?
+                .into_iter()
+                .filter(|(shard_name, _)| shard_name == name)
+                .collect()
+        }
+        _ => manager.load_active_manifests()?,
+    };
+
+    // Map every managed package to the shards declaring it
+    let mut managed_formulae: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut managed_casks: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for (shard_name, manifest) in &manifests {
+        for formula in &manifest.formulae {
+            managed_formulae.entry(formula.clone()).or_default().push(shard_name.clone());
+        }
+        for cask in &manifest.casks {
+            managed_casks.entry(cask.clone()).or_default().push(shard_name.clone());
+        }
+    }
+
+    if !json {
+        log_step(&format!("Auditing {} formula(e) and {} cask(s)...", managed_formulae.len(), managed_casks.len()));
+    }
+
+    let report = build_report(&managed_formulae, &managed_casks, overrides)?;
+
+    if json {
+        let output = serde_json::to_string_pretty(&report)
+            .with_context(|| "Failed to serialize audit report")?;
+        println!("{}", output);
+        return Ok(());
+    }
+
+    print_licenses(&report.licenses);
+    print_findings(&report.findings);
+    Ok(())
+}
+
+fn build_report(
+    managed_formulae: &BTreeMap<String, Vec<String>>,
+    managed_casks: &BTreeMap<String, Vec<String>>,
+    overrides: ClientOverrides,
+) -> ShardResult<AuditReport> {
+    let brew_client = get_client(overrides);
+    let formulae: Vec<String> = managed_formulae.keys().cloned().collect();
+    let casks: Vec<String> = managed_casks.keys().cloned().collect();
+    let shards_of = |managed: &BTreeMap<String, Vec<String>>, name: &str| managed.get(name).cloned().unwrap_or_default();
+
+    let mut findings = Vec::new();
+    let mut finding = |severity, name: &str, package_type: &str, message: String| {
+        let managed = if package_type == "cask" { managed_casks } else { managed_formulae };
+        findings.push(AuditFinding {
+            severity,
+            name: name.to_string(),
+            package_type: package_type.to_string(),
+            message,
+            shards: shards_of(managed, name),
+        });
+    };
+
+    let licenses: Vec<LicenseEntry> = brew_client.get_licenses(&formulae)?
+        .into_iter()
+        .map(|formula| LicenseEntry {
+            shards: shards_of(managed_formulae, &formula.name),
+            name: formula.name,
+            version: formula.version,
+            license: formula.license,
+        })
+        .collect();
+
+    for entry in licenses.iter().filter(|entry| entry.license.is_none()) {
+        finding(Severity::Low, &entry.name, "formula", "No license declared".to_string());
+    }
+
+    let deprecations = brew_client.get_deprecations(&formulae, &casks)?;
+    for (package_type, deprecation) in deprecations.formulae.iter().map(|d| ("formula", d))
+        .chain(deprecations.casks.iter().map(|d| ("cask", d))) {
+        let severity = if deprecation.disabled { Severity::Critical } else { Severity::High };
+        finding(severity, &deprecation.name, package_type, deprecation.describe());
+    }
+
+    match brew_client.audit(&formulae) {
+        Ok(problems) => {
+            for problem in problems {
+                finding(Severity::Medium, &problem.name, "formula", problem.message);
+            }
+        }
+        Err(e) => log_warning(&format!("Could not run brew audit: {}", e)),
+    }
+
+    findings.sort_by(|a, b| a.severity.cmp(&b.severity).then_with(|| a.name.cmp(&b.name)));
+    Ok(AuditReport { licenses, findings })
+}
+
+/// Print formulae grouped by license
+fn print_licenses(licenses: &[LicenseEntry]) {
+    if licenses.is_empty() {
+        return;
+    }
+
+    let mut by_license: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for entry in licenses {
+        by_license.entry(entry.license.as_deref().unwrap_or("unknown"))
+            .or_default()
+            .push(&entry.name);
+    }
+
+    println!();
+    println!("{}", style("Licenses").bold());
+    for (license, names) in by_license {
+        println!("  {:<30} {}", style(license).cyan(), style(names.join(", ")).dim());
+    }
+}
+
+/// Print findings grouped by severity
+fn print_findings(findings: &[AuditFinding]) {
+    if findings.is_empty() {
+        println!();
+        log_success("No problems found");
+        return;
+    }
+
+    for severity in Severity::ALL {
+        let section: Vec<&AuditFinding> = findings.iter().filter(|f| f.severity == severity).collect();
+        if section.is_empty() {
+            continue;
+        }
+
+        println!();
+        println!("{} ({})", severity.paint(severity.title()), section.len());
+        for finding in section {
+            println!("  {:<30} {:<8} {}  {}",
+                finding.name,
+                finding.package_type,
+                finding.message,
+                style(format!("[{}]", finding.shards.join(", "))).italic());
+        }
+    }
+    println!();
+
+    log_step(&format!("{} finding(s)", findings.len()));
+}
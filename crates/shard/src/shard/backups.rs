@@ -0,0 +1,232 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Local, TimeZone};
+use console::style;
+use crate::core::config::BackupRetention;
+use crate::core::manifest::{Manifest, ManifestFormat};
+use crate::shard::manager::{ShardManager, ShardStatus};
+use crate::utils::{Glyph, ShardError, ShardResult, ResultExt, log_step, log_success, log_warning, log_debug, format_size};
+
+/// Separator between shard name and timestamp in backup file names
+const BACKUP_MARKER: &str = "_backup_";
+
+/// A copy of a shard saved before it was modified
+#[derive(Debug, Clone)]
+pub struct Backup {
+    /// Name of the shard the backup belongs to
+    pub shard: String,
+    /// Unix timestamp of the backup, also used to refer to it
+    pub timestamp: u64,
+    /// Location of the backup file
+    pub path: PathBuf,
+}
+
+impl Backup {
+    /// Parse a `<shard>_backup_<timestamp>.<ext>` file name
+    fn from_path(path: &Path) -> Option<Self> {
+        if !ManifestFormat::is_manifest(path) {
+            return None;
+        }
+
+        let stem = path.file_stem()?.to_str()?;
+        let (shard, timestamp) = stem.rsplit_once(BACKUP_MARKER)?;
+
+        Some(Self {
+            shard: shard.to_string(),
+            timestamp: timestamp.parse().ok()?,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Local time the backup was taken
+    pub fn created(&self) -> DateTime<Local> {
+        i64::try_from(self.timestamp).ok()
+            .and_then(|secs| Local.timestamp_opt(secs, 0).single())
+            .unwrap_or_default()
+    }
+
+    /// Age of the backup in whole days
+    pub fn age_days(&self) -> u64 {
+        (Local::now() - self.created()).num_days().max(0) as u64
+    }
+}
+
+/// List backups in a directory, newest first
+///
+/// With `shard`, only backups of that shard are returned.
+pub fn list_backups(dir: &Path, shard: Option<&str>) -> ShardResult<Vec<Backup>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read backup directory: {}", dir.display()))?;
+
+    let mut backups: Vec<Backup> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| Backup::from_path(&entry.path()))
+        .filter(|backup| shard.is_none_or(|name| backup.shard == name))
+        .collect();
+
+    backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| a.shard.cmp(&b.shard)));
+    Ok(backups)
+}
+
+/// Delete backups exceeding the retention policy and return them
+///
+/// The count limit applies per shard. The newest backup of a shard is never
+/// deleted for its age, so every shard keeps at least one backup.
+pub fn enforce_retention(dir: &Path, shard: Option<&str>, retention: &BackupRetention) -> ShardResult<Vec<Backup>> {
+    let backups = list_backups(dir, shard)?;
+    let shards: BTreeSet<&str> = backups.iter().map(|backup| backup.shard.as_str()).collect();
+
+    let mut removed = Vec::new();
+    for shard in shards {
+        let expired = backups.iter()
+            .filter(|backup| backup.shard == shard)
+            .enumerate()
+            .filter(|(index, backup)| {
+                let too_many = retention.max_count > 0 && *index >= retention.max_count;
+                let too_old = retention.max_age_days > 0 && *index > 0 && backup.age_days() > retention.max_age_days;
+                too_many || too_old
+            });
+
+        for (_, backup) in expired {
+            std::fs::remove_file(&backup.path)
+                .with_context(|| format!("Failed to delete backup: {}", backup.path.display()))?;
+            log_debug(&format!("Deleted expired backup: {}", backup.path.display()));
+            removed.push(backup.clone());
+        }
+    }
+
+    Ok(removed)
+}
+
+/// List, inspect, prune or restore shard backups
+///
+/// `inspect` and `restore` take a backup timestamp or `latest` and require a
+/// shard name.
+pub fn backups(name: Option<&str>, inspect: Option<&str>, prune: bool, restore: Option<&str>) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+
+    if let Some(id) = inspect {
+        let backup = find_backup(&manager, name, id)?;
+        return inspect_backup(&manager, &backup);
+    }
+
+    if let Some(id) = restore {
+        let backup = find_backup(&manager, name, id)?;
+        return manager.restore_backup(&backup);
+    }
+
+    if prune {
+        let removed = manager.prune_backups(name)?;
+        if removed.is_empty() {
+            log_success("No backups exceed the retention policy");
+        } else {
+            log_success(&format!("Deleted {} expired backup(s)", removed.len()));
+        }
+        return Ok(());
+    }
+
+    let backups = list_backups(manager.backups_dir(), name)?;
+    if backups.is_empty() {
+        log_step("No backups found");
+        return Ok(());
+    }
+
+    println!();
+    println!("{:<24} {:<12} {:<18} {:>10}", style("Shard").bold(), style("Backup").bold(),
+        style("Created").bold(), style("Size").bold());
+    for backup in &backups {
+        let size = std::fs::metadata(&backup.path).map(|m| m.len()).unwrap_or(0);
+        println!("{:<24} {:<12} {:<18} {:>10}", backup.shard, backup.timestamp,
+            backup.created().format("%Y-%m-%d %H:%M"), format_size(size));
+    }
+    println!();
+    log_step("Use --inspect <BACKUP> or --restore <BACKUP> with a shard name to compare or restore a backup");
+
+    Ok(())
+}
+
+/// Look up a backup of a shard by timestamp or `latest`
+fn find_backup(manager: &ShardManager, name: Option<&str>, id: &str) -> ShardResult<Backup> {
+    let Some(name) = name else {
+        return Err(ShardError::ValidationError("A shard name is required to select a backup".to_string()));
+    };
+
+    let backups = list_backups(manager.backups_dir(), Some(name))?;
+    let backup = if id.eq_ignore_ascii_case("latest") {
+        backups.into_iter().next()
+    } else {
+        backups.into_iter().find(|backup| backup.timestamp.to_string() == id)
+    };
+
+    backup.ok_or_else(|| ShardError::NotFound(format!("{} (backup {})", name, id)))
+}
+
+/// Show how a backup differs from the current version of its shard
+fn inspect_backup(manager: &ShardManager, backup: &Backup) -> ShardResult<()> {
+    let saved = Manifest::from_file(&backup.path)
+        .with_context(|| format!("Failed to load backup: {}", backup.path.display()))?;
+
+    let current = match manager.get_shard_status(&backup.shard) {
+        ShardStatus::NotFound => {
+            log_warning(&format!("Shard '{}' no longer exists, showing the full backup", backup.shard));
+            Manifest::new()
+        }
+        _ => Manifest::from_file(manager.get_shard_info(&backup.shard)?.path)?,
+    };
+
+    log_step(&format!("Backup {} of shard '{}' from {} compared to the current shard",
+        backup.timestamp, backup.shard, backup.created().format("%Y-%m-%d %H:%M")));
+
+    let changed = print_changes(&saved, &current);
+
+    println!();
+    if changed {
+        log_step("- only in the backup, + only in the current shard");
+    } else {
+        log_success("The backup matches the current shard");
+    }
+
+    Ok(())
+}
+
+/// Print the taps, packages and owner that differ between two versions of a shard
+///
+/// Returns whether anything differs.
+pub fn print_changes(before: &Manifest, after: &Manifest) -> bool {
+    let mut changed = false;
+    let tap_labels = |manifest: &Manifest| manifest.taps.iter().map(ToString::to_string).collect::<Vec<_>>();
+    for (label, old, new) in [
+        ("taps", &tap_labels(before), &tap_labels(after)),
+        ("formulae", &before.formulae, &after.formulae),
+        ("casks", &before.casks, &after.casks),
+        ("vscode extensions", &before.vscode_extensions, &after.vscode_extensions),
+    ] {
+        let old: BTreeSet<&String> = old.iter().collect();
+        let new: BTreeSet<&String> = new.iter().collect();
+        if old == new {
+            continue;
+        }
+
+        changed = true;
+        println!();
+        println!("{}", style(label).bold());
+        for name in old.difference(&new) {
+            println!("  {} {}", style("-").red(), style(name).red());
+        }
+        for name in new.difference(&old) {
+            println!("  {} {}", style("+").green(), style(name).green());
+        }
+    }
+
+    if before.metadata.owner != after.metadata.owner {
+        changed = true;
+        println!();
+        println!("{} {} {} {}", style("owner").bold(), before.metadata.owner, Glyph::Arrow, after.metadata.owner);
+    }
+
+    changed
+}
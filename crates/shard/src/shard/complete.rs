@@ -0,0 +1,41 @@
+use std::collections::BTreeSet;
+use crate::brew::catalog;
+use crate::core::manifest::Manifest;
+use crate::shard::manager::ShardManager;
+use crate::utils::ShardResult;
+
+/// Print the names of all shards, enabled and disabled, one per line
+///
+/// Used by shell completions, so nothing but the names is printed.
+pub fn shards() -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    let names: BTreeSet<String> = manager.list_shards()?.into_iter()
+        .chain(manager.list_disabled_shards()?)
+        .collect();
+
+    for name in names {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// Print packages starting with `prefix`, one per line
+///
+/// Candidates are the packages the enabled shards declare and every formula
+/// and cask in Homebrew's cached catalog.
+pub fn packages(prefix: &str) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    let declared = manager.list_shards()?.into_iter()
+        .filter_map(|name| Manifest::from_file(manager.get_shard_path(&name)).ok())
+        .flat_map(|manifest| manifest.formulae.into_iter().chain(manifest.casks));
+
+    let candidates: BTreeSet<String> = declared
+        .chain(catalog::cached_names())
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+
+    for name in candidates {
+        println!("{}", name);
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+use console::style;
+use sapphire_core::paths::SapphirePaths;
+use crate::utils::{ShardError, ShardResult, ResultExt, log_step, log_success, log_warning, path_exists, ensure_parent_dir_exists};
+
+/// File holding the name of the active context
+const CONTEXT_FILE: &str = "context";
+
+/// The active context, e.g. "work", `None` if no context is set
+///
+/// Packages declared for a context only apply while it is active, see
+/// [`Manifest::contexts`](crate::core::manifest::Manifest::contexts).
+pub fn active() -> ShardResult<Option<String>> {
+    let path = context_path();
+    if !path_exists(&path) {
+        return Ok(None);
+    }
+
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read context file: {}", path.display()))?;
+    let name = content.trim();
+    Ok((!name.is_empty()).then(|| name.to_string()))
+}
+
+/// Make `name` the active context
+pub fn set(name: &str) -> ShardResult<()> {
+    validate_name(name)?;
+
+    let path = context_path();
+    ensure_parent_dir_exists(&path)?;
+    std::fs::write(&path, format!("{}\n", name))
+        .with_context(|| format!("Failed to write context file: {}", path.display()))?;
+
+    log_success(&format!("Switched to context {}", style(name).bold()));
+    log_step("Run `shard diff all` to see what applying in this context would change");
+    Ok(())
+}
+
+/// Unset the active context, so only packages without contexts apply
+pub fn clear() -> ShardResult<()> {
+    let Some(previous) = active()? else {
+        log_warning("No context is set");
+        return Ok(());
+    };
+
+    let path = context_path();
+    std::fs::remove_file(&path)
+        .with_context(|| format!("Failed to remove context file: {}", path.display()))?;
+
+    log_success(&format!("Cleared context {}", style(previous).bold()));
+    Ok(())
+}
+
+/// Print the active context
+pub fn show() -> ShardResult<()> {
+    match active()? {
+        Some(name) => println!("{}", name),
+        None => log_step("No context is set, packages declared for a context are skipped"),
+    }
+    Ok(())
+}
+
+/// How a context is named in messages, "context work" or "no context"
+pub fn describe(context: Option<&str>) -> String {
+    match context {
+        Some(name) => format!("context {}", name),
+        None => "no context".to_string(),
+    }
+}
+
+/// Context names are letters, digits, dashes and underscores
+pub fn validate_name(name: &str) -> ShardResult<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+        return Err(ShardError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+fn context_path() -> PathBuf {
+    SapphirePaths::resolve().root().join(CONTEXT_FILE)
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use dialoguer::Select;
+use crate::core::manifest::{FormulaBuild, FormulaLink, Manifest};
+use crate::package::processor::PackageType;
+use crate::shard::manager::ShardManager;
+use crate::utils::{ShardResult, log_debug, log_plain, log_step, log_success, log_warning};
+
+/// Kind of a package declared in more than one shard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Formula,
+    Cask,
+    Extension,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Formula => PackageType::Formula.as_str(),
+            Kind::Cask => PackageType::Cask.as_str(),
+            Kind::Extension => "vscode",
+        }
+    }
+
+    fn entries(self, manifest: &mut Manifest) -> &mut Vec<String> {
+        match self {
+            Kind::Formula => &mut manifest.formulae,
+            Kind::Cask => &mut manifest.casks,
+            Kind::Extension => &mut manifest.vscode_extensions,
+        }
+    }
+}
+
+/// Settings of a package that move to the shard that keeps it
+#[derive(Debug, Default)]
+struct PackageSettings {
+    comment: Option<String>,
+    cask_options: Option<Vec<String>>,
+    formula_options: Option<Vec<String>>,
+    formula_build: Option<FormulaBuild>,
+    link: Option<FormulaLink>,
+}
+
+impl PackageSettings {
+    /// Remove the settings of a package from a manifest
+    ///
+    /// Contexts are dropped rather than moved, they decide where the package
+    /// applies and the owning shard keeps its own.
+    fn take(manifest: &mut Manifest, name: &str) -> Self {
+        manifest.contexts.remove(name);
+        Self {
+            comment: manifest.comments.remove(name),
+            cask_options: manifest.cask_options.remove(name),
+            formula_options: manifest.formula_options.remove(name),
+            formula_build: manifest.formula_builds.remove(name),
+            link: manifest.links.remove(name),
+        }
+    }
+
+    /// Add the settings to a manifest that has none of its own for the package
+    fn merge_into(self, manifest: &mut Manifest, name: &str) {
+        if let Some(comment) = self.comment {
+            manifest.comments.entry(name.to_string()).or_insert(comment);
+        }
+        if let Some(options) = self.cask_options {
+            manifest.cask_options.entry(name.to_string()).or_insert(options);
+        }
+        if let Some(options) = self.formula_options {
+            manifest.formula_options.entry(name.to_string()).or_insert(options);
+        }
+        if let Some(build) = self.formula_build {
+            manifest.formula_builds.entry(name.to_string()).or_insert(build);
+        }
+        if let Some(link) = self.link {
+            manifest.links.entry(name.to_string()).or_insert(link);
+        }
+    }
+}
+
+/// List packages declared in more than one enabled shard and pick the shard
+/// that keeps each, removing it from the others
+///
+/// Without a terminal, or with `dry_run`, the duplicates are only listed.
+pub fn dedupe(dry_run: bool) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    let mut manifests = manager.load_active_manifests()?;
+    let duplicates = find_duplicates(&manifests);
+
+    if duplicates.is_empty() {
+        log_success("No package is declared in more than one shard");
+        return Ok(());
+    }
+
+    log_step(&format!("{} package(s) are declared in more than one shard:", duplicates.len()));
+    for ((kind, name), owners) in &duplicates {
+        let shards: Vec<&str> = owners.iter().map(|index| manifests[*index].0.as_str()).collect();
+        log_plain(&format!("  {:<40} {:<8} {}", name, kind.as_str(), shards.join(", ")));
+    }
+
+    if dry_run || !console::user_attended() {
+        log_step("Run `shard dedupe` in a terminal to choose the shard that keeps each package");
+        return Ok(());
+    }
+
+    let mut changed = vec![false; manifests.len()];
+    let mut moved = 0;
+    for ((kind, name), owners) in &duplicates {
+        let mut labels: Vec<String> = owners.iter()
+            .map(|index| owner_label(&manifests[*index].0, &manifests[*index].1, name))
+            .collect();
+        labels.push("Keep it in all of them".to_string());
+
+        let selection = Select::new()
+            .with_prompt(format!("'{}' ({}) is declared in {} shards. Which one should keep it?", name, kind.as_str(), owners.len()))
+            .items(&labels)
+            .default(0)
+            .interact()?;
+        let Some(&keeper) = owners.get(selection) else {
+            continue;
+        };
+
+        for &index in owners.iter().filter(|index| **index != keeper) {
+            let manifest = &mut manifests[index].1;
+            kind.entries(manifest).retain(|entry| !entry.eq_ignore_ascii_case(name));
+            let settings = PackageSettings::take(manifest, name);
+            settings.merge_into(&mut manifests[keeper].1, name);
+            changed[index] = true;
+        }
+        changed[keeper] = true;
+        moved += 1;
+    }
+
+    for ((name, manifest), _) in manifests.iter_mut().zip(&changed).filter(|(_, changed)| **changed) {
+        if let Err(e) = manager.ensure_editable(name) {
+            log_warning(&format!("Cannot update shard '{}': {}", name, e));
+            continue;
+        }
+        log_debug(&format!("Saving shard '{}'", name));
+        manifest.update_modification_info();
+        manifest.to_file(manager.get_shard_path(name))?;
+    }
+
+    if moved == 0 {
+        log_success("Kept every duplicate as it is");
+    } else {
+        log_success(&format!("Each of {} package(s) now belongs to a single shard", moved));
+    }
+    Ok(())
+}
+
+/// Packages declared in more than one shard, with the indices of those shards
+fn find_duplicates(manifests: &[(String, Manifest)]) -> BTreeMap<(Kind, String), Vec<usize>> {
+    let mut declared: BTreeMap<(Kind, String), Vec<usize>> = BTreeMap::new();
+
+    for (index, (_, manifest)) in manifests.iter().enumerate() {
+        // VS Code treats extension IDs case-insensitively
+        let extensions = manifest.vscode_extensions.iter().map(|id| (Kind::Extension, id.to_lowercase()));
+        let entries = manifest.formulae.iter().map(|name| (Kind::Formula, name.clone()))
+            .chain(manifest.casks.iter().map(|name| (Kind::Cask, name.clone())))
+            .chain(extensions);
+
+        for key in entries {
+            let owners = declared.entry(key).or_default();
+            if owners.last() != Some(&index) {
+                owners.push(index);
+            }
+        }
+    }
+
+    declared.retain(|_, owners| owners.len() > 1);
+    declared
+}
+
+/// Choice label of a shard, with the package's contexts and comment there
+fn owner_label(shard: &str, manifest: &Manifest, name: &str) -> String {
+    let mut label = shard.to_string();
+    let contexts = manifest.contexts_for(name);
+    if !contexts.is_empty() {
+        label.push_str(&format!(" (only in {})", contexts.join(", ")));
+    }
+    if let Some(comment) = manifest.comment_for(name) {
+        label.push_str(&format!(" - {}", comment));
+    }
+    label
+}
@@ -1,358 +1,407 @@
-use crate::utils::{ShardResult, log_step, log_debug};
-use crate::core::manifest::{Manifest, PackageState, Formula, Cask};
-use crate::brew::get_client;
-use crate::package::processor::{PackageProcessor, PackageType};
-use std::collections::{HashSet, HashMap};
-use std::path::{Path, PathBuf};
-use shellexpand;
+use std::collections::{HashMap, HashSet};
+use console::{style, StyledObject};
+use crate::utils::{Glyph, ShardResult, log_step, log_warning};
+use crate::brew::{options, BrewClient, ClientOverrides, Deprecation, DeprecationReport, InstalledBuild};
+use crate::core::manifest::{FormulaBuild, FormulaLink, ManifestSection};
+use crate::engine::{Action, Plan, ShardEngine};
+use crate::package::processor::PackageType;
+use crate::plugins::PluginChange;
+use crate::reporter::ConsoleReporter;
+use crate::notify::{self, DriftSummary};
+use crate::shard::{context, renames};
 use crate::utils::filesystem;
 
+/// How applying a plan would affect a package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Install,
+    Upgrade,
+    Remove,
+    Unchanged,
+}
+
+impl Change {
+    const ALL: [Change; 4] = [Change::Install, Change::Upgrade, Change::Remove, Change::Unchanged];
+
+    fn title(&self) -> &'static str {
+        match self {
+            Change::Install => "Install",
+            Change::Upgrade => "Upgrade",
+            Change::Remove => "Remove",
+            Change::Unchanged => "Unchanged",
+        }
+    }
+
+    fn paint<D>(&self, text: D) -> StyledObject<D> {
+        match self {
+            Change::Install => style(text).green(),
+            Change::Upgrade => style(text).yellow(),
+            Change::Remove => style(text).red(),
+            Change::Unchanged => style(text).dim(),
+        }
+    }
+
+    fn marker(&self) -> &'static str {
+        match self {
+            Change::Install => "+",
+            Change::Upgrade => "↑",
+            Change::Remove => "-",
+            Change::Unchanged => "=",
+        }
+    }
+}
+
+/// A single line of the diff
+struct Entry {
+    change: Change,
+    kind: &'static str,
+    name: String,
+    /// Version change for upgrades
+    detail: Option<String>,
+}
+
 /// Check for differences between manifest and installed packages
 /// This replaces the functionality previously in apply --dry-run
-pub fn diff(path: &str) -> ShardResult<()> {
+///
+/// With `verbose`, declared packages that are already up to date are listed too.
+pub fn diff(path: &str, verbose: bool, overrides: ClientOverrides) -> ShardResult<()> {
     // Handle "all" special case
-    if path.to_lowercase() == "all" {
-        return diff_all_enabled_shards();
+    if path.eq_ignore_ascii_case("all") {
+        return diff_all_enabled_shards(verbose, overrides);
     }
-    
+
     // Resolve the shard name to a proper path
     let manifest_path = filesystem::resolve_manifest_path(path)?;
     log_step(&format!("Checking changes that would be made by applying: {}", manifest_path));
-    
-    // Get the manifest
-    let manifest_path_obj = Path::new(&manifest_path);
-    let manifest = Manifest::from_file(manifest_path_obj)?;
-    
-    // Call internal function to perform the diff
-    diff_manifest(&manifest, true)  // true for additive_only for single shard
+
+    report_plan(path, verbose, overrides)
 }
 
 /// Check for differences across all enabled shards
-pub fn diff_all_enabled_shards() -> ShardResult<()> {
+pub fn diff_all_enabled_shards(verbose: bool, overrides: ClientOverrides) -> ShardResult<()> {
     log_step("Checking changes that would be made by applying all enabled shards");
+    report_plan("all", verbose, overrides)
+}
 
-    let shards_dir_path = PathBuf::from(shellexpand::tilde("~/.sapphire/shards").into_owned());
+/// Plan a target and print the changes applying it would make
+fn report_plan(target: &str, verbose: bool, overrides: ClientOverrides) -> ShardResult<()> {
+    let engine = ShardEngine::new(overrides)?.with_reporter(ConsoleReporter);
+    let plan = engine.plan(target)?;
 
-    if !std::path::Path::new(&shards_dir_path).exists() {
-        log_debug("Shards directory (~/.sapphire/shards) not found. Nothing to apply.");
+    if plan.shards.is_empty() {
+        log_step("No enabled shards found. Nothing to apply.");
         return Ok(());
     }
 
-    // --- Collect all manifests and desired state ---
-    let mut all_manifests = Vec::new();
-    let mut desired_taps = HashSet::new();
-    let mut desired_formulae: HashMap<String, (PackageState, Vec<String>)> = HashMap::new(); // name -> (state, options)
-    let mut desired_casks: HashMap<String, (PackageState, Vec<String>)> = HashMap::new();
-
-    let entries = std::fs::read_dir(&shards_dir_path)?;
-
-    let mut shard_files = Vec::new();
-    for entry_res in entries {
-        if let Ok(entry) = entry_res {
-            let path = entry.path();
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "toml") {
-                shard_files.push(path);
-            }
-        }
+    renames::warn_renamed(&plan);
+    for unmet in &plan.unmet_requirements {
+        log_warning(&format!("Requirement of shard '{}' not met, applying will be refused: {}", unmet.shard, unmet.problem));
     }
-    shard_files.sort(); // Consistent order
 
-    if shard_files.is_empty() {
-        log_debug("No shard files (.toml) found in shards directory. Nothing to apply.");
-        return Ok(());
+    let entries = classify(&plan);
+    print_sections(&entries, verbose);
+    print_context_skips(&plan);
+    print_mdm_managed(&plan);
+    print_dependency_holds(&plan);
+    print_ignored(&plan);
+    print_links(&plan);
+    print_plugins(&plan);
+    print_build_mismatches(engine.brew_client(), &plan);
+    for unknown in options::check(engine.brew_client(), &plan.formula_options) {
+        log_warning(&unknown.describe());
     }
-
-    log_step(&format!("Found {} shard file(s). Checking changes...", shard_files.len()));
-
-    for path in &shard_files {
-        match Manifest::from_file(path) {
-            Ok(manifest) => {
-                // Collect taps (these are strings, not structured objects)
-                manifest.taps.iter().for_each(|tap| { desired_taps.insert(tap.clone()); });
-                
-                // Also check taps_structured if they exist
-                manifest.taps_structured.iter().for_each(|tap| { desired_taps.insert(tap.name.clone()); });
-
-                // Collect formulae (structured and simple lists)
-                manifest.formulas.iter().for_each(|f| {
-                    let entry = desired_formulae.entry(f.name.clone()).or_insert((f.state.clone(), f.options.clone()));
-                    // Prioritize 'Latest' state if seen multiple times
-                    if f.state == PackageState::Latest && entry.0 != PackageState::Latest { entry.0 = PackageState::Latest; }
-                    // Merge options? For now, first non-empty options win.
-                    if entry.1.is_empty() && !f.options.is_empty() { entry.1 = f.options.clone(); }
-                });
-                
-                // Simple formulae list
-                manifest.formulae.iter().for_each(|name| {
-                    // Add only if not already processed from structured list
-                    desired_formulae.entry(name.clone()).or_insert((PackageState::Latest, Vec::new()));
-                });
-
-                // Collect casks (structured and simple lists)
-                manifest.casks_structured.iter().for_each(|c| {
-                    let entry = desired_casks.entry(c.name.clone()).or_insert((c.state.clone(), c.options.clone()));
-                    if c.state == PackageState::Latest && entry.0 != PackageState::Latest { entry.0 = PackageState::Latest; }
-                    if entry.1.is_empty() && !c.options.is_empty() { entry.1 = c.options.clone(); }
-                });
-                
-                // Simple casks list
-                manifest.casks.iter().for_each(|name| {
-                    desired_casks.entry(name.clone()).or_insert((PackageState::Latest, Vec::new()));
-                });
-
-                all_manifests.push(manifest);
-            }
-            Err(e) => {
-                log_debug(&format!("Skipping invalid manifest file {}: {}", path.display(), e));
-            }
-        }
+    print_deprecations(engine.brew_client(), &plan);
+    print_summary(&entries, &plan);
+
+    if let Some(config) = notify::unattended_config() {
+        let count = |change: Change| entries.iter().filter(|entry| entry.change == change).count();
+        let label = if target.eq_ignore_ascii_case("all") { "all shards" } else { target };
+        notify::notify_drift(&config, label, DriftSummary {
+            to_install: count(Change::Install),
+            to_upgrade: count(Change::Upgrade),
+            to_remove: count(Change::Remove),
+        });
     }
 
-    if all_manifests.is_empty() {
-        log_debug("No valid manifests loaded. Nothing to apply.");
-        return Ok(());
-    }
+    Ok(())
+}
 
-    // --- Create a single "virtual" manifest representing the combined desired state ---
-    let mut combined_manifest = Manifest::new();
-    
-    // Convert tap names to strings
-    combined_manifest.taps = desired_taps.into_iter().collect();
-
-    // Convert formulae map to structured Formula objects
-    combined_manifest.formulas = desired_formulae.into_iter()
-        .map(|(name, (state, options))| Formula {
-            name,
-            state,
-            options,
-            version: "latest".to_string(),
-        })
+/// Sort the planned actions into diff entries
+fn classify(plan: &Plan) -> Vec<Entry> {
+    let outdated = &plan.outdated;
+    let versions: HashMap<(&str, &str), String> = [(PackageType::Formula, &outdated.formulae), (PackageType::Cask, &outdated.casks)]
+        .into_iter()
+        .flat_map(|(package_type, packages)| packages.iter().map(move |package| (
+            (package_type.as_str(), package.name.as_str()),
+            format!("{} {} {} ({})", package.installed_version(), Glyph::Arrow, package.current_version, package.change()),
+        )))
         .collect();
 
-    // Convert casks map to structured Cask objects
-    combined_manifest.casks_structured = desired_casks.into_iter()
-        .map(|(name, (state, options))| Cask {
-            name,
-            state,
-            options,
-            version: "latest".to_string(),
+    let orphans = plan.orphans.iter().map(|name| Entry {
+        change: Change::Remove,
+        kind: PackageType::Formula.as_str(),
+        name: name.clone(),
+        detail: Some("orphaned dependency".to_string()),
+    });
+
+    // Declared packages without an install or upgrade are installed and stay as they are
+    let planned: HashSet<(&str, &str)> = plan.actions.iter()
+        .filter_map(|action| match action {
+            Action::Install { package_type, name } | Action::Upgrade { package_type, name } => Some((package_type.as_str(), name.as_str())),
+            _ => None,
         })
         .collect();
+    let unchanged = [(PackageType::Formula, &plan.formulae), (PackageType::Cask, &plan.casks)]
+        .into_iter()
+        .flat_map(|(package_type, names)| names.iter().map(move |name| (package_type.as_str(), name)))
+        .filter(|(kind, name)| !planned.contains(&(*kind, name.as_str())))
+        .map(|(kind, name)| Entry {
+            change: Change::Unchanged,
+            kind,
+            name: name.clone(),
+            // Outdated but not upgraded, so pinned
+            detail: versions.contains_key(&(kind, name.as_str())).then(|| "pinned".to_string()),
+        })
+        .collect::<Vec<_>>();
+
+    // Link changes get their own section, see `print_links`
+    plan.actions.iter()
+        .filter_map(|action| Some(match action {
+            Action::AddTap { name, url } => Entry { change: Change::Install, kind: "tap", name: name.clone(), detail: url.clone() },
+            Action::Install { package_type, name } => {
+                Entry { change: Change::Install, kind: package_type.as_str(), name: name.clone(), detail: None }
+            }
+            Action::Upgrade { package_type, name } => {
+                let detail = versions.get(&(package_type.as_str(), name.as_str())).cloned();
+                let change = if detail.is_some() { Change::Upgrade } else { Change::Unchanged };
+                Entry { change, kind: package_type.as_str(), name: name.clone(), detail }
+            }
+            Action::Uninstall { package_type, name } => {
+                Entry { change: Change::Remove, kind: package_type.as_str(), name: name.clone(), detail: None }
+            }
+            Action::InstallExtension { name } => {
+                Entry { change: Change::Install, kind: "vscode", name: name.clone(), detail: None }
+            }
+            Action::Link { .. } => return None,
+        }))
+        .chain(unchanged)
+        .chain(orphans)
+        .collect()
+}
 
-    // Sort for consistent output/processing
-    combined_manifest.taps.sort();
-    combined_manifest.formulas.sort_by(|a, b| a.name.cmp(&b.name));
-    combined_manifest.casks_structured.sort_by(|a, b| a.name.cmp(&b.name));
+/// Print one colored section per kind of change
+fn print_sections(entries: &[Entry], verbose: bool) {
+    for change in Change::ALL {
+        if change == Change::Unchanged && !verbose {
+            continue;
+        }
 
-    // Perform the diff for the combined manifest
-    diff_manifest(&combined_manifest, false) // false for additive_only for "all" shards
-}
+        let section: Vec<&Entry> = entries.iter().filter(|entry| entry.change == change).collect();
+        if section.is_empty() {
+            continue;
+        }
 
-/// Internal function to diff a manifest against the current system state
-fn diff_manifest(manifest: &Manifest, additive_only: bool) -> ShardResult<()> {
-    let brew_client = get_client();
-
-    // --- Process Taps ---
-    if !manifest.taps.is_empty() {
-        log_step(&format!("Checking {} taps...", manifest.taps.len()));
-        let installed_taps = brew_client.get_installed_taps()?;
-        
-        for tap in &manifest.taps {
-            if installed_taps.contains(tap) {
-                log_debug(&format!("✅ Tap already installed: {}", tap));
-            } else {
-                log_step(&format!("❌ Tap would be installed: {}", tap));
-            }
+        println!();
+        println!("{} ({})", change.paint(change.title()).bold(), section.len());
+        for entry in section {
+            let detail = entry.detail.as_deref().map(|d| style(d).dim().to_string()).unwrap_or_default();
+            println!("  {} {:<40} {:<8} {}", change.paint(change.marker()), entry.name, entry.kind, detail);
         }
     }
+}
 
-    // --- Process Formulas & Casks ---
-    let installed_formulae = brew_client.get_installed_formulae()?;
-    let installed_casks = brew_client.get_installed_casks()?;
-
-    // Create processors
-    let formula_processor = PackageProcessor::new(PackageType::Formula, installed_formulae.clone(), true);
-    let cask_processor = PackageProcessor::new(PackageType::Cask, installed_casks.clone(), true);
-
-    // Process packages using the processors - check both structured and simple lists
-    // For individual shards, formulae (simple string list) is the primary storage
-    let total_formulae_count = manifest.formulae.len() + manifest.formulas.len();
-    log_step(&format!("Checking {} formulae...", total_formulae_count));
-    
-    // Process structured formulas
-    let formula_ops = formula_processor.process_packages(&manifest.formulas)?;
-    
-    // Process simple formulae list (added via shard add)
-    let formulae_ops = formula_processor.process_packages(&manifest.formulae)?;
-    
-    // Combine to-install lists
-    let mut combined_formulae_to_install = formula_ops.to_install.clone();
-    combined_formulae_to_install.extend(formulae_ops.to_install);
-    
-    if !combined_formulae_to_install.is_empty() {
-        log_step(&format!("Would install {} formula(s):", combined_formulae_to_install.len()));
-        for formula in &combined_formulae_to_install {
-            log_step(&format!("  • {}", formula));
-        }
+/// Print declared packages left out because they belong to other contexts
+fn print_context_skips(plan: &Plan) {
+    if plan.skipped_by_context.is_empty() {
+        return;
     }
-    
-    // Handle with_options for both
-    for (name, options) in &formula_ops.with_options {
-        // Only show installation messages for packages not already installed
-        if !formula_processor.is_installed(name) {
-            log_step(&format!("Would install formula {} with options: {}", name, options.join(" ")));
-        }
+
+    println!();
+    println!("{} ({}, {})", style("Skipped by context").bold().dim(), plan.skipped_by_context.len(),
+        context::describe(plan.context.as_deref()));
+    for skip in &plan.skipped_by_context {
+        let kind = match skip.section {
+            ManifestSection::Formulae => "formula",
+            ManifestSection::Casks => "cask",
+            _ => "vscode",
+        };
+        let detail = format!("only in {}", skip.contexts.join(", "));
+        println!("  {} {:<40} {:<8} {}", style("·").dim(), skip.name, kind, style(detail).dim());
     }
-    for (name, options) in &formulae_ops.with_options {
-        // Only show installation messages for packages not already installed
-        if !formula_processor.is_installed(name) {
-            log_step(&format!("Would install formula {} with options: {}", name, options.join(" ")));
-        }
+}
+
+/// Print undeclared casks kept because device management controls them
+fn print_mdm_managed(plan: &Plan) {
+    if plan.mdm_managed.is_empty() {
+        return;
     }
-    
-    // Combine to-uninstall lists
-    let mut combined_formulae_to_uninstall = formula_ops.to_uninstall.clone();
-    combined_formulae_to_uninstall.extend(formulae_ops.to_uninstall);
-    
-    if !combined_formulae_to_uninstall.is_empty() {
-        log_step(&format!("Would uninstall {} formula(s):", combined_formulae_to_uninstall.len()));
-        for formula in &combined_formulae_to_uninstall {
-            log_step(&format!("  • {}", formula));
-        }
+
+    println!();
+    println!("{} ({})", style("Managed by MDM").bold().dim(), plan.mdm_managed.len());
+    for managed in &plan.mdm_managed {
+        let detail = format!("kept, {} {}", managed.item.source.as_str(), managed.item.name);
+        println!("  {} {:<40} {:<8} {}", style("·").dim(), managed.cask, "cask", style(detail).dim());
     }
+}
 
-    // Process casks - handle both structured and simple lists
-    let total_casks_count = manifest.casks.len() + manifest.casks_structured.len();
-    log_step(&format!("Checking {} casks...", total_casks_count));
-    
-    // Process structured casks
-    let cask_ops = cask_processor.process_packages(&manifest.casks_structured)?;
-    
-    // Process simple casks list (added via shard add)
-    let casks_ops = cask_processor.process_packages(&manifest.casks)?;
-    
-    // Combine to-install lists
-    let mut combined_casks_to_install = cask_ops.to_install.clone();
-    combined_casks_to_install.extend(casks_ops.to_install);
-    
-    if !combined_casks_to_install.is_empty() {
-        log_step(&format!("Would install {} cask(s):", combined_casks_to_install.len()));
-        for cask in &combined_casks_to_install {
-            log_step(&format!("  • {}", cask));
-        }
+/// Print undeclared formulae kept because packages that stay depend on them
+fn print_dependency_holds(plan: &Plan) {
+    if plan.held_by_dependents.is_empty() {
+        return;
     }
-    
-    // Handle with_options for both
-    for (name, options) in &cask_ops.with_options {
-        // Only show installation messages for packages not already installed
-        if !cask_processor.is_installed(name) {
-            log_step(&format!("Would install cask {} with options: {}", name, options.join(" ")));
-        }
+
+    println!();
+    println!("{} ({})", style("Required by other packages").bold().dim(), plan.held_by_dependents.len());
+    for hold in &plan.held_by_dependents {
+        let detail = format!("kept, {}", hold.describe());
+        println!("  {} {:<40} {:<8} {}", style("·").dim(), hold.formula, "formula", style(detail).dim());
     }
-    for (name, options) in &casks_ops.with_options {
-        // Only show installation messages for packages not already installed
-        if !cask_processor.is_installed(name) {
-            log_step(&format!("Would install cask {} with options: {}", name, options.join(" ")));
+}
+
+/// Print link changes of formulae and versions that conflict
+fn print_links(plan: &Plan) {
+    let changes: Vec<(&str, FormulaLink)> = plan.actions.iter()
+        .filter_map(|action| match action {
+            Action::Link { name, link } => Some((name.as_str(), *link)),
+            _ => None,
+        })
+        .collect();
+
+    if !changes.is_empty() {
+        println!();
+        println!("{} ({})", style("Links").bold().yellow(), changes.len());
+        for (name, link) in changes {
+            let marker = if link.is_linked() { "+" } else { "-" };
+            println!("  {} {:<40} {:<8} {}", style(marker).yellow(), name, "formula", style(link.describe()).dim());
         }
     }
-    
-    // Combine to-uninstall lists
-    let mut combined_casks_to_uninstall = cask_ops.to_uninstall.clone();
-    combined_casks_to_uninstall.extend(casks_ops.to_uninstall);
-    
-    if !combined_casks_to_uninstall.is_empty() {
-        log_step(&format!("Would uninstall {} cask(s):", combined_casks_to_uninstall.len()));
-        for cask in &combined_casks_to_uninstall {
-            log_step(&format!("  • {}", cask));
-        }
+
+    for formula in &plan.keg_only_links {
+        log_warning(&format!("{} is keg-only and stays unlinked, set link = \"force\" to link it anyway", formula));
     }
 
-    // --- Process Implied Uninstalls (only if not additive and this is an "all" operation) ---
-    if !additive_only {
-        log_step("Checking for packages to uninstall (not present in any shard)...");
+    for versions in &plan.link_conflicts {
+        log_warning(&format!("{} all request linking, only one version can be linked. Leaving their links unchanged",
+            versions.join(" and ")));
+    }
+}
 
-        // Get all *main* packages currently installed (exclude dependencies)
-        let (main_formulae, main_casks) = match get_all_main_packages() {
-            Ok(packages) => packages,
-            Err(e) => {
-                log_debug(&format!("Error getting installed packages: {}", e));
-                return Ok(());
-            }
-        };
+/// Print changes plugins would make to their custom sections
+fn print_plugins(plan: &Plan) {
+    let changes: Vec<(&str, &PluginChange)> = plan.plugins.iter()
+        .flat_map(|planned| planned.changes.iter().map(move |change| (planned.section.as_str(), change)))
+        .collect();
 
-        // Identify formulae defined in the manifest (packages we want to keep or manage)
-        // Include both structured and simple lists
-        let mut desired_formulae_names: HashSet<String> = manifest.formulas.iter()
-            .filter(|f| f.state != PackageState::Absent) // Only count packages meant to be present/latest
-            .map(|f| f.name.clone())
-            .collect();
-        
-        // Add formulae from simple list
-        desired_formulae_names.extend(manifest.formulae.iter().cloned());
-
-        // Identify casks defined in the manifest (both structured and simple lists)
-        let mut desired_casks_names: HashSet<String> = manifest.casks_structured.iter()
-            .filter(|c| c.state != PackageState::Absent)
-            .map(|c| c.name.clone())
-            .collect();
-            
-        // Add casks from simple list
-        desired_casks_names.extend(manifest.casks.iter().cloned());
-
-        // Find packages installed but not desired anymore
-        let formulae_to_uninstall: Vec<_> = main_formulae.iter()
-            .filter(|name| !desired_formulae_names.contains(*name))
-            .cloned()
-            .collect();
-
-        let casks_to_uninstall: Vec<_> = main_casks.iter()
-            .filter(|name| !desired_casks_names.contains(*name))
-            .cloned()
-            .collect();
-
-        if !formulae_to_uninstall.is_empty() {
-            log_step(&format!("Would uninstall {} formula(s):", formulae_to_uninstall.len()));
-            for formula in &formulae_to_uninstall {
-                log_step(&format!("  • {}", formula));
-            }
+    if !changes.is_empty() {
+        println!();
+        println!("{} ({})", style("Plugins").bold().cyan(), changes.len());
+        for (section, change) in changes {
+            let detail = change.detail.as_deref().map(|d| style(d).dim().to_string()).unwrap_or_default();
+            println!("  {} {:<40} {:<8} {}", style("~").cyan(), format!("{} {}", change.action, change.target), section, detail);
         }
+    }
 
-        if !casks_to_uninstall.is_empty() {
-            log_step(&format!("Would uninstall {} cask(s):", casks_to_uninstall.len()));
-            for cask in &casks_to_uninstall {
-                log_step(&format!("  • {}", cask));
-            }
+    for planned in &plan.plugins {
+        if let Some(error) = &planned.error {
+            log_warning(&format!("Plugin {} could not plan the [{}] section: {}", planned.plugin, planned.section, error));
         }
     }
+    for section in &plan.unclaimed_sections {
+        log_warning(&format!("No plugin claims the [{}] section, leaving it alone", section));
+    }
+}
 
-    // --- Cleanup ---
-    log_debug("Would run cleanup if needed");
+/// Print packages the `ignore` list leaves to other tools
+fn print_ignored(plan: &Plan) {
+    if plan.ignored.is_empty() {
+        return;
+    }
 
-    Ok(())
+    println!();
+    println!("{} ({})", style("Ignored").bold().dim(), plan.ignored.len());
+    for name in &plan.ignored {
+        println!("  {} {:<40} {:<8} {}", style("·").dim(), name, "", style("unmanaged, matches shard.ignore").dim());
+    }
 }
 
-/// Helper function to get all main packages (not dependencies)
-fn get_all_main_packages() -> ShardResult<(Vec<String>, Vec<String>)> {
-    let brew_client = get_client();
-    
-    // Get all installed packages
-    let formulae = brew_client.get_installed_formulae()?;
-    let casks = brew_client.get_installed_casks()?;
-    
-    // Get dependency packages (these will be excluded)
-    let dependencies = brew_client.get_dependency_packages()?;
-    
-    // Filter out dependencies
-    let main_formulae: Vec<String> = formulae
-        .into_iter()
-        .filter(|f| !dependencies.contains(f))
+/// Print installed formulae whose build differs from the requested build mode
+///
+/// Apply does not rebuild these, they have to be reinstalled manually.
+fn print_build_mismatches(brew_client: &BrewClient, plan: &Plan) {
+    if plan.formula_builds.is_empty() {
+        return;
+    }
+
+    let installed = brew_client.get_installed_builds().unwrap_or_else(|e| {
+        log_warning(&format!("Could not check how formulae were built: {}", e));
+        Vec::new()
+    });
+
+    let mismatches: Vec<(&InstalledBuild, &FormulaBuild)> = installed.iter()
+        .filter_map(|build| Some((build, plan.formula_builds.get(&build.name)?)))
+        .filter(|(installed, requested)| {
+            installed.head != requested.head || (requested.build_from_source && !installed.from_source)
+        })
         .collect();
-    
-    // Casks are typically not dependencies, but filter for consistency
-    let main_casks: Vec<String> = casks
-        .into_iter()
-        .filter(|c| !dependencies.contains(c))
+
+    if mismatches.is_empty() {
+        return;
+    }
+
+    println!();
+    println!("{} ({})", style("Build mode differs").magenta().bold(), mismatches.len());
+    for (installed, requested) in mismatches {
+        let actual = FormulaBuild { build_from_source: installed.from_source, head: installed.head };
+        let detail = format!("installed {}, requested {}", actual.describe(), requested.describe());
+        println!("  {} {:<40} {:<8} {}", style("≠").magenta(), installed.name, "formula", style(detail).dim());
+    }
+    log_step("Reinstall these formulae with `brew reinstall` and the matching flags to switch build mode");
+}
+
+/// Print declared packages that Homebrew has deprecated or disabled
+fn print_deprecations(brew_client: &BrewClient, plan: &Plan) {
+    let formulae = plan.declared(PackageType::Formula);
+    let casks = plan.declared(PackageType::Cask);
+
+    let report = brew_client.get_deprecations(&formulae, &casks).unwrap_or_else(|e| {
+        log_warning(&format!("Could not check for deprecated packages: {}", e));
+        DeprecationReport::default()
+    });
+
+    let deprecations: Vec<(&Deprecation, PackageType)> = report.formulae.iter().map(|d| (d, PackageType::Formula))
+        .chain(report.casks.iter().map(|d| (d, PackageType::Cask)))
         .collect();
-    
-    Ok((main_formulae, main_casks))
-}
\ No newline at end of file
+
+    if deprecations.is_empty() {
+        return;
+    }
+
+    println!();
+    println!("{} ({})", style("Deprecated").red().bold(), deprecations.len());
+    for (deprecation, package_type) in deprecations {
+        let marker = if deprecation.disabled { style(Glyph::Error).red() } else { style(Glyph::Warning).yellow() };
+        println!("  {} {:<40} {:<8} {}", marker, deprecation.name, package_type.as_str(), style(deprecation.describe()).dim());
+    }
+}
+
+/// Print counts per change and package type
+fn print_summary(entries: &[Entry], plan: &Plan) {
+    let kinds = ["tap", "formula", "cask", "vscode"];
+    let count = |change: Change, kind: &str| {
+        entries.iter().filter(|entry| entry.change == change && entry.kind == kind).count()
+    };
+
+    println!();
+    println!("{:<12} {:>8} {:>8} {:>8} {:>8} {:>8}", style("Summary").bold(), "taps", "formulae", "casks", "vscode", "total");
+    for change in Change::ALL {
+        let counts: Vec<usize> = kinds.iter().map(|kind| count(change, kind)).collect();
+        let total: usize = counts.iter().sum();
+        let row = format!("{:<12} {:>8} {:>8} {:>8} {:>8} {:>8}", change.title(), counts[0], counts[1], counts[2], counts[3], total);
+        println!("{}", if total == 0 { style(row).dim() } else { change.paint(row) });
+    }
+    println!();
+
+    if entries.iter().all(|entry| entry.change == Change::Unchanged) && plan.plugins.iter().all(|planned| planned.changes.is_empty()) {
+        log_step("System is up to date with the shard(s)");
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use console::style;
+use crate::brew::{get_client, BrewClient, ClientOverrides, DoctorWarning};
+use crate::core::manifest::Manifest;
+use crate::shard::manager::{ShardManager, SYSTEM_SHARDS_DIR};
+use crate::shard::validate::check_removed;
+use crate::utils::{Glyph, ShardResult, log_step, log_success, log_warning, log_debug, log_plain};
+
+/// Homebrew directories that must be writable by the user running shard
+const PREFIX_SUBDIRS: &[&str] = &["bin", "Cellar", "Caskroom", "var/homebrew"];
+
+/// A detected problem with a suggested fix
+struct Finding {
+    problem: String,
+    details: Vec<String>,
+    suggestion: String,
+}
+
+impl Finding {
+    fn new(problem: impl Into<String>, suggestion: impl Into<String>) -> Self {
+        Self {
+            problem: problem.into(),
+            details: Vec::new(),
+            suggestion: suggestion.into(),
+        }
+    }
+}
+
+/// Check the shard setup and, with `brew`, the Homebrew installation
+///
+/// Shard checks make sure every shard, enabled or disabled, can be loaded.
+/// Homebrew checks look for renamed and removed packages in disabled shards,
+/// run `brew doctor` and `brew config`, look for missing Command Line Tools,
+/// Homebrew directories owned by another user and shallow taps, and translate
+/// the results into suggestions for managing the system with shard.
+pub fn doctor(brew: bool, overrides: ClientOverrides) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+
+    log_step("Checking shards...");
+    let mut findings = check_shards(&manager)?;
+
+    if brew {
+        log_step("Checking Homebrew environment...");
+        let brew_client = get_client(overrides);
+        let managed = managed_packages(&manager)?;
+
+        findings.extend(check_brew_config(&brew_client)?);
+        findings.extend(check_shallow_taps(&brew_client)?);
+        findings.extend(check_disabled_packages(&manager, &brew_client)?);
+
+        log_step("Running brew doctor...");
+        findings.extend(brew_client.doctor()?.into_iter().map(|warning| explain_warning(warning, &managed)));
+    }
+
+    if findings.is_empty() {
+        log_success("No problems found");
+        return Ok(());
+    }
+
+    log_plain("");
+    for finding in &findings {
+        log_warning(&finding.problem);
+        for detail in &finding.details {
+            log_plain(&format!("    {}", style(detail).dim()));
+        }
+        log_plain(&format!("    {} {}", style(Glyph::Step).cyan(), finding.suggestion));
+        log_plain("");
+    }
+
+    log_step(&format!("Found {} problem(s)", findings.len()));
+    Ok(())
+}
+
+/// Make sure every enabled shard parses
+fn check_shards(manager: &ShardManager) -> ShardResult<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    for name in manager.list_shards()? {
+        if let Err(e) = Manifest::from_file(manager.get_shard_path(&name)) {
+            findings.push(Finding::new(
+                format!("Shard '{}' cannot be loaded: {}", name, e),
+                format!("Fix the syntax in {} or disable it with `shard disable {}`",
+                    manager.get_shard_path(&name).display(), name),
+            ));
+        }
+    }
+
+    for name in manager.list_disabled_shards()? {
+        let path = manager.get_disabled_shard_path(&name);
+        if let Err(e) = Manifest::from_file(&path) {
+            findings.push(Finding::new(
+                format!("Disabled shard '{}' cannot be loaded: {}", name, e),
+                format!("Fix the syntax in {} before enabling it, or remove it", path.display()),
+            ));
+        }
+    }
+
+    for name in manager.list_shadowed_shards()? {
+        findings.push(Finding::new(
+            format!("User shard '{}' is hidden by the system shard of the same name", name),
+            format!("Rename {} to apply its packages on top of the system layer in {}",
+                manager.shards_dir().join(format!("{}.toml", name)).display(), SYSTEM_SHARDS_DIR),
+        ));
+    }
+
+    Ok(findings)
+}
+
+/// Look for renamed and removed packages in disabled shards
+///
+/// Enabled shards hit these on the next apply, disabled ones only when they
+/// are enabled again, possibly long after.
+fn check_disabled_packages(manager: &ShardManager, brew_client: &BrewClient) -> ShardResult<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    for name in manager.list_disabled_shards()? {
+        let path = manager.get_disabled_shard_path(&name);
+        let Ok(manifest) = Manifest::from_file(&path) else {
+            continue;
+        };
+        let problems = check_removed(brew_client, &manifest);
+        if problems.is_empty() {
+            continue;
+        }
+
+        let mut finding = Finding::new(
+            format!("Disabled shard '{}' declares packages that changed in Homebrew", name),
+            format!("Update it before enabling, renamed formulae with `shard fix-renames {}`", path.display()),
+        );
+        finding.details = problems;
+        findings.push(finding);
+    }
+
+    Ok(findings)
+}
+
+/// Map every package declared in an enabled shard to the shards declaring it
+fn managed_packages(manager: &ShardManager) -> ShardResult<BTreeMap<String, Vec<String>>> {
+    let mut managed: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for (shard_name, manifest) in manager.load_active_manifests()? {
+        for package in manifest.formulae.iter().chain(&manifest.casks) {
+            managed.entry(package.clone()).or_default().push(shard_name.clone());
+        }
+    }
+
+    Ok(managed)
+}
+
+/// Inspect `brew config` for missing tools and wrong ownership
+fn check_brew_config(brew_client: &BrewClient) -> ShardResult<Vec<Finding>> {
+    let config = brew_client.get_config()?;
+    let mut findings = Vec::new();
+
+    let missing = |key: &str| config.get(key).is_none_or(|value| value == "N/A");
+    if missing("CLT") && missing("Xcode") {
+        findings.push(Finding::new(
+            "Xcode Command Line Tools are not installed",
+            "Run `xcode-select --install`, then re-run `shard apply all`",
+        ));
+    }
+
+    if let Some(prefix) = config.get("HOMEBREW_PREFIX") {
+        log_debug(&format!("Homebrew prefix: {}", prefix));
+        findings.extend(check_ownership(Path::new(prefix)));
+    }
+
+    Ok(findings)
+}
+
+/// Report Homebrew directories not owned by the current user
+#[cfg(unix)]
+fn check_ownership(prefix: &Path) -> Vec<Finding> {
+    use std::os::unix::fs::MetadataExt;
+
+    // The home directory is owned by the current user, compare against it
+    let home = shellexpand::tilde("~").to_string();
+    let Ok(user_uid) = std::fs::metadata(&home).map(|m| m.uid()) else {
+        return Vec::new();
+    };
+
+    PREFIX_SUBDIRS.iter()
+        .map(|subdir| prefix.join(subdir))
+        .filter(|path| std::fs::metadata(path).is_ok_and(|m| m.uid() != user_uid))
+        .map(|path| Finding::new(
+            format!("{} is not owned by the current user", path.display()),
+            format!("Run `sudo chown -R $(whoami) {}` so shard can install packages without sudo", path.display()),
+        ))
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn check_ownership(_prefix: &Path) -> Vec<Finding> {
+    Vec::new()
+}
+
+/// Find taps that were cloned with a shallow history
+fn check_shallow_taps(brew_client: &BrewClient) -> ShardResult<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    for tap in brew_client.get_installed_taps()? {
+        let repository = match brew_client.get_tap_repository(&tap) {
+            Ok(repository) => repository,
+            Err(e) => {
+                log_debug(&format!("Could not locate repository of tap {}: {}", tap, e));
+                continue;
+            }
+        };
+
+        if repository.join(".git").join("shallow").exists() {
+            findings.push(Finding::new(
+                format!("Tap {} is a shallow clone", tap),
+                format!("Run `git -C {} fetch --unshallow`, then `brew update`", repository.display()),
+            ));
+        }
+    }
+
+    Ok(findings)
+}
+
+/// Turn a `brew doctor` warning into a finding with a shard-aware suggestion
+fn explain_warning(warning: DoctorWarning, managed: &BTreeMap<String, Vec<String>>) -> Finding {
+    let title = warning.title.to_lowercase();
+
+    let suggestion = if title.contains("deprecated or disabled") {
+        // The warning lists the affected packages one per line
+        let (in_shards, unmanaged): (Vec<&String>, Vec<&String>) = warning.details.iter()
+            .filter(|line| !line.contains(' '))
+            .partition(|name| managed.contains_key(name.as_str()));
+
+        let mut steps: Vec<String> = in_shards.iter()
+            .flat_map(|name| managed[name.as_str()].iter()
+                .map(move |shard| format!("`shard del {} -s {}`", name, shard)))
+            .collect();
+        if !unmanaged.is_empty() {
+            let names: Vec<&str> = unmanaged.iter().map(|name| name.as_str()).collect();
+            steps.push(format!("`brew uninstall {}`", names.join(" ")));
+        }
+
+        if steps.is_empty() {
+            "Find replacements for these packages and remove them".to_string()
+        } else {
+            format!("Find replacements, then remove them with {}", steps.join(", "))
+        }
+    } else if title.contains("command line tools") {
+        "Update with `softwareupdate --all --install`, or reinstall with `xcode-select --install`".to_string()
+    } else if title.contains("unlinked kegs") {
+        "Run `brew link <formula>` for each keg, or `shard apply all` to reinstall managed packages".to_string()
+    } else if title.contains("path") {
+        "Add `eval \"$(brew shellenv)\"` to your shell profile so Homebrew binaries come first".to_string()
+    } else if title.contains("unbrewed") {
+        "These files were not installed by Homebrew; remove them if no other software needs them".to_string()
+    } else if title.contains("outdated") {
+        "Run `shard upgrade all` to upgrade managed packages".to_string()
+    } else {
+        "Follow the advice printed by `brew doctor`".to_string()
+    };
+
+    Finding {
+        problem: format!("brew doctor: {}", warning.title),
+        details: warning.details,
+        suggestion,
+    }
+}
@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use console::style;
+use dialoguer::Confirm;
+use crate::brew::ClientOverrides;
+use crate::core::manifest::Manifest;
+use crate::shard::{apply, backups, update, validate};
+use crate::shard::manager::{ShardManager, ShardStatus};
+use crate::utils::{ShardError, ShardResult, ResultExt, log_error, log_step, log_success, log_warning};
+
+/// Editor used when neither `$VISUAL` nor `$EDITOR` is set
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Edit a shard in `$EDITOR`
+///
+/// The shard is edited as a copy and only replaced once the copy parses and
+/// declares valid, unique names, so a typo never reaches apply. With
+/// `apply_after`, all enabled shards are applied once the edit is saved.
+pub fn edit(name: &str, apply_after: bool, overrides: ClientOverrides) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    let info = manager.get_shard_info(name)?;
+    manager.ensure_editable(name)?;
+
+    let original = std::fs::read_to_string(&info.path)
+        .with_context(|| format!("Failed to read shard file: {}", info.path.display()))?;
+    // A shard that no longer parses can be edited to fix it
+    let before = info.manifest.unwrap_or_else(Manifest::new);
+
+    let draft = draft_path(name, &info.path);
+    std::fs::write(&draft, &original)
+        .with_context(|| format!("Failed to write {}", draft.display()))?;
+    let edited = edit_draft(name, &draft, &original);
+    let _ = std::fs::remove_file(&draft);
+
+    let Some((content, after)) = edited? else {
+        log_success(&format!("Shard {} was not changed", style(name).bold()));
+        return Ok(());
+    };
+
+    if backups::print_changes(&before, &after) {
+        println!();
+    } else {
+        log_step("No taps or packages changed");
+    }
+
+    manager.backup_shard(name)
+        .with_context(|| format!("Failed to backup shard before editing: {}", name))?;
+    std::fs::write(&info.path, content)
+        .with_context(|| format!("Failed to write shard file: {}", info.path.display()))?;
+    log_success(&format!("Saved shard {}", style(name).bold()));
+
+    if info.status == ShardStatus::Disabled {
+        return Ok(());
+    }
+    if apply_after {
+        update::refresh(update::UpdatePolicy::Auto, overrides)?;
+        apply::apply_all_enabled_shards(false, false, &[], overrides)
+    } else {
+        log_step("Run `shard apply all` to install and remove the changed packages");
+        Ok(())
+    }
+}
+
+/// Open the draft until it is valid or the user gives up
+///
+/// Returns the new content and manifest, `None` if nothing was changed.
+fn edit_draft(name: &str, draft: &Path, original: &str) -> ShardResult<Option<(String, Manifest)>> {
+    loop {
+        open_editor(draft)?;
+        let content = std::fs::read_to_string(draft)
+            .with_context(|| format!("Failed to read {}", draft.display()))?;
+        if content == original {
+            return Ok(None);
+        }
+
+        let problems = match Manifest::from_file(draft) {
+            Ok(manifest) => {
+                let mut problems = validate::check_names(&manifest);
+                problems.extend(validate::check_duplicates(&manifest));
+                if problems.is_empty() {
+                    return Ok(Some((content, manifest)));
+                }
+                problems
+            }
+            Err(e) => vec![e.to_string()],
+        };
+
+        for problem in &problems {
+            log_error(problem);
+        }
+        let retry = Confirm::new()
+            .with_prompt("Edit the shard again?")
+            .default(true)
+            .interact()
+            .with_context(|| "Failed to get user confirmation")?;
+        if !retry {
+            log_warning(&format!("Discarded the changes to shard {}", name));
+            return Err(ShardError::ValidationError(format!("{} problem(s) in the edited shard", problems.len())));
+        }
+    }
+}
+
+/// Run the user's editor on a file and wait for it to exit
+fn open_editor(path: &Path) -> ShardResult<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+
+    // Through the shell, editors are often configured with arguments like `code --wait`
+    let status = Command::new("sh")
+        .args(["-c", &format!("{} \"$1\"", editor), "sh"])
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to start editor: {}", editor))?;
+    if !status.success() {
+        return Err(ShardError::ApplicationError(format!("Editor '{}' exited with {}", editor, status)));
+    }
+    Ok(())
+}
+
+/// Temporary copy of a shard, with its extension so the format is kept
+fn draft_path(name: &str, path: &Path) -> PathBuf {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("toml");
+    std::env::temp_dir().join(format!("shard-edit-{}-{}.{}", name, std::process::id(), extension))
+}
@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use crate::core::config::ShardConfig;
+use crate::core::manifest::{merge_options, FormulaBuild, Manifest, Tap};
+use crate::shard::manager::ShardManager;
+use crate::utils::{ShardError, ShardResult, ResultExt, log_success};
+
+/// Export one or all enabled shards for another package manager
+///
+/// Only `"nix"` is supported, which emits a nix-darwin `homebrew` module.
+/// Without `output` the result is printed.
+pub fn export(target: &str, format: &str, output: Option<&str>) -> ShardResult<()> {
+    if !format.eq_ignore_ascii_case("nix") {
+        return Err(ShardError::ValidationError(format!(
+            "Unknown export format '{}', expected \"nix\"", format)));
+    }
+
+    let manager = ShardManager::new()?;
+    let mut manifests = manager.load_active_manifests()?;
+    if !target.eq_ignore_ascii_case("all") {
+        if !manager.shard_is_active(target) {
+            return Err(ShardError::NotFound(target.to_string()));
+        }
+        manifests.retain(|(name, _)| name == target);
+    }
+
+    let config = ShardConfig::load().unwrap_or_default();
+    let document = to_nix(&manifests, &config.cask_options);
+
+    match output {
+        Some(path) => {
+            let path = shellexpand::tilde(path).to_string();
+            std::fs::write(&path, document)
+                .with_context(|| format!("Failed to write export to {}", path))?;
+            log_success(&format!("Exported {} shard(s) to {}", manifests.len(), path));
+        }
+        None => print!("{}", document),
+    }
+    Ok(())
+}
+
+/// Render manifests as a nix-darwin module with a `homebrew` block
+///
+/// Build modes become brew `args`, cask options become cask `args`, e.g.
+/// `--appdir=~/Applications` turns into `appdir = "~/Applications";`.
+fn to_nix(manifests: &[(String, Manifest)], default_cask_options: &[String]) -> String {
+    let mut taps: BTreeMap<String, Option<String>> = BTreeMap::new();
+    let mut brews: BTreeMap<String, FormulaBuild> = BTreeMap::new();
+    let mut casks: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for (_, manifest) in manifests {
+        for Tap { name, url } in &manifest.taps {
+            let entry = taps.entry(name.clone()).or_default();
+            *entry = entry.take().or_else(|| url.clone());
+        }
+        for formula in &manifest.formulae {
+            brews.insert(formula.clone(), manifest.formula_build(formula));
+        }
+        for cask in &manifest.casks {
+            casks.insert(cask.clone(), merge_options(default_cask_options, manifest.cask_options_for(cask)));
+        }
+    }
+
+    let names: Vec<&str> = manifests.iter().map(|(name, _)| name.as_str()).collect();
+    let mut out = format!("# Generated by shard from: {}\n{{\n  homebrew = {{\n    enable = true;\n", names.join(", "));
+
+    out.push_str("    taps = [\n");
+    for (name, url) in &taps {
+        match url {
+            Some(url) => out.push_str(&format!("      {{ name = {}; clone_target = {}; }}\n", nix_string(name), nix_string(url))),
+            None => out.push_str(&format!("      {}\n", nix_string(name))),
+        }
+    }
+    out.push_str("    ];\n");
+
+    out.push_str("    brews = [\n");
+    for (name, build) in &brews {
+        let args: Vec<String> = build.install_args().iter()
+            .map(|arg| nix_string(arg.trim_start_matches('-')))
+            .collect();
+        if args.is_empty() {
+            out.push_str(&format!("      {}\n", nix_string(name)));
+        } else {
+            out.push_str(&format!("      {{ name = {}; args = [ {} ]; }}\n", nix_string(name), args.join(" ")));
+        }
+    }
+    out.push_str("    ];\n");
+
+    out.push_str("    casks = [\n");
+    for (name, options) in &casks {
+        if options.is_empty() {
+            out.push_str(&format!("      {}\n", nix_string(name)));
+        } else {
+            let args: Vec<String> = options.iter().map(|option| cask_arg(option)).collect();
+            out.push_str(&format!("      {{ name = {}; args = {{ {} }}; }}\n", nix_string(name), args.join(" ")));
+        }
+    }
+    out.push_str("    ];\n  };\n}\n");
+
+    out
+}
+
+/// Convert a cask option like `--no-quarantine` to a nix attribute
+fn cask_arg(option: &str) -> String {
+    let option = option.trim_start_matches('-');
+    match option.split_once('=') {
+        Some((key, value)) => format!("{} = {};", key.replace('-', "_"), nix_string(value)),
+        None => format!("{} = true;", option.replace('-', "_")),
+    }
+}
+
+/// Quote a string for nix, escaping interpolation
+fn nix_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace("${", "\\${"))
+}
@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sapphire_core::paths::SapphirePaths;
+use crate::utils::{ShardResult, ResultExt, log_success, log_warning, path_exists, ensure_parent_dir_exists};
+
+/// Sentinel file whose presence freezes reconciliation
+const FREEZE_FILE: &str = "frozen";
+
+/// Contents of the freeze sentinel file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreezeState {
+    /// When the freeze started
+    pub since: DateTime<Local>,
+    /// Optional note shown while frozen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl FreezeState {
+    /// Human-readable description for warnings
+    pub fn describe(&self) -> String {
+        let since = self.since.format("%Y-%m-%d %H:%M");
+        match &self.reason {
+            Some(reason) => format!("frozen since {} ({})", since, reason),
+            None => format!("frozen since {}", since),
+        }
+    }
+}
+
+/// Current freeze state, `None` if reconciliation is not frozen
+///
+/// An unreadable sentinel file still counts as frozen, so a damaged file
+/// never silently re-enables scheduled runs.
+pub fn freeze_state() -> ShardResult<Option<FreezeState>> {
+    let path = freeze_path();
+    if !path_exists(&path) {
+        return Ok(None);
+    }
+
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read freeze file: {}", path.display()))?;
+
+    let state = toml::from_str(&content).unwrap_or_else(|_| {
+        let since = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .map(DateTime::<Local>::from)
+            .unwrap_or_else(|_| Local::now());
+        FreezeState { since, reason: None }
+    });
+
+    Ok(Some(state))
+}
+
+/// Stop `apply all` from changing the system until [`thaw`] is called
+pub fn freeze(reason: Option<&str>) -> ShardResult<()> {
+    if let Some(state) = freeze_state()? {
+        log_warning(&format!("Shard is already {}", state.describe()));
+        return Ok(());
+    }
+
+    let state = FreezeState {
+        since: Local::now(),
+        reason: reason.map(str::to_string),
+    };
+
+    let path = freeze_path();
+    ensure_parent_dir_exists(&path)?;
+    let content = toml::to_string(&state)
+        .with_context(|| "Failed to serialize freeze state")?;
+    std::fs::write(&path, content)
+        .with_context(|| format!("Failed to write freeze file: {}", path.display()))?;
+
+    log_success("Froze shard reconciliation. `apply all` does nothing until `shard thaw`");
+    Ok(())
+}
+
+/// Resume reconciliation after a [`freeze`]
+pub fn thaw() -> ShardResult<()> {
+    let Some(state) = freeze_state()? else {
+        log_warning("Shard is not frozen");
+        return Ok(());
+    };
+
+    let path = freeze_path();
+    std::fs::remove_file(&path)
+        .with_context(|| format!("Failed to remove freeze file: {}", path.display()))?;
+
+    log_success(&format!("Thawed shard reconciliation (was {})", state.describe()));
+    Ok(())
+}
+
+fn freeze_path() -> PathBuf {
+    SapphirePaths::resolve().root().join(FREEZE_FILE)
+}
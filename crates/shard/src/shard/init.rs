@@ -1,23 +1,22 @@
 use std::path::PathBuf;
 use std::env;
 use console::style;
-use shellexpand;
+use sapphire_core::paths::SapphirePaths;
 use crate::core::manifest::Manifest;
+use crate::shard::manager::{ShardManager, SYSTEM_SHARDS_DIR};
 use crate::utils::{
     ShardResult, ResultExt, 
     log_success, log_warning, log_step, log_debug,
     ensure_dir_exists
 };
 
-const SHARDS_DIR: &str = "~/.sapphire/shards";
-const DISABLED_DIR: &str = "~/.sapphire/disabled";
-
 /// Initialize default system and user shards
 pub fn init_shards(force: bool) -> ShardResult<()> {
     log_step("Initializing system and user shards");
     
-    let shards_dir = PathBuf::from(shellexpand::tilde(SHARDS_DIR).into_owned());
-    let disabled_dir = PathBuf::from(shellexpand::tilde(DISABLED_DIR).into_owned());
+    let paths = SapphirePaths::resolve();
+    let shards_dir = paths.shards_dir();
+    let disabled_dir = paths.disabled_dir();
     
     // Create directories if they don't exist
     ensure_dir_exists(&shards_dir)
@@ -25,14 +24,24 @@ pub fn init_shards(force: bool) -> ShardResult<()> {
     ensure_dir_exists(&disabled_dir)
         .with_context(|| format!("Failed to create disabled shards directory: {}", disabled_dir.display()))?;
     
-    // Get system shard path
-    let system_path = PathBuf::from(format!("{}/system.toml", shards_dir.display()));
+    // In managed mode the system shard lives in the shared system layer
+    let manager = ShardManager::new()?;
+    let system_path = match manager.system_dir() {
+        Some(system_dir) => system_dir.join("system.toml"),
+        None => PathBuf::from(format!("{}/system.toml", shards_dir.display())),
+    };
     
     // Create system shard if it doesn't exist or force overwrite
-    if !system_path.exists() || force {
-        create_system_shard(&system_path)?;
-    } else {
+    if system_path.exists() && !force {
         log_warning("System shard already exists. Use --force to overwrite.");
+    } else if manager.system_dir().is_some() && !manager.current_user_is_admin() {
+        log_warning(&format!("Skipping system shard: only administrators can create it in {}", SYSTEM_SHARDS_DIR));
+    } else {
+        if let Some(system_dir) = manager.system_dir() {
+            ensure_dir_exists(system_dir)
+                .with_context(|| format!("Failed to create system shards directory: {}", system_dir.display()))?;
+        }
+        create_system_shard(&system_path)?;
     }
     
     // Get user shard path
@@ -75,10 +84,11 @@ fn create_system_shard(path: &PathBuf) -> ShardResult<()> {
     manifest.metadata.protected = true;
     
     // Add some common taps
-    manifest.taps.push("homebrew/core".to_string());
-    manifest.taps.push("homebrew/cask".to_string());
+    manifest.taps.push("homebrew/core".into());
+    manifest.taps.push("homebrew/cask".into());
     
     // Write to file
+    manifest.update_modification_info();
     manifest.to_file(path.to_str().unwrap_or_default())
         .with_context(|| format!("Failed to create system shard at {}", path.display()))?;
     
@@ -96,6 +106,7 @@ fn create_user_shard(path: &PathBuf, username: &str) -> ShardResult<()> {
     manifest.metadata.owner = username.to_string();
     
     // Write to file
+    manifest.update_modification_info();
     manifest.to_file(path.to_str().unwrap_or_default())
         .with_context(|| format!("Failed to create user shard at {}", path.display()))?;
     
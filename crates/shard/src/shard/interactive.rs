@@ -0,0 +1,63 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use sapphire_core::paths::SapphirePaths;
+use crate::utils::{ShardResult, ResultExt, ensure_parent_dir_exists, path_exists};
+
+/// Casks whose installers asked for input during an unattended run, one per line
+const INTERACTIVE_FILE: &str = "interactive";
+
+/// Casks waiting for an interactive install
+///
+/// Scheduled runs skip them until `apply --interactive-only` installed them
+/// from a terminal.
+pub fn pending() -> BTreeSet<String> {
+    std::fs::read_to_string(interactive_path())
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Remember that casks need an interactive install
+pub fn mark(casks: &[String]) -> ShardResult<()> {
+    let mut pending = pending();
+    let before = pending.len();
+    pending.extend(casks.iter().cloned());
+    if pending.len() == before {
+        return Ok(());
+    }
+    save(&pending)
+}
+
+/// Forget casks that were installed or are no longer declared
+pub fn clear(casks: &[String]) -> ShardResult<()> {
+    let mut pending = pending();
+    let before = pending.len();
+    pending.retain(|cask| !casks.contains(cask));
+    if pending.len() == before {
+        return Ok(());
+    }
+    save(&pending)
+}
+
+fn save(pending: &BTreeSet<String>) -> ShardResult<()> {
+    let path = interactive_path();
+    if pending.is_empty() {
+        if path_exists(&path) {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+        return Ok(());
+    }
+
+    ensure_parent_dir_exists(&path)?;
+    let content: String = pending.iter().map(|cask| format!("{}\n", cask)).collect();
+    std::fs::write(&path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn interactive_path() -> PathBuf {
+    SapphirePaths::resolve().root().join(INTERACTIVE_FILE)
+}
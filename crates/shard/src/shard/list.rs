@@ -0,0 +1,168 @@
+use chrono::{DateTime, Local, Utc};
+use console::style;
+use crate::core::manifest::Manifest;
+use crate::shard::manager::{ShardInfo, ShardManager, ShardStatus};
+use crate::utils::{ShardResult, log_step, log_warning};
+use sapphire_core::history::{self, Applied, Kind};
+
+/// List all shards with their status and package counts
+///
+/// With `verbose`, the packages of every shard are listed with their comments,
+/// along with when and by whom the shard was created and last modified.
+pub fn list(verbose: bool) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    let mut shards: Vec<ShardInfo> = manager.get_all_shards_info()?.into_values().collect();
+    shards.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if shards.is_empty() {
+        log_step("No shards found. Create one with `shard grow <name>`");
+        return Ok(());
+    }
+
+    let applied = history::load().unwrap_or_else(|e| {
+        log_warning(&format!("Could not read apply history: {}", e));
+        Vec::new()
+    });
+
+    println!();
+    println!("{:<24} {:<10} {:>8} {:>6}  {:<18}  {}", style("Shard").bold(), style("Status").bold(),
+        style("Formulae").bold(), style("Casks").bold(), style("Last applied").bold(), style("Description").bold());
+    for shard in &shards {
+        let status = status_label(&manager, shard);
+        let last_applied = describe_last_applied(last_apply(&applied, &shard.name));
+        match &shard.manifest {
+            Some(manifest) => println!("{:<24} {:<10} {:>8} {:>6}  {}  {}", shard.name, status,
+                manifest.formulae.len(), manifest.casks.len(), last_applied, manifest.metadata.description),
+            None => println!("{:<24} {:<10} {:>8} {:>6}  {}  {}", shard.name, status, "-", "-", last_applied,
+                style("unreadable, run `shard validate`").red()),
+        }
+    }
+
+    if verbose {
+        for shard in &shards {
+            let Some(manifest) = &shard.manifest else {
+                continue;
+            };
+            println!();
+            println!("{}", style(&shard.name).bold().underlined());
+            print_modification(manifest);
+            print_packages(manifest);
+        }
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Show the details and packages of a shard
+pub fn info(name: &str) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    let shard = manager.get_shard_info(name)?;
+    let manifest = Manifest::from_file(&shard.path)?;
+
+    println!();
+    println!("{}", style(&shard.name).bold().underlined());
+    if !manifest.metadata.description.is_empty() {
+        println!("{}", manifest.metadata.description);
+    }
+    println!();
+    println!("  {:<12} {}", "Path", shard.path.display());
+    println!("  {:<12} {}", "Status", status_label(&manager, &shard));
+    if !manifest.metadata.owner.is_empty() {
+        let kind = if manifest.metadata.shared { " (group)" } else { "" };
+        println!("  {:<12} {}{}", "Owner", manifest.metadata.owner, kind);
+    }
+    if manifest.metadata.protected {
+        println!("  {:<12} yes", "Protected");
+    }
+    print_modification(&manifest);
+    let applied = history::load().unwrap_or_default();
+    match last_apply(&applied, name) {
+        Some(entry) => {
+            let by = if entry.user.is_empty() { String::new() } else { format!(" by {}", entry.user) };
+            let outcome = entry.outcome.map(|outcome| format!(" ({})", outcome.describe())).unwrap_or_default();
+            let line = format!("{}{}{}", entry.describe_age(), by, outcome);
+            let line = if entry.succeeded() { style(line) } else { style(line).red() };
+            println!("  {:<12} {}", "Applied", line);
+        }
+        None => println!("  {:<12} {}", "Applied", style("never").yellow()),
+    }
+    print_packages(&manifest);
+    println!();
+
+    Ok(())
+}
+
+/// Print when a manifest was created and last written by shard, and by whom
+fn print_modification(manifest: &Manifest) {
+    let format = |time: DateTime<Utc>| time.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string();
+    if let Some(created) = manifest.metadata.created_at {
+        println!("  {:<12} {}", "Created", format(created));
+    }
+    if let Some(modified) = manifest.metadata.modified_at {
+        let by = &manifest.metadata.modified_by;
+        let by = if by.is_empty() { String::new() } else { format!(" by {}", by) };
+        println!("  {:<12} {}{}", "Modified", format(modified), by);
+    }
+}
+
+/// Print the taps, formulae, casks and VS Code extensions of a manifest with their comments and contexts
+fn print_packages(manifest: &Manifest) {
+    let taps: Vec<String> = manifest.taps.iter().map(ToString::to_string).collect();
+    for (label, names) in [
+        ("taps", &taps),
+        ("formulae", &manifest.formulae),
+        ("casks", &manifest.casks),
+        ("vscode extensions", &manifest.vscode_extensions),
+    ] {
+        if names.is_empty() {
+            continue;
+        }
+        println!();
+        println!("  {}", style(label).bold());
+        for name in names {
+            let mut details = Vec::new();
+            if label == "formulae" && manifest.formula_build(name).describe() != "bottle" {
+                details.push(manifest.formula_build(name).describe().to_string());
+            }
+            if label == "casks" && !manifest.cask_options_for(name).is_empty() {
+                details.push(manifest.cask_options_for(name).join(" "));
+            }
+            if !manifest.contexts_for(name).is_empty() {
+                details.push(format!("context: {}", manifest.contexts_for(name).join(", ")));
+            }
+            let details = if details.is_empty() { String::new() } else { format!(" ({})", details.join(", ")) };
+            match manifest.comment_for(name) {
+                Some(comment) => println!("    {}{}  {}", name, details, style(format!("# {}", comment)).dim()),
+                None => println!("    {}{}", name, details),
+            }
+        }
+    }
+}
+
+/// Most recent apply of a shard
+fn last_apply<'a>(applied: &'a [Applied], name: &str) -> Option<&'a Applied> {
+    applied.iter().find(|entry| entry.kind == Kind::Shard && entry.name == name)
+}
+
+/// Age of the last apply, padded for the table, red if it failed
+fn describe_last_applied(entry: Option<&Applied>) -> String {
+    let Some(entry) = entry else {
+        return style(format!("{:<18}", "never")).yellow().to_string();
+    };
+    let age = format!("{:<18}", entry.describe_age());
+    if entry.succeeded() {
+        age
+    } else {
+        style(format!("{:<18}", format!("failed {}", entry.describe_age()))).red().to_string()
+    }
+}
+
+fn status_label(manager: &ShardManager, shard: &ShardInfo) -> &'static str {
+    match shard.status {
+        ShardStatus::Active if manager.is_system_shard(&shard.name) => "system",
+        ShardStatus::Active => "active",
+        ShardStatus::Disabled => "disabled",
+        ShardStatus::NotFound => "missing",
+    }
+}
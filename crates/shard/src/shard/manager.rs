@@ -1,17 +1,33 @@
 #[allow(dead_code)]
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
 use anyhow::Context;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use console::style;
+use lazy_static::lazy_static;
 use dialoguer::Confirm;
-use shellexpand;
+use sapphire_core::paths::SapphirePaths;
 use crate::utils::{
     ShardError, ShardResult,
-    log_success, log_warning, log_debug
+    log_success, log_warning, log_debug, log_plain, log_step
 };
-use crate::core::manifest::Manifest;
+use crate::core::config::{BackupRetention, ShardConfig};
+use crate::core::manifest::{Manifest, ManifestFormat};
+use crate::engine::{Action, ApplyMode, ApplyOptions, Plan, ShardEngine};
+use crate::brew::{get_client, ClientOverrides};
+use crate::reporter::ConsoleReporter;
+use crate::shard::backups::{self, Backup};
+use crate::shard::freeze;
+use crate::shard::templates;
+use crate::shard::validate::check_removed;
+
+/// Shared system layer used in managed mode, applied for every user
+///
+/// Only administrators may write it. Its shards are loaded by every user, so
+/// loading never writes them, older schemas are migrated in memory.
+pub const SYSTEM_SHARDS_DIR: &str = "/Library/Sapphire/shards";
 
 /// Status of a shard
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -45,6 +61,10 @@ pub struct ShardManager {
     disabled_dir: PathBuf,
     /// Directory where backups are stored
     backups_dir: PathBuf,
+    /// How many backups are kept after each new backup
+    backup_retention: BackupRetention,
+    /// System layer shared by all users, only set in managed mode
+    system_dir: Option<PathBuf>,
     /// Protected shard names that cannot be disabled
     protected_shards: Vec<String>,
     /// Current username for permission checks
@@ -54,13 +74,14 @@ pub struct ShardManager {
 impl ShardManager {
     /// Create a new shard manager with default paths
     pub fn new() -> ShardResult<Self> {
-        let shards_dir = shellexpand::tilde("~/.sapphire/shards").to_string();
-        let disabled_dir = shellexpand::tilde("~/.sapphire/disabled").to_string();
-        let backups_dir = shellexpand::tilde("~/.sapphire/backups").to_string();
-        
-        let shards_dir_path = PathBuf::from(&shards_dir);
-        let disabled_dir_path = PathBuf::from(&disabled_dir);
-        let backups_dir_path = PathBuf::from(&backups_dir);
+        Self::with_sapphire_paths(&SapphirePaths::resolve())
+    }
+    
+    /// Create a new shard manager for the directories below a Sapphire root
+    pub fn with_sapphire_paths(paths: &SapphirePaths) -> ShardResult<Self> {
+        let shards_dir_path = paths.shards_dir();
+        let disabled_dir_path = paths.disabled_dir();
+        let backups_dir_path = paths.backups_dir();
         
         // Get current username for permission checks
         let current_user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
@@ -76,10 +97,17 @@ impl ShardManager {
                 .with_context(|| format!("Failed to create disabled shards directory: {}", disabled_dir_path.display()))?;
         }
         
+        let config = ShardConfig::load()
+            .inspect_err(|e| log_warning(&format!("Ignoring unreadable configuration: {}", e)))
+            .unwrap_or_default();
+        
         Ok(Self {
             shards_dir: shards_dir_path,
             disabled_dir: disabled_dir_path,
             backups_dir: backups_dir_path,
+            backup_retention: config.backups,
+            // In managed mode the system layer overlays the user's own shards
+            system_dir: config.managed.then(|| PathBuf::from(SYSTEM_SHARDS_DIR)),
             protected_shards: vec!["system".to_string()], // Only protect system shard by default
             current_user,
         })
@@ -87,15 +115,15 @@ impl ShardManager {
     
     /// Create a new shard manager with custom paths
     pub fn with_paths(shards_dir: PathBuf, disabled_dir: PathBuf) -> Self {
-        let backups_dir = shellexpand::tilde("~/.sapphire/backups").to_string();
-        
         // Get current username for permission checks
         let current_user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
         
         Self {
             shards_dir,
             disabled_dir,
-            backups_dir: PathBuf::from(backups_dir),
+            backups_dir: SapphirePaths::resolve().backups_dir(),
+            backup_retention: BackupRetention::default(),
+            system_dir: None,
             protected_shards: vec!["system".to_string()],
             current_user,
         }
@@ -110,6 +138,8 @@ impl ShardManager {
             shards_dir,
             disabled_dir,
             backups_dir,
+            backup_retention: BackupRetention::default(),
+            system_dir: None,
             protected_shards: vec!["system".to_string()],
             current_user,
         }
@@ -121,6 +151,18 @@ impl ShardManager {
         self
     }
     
+    /// Set how many backups are kept per shard
+    pub fn with_backup_retention(mut self, retention: BackupRetention) -> Self {
+        self.backup_retention = retention;
+        self
+    }
+    
+    /// Overlay a shared system layer, or `None` to only use the user's shards
+    pub fn with_system_dir(mut self, system_dir: Option<PathBuf>) -> Self {
+        self.system_dir = system_dir;
+        self
+    }
+    
     /// Set protected shards that cannot be disabled
     pub fn with_protected_shards(mut self, shards: Vec<String>) -> Self {
         self.protected_shards = shards;
@@ -130,7 +172,7 @@ impl ShardManager {
     /// Check if a shard is protected
     fn is_protected(&self, name: &str) -> ShardResult<bool> {
         // System protected shards can't be modified
-        if self.protected_shards.contains(&name.to_string()) {
+        if self.protected_shards.contains(&name.to_string()) || self.is_system_shard(name) {
             return Ok(true);
         }
         
@@ -154,33 +196,87 @@ impl ShardManager {
         Ok(false)
     }
     
+    /// Load a shard from the active or disabled directory
+    pub fn load_shard_manifest(&self, name: &str) -> Option<Manifest> {
+        [self.get_shard_path(name), self.get_disabled_shard_path(name)]
+            .into_iter()
+            .find(|path| path.exists())
+            .and_then(|path| Manifest::from_file(path).ok())
+    }
+    
     /// Check if current user can modify a shard
     fn can_modify_shard(&self, name: &str) -> ShardResult<bool> {
-        let shard_path = self.get_shard_path(name);
-        if shard_path.exists() {
-            if let Ok(manifest) = Manifest::from_file(shard_path.to_str().unwrap_or_default()) {
-                return Ok(manifest.can_modify(&self.current_user));
+        // Default to true - if shard doesn't exist yet, we can create it
+        Ok(self.load_shard_manifest(name)
+            .is_none_or(|manifest| manifest.can_modify(&self.current_user, &user_groups(&self.current_user))))
+    }
+    
+    /// Check if the current user owns a shard, ignoring its protection
+    fn is_owner(&self, name: &str) -> bool {
+        self.load_shard_manifest(name)
+            .is_none_or(|manifest| manifest.is_owned_by(&self.current_user, &user_groups(&self.current_user)))
+    }
+    
+    /// Error for a shard the current user is not allowed to modify
+    fn permission_error(&self, name: &str) -> ShardError {
+        match self.load_shard_manifest(name) {
+            Some(manifest) if !manifest.is_protected() && !manifest.metadata.owner.is_empty() => {
+                let kind = if manifest.metadata.shared { "group" } else { "user" };
+                ShardError::PermissionDenied(format!("shard '{}' is owned by {} {}", name, kind, manifest.metadata.owner))
             }
+            _ => ShardError::Protected(name.to_string()),
         }
-        
-        // Check disabled path as well
-        let disabled_path = self.get_disabled_shard_path(name);
-        if disabled_path.exists() {
-            if let Ok(manifest) = Manifest::from_file(disabled_path.to_str().unwrap_or_default()) {
-                return Ok(manifest.can_modify(&self.current_user));
+    }
+    
+    /// The shared system layer, if running in managed mode
+    pub fn system_dir(&self) -> Option<&Path> {
+        self.system_dir.as_deref()
+    }
+    
+    /// Check if a shard lives in the shared system layer
+    pub fn is_system_shard(&self, name: &str) -> bool {
+        self.system_dir.as_ref()
+            .is_some_and(|dir| find_shard_file(dir, name).is_some())
+    }
+    
+    /// Check if the current user may modify the system layer
+    pub fn current_user_is_admin(&self) -> bool {
+        is_admin(&self.current_user)
+    }
+    
+    /// Make sure the current user may change the packages of a shard
+    ///
+    /// Administrators may edit shards in the system layer. Other shards can be
+    /// edited by their owners unless protected, and by their `allowed_users`.
+    pub fn ensure_editable(&self, name: &str) -> ShardResult<()> {
+        if self.is_system_shard(name) {
+            if self.current_user_is_admin() {
+                return Ok(());
             }
+            log_warning(&format!("Shard '{}' is managed by an administrator in {}", name, SYSTEM_SHARDS_DIR));
+            return Err(ShardError::Protected(name.to_string()));
         }
         
-        // Default to true - if shard doesn't exist yet, we can create it
-        Ok(true)
+        if self.protected_shards.contains(&name.to_string()) {
+            return Err(ShardError::Protected(name.to_string()));
+        }
+        
+        if !self.can_modify_shard(name)? {
+            return Err(self.permission_error(name));
+        }
+        
+        Ok(())
     }
     
     /// Create a backup of a shard before modification
-    fn backup_shard(&self, name: &str) -> ShardResult<PathBuf> {
-        let shard_path = self.get_shard_path(name);
-        if !shard_path.exists() {
+    ///
+    /// Backups exceeding the retention policy are deleted afterwards.
+    pub fn backup_shard(&self, name: &str) -> ShardResult<PathBuf> {
+        let Some(shard_path) = [self.get_shard_path(name), self.get_disabled_shard_path(name)]
+            .into_iter()
+            .find(|path| path.exists()) else {
             return Err(ShardError::NotFound(name.to_string()));
-        }
+        };
         
         // Create backup directory if it doesn't exist
         fs::create_dir_all(&self.backups_dir)
@@ -191,7 +287,9 @@ impl ShardManager {
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs();
-        let backup_path = self.backups_dir.join(format!("{}_backup_{}.toml", name, timestamp));
+        // Keep the extension so the backup restores in the same format
+        let extension = shard_path.extension().and_then(|ext| ext.to_str()).unwrap_or("toml");
+        let backup_path = self.backups_dir.join(format!("{}_backup_{}.{}", name, timestamp, extension));
         
         // Copy the shard file to the backup location
         fs::copy(&shard_path, &backup_path)
@@ -199,11 +297,83 @@ impl ShardManager {
         
         log_debug(&format!("Created backup of shard '{}' at '{}'", name, backup_path.display()));
         
+        if let Err(e) = backups::enforce_retention(&self.backups_dir, Some(name), &self.backup_retention) {
+            log_warning(&format!("Failed to delete expired backups of shard '{}': {}", name, e));
+        }
+        
         Ok(backup_path)
     }
     
-    /// Create a new shard
-    pub fn grow_shard(&self, name: &str, description: Option<&str>) -> ShardResult<()> {
+    /// Directory of the enabled user shards
+    pub fn shards_dir(&self) -> &Path {
+        &self.shards_dir
+    }
+    
+    /// Directory where backups are stored
+    pub fn backups_dir(&self) -> &Path {
+        &self.backups_dir
+    }
+    
+    /// Delete backups exceeding the retention policy, for one or all shards
+    pub fn prune_backups(&self, name: Option<&str>) -> ShardResult<Vec<Backup>> {
+        backups::enforce_retention(&self.backups_dir, name, &self.backup_retention)
+    }
+    
+    /// Replace a shard with one of its backups
+    ///
+    /// The current version is backed up first, so a restore can be undone.
+    /// Shards that no longer exist are restored as active shards.
+    pub fn restore_backup(&self, backup: &Backup) -> ShardResult<()> {
+        let name = &backup.shard;
+        if !self.is_valid_shard_name(name) {
+            return Err(ShardError::InvalidName(name.to_string()));
+        }
+        
+        // Make sure the backup is a valid shard before replacing anything
+        Manifest::from_file(&backup.path)
+            .with_context(|| format!("Backup is not a valid shard: {}", backup.path.display()))?;
+        
+        // Read it now, retention may delete it when the current version is backed up
+        let content = fs::read(&backup.path)
+            .with_context(|| format!("Failed to read backup: {}", backup.path.display()))?;
+        
+        // The restored file takes the format of the backup
+        let file_name = backup.path.extension()
+            .map(|ext| format!("{}.{}", name, ext.to_string_lossy()))
+            .unwrap_or_else(|| format!("{}.toml", name));
+        let (current, target) = match self.get_shard_status(name) {
+            ShardStatus::NotFound => (None, self.shards_dir.join(&file_name)),
+            status => {
+                self.ensure_editable(name)?;
+                self.backup_shard(name)
+                    .with_context(|| format!("Failed to backup shard before restoring: {}", name))?;
+                
+                let current = if status == ShardStatus::Disabled {
+                    self.get_disabled_shard_path(name)
+                } else {
+                    self.get_shard_path(name)
+                };
+                let target = current.with_file_name(&file_name);
+                (Some(current), target)
+            }
+        };
+        
+        fs::write(&target, content)
+            .with_context(|| format!("Failed to restore shard file: {}", target.display()))?;
+        
+        // Don't leave the shard behind twice in different formats
+        if let Some(current) = current && current != target {
+            fs::remove_file(&current)
+                .with_context(|| format!("Failed to remove shard file: {}", current.display()))?;
+        }
+        
+        log_success(&format!("Restored shard {} from backup {} ({})", style(name).bold(), backup.timestamp,
+            backup.created().format("%Y-%m-%d %H:%M")));
+        Ok(())
+    }
+    
+    /// Create a new shard, optionally populated from a template
+    pub fn grow_shard(&self, name: &str, description: Option<&str>, template: Option<&str>) -> ShardResult<()> {
         // Validate shard name for safety
         if !self.is_valid_shard_name(name) {
             return Err(ShardError::InvalidName(name.to_string()));
@@ -223,24 +393,65 @@ impl ShardManager {
         // Create default manifest
         let mut manifest = Manifest::new();
         
+        // Start from the template's packages
+        let mut default_description = "Custom shard".to_string();
+        if let Some(template_name) = template {
+            let template = templates::load_template(template_name)?;
+            manifest.formulae = template.formulae;
+            manifest.casks = template.casks;
+            manifest.taps = template.taps;
+            manifest.vscode_extensions = template.vscode_extensions;
+            if !template.metadata.description.is_empty() {
+                default_description = template.metadata.description;
+            }
+        }
+        
         // Add metadata
         manifest.metadata.name = name.to_string();
-        manifest.metadata.description = description.unwrap_or("Custom shard").to_string();
+        manifest.metadata.description = description.map(str::to_string).unwrap_or(default_description);
         
         // Set permissions based on current user
         manifest.metadata.owner = self.current_user.clone();
         
         // Write manifest to file
+        manifest.update_modification_info();
         manifest.to_file(shard_path.to_str().unwrap_or_default())
             .with_context(|| format!("Failed to create shard file: {}", shard_path.display()))?;
         
-        log_success(&format!("Created new shard: {}", style(name).bold()));
+        match template {
+            Some(template_name) => log_success(&format!("Created new shard {} from template '{}' ({} formulae, {} casks)",
+                style(name).bold(), template_name, manifest.formulae.len(), manifest.casks.len())),
+            None => log_success(&format!("Created new shard: {}", style(name).bold())),
+        }
         
         Ok(())
     }
     
     /// Delete a shard permanently
     pub fn shatter_shard(&self, name: &str, force: bool) -> ShardResult<()> {
+        self.ensure_shatterable(name, force)?;
+        
+        // If not forced, let the user confirm
+        if !force {
+            let confirm = Confirm::new()
+                .with_prompt(format!("Are you sure you want to permanently delete the shard '{}'?", name))
+                .default(false)
+                .interact()
+                .with_context(|| "Failed to get user confirmation")?;
+                
+            if !confirm {
+                log_warning("Shard deletion cancelled");
+                return Ok(());
+            }
+        }
+        
+        self.delete_shard(name)
+    }
+    
+    /// Check that the current user may delete a shard
+    ///
+    /// User-protected shards may only be deleted with `force`.
+    pub fn ensure_shatterable(&self, name: &str, force: bool) -> ShardResult<()> {
         // Validate shard name for safety
         if !self.is_valid_shard_name(name) {
             return Err(ShardError::InvalidName(name.to_string()));
@@ -251,6 +462,16 @@ impl ShardManager {
             return Err(ShardError::NotFound(name.to_string()));
         }
         
+        // Only administrators may delete from the system layer
+        if self.is_system_shard(name) && !self.current_user_is_admin() {
+            return Err(ShardError::Protected(name.to_string()));
+        }
+        
+        // Only owners may delete a shard, even with force
+        if !self.is_system_shard(name) && !self.is_owner(name) {
+            return Err(self.permission_error(name));
+        }
+        
         // Check if shard is protected
         if self.is_protected(name)? {
             // If it's a system shard, hard block deletion
@@ -267,20 +488,11 @@ impl ShardManager {
             log_warning(&format!("Deleting protected shard: {} (forced)", style(name).bold()));
         }
         
-        // If not forced, let the user confirm
-        if !force {
-            let confirm = Confirm::new()
-                .with_prompt(format!("Are you sure you want to permanently delete the shard '{}'?", name))
-                .default(false)
-                .interact()
-                .with_context(|| "Failed to get user confirmation")?;
-                
-            if !confirm {
-                log_warning("Shard deletion cancelled");
-                return Ok(());
-            }
-        }
-        
+        Ok(())
+    }
+    
+    /// Back up and delete a shard without any checks or confirmation
+    fn delete_shard(&self, name: &str) -> ShardResult<()> {
         // First create a backup
         let backup_path = self.backup_shard(name)
             .with_context(|| format!("Failed to backup shard before deletion: {}", name))?;
@@ -303,6 +515,93 @@ impl ShardManager {
         Ok(())
     }
     
+    /// Rename a shard, keeping its location, format, owner and protection
+    ///
+    /// An existing shard named `new` is only replaced with `force`.
+    pub fn rename_shard(&self, old: &str, new: &str, force: bool) -> ShardResult<()> {
+        self.ensure_editable(old)?;
+        
+        // Renaming removes the old shard, which only its owner may do
+        if !self.is_system_shard(old) && !self.is_owner(old) {
+            return Err(self.permission_error(old));
+        }
+        
+        let old_path = self.get_shard_info(old)?.path;
+        self.backup_shard(old)
+            .with_context(|| format!("Failed to backup shard before renaming: {}", old))?;
+        
+        let new_path = self.write_shard_as(old, new, force)?;
+        if new_path != old_path {
+            fs::remove_file(&old_path)
+                .with_context(|| format!("Failed to remove shard file: {}", old_path.display()))?;
+        }
+        
+        log_success(&format!("Renamed shard {} to {}", style(old).bold(), style(new).bold()));
+        Ok(())
+    }
+    
+    /// Copy a shard under a new name, keeping its format, owner and protection
+    ///
+    /// The copy is created next to the source, copies of system shards made
+    /// by other users go to the user's shards. An existing shard named `dest`
+    /// is only replaced with `force`.
+    pub fn copy_shard(&self, source: &str, dest: &str, force: bool) -> ShardResult<()> {
+        let path = self.write_shard_as(source, dest, force)?;
+        log_success(&format!("Copied shard {} to {} ({})", style(source).bold(), style(dest).bold(),
+            path.display()));
+        Ok(())
+    }
+    
+    /// Write the manifest of `source` as shard `dest`, returning the new path
+    fn write_shard_as(&self, source: &str, dest: &str, force: bool) -> ShardResult<PathBuf> {
+        for name in [source, dest] {
+            if !self.is_valid_shard_name(name) {
+                return Err(ShardError::InvalidName(name.to_string()));
+            }
+        }
+        if source == dest {
+            return Err(ShardError::ValidationError(format!("Source and destination are both '{}'", source)));
+        }
+        
+        let source_path = self.get_shard_info(source)?.path;
+        let mut manifest = Manifest::from_file(&source_path)
+            .with_context(|| format!("Failed to load shard: {}", source))?;
+        manifest.metadata.name = dest.to_string();
+        manifest.update_modification_info();
+        
+        let dir = match source_path.parent() {
+            Some(_) if self.is_system_shard(source) && !self.current_user_is_admin() => self.shards_dir.clone(),
+            Some(dir) => dir.to_path_buf(),
+            None => self.shards_dir.clone(),
+        };
+        let extension = source_path.extension().and_then(|ext| ext.to_str()).unwrap_or("toml");
+        let dest_path = dir.join(format!("{}.{}", dest, extension));
+        
+        // The shard being replaced, if any
+        let replaced = if self.shard_exists(dest) {
+            if !force {
+                return Err(ShardError::AlreadyExists(dest.to_string()));
+            }
+            self.ensure_editable(dest)?;
+            self.backup_shard(dest)
+                .with_context(|| format!("Failed to backup shard before replacing it: {}", dest))?;
+            log_warning(&format!("Replacing existing shard: {} (forced)", style(dest).bold()));
+            Some(self.get_shard_info(dest)?.path)
+        } else {
+            None
+        };
+        
+        manifest.to_file(&dest_path)
+            .with_context(|| format!("Failed to write shard file: {}", dest_path.display()))?;
+        
+        if let Some(replaced) = replaced && replaced != dest_path {
+            fs::remove_file(&replaced)
+                .with_context(|| format!("Failed to remove shard file: {}", replaced.display()))?;
+        }
+        
+        Ok(dest_path)
+    }
+    
     /// Disable a shard without deleting it
     pub fn disable_shard(&self, name: &str) -> ShardResult<()> {
         // Validate shard name for safety
@@ -310,11 +609,15 @@ impl ShardManager {
             return Err(ShardError::InvalidName(name.to_string()));
         }
         
-        // Check if the shard is protected and user doesn't have permission
-        if self.is_protected(name)? && !self.can_modify_shard(name)? {
+        // System layer shards apply to every user and can't be disabled per user
+        if self.is_system_shard(name) {
+            log_warning(&format!("Shard '{}' is part of the system layer in {} and applies to every user", name, SYSTEM_SHARDS_DIR));
             return Err(ShardError::Protected(name.to_string()));
         }
         
+        // Check if the shard is protected or owned by someone else
+        self.ensure_editable(name)?;
+        
         // Get source and destination paths
         let source_path = self.get_shard_path(name);
         
@@ -339,7 +642,7 @@ impl ShardManager {
         fs::create_dir_all(&self.disabled_dir)
             .with_context(|| "Failed to create disabled shards directory")?;
         
-        let dest_path = self.get_disabled_shard_path(name);
+        let dest_path = self.disabled_dir.join(source_path.file_name().unwrap_or_default());
         
         // Move the file to disabled directory
         fs::rename(&source_path, &dest_path)
@@ -375,30 +678,89 @@ impl ShardManager {
         fs::create_dir_all(&self.shards_dir)
             .with_context(|| "Failed to create shards directory")?;
         
-        let dest_path = self.get_shard_path(name);
+        let dest_path = self.shards_dir.join(source_path.file_name().unwrap_or_default());
         
         // Read the manifest to update last modified information
-        if let Ok(mut manifest) = Manifest::from_file(source_path.to_str().unwrap_or_default()) {
-            // Update modification info
-            manifest.update_modification_info();
+        match Manifest::from_file(source_path.to_str().unwrap_or_default()) {
+            Ok(mut manifest) => {
+                // Packages may have been renamed or removed while the shard was disabled
+                for problem in check_removed(&get_client(ClientOverrides::default()), &manifest) {
+                    log_warning(&format!("Shard '{}': {}", name, problem));
+                }
             
-            // Write the updated manifest directly to the destination
-            manifest.to_file(dest_path.to_str().unwrap_or_default())
-                .with_context(|| format!("Failed to write updated manifest when enabling shard: {}", name))?;
+                // Update modification info
+                manifest.update_modification_info();
             
-            // Delete the source file
-            fs::remove_file(&source_path)
-                .with_context(|| format!("Failed to remove disabled shard file after enabling: {}", name))?;
-        } else {
-            // Fall back to simple file move if manifest can't be read
-            fs::rename(&source_path, &dest_path)
-                .with_context(|| format!("Failed to enable shard: {}", name))?;
+                // Write the updated manifest directly to the destination
+                manifest.to_file(dest_path.to_str().unwrap_or_default())
+                    .with_context(|| format!("Failed to write updated manifest when enabling shard: {}", name))?;
+            
+                // Delete the source file
+                fs::remove_file(&source_path)
+                    .with_context(|| format!("Failed to remove disabled shard file after enabling: {}", name))?;
+            }
+            Err(e) => {
+                // Fall back to simple file move if manifest can't be read
+                log_warning(&format!("Shard '{}' cannot be loaded and will fail to apply until fixed: {}", name, e));
+                fs::rename(&source_path, &dest_path)
+                    .with_context(|| format!("Failed to enable shard: {}", name))?;
+            }
         }
         
         log_success(&format!("Enabled shard: {}", style(name).bold()));
         Ok(())
     }
     
+    /// Change the owner of a shard
+    ///
+    /// With `shared`, `owner` is a group and all of its members may modify the
+    /// shard. Only the current owner or an administrator can change ownership.
+    pub fn chown_shard(&self, name: &str, owner: &str, shared: bool) -> ShardResult<()> {
+        // Validate shard name for safety
+        if !self.is_valid_shard_name(name) {
+            return Err(ShardError::InvalidName(name.to_string()));
+        }
+        
+        if owner.is_empty() || !owner.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+            return Err(ShardError::ValidationError(format!("Invalid owner: {}", owner)));
+        }
+        
+        let path = match self.get_shard_status(name) {
+            ShardStatus::Active => self.get_shard_path(name),
+            ShardStatus::Disabled => self.get_disabled_shard_path(name),
+            ShardStatus::NotFound => return Err(ShardError::NotFound(name.to_string())),
+        };
+        
+        let is_admin = self.current_user_is_admin();
+        if self.is_system_shard(name) && !is_admin {
+            return Err(ShardError::Protected(name.to_string()));
+        }
+        
+        let mut manifest = Manifest::from_file(&path)?;
+        if !is_admin && !manifest.is_owned_by(&self.current_user, &user_groups(&self.current_user)) {
+            return Err(self.permission_error(name));
+        }
+        
+        if !shared && !user_exists(owner) {
+            return Err(ShardError::ValidationError(format!("Unknown user: {}", owner)));
+        }
+        
+        if path == self.get_shard_path(name) {
+            self.backup_shard(name)
+                .with_context(|| format!("Failed to backup shard before changing its owner: {}", name))?;
+        }
+        
+        manifest.metadata.owner = owner.to_string();
+        manifest.metadata.shared = shared;
+        manifest.update_modification_info();
+        manifest.to_file(&path)
+            .with_context(|| format!("Failed to write shard file: {}", path.display()))?;
+        
+        let owner_desc = if shared { format!("group {}", owner) } else { owner.to_string() };
+        log_success(&format!("Changed owner of shard {} to {}", style(name).bold(), owner_desc));
+        Ok(())
+    }
+    
     /// Get the status of a shard
     pub fn get_shard_status(&self, name: &str) -> ShardStatus {
         if !self.is_valid_shard_name(name) {
@@ -506,33 +868,27 @@ impl ShardManager {
             shards_dir: self.shards_dir.clone(),
             disabled_dir: self.disabled_dir.clone(),
             backups_dir: self.backups_dir.clone(),
+            backup_retention: self.backup_retention,
+            system_dir: self.system_dir.clone(),
             protected_shards: self.protected_shards.clone(),
             current_user: self.current_user.clone(),
         }
     }
     
     /// List all available shards
+    ///
+    /// In managed mode this includes the shards of the system layer. A user
+    /// shard with the same name as a system shard is shadowed by it.
     pub fn list_shards(&self) -> ShardResult<Vec<String>> {
-        // Create directory if it doesn't exist
-        if !self.shards_dir.exists() {
-            return Ok(Vec::new());
-        }
-        
-        let entries = fs::read_dir(&self.shards_dir)
+        let mut shards = shard_names_in(&self.shards_dir)
             .with_context(|| "Failed to read shards directory")?;
-            
-        let mut shards = Vec::new();
         
-        for entry in entries {
-            let entry = entry.with_context(|| "Failed to read directory entry")?;
-            let path = entry.path();
-            
-            // Only include .toml files and skip directories
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "toml") {
-                if let Some(name) = path.file_stem() {
-                    if let Some(name_str) = name.to_str() {
-                        shards.push(name_str.to_string());
-                    }
+        if let Some(system_dir) = &self.system_dir {
+            for name in shard_names_in(system_dir).with_context(|| "Failed to read system shards directory")? {
+                if shards.contains(&name) {
+                    log_debug(&format!("User shard '{}' is shadowed by the system layer", name));
+                } else {
+                    shards.push(name);
                 }
             }
         }
@@ -540,32 +896,41 @@ impl ShardManager {
         Ok(shards)
     }
     
-    /// List all disabled shards
-    pub fn list_disabled_shards(&self) -> ShardResult<Vec<String>> {
-        // Create directory if it doesn't exist
-        if !self.disabled_dir.exists() {
+    /// List user shards hidden by a system layer shard of the same name
+    pub fn list_shadowed_shards(&self) -> ShardResult<Vec<String>> {
+        let Some(system_dir) = &self.system_dir else {
             return Ok(Vec::new());
-        }
-        
-        let entries = fs::read_dir(&self.disabled_dir)
-            .with_context(|| "Failed to read disabled shards directory")?;
-            
-        let mut shards = Vec::new();
+        };
         
-        for entry in entries {
-            let entry = entry.with_context(|| "Failed to read directory entry")?;
-            let path = entry.path();
-            
-            // Only include .toml files
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "toml") {
-                if let Some(name) = path.file_stem() {
-                    if let Some(name_str) = name.to_str() {
-                        shards.push(name_str.to_string());
-                    }
-                }
+        let system_shards = shard_names_in(system_dir)?;
+        Ok(shard_names_in(&self.shards_dir)?
+            .into_iter()
+            .filter(|name| system_shards.contains(name))
+            .collect())
+    }
+    
+    /// Load the manifests of all active shards, sorted by shard name
+    ///
+    /// Shards that fail to parse are skipped with a warning.
+    pub fn load_active_manifests(&self) -> ShardResult<Vec<(String, Manifest)>> {
+        let mut names = self.list_shards()?;
+        names.sort();
+
+        let mut manifests = Vec::new();
+        for name in names {
+            match Manifest::from_file(self.get_shard_path(&name)) {
+                Ok(manifest) => manifests.push((name, manifest)),
+                Err(e) => log_warning(&format!("Skipping invalid shard {}: {}", name, e)),
             }
         }
-        
+
+        Ok(manifests)
+    }
+
+    /// List all disabled shards
+    pub fn list_disabled_shards(&self) -> ShardResult<Vec<String>> {
+        let shards = shard_names_in(&self.disabled_dir)
+            .with_context(|| "Failed to read disabled shards directory")?;
         Ok(shards)
     }
     
@@ -575,32 +940,209 @@ impl ShardManager {
     }
     
     /// Get the full path to a shard by name
-    fn get_shard_path(&self, name: &str) -> PathBuf {
-        self.shards_dir.join(format!("{}.toml", name))
+    ///
+    /// Shards in the system layer take precedence over user shards. A shard
+    /// that doesn't exist yet gets a TOML path.
+    pub fn get_shard_path(&self, name: &str) -> PathBuf {
+        if let Some(system_dir) = &self.system_dir
+            && let Some(path) = find_shard_file(system_dir, name) {
+            return path;
+        }
+        shard_file_in(&self.shards_dir, name)
     }
     
     /// Get the full path to a disabled shard by name
-    fn get_disabled_shard_path(&self, name: &str) -> PathBuf {
-        self.disabled_dir.join(format!("{}.toml", name))
+    pub fn get_disabled_shard_path(&self, name: &str) -> PathBuf {
+        shard_file_in(&self.disabled_dir, name)
+    }
+}
+
+/// Names of the shard files in a directory, empty if it doesn't exist
+fn shard_names_in(dir: &Path) -> std::io::Result<Vec<String>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
     }
+    
+    let mut shards = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        
+        // Only include manifest files and skip directories
+        if path.is_file() && ManifestFormat::is_manifest(&path) {
+            if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                shards.push(name.to_string());
+            }
+        }
+    }
+    
+    // A shard saved in several formats is listed once
+    shards.sort();
+    shards.dedup();
+    Ok(shards)
+}
+
+/// Existing file of a shard in a directory, in any manifest format
+///
+/// If a shard exists in several formats, the first extension in
+/// [`ManifestFormat::EXTENSIONS`] wins.
+fn find_shard_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    ManifestFormat::EXTENSIONS.iter()
+        .map(|ext| dir.join(format!("{}.{}", name, ext)))
+        .find(|path| path.is_file())
+}
+
+/// File of a shard in a directory, a new TOML file if it doesn't exist
+fn shard_file_in(dir: &Path, name: &str) -> PathBuf {
+    find_shard_file(dir, name).unwrap_or_else(|| dir.join(format!("{}.toml", name)))
+}
+
+lazy_static! {
+    /// Groups of every user looked up so far, they do not change during a run
+    static ref USER_GROUPS: Mutex<HashMap<String, Vec<String>>> = Mutex::new(HashMap::new());
+}
+
+/// Names of the groups a user belongs to, empty if the user is unknown
+///
+/// `id` only runs on the first lookup of each user.
+fn user_groups(username: &str) -> Vec<String> {
+    let mut cache = USER_GROUPS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    cache.entry(username.to_string())
+        .or_insert_with(|| match std::process::Command::new("id").args(["-Gn", username]).output() {
+            Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+            _ => Vec::new(),
+        })
+        .clone()
+}
+
+/// Check if a user is root or a member of the macOS `admin` group
+fn is_admin(username: &str) -> bool {
+    username == "root" || user_groups(username).iter().any(|group| group == "admin")
+}
+
+/// Check if a local user account exists
+fn user_exists(username: &str) -> bool {
+    std::process::Command::new("id")
+        .arg(username)
+        .output()
+        .is_ok_and(|output| output.status.success())
 }
 
 /// Create a new shard
-pub fn grow_shard(name: &str, description: Option<&str>) -> ShardResult<()> {
+pub fn grow_shard(name: &str, description: Option<&str>, template: Option<&str>) -> ShardResult<()> {
     let manager = ShardManager::new()?;
-    manager.grow_shard(name, description)
+    manager.grow_shard(name, description, template)
 }
 
 /// Delete a shard
-pub fn shatter_shard(name: &str, force: bool) -> ShardResult<()> {
+///
+/// With `purge`, the installed packages no other enabled shard declares are
+/// uninstalled as well, after confirming the list unless `force` is set.
+pub fn shatter_shard(name: &str, force: bool, purge: bool, overrides: ClientOverrides) -> ShardResult<()> {
     let manager = ShardManager::new()?;
-    manager.shatter_shard(name, force)
+    if !purge {
+        return manager.shatter_shard(name, force);
+    }
+
+    manager.ensure_shatterable(name, force)?;
+    let (engine, plan) = plan_exclusive_uninstalls(&manager, name, overrides)?;
+
+    if !force {
+        let prompt = match plan.actions.len() {
+            0 => format!("Are you sure you want to permanently delete the shard '{}'?", name),
+            count => format!("Delete the shard '{}' and uninstall these {} package(s)?", name, count),
+        };
+        let confirm = Confirm::new()
+            .with_prompt(prompt)
+            .default(false)
+            .interact()
+            .with_context(|| "Failed to get user confirmation")?;
+        if !confirm {
+            log_warning("Shard deletion cancelled");
+            return Ok(());
+        }
+    }
+
+    manager.delete_shard(name)?;
+    uninstall_exclusive(&engine, &plan, name)
+}
+
+/// Plan uninstalling the installed packages of a shard no other enabled shard declares
+///
+/// Synchronizing the other shards would uninstall exactly these, sparing
+/// dependencies, critical and MDM-managed packages. The packages are listed.
+fn plan_exclusive_uninstalls(manager: &ShardManager, name: &str, overrides: ClientOverrides) -> ShardResult<(ShardEngine, Plan)> {
+    let manifest = manager.load_shard_manifest(name)
+        .ok_or_else(|| ShardError::NotFound(name.to_string()))?;
+    let declared: HashSet<&str> = manifest.formulae.iter().chain(&manifest.casks).map(String::as_str).collect();
+
+    let others = manager.load_active_manifests()?
+        .into_iter()
+        .filter(|(shard, _)| shard != name)
+        .collect();
+    let engine = ShardEngine::new(overrides)?.with_reporter(ConsoleReporter);
+    let mut plan = engine.plan_manifests(others, ApplyMode::Synchronize)?;
+    plan.actions.retain(|action| matches!(action, Action::Uninstall { .. }) && declared.contains(action.name()));
+    // Orphans were computed for every undeclared package, not just this shard's
+    plan.orphans.clear();
+
+    if plan.is_empty() {
+        log_step(&format!("No installed package is declared only by shard {}", style(name).bold()));
+    } else {
+        log_step(&format!("Packages declared only by shard {}:", style(name).bold()));
+        for action in &plan.actions {
+            log_plain(&format!("  {} {}", style("-").red(), action));
+        }
+    }
+    Ok((engine, plan))
+}
+
+/// Run a plan of [`plan_exclusive_uninstalls`]
+fn uninstall_exclusive(engine: &ShardEngine, plan: &Plan, name: &str) -> ShardResult<()> {
+    if plan.is_empty() {
+        return Ok(());
+    }
+
+    let report = engine.apply(plan, &ApplyOptions::default())?;
+    if !report.is_success() {
+        return Err(ShardError::PackageError(format!("{} of {} package(s) could not be uninstalled",
+            report.failed.len(), plan.actions.len())));
+    }
+    log_success(&format!("Uninstalled {} package(s) of shard {}", report.succeeded.len(), style(name).bold()));
+    Ok(())
+}
+
+/// Rename a shard
+pub fn rename_shard(old: &str, new: &str, force: bool) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    manager.rename_shard(old, new, force)
+}
+
+/// Copy a shard under a new name
+pub fn copy_shard(source: &str, dest: &str, force: bool) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    manager.copy_shard(source, dest, force)
 }
 
 /// Disable a shard without deleting it
-pub fn disable_shard(name: &str) -> ShardResult<()> {
+///
+/// With `apply`, the installed packages no remaining enabled shard declares
+/// are uninstalled right away instead of by the next `apply all`.
+pub fn disable_shard(name: &str, apply: bool, overrides: ClientOverrides) -> ShardResult<()> {
     let manager = ShardManager::new()?;
-    manager.disable_shard(name)
+    manager.disable_shard(name)?;
+    if !apply {
+        return Ok(());
+    }
+    if let Some(state) = freeze::freeze_state()? {
+        log_warning(&format!("Shard is {}, not uninstalling the packages of {}. Run `shard thaw` to resume", state.describe(), name));
+        return Ok(());
+    }
+
+    let (engine, plan) = plan_exclusive_uninstalls(&manager, name, overrides)?;
+    uninstall_exclusive(&engine, &plan, name)
 }
 
 /// Enable a previously disabled shard
@@ -609,6 +1151,12 @@ pub fn enable_shard(name: &str) -> ShardResult<()> {
     manager.enable_shard(name)
 }
 
+/// Change the owner of a shard
+pub fn chown_shard(name: &str, owner: &str, shared: bool) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    manager.chown_shard(name, owner, shared)
+}
+
 /// Check if a shard is protected
 pub fn is_protected_shard(name: &str) -> ShardResult<bool> {
     let manager = ShardManager::new()?;
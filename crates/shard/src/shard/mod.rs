@@ -1,10 +1,52 @@
 pub mod apply;
+pub mod apps;
+pub mod audit;
+pub mod backups;
+pub mod complete;
+pub mod context;
+pub mod dedupe;
 pub mod diff;
+pub mod doctor;
+pub mod edit;
+pub mod export;
+pub mod freeze;
 pub mod init;
+pub mod interactive;
+pub mod list;
 pub mod manager;
+pub mod outdated;
+pub mod passthrough;
+pub mod profile;
+pub mod renames;
+pub mod report;
+pub mod shellhook;
+pub mod size;
+pub mod snapshot;
+pub mod state;
+pub mod suggestions;
+pub mod templates;
+pub mod update;
+pub mod upgrade;
+pub mod validate;
 
 // Re-export common functions for convenience
 pub use apply::{apply, apply_all_enabled_shards};
+pub use audit::audit;
+pub use backups::backups;
+pub use dedupe::dedupe;
 pub use diff::diff;
+pub use doctor::doctor;
+pub use edit::edit;
+pub use export::export;
+pub use freeze::{freeze, thaw};
 pub use init::init_shards;
-pub use manager::{disable_shard, enable_shard, grow_shard, shatter_shard, is_protected_shard};
+pub use list::{info, list};
+pub use manager::{chown_shard, copy_shard, disable_shard, enable_shard, grow_shard, rename_shard, shatter_shard, is_protected_shard};
+pub use outdated::outdated;
+pub use renames::fix_renames;
+pub use report::report;
+pub use size::size;
+pub use snapshot::snapshot;
+pub use update::{refresh, UpdatePolicy};
+pub use upgrade::upgrade;
+pub use validate::validate;
@@ -0,0 +1,140 @@
+use std::collections::{BTreeMap, HashSet};
+use console::style;
+use serde::Serialize;
+use crate::brew::{get_client, ClientOverrides, OutdatedPackage};
+use crate::shard::manager::ShardManager;
+use crate::utils::{Glyph, ShardResult, ShardError, ResultExt, log_step, log_success};
+
+/// A managed package with an available upgrade
+#[derive(Debug, Clone, Serialize)]
+pub struct OutdatedEntry {
+    /// Package name
+    pub name: String,
+    /// Package type ("formula" or "cask")
+    pub package_type: String,
+    /// Currently installed version
+    pub installed_version: String,
+    /// Latest available version
+    pub latest_version: String,
+    /// Size of the upgrade ("major", "minor", "patch", "revision", ...)
+    pub change: String,
+    /// Whether the formula is pinned with `brew pin`
+    pub pinned: bool,
+    /// Whether the cask auto-updates or uses `version :latest`, so it is only
+    /// reported with `--greedy`
+    pub greedy_only: bool,
+    /// Shards that declare this package
+    pub shards: Vec<String>,
+}
+
+/// List managed packages that have an upgrade available
+///
+/// Only packages declared in an enabled shard are reported. Pass a shard name
+/// to restrict the report to that shard, or `None`/"all" for every enabled shard.
+pub fn outdated(shard: Option<&str>, greedy: bool, json: bool, overrides: ClientOverrides) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+
+    let manifests: Vec<_> = match shard {
+        Some(name) if !name.eq_ignore_ascii_case("all") => {
+            if !manager.shard_is_active(name) {
+                return Err(ShardError::NotFound(name.to_string()));
+            }
+            manager.load_active_manifests()?
+                .into_iter()
+                .filter(|(shard_name, _)| shard_name == name)
+                .collect()
+        }
+        _ => manager.load_active_manifests()?,
+    };
+
+    // Map every managed package to the shards declaring it
+    let mut managed_formulae: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut managed_casks: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for (shard_name, manifest) in &manifests {
+        for formula in &manifest.formulae {
+            managed_formulae.entry(formula.clone()).or_default().push(shard_name.clone());
+        }
+        for cask in &manifest.casks {
+            managed_casks.entry(cask.clone()).or_default().push(shard_name.clone());
+        }
+    }
+
+    if !json {
+        log_step("Checking for outdated packages...");
+    }
+
+    let brew_client = get_client(overrides);
+    let report = brew_client.get_outdated(greedy)?;
+    // Without --greedy brew leaves these casks out of the report entirely
+    let greedy_casks = if greedy && !report.casks.is_empty() {
+        brew_client.get_greedy_casks()?
+    } else {
+        HashSet::new()
+    };
+
+    let mut entries = Vec::new();
+    collect_entries(&report.formulae, &managed_formulae, "formula", &HashSet::new(), &mut entries);
+    collect_entries(&report.casks, &managed_casks, "cask", &greedy_casks, &mut entries);
+
+    if json {
+        let output = serde_json::to_string_pretty(&entries)
+            .with_context(|| "Failed to serialize outdated packages")?;
+        println!("{}", output);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        log_success("All managed packages are up to date");
+        return Ok(());
+    }
+
+    println!();
+    for entry in &entries {
+        let pinned = if entry.pinned { format!(" {}", style("[pinned]").yellow()) } else { String::new() };
+        let greedy_only = if entry.greedy_only { format!(" {}", style("[greedy]").cyan()) } else { String::new() };
+        let change = match entry.change.as_str() {
+            "major" => style(format!(" ({})", entry.change)).red(),
+            "minor" => style(format!(" ({})", entry.change)).yellow(),
+            _ => style(format!(" ({})", entry.change)).dim(),
+        };
+        println!("  {} ({}) {} {} {}{}{}{}  {}",
+            style(&entry.name).bold(),
+            entry.package_type,
+            style(&entry.installed_version).dim(),
+            Glyph::Arrow,
+            style(&entry.latest_version).green(),
+            change,
+            pinned,
+            greedy_only,
+            style(format!("[{}]", entry.shards.join(", "))).italic());
+    }
+    println!();
+
+    log_step(&format!("{} managed package(s) can be upgraded", entries.len()));
+    Ok(())
+}
+
+/// Keep only outdated packages that are managed by a shard
+fn collect_entries(
+    packages: &[OutdatedPackage],
+    managed: &BTreeMap<String, Vec<String>>,
+    package_type: &str,
+    greedy_only: &HashSet<String>,
+    entries: &mut Vec<OutdatedEntry>,
+) {
+    for package in packages {
+        if let Some(shards) = managed.get(&package.name) {
+            entries.push(OutdatedEntry {
+                name: package.name.clone(),
+                package_type: package_type.to_string(),
+                installed_version: package.installed_version().to_string(),
+                latest_version: package.current_version.clone(),
+                change: package.change().to_string(),
+                pinned: package.pinned,
+                greedy_only: greedy_only.contains(&package.name),
+                shards: shards.clone(),
+            });
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use crate::brew::{get_client, ClientOverrides};
+use crate::utils::{ShardError, ShardResult, log_debug, log_step, log_warning};
+use sapphire_core::history::{self, Kind, Outcome};
+
+/// Run a brew command through shard
+///
+/// Uses the configured brew with its environment and timeouts. Interactive
+/// commands like `brew edit` or `brew sh` run as long as the user needs, so
+/// only `--timeout` stops them. The run is
+/// recorded in the apply history, so one-off changes made this way show up
+/// next to the applies.
+pub fn brew(args: &[String], overrides: ClientOverrides) -> ShardResult<()> {
+    let client = get_client(overrides);
+
+    let command = format!("brew {}", args.join(" "));
+    log_step(&format!("Running {}", command));
+    let result = client.passthrough(args);
+
+    // Rejected commands never ran, there is nothing to record
+    if !matches!(result, Err(ShardError::ValidationError(_))) {
+        let outcome = Outcome { success: result.as_ref().is_ok_and(|status| status.success()), ..Outcome::default() };
+        if let Err(e) = history::record(Kind::Brew, &command, outcome) {
+            log_warning(&format!("Could not record {}: {}", command, e));
+        }
+    }
+
+    let status = result?;
+    log_debug(&format!("{} exited with {}", command, status));
+    if !status.success() {
+        return Err(ShardError::BrewError(format!("{} failed with {}", command, status)));
+    }
+    Ok(())
+}
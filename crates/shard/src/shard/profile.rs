@@ -0,0 +1,176 @@
+use std::path::PathBuf;
+use chrono::{DateTime, Local};
+use console::style;
+use serde::{Deserialize, Serialize};
+use sapphire_core::paths::SapphirePaths;
+use crate::shard::manager::ShardManager;
+use crate::utils::{ShardError, ShardResult, ResultExt, ensure_dir_exists, log_step, log_success, log_warning};
+
+/// Which user shards are enabled and disabled
+///
+/// System layer shards always apply and are not part of profiles.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub saved: DateTime<Local>,
+    #[serde(default)]
+    pub enabled: Vec<String>,
+    #[serde(default)]
+    pub disabled: Vec<String>,
+}
+
+/// Snapshot the enabled and disabled shards as a named profile
+pub fn save(name: &str) -> ShardResult<()> {
+    let path = profile_path(name)?;
+    let manager = ShardManager::new()?;
+
+    let mut enabled: Vec<String> = manager.list_shards()?
+        .into_iter()
+        .filter(|shard| !manager.is_system_shard(shard))
+        .collect();
+    let mut disabled = manager.list_disabled_shards()?;
+    enabled.sort();
+    disabled.sort();
+
+    let profile = Profile { saved: Local::now(), enabled, disabled };
+    ensure_dir_exists(&profiles_dir())?;
+    let content = toml::to_string(&profile)
+        .with_context(|| "Failed to serialize profile")?;
+    let existed = path.exists();
+    std::fs::write(&path, content)
+        .with_context(|| format!("Failed to write profile: {}", path.display()))?;
+
+    log_success(&format!("{} profile {} ({} enabled, {} disabled)",
+        if existed { "Updated" } else { "Saved" },
+        style(name).bold(), profile.enabled.len(), profile.disabled.len()));
+    Ok(())
+}
+
+/// Enable and disable shards to match a profile
+///
+/// Shards created after the profile was saved are left as they are. Run
+/// `shard apply all` afterwards to install and remove the packages.
+pub fn load(name: &str) -> ShardResult<()> {
+    let profile = read(name)?;
+    let manager = ShardManager::new()?;
+    let active = manager.list_shards()?;
+    let inactive = manager.list_disabled_shards()?;
+
+    log_step(&format!("Loading profile {}", style(name).bold()));
+    let mut changed = 0;
+    let mut failed = Vec::new();
+
+    for shard in &profile.enabled {
+        if active.contains(shard) {
+            continue;
+        }
+        if !inactive.contains(shard) {
+            log_warning(&format!("Shard '{}' of the profile no longer exists", shard));
+            continue;
+        }
+        match manager.enable_shard(shard) {
+            Ok(()) => changed += 1,
+            Err(e) => failed.push(format!("{}: {}", shard, e)),
+        }
+    }
+
+    for shard in &profile.disabled {
+        if inactive.contains(shard) {
+            continue;
+        }
+        if !active.contains(shard) {
+            log_warning(&format!("Shard '{}' of the profile no longer exists", shard));
+            continue;
+        }
+        match manager.disable_shard(shard) {
+            Ok(()) => changed += 1,
+            Err(e) => failed.push(format!("{}: {}", shard, e)),
+        }
+    }
+
+    for shard in active.iter().chain(&inactive) {
+        if !profile.enabled.contains(shard) && !profile.disabled.contains(shard) && !manager.is_system_shard(shard) {
+            log_warning(&format!("Shard '{}' is not part of the profile and was left unchanged", shard));
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(ShardError::ValidationError(format!(
+            "Could not switch {} shard(s): {}", failed.len(), failed.join("; "))));
+    }
+
+    if changed == 0 {
+        log_success(&format!("Shards already match profile {}", style(name).bold()));
+    } else {
+        log_success(&format!("Switched {} shard(s). Run `shard apply all` to update packages", changed));
+    }
+    Ok(())
+}
+
+/// List saved profiles
+pub fn list() -> ShardResult<()> {
+    let dir = profiles_dir();
+    let mut names: Vec<String> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    names.sort();
+
+    if names.is_empty() {
+        log_warning("No profiles saved yet. Create one with `shard profile save <name>`");
+        return Ok(());
+    }
+
+    for name in names {
+        match read(&name) {
+            Ok(profile) => println!("  {:<20} {} enabled, {} disabled, saved {}",
+                style(&name).bold(),
+                profile.enabled.len(),
+                profile.disabled.len(),
+                profile.saved.format("%Y-%m-%d %H:%M")),
+            Err(e) => println!("  {:<20} {}", style(&name).bold(), style(format!("unreadable: {}", e)).red()),
+        }
+    }
+    Ok(())
+}
+
+/// Delete a saved profile
+pub fn remove(name: &str) -> ShardResult<()> {
+    let path = profile_path(name)?;
+    if !path.exists() {
+        return Err(ShardError::NotFound(format!("profile {}", name)));
+    }
+    std::fs::remove_file(&path)
+        .with_context(|| format!("Failed to delete profile: {}", path.display()))?;
+    log_success(&format!("Deleted profile {}", style(name).bold()));
+    Ok(())
+}
+
+fn read(name: &str) -> ShardResult<Profile> {
+    let path = profile_path(name)?;
+    if !path.exists() {
+        return Err(ShardError::NotFound(format!("profile {}", name)));
+    }
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read profile: {}", path.display()))?;
+    let profile = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse profile: {}", path.display()))?;
+    Ok(profile)
+}
+
+fn profile_path(name: &str) -> ShardResult<PathBuf> {
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+        return Err(ShardError::InvalidName(name.to_string()));
+    }
+    Ok(profiles_dir().join(format!("{}.toml", name)))
+}
+
+/// Directory holding one file per profile
+fn profiles_dir() -> PathBuf {
+    SapphirePaths::resolve().profiles_dir()
+}
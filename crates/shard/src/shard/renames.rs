@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+use crate::brew::FormulaRenames;
+use crate::core::manifest::Manifest;
+use crate::engine::Plan;
+use crate::package::processor::PackageType;
+use crate::shard::manager::ShardManager;
+use crate::utils::{Glyph, ShardResult, ResultExt, filesystem, log_debug, log_plain, log_step, log_success, log_warning};
+
+/// Warn about formulae in a plan that Homebrew has renamed
+///
+/// Renames are only a hint, so failing to fetch them never fails diff or apply.
+pub fn warn_renamed(plan: &Plan) {
+    let formulae = plan.declared(PackageType::Formula);
+
+    if formulae.is_empty() {
+        return;
+    }
+
+    let renames = match FormulaRenames::load() {
+        Ok(renames) => renames,
+        Err(e) => {
+            log_debug(&format!("Skipping the check for renamed formulae: {}", e));
+            return;
+        }
+    };
+
+    let mut found = false;
+    for name in &formulae {
+        if let Some(current) = renames.current_name(name) {
+            log_warning(&format!("Formula '{}' was renamed to '{}'", name, current));
+            found = true;
+        }
+    }
+    if found {
+        log_warning("Run `shard fix-renames` to update your shards");
+    }
+}
+
+/// Rewrite renamed formulae in a shard, or in every shard with `"all"`
+pub fn fix_renames(target: &str, dry_run: bool) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    let renames = FormulaRenames::load()
+        .with_context(|| "Could not load formula renames")?;
+
+    let shards: Vec<(String, PathBuf)> = if target.eq_ignore_ascii_case("all") {
+        let mut names = manager.list_shards()?;
+        names.sort();
+        names.into_iter()
+            .map(|name| { let path = manager.get_shard_path(&name); (name, path) })
+            .collect()
+    } else {
+        let path = PathBuf::from(filesystem::resolve_manifest_path(target)?);
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        vec![(name, path)]
+    };
+
+    let mut fixed = 0;
+    for (name, path) in shards {
+        let mut manifest = Manifest::from_file(&path)
+            .with_context(|| format!("Failed to load manifest: {}", path.display()))?;
+
+        let renamed = rename_formulae(&mut manifest, &renames);
+        if renamed.is_empty() {
+            continue;
+        }
+
+        log_step(&format!("Shard '{}':", name));
+        for (old, current) in &renamed {
+            log_plain(&format!("  {} {} {}", old, Glyph::Arrow, current));
+        }
+
+        if dry_run {
+            log_debug("Dry run: Would have saved manifest.");
+        } else if let Err(e) = manager.ensure_editable(&name) {
+            log_warning(&format!("Cannot update shard '{}': {}", name, e));
+            continue;
+        } else {
+            manifest.update_modification_info();
+            manifest.to_file(&path)?;
+        }
+        fixed += renamed.len();
+    }
+
+    match (fixed, dry_run) {
+        (0, _) => log_success("No renamed formulae found"),
+        (_, true) => log_step(&format!("Would rename {} formula(e)", fixed)),
+        (_, false) => log_success(&format!("Renamed {} formula(e)", fixed)),
+    }
+    Ok(())
+}
+
+/// Replace renamed formulae with their current names, keeping build settings
+///
+/// Returns the old and current name of every replaced formula.
+fn rename_formulae(manifest: &mut Manifest, renames: &FormulaRenames) -> Vec<(String, String)> {
+    let mut renamed = Vec::new();
+
+    for formula in &mut manifest.formulae {
+        if let Some(current) = renames.current_name(formula) {
+            renamed.push((formula.clone(), current.to_string()));
+            *formula = current.to_string();
+        }
+    }
+
+    for (old, current) in &renamed {
+        if let Some(build) = manifest.formula_builds.remove(old) {
+            manifest.formula_builds.entry(current.clone()).or_insert(build);
+        }
+        if let Some(options) = manifest.formula_options.remove(old) {
+            manifest.formula_options.entry(current.clone()).or_insert(options);
+        }
+    }
+
+    // The current name may have been declared already
+    let mut seen = std::collections::HashSet::new();
+    manifest.formulae.retain(|formula| seen.insert(formula.clone()));
+
+    renamed
+}
@@ -0,0 +1,284 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::SystemTime;
+use chrono::{DateTime, Local};
+use sapphire_core::history::{self, Kind};
+use crate::brew::{get_client, BrewClient, ClientOverrides};
+use crate::core::config::ShardConfig;
+use crate::engine::{Action, ShardEngine};
+use crate::mdm::MdmInventory;
+use crate::shard::apply;
+use crate::shard::manager::{ShardManager, ShardStatus};
+use crate::utils::{ShardError, ShardResult, ResultExt, log_step, log_success, log_warning};
+
+/// A titled table of the report
+struct Section {
+    title: &'static str,
+    /// Shown above the table, e.g. why it is empty
+    note: Option<String>,
+    headers: &'static [&'static str],
+    rows: Vec<Vec<String>>,
+}
+
+/// Write a document of the managed state of this machine
+///
+/// Covers shards, taps, packages with their installed versions, applied
+/// fragments, the changes applying all shards would still make and, in
+/// managed mode, software controlled by device management. `format` is
+/// `"markdown"` or `"html"`. Without `output` the report is printed.
+pub fn report(format: &str, output: Option<&str>, overrides: ClientOverrides) -> ShardResult<()> {
+    let render = match format.to_lowercase().as_str() {
+        "markdown" | "md" => render_markdown,
+        "html" => render_html,
+        other => return Err(ShardError::ValidationError(format!(
+            "Unknown report format '{}', expected \"markdown\" or \"html\"", other))),
+    };
+
+    if output.is_some() {
+        log_step("Collecting system state...");
+    }
+
+    let sections = collect_sections(overrides)?;
+    let user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    let subtitle = format!("Generated {} for {}", Local::now().format("%Y-%m-%d %H:%M"), user);
+    let document = render(&subtitle, &sections);
+
+    match output {
+        Some(path) => {
+            let path = shellexpand::tilde(path).to_string();
+            std::fs::write(&path, document)
+                .with_context(|| format!("Failed to write report to {}", path))?;
+            log_success(&format!("Report written to {}", path));
+        }
+        None => print!("{}", document),
+    }
+    Ok(())
+}
+
+fn collect_sections(overrides: ClientOverrides) -> ShardResult<Vec<Section>> {
+    let manager = ShardManager::new()?;
+    let brew_client = get_client(overrides);
+
+    let applied = history::load().unwrap_or_else(|e| {
+        log_warning(&format!("Could not read apply history: {}", e));
+        Vec::new()
+    });
+    let last_applied = |kind: Kind, name: &str| applied.iter()
+        .find(|entry| entry.kind == kind && entry.name == name)
+        .map(|entry| match entry.succeeded() {
+            true => format_time(entry.at),
+            false => format!("{} (failed)", format_time(entry.at)),
+        })
+        .unwrap_or_else(|| "never".to_string());
+
+    // Shards, active and disabled
+    let mut infos: Vec<_> = manager.get_all_shards_info()?.into_values().collect();
+    infos.sort_by(|a, b| a.name.cmp(&b.name));
+    let shard_rows = infos.iter()
+        .map(|info| {
+            let status = match info.status {
+                ShardStatus::Active => "enabled",
+                ShardStatus::Disabled => "disabled",
+                ShardStatus::NotFound => "missing",
+            };
+            let (description, packages) = match &info.manifest {
+                Some(manifest) => (
+                    manifest.metadata.description.clone(),
+                    (manifest.formulae.len() + manifest.casks.len()).to_string(),
+                ),
+                None => ("unreadable".to_string(), "-".to_string()),
+            };
+            vec![info.name.clone(), status.to_string(), description, packages, last_applied(Kind::Shard, &info.name)]
+        })
+        .collect();
+
+    // Taps and packages of the enabled shards
+    let manifests = manager.load_active_manifests()?;
+    let mut taps: BTreeMap<String, (Option<String>, Vec<String>)> = BTreeMap::new();
+    let mut packages: BTreeMap<(String, &'static str), Vec<String>> = BTreeMap::new();
+    for (shard, manifest) in &manifests {
+        for tap in &manifest.taps {
+            let entry = taps.entry(tap.name.clone()).or_default();
+            entry.0 = entry.0.take().or_else(|| tap.url.clone());
+            entry.1.push(shard.clone());
+        }
+        for formula in &manifest.formulae {
+            packages.entry((formula.clone(), "formula")).or_default().push(shard.clone());
+        }
+        for cask in &manifest.casks {
+            packages.entry((cask.clone(), "cask")).or_default().push(shard.clone());
+        }
+    }
+
+    let tap_rows = taps.into_iter()
+        .map(|(name, (url, shards))| vec![name, url.unwrap_or_default(), shards.join(", ")])
+        .collect();
+
+    let mut versions: HashMap<&str, BTreeMap<String, String>> = HashMap::new();
+    for (kind, cask) in [("formula", false), ("cask", true)] {
+        let installed = brew_client.get_installed_versions(cask).unwrap_or_else(|e| {
+            log_warning(&format!("Could not list installed {} versions: {}", kind, e));
+            BTreeMap::new()
+        });
+        versions.insert(kind, installed);
+    }
+    let package_rows = packages.into_iter()
+        .map(|((name, kind), shards)| {
+            let version = versions[kind].get(&name).cloned().unwrap_or_else(|| "not installed".to_string());
+            vec![name, kind.to_string(), version, shards.join(", ")]
+        })
+        .collect();
+
+    // Fragments are only known once they have been applied
+    let fragment_rows = applied.iter()
+        .filter(|entry| entry.kind == Kind::Fragment)
+        .map(|entry| {
+            let name = Path::new(&entry.name).file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| entry.name.clone());
+            vec![name, entry.name.clone(), format_time(entry.at)]
+        })
+        .collect();
+
+    // Changes applying all shards would still make
+    let (drift_note, drift_rows) = match drift(overrides) {
+        Ok(rows) if rows.is_empty() => (Some("The system matches all enabled shards.".to_string()), rows),
+        Ok(rows) => (None, rows),
+        Err(e) => (Some(format!("Could not plan the enabled shards: {}", e)), Vec::new()),
+    };
+
+    let mut sections = vec![
+        Section { title: "Shards", note: None, headers: &["Shard", "Status", "Description", "Packages", "Last applied"], rows: shard_rows },
+        Section { title: "Taps", note: None, headers: &["Tap", "Remote", "Shards"], rows: tap_rows },
+        Section { title: "Packages", note: None, headers: &["Package", "Type", "Version", "Shards"], rows: package_rows },
+        Section { title: "Fragments", note: None, headers: &["Fragment", "Path", "Last applied"], rows: fragment_rows },
+        Section { title: "Pending changes", note: drift_note, headers: &["Change", "Type", "Name"], rows: drift_rows },
+    ];
+
+    // Only managed Macs are checked for device management
+    if ShardConfig::load().unwrap_or_default().managed {
+        let (note, rows) = match mdm_rows(&brew_client) {
+            Ok(rows) if rows.is_empty() => (Some("No software controlled by device management was found.".to_string()), rows),
+            Ok(rows) => (Some("Casks listed here are never uninstalled by `apply all`.".to_string()), rows),
+            Err(e) => (Some(format!("Could not check for software controlled by device management: {}", e)), Vec::new()),
+        };
+        sections.push(Section { title: "Managed by MDM", note, headers: &["Item", "Source", "Cask"], rows });
+    }
+
+    Ok(sections)
+}
+
+/// Rows for the items device management controls, with the installed casks they match
+fn mdm_rows(brew_client: &BrewClient) -> ShardResult<Vec<Vec<String>>> {
+    let inventory = MdmInventory::detect();
+    let managed = inventory.managed_casks(brew_client, &brew_client.get_installed_casks()?)?;
+
+    Ok(inventory.items.iter()
+        .map(|item| {
+            let casks: Vec<&str> = managed.iter()
+                .filter(|managed| &managed.item == item)
+                .map(|managed| managed.cask.as_str())
+                .collect();
+            let name = match &item.display_name {
+                Some(display_name) => format!("{} ({})", display_name, item.name),
+                None => item.name.clone(),
+            };
+            vec![name, item.source.as_str().to_string(), casks.join(", ")]
+        })
+        .collect())
+}
+
+/// Rows for the actions `apply all` would still take
+fn drift(overrides: ClientOverrides) -> ShardResult<Vec<Vec<String>>> {
+    let plan = ShardEngine::new(overrides)?.plan("all")?;
+    Ok(apply::pending_actions(&plan)
+        .into_iter()
+        .map(|action| {
+            let (change, kind) = match action {
+                Action::AddTap { .. } => ("add", "tap"),
+                Action::Install { package_type, .. } => ("install", package_type.as_str()),
+                Action::Upgrade { package_type, .. } => ("upgrade", package_type.as_str()),
+                Action::Uninstall { package_type, .. } => ("remove", package_type.as_str()),
+                Action::InstallExtension { .. } => ("install", "vscode"),
+                Action::Link { link, .. } => (link.describe(), "formula"),
+            };
+            vec![change.to_string(), kind.to_string(), action.name().to_string()]
+        })
+        .collect())
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn render_markdown(subtitle: &str, sections: &[Section]) -> String {
+    let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+
+    let mut out = format!("# System report\n\n_{}_\n", subtitle);
+    for section in sections {
+        out.push_str(&format!("\n## {}\n\n", section.title));
+        if let Some(note) = &section.note {
+            out.push_str(&format!("{}\n\n", note));
+        }
+        if section.rows.is_empty() {
+            if section.note.is_none() {
+                out.push_str("_None_\n");
+            }
+            continue;
+        }
+
+        out.push_str(&format!("| {} |\n", section.headers.join(" | ")));
+        out.push_str(&format!("|{}\n", " --- |".repeat(section.headers.len())));
+        for row in &section.rows {
+            let cells: Vec<String> = row.iter().map(|text| cell(text)).collect();
+            out.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+    }
+    out
+}
+
+fn render_html(subtitle: &str, sections: &[Section]) -> String {
+    let escape = |text: &str| text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+
+    let mut out = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>System report</title>\n",
+        "<style>body{font-family:-apple-system,sans-serif;margin:2em}table{border-collapse:collapse}",
+        "th,td{border:1px solid #ccc;padding:4px 8px;text-align:left}th{background:#f4f4f4}</style>\n",
+        "</head>\n<body>\n<h1>System report</h1>\n",
+    ));
+    out.push_str(&format!("<p><em>{}</em></p>\n", escape(subtitle)));
+
+    for section in sections {
+        out.push_str(&format!("<h2>{}</h2>\n", escape(section.title)));
+        if let Some(note) = &section.note {
+            out.push_str(&format!("<p>{}</p>\n", escape(note)));
+        }
+        if section.rows.is_empty() {
+            if section.note.is_none() {
+                out.push_str("<p><em>None</em></p>\n");
+            }
+            continue;
+        }
+
+        out.push_str("<table>\n<tr>");
+        for header in section.headers {
+            out.push_str(&format!("<th>{}</th>", escape(header)));
+        }
+        out.push_str("</tr>\n");
+        for row in &section.rows {
+            out.push_str("<tr>");
+            for text in row {
+                out.push_str(&format!("<td>{}</td>", escape(text)));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
@@ -0,0 +1,96 @@
+use dialoguer::Confirm;
+use crate::brew::ClientOverrides;
+use crate::core::config::ShardConfig;
+use crate::package::operations as package;
+use crate::shard::manager::ShardManager;
+use crate::utils::{ShardError, ShardResult, log_step, log_warning};
+
+/// Shard that packages installed with `brew install` are suggested for
+const DEFAULT_SHARD: &str = "user";
+
+/// `brew` wrapper for bash and zsh, `{flags}` are passed to `shard shellhook`
+const POSIX_HOOK: &str = r#"# Suggest recording packages installed with `brew install` in a shard
+# Added with: eval "$(shard shellhook {shell}{flags})"
+brew() {
+  command brew "$@" || return
+  if [ "$1" = "install" ]; then
+    shift
+    command shard shellhook{flags} --installed "$@"
+  fi
+  return 0
+}
+"#;
+
+/// `brew` wrapper for fish
+const FISH_HOOK: &str = r#"# Suggest recording packages installed with `brew install` in a shard
+# Added with: shard shellhook fish{flags} | source
+function brew --wraps brew
+    command brew $argv; or return
+    if test "$argv[1]" = install
+        command shard shellhook{flags} --installed $argv[2..-1]
+    end
+end
+"#;
+
+/// Print the `brew` wrapper for a shell
+///
+/// With `prompt`, the wrapper offers to add the packages right away instead
+/// of printing a reminder.
+pub fn print_hook(shell: &str, prompt: bool) -> ShardResult<()> {
+    let template = match shell.to_lowercase().as_str() {
+        "bash" | "zsh" => POSIX_HOOK,
+        "fish" => FISH_HOOK,
+        other => return Err(ShardError::ValidationError(format!(
+            "Unsupported shell '{}', expected \"bash\", \"zsh\" or \"fish\"", other))),
+    };
+
+    let flags = if prompt { " --prompt" } else { "" };
+    print!("{}", template.replace("{shell}", shell).replace("{flags}", flags));
+    Ok(())
+}
+
+/// Point out packages a `brew install` with `args` installed that no shard declares
+///
+/// Called by the hook after the install succeeded. With `prompt`, offers to
+/// add them to the user shard.
+pub fn installed(args: &[String], prompt: bool, overrides: ClientOverrides) -> ShardResult<()> {
+    let force_cask = args.iter().any(|arg| arg == "--cask" || arg == "--casks");
+    let force_formula = args.iter().any(|arg| arg == "--formula" || arg == "--formulae");
+    let ignore = ShardConfig::load().map(|config| config.ignore).unwrap_or_default();
+    let manifests = ShardManager::new()?.load_active_manifests()?;
+
+    let declared = |name: &str| manifests.iter().any(|(_, manifest)| {
+        manifest.formulae.iter().chain(&manifest.casks)
+            .any(|declared| declared == name || short_name(declared) == short_name(name))
+    });
+    let undeclared: Vec<String> = args.iter()
+        .filter(|arg| !arg.starts_with('-'))
+        .filter(|name| !declared(name) && !ignore.is_ignored(name))
+        .cloned()
+        .collect();
+
+    if undeclared.is_empty() {
+        return Ok(());
+    }
+
+    let names = undeclared.join(" ");
+    if !prompt || !console::user_attended() {
+        log_warning(&format!("Not declared in any shard: {}", names));
+        log_step(&format!("Record with `shard add {}` so the next `shard apply all` keeps it", names));
+        return Ok(());
+    }
+
+    let add = Confirm::new()
+        .with_prompt(format!("Add {} to shard '{}'?", names, DEFAULT_SHARD))
+        .default(true)
+        .interact()?;
+    if add {
+        package::add_packages(&undeclared, force_formula, force_cask, DEFAULT_SHARD, false, false, false, overrides)?;
+    }
+    Ok(())
+}
+
+/// Name of a package without its tap, e.g. `tool` for `org/tap/tool`
+fn short_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
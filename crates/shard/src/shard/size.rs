@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use console::style;
+use crate::brew::{get_client, ClientOverrides};
+use crate::core::config::ShardConfig;
+use crate::core::manifest::merge_options;
+use crate::package::processor::PackageType;
+use crate::shard::apps;
+use crate::shard::manager::ShardManager;
+use crate::utils::{ShardResult, ShardError, log_step, log_warning, dir_size, format_size};
+
+/// Disk usage of a single package
+struct PackageSize {
+    name: String,
+    package_type: PackageType,
+    /// Size in bytes, or None if the package is not installed
+    bytes: Option<u64>,
+}
+
+/// Report installed size per package and per shard
+///
+/// Sizes are taken from the package directories in the Homebrew Cellar
+/// (formulae) and Caskroom (casks). Casks also count the apps brew moved to
+/// `/Applications` or their `--appdir`. Packages declared in several shards
+/// are counted towards each of them, but only once in the overall total.
+pub fn size(target: &str, overrides: ClientOverrides) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    let mut manifests = manager.load_active_manifests()?;
+
+    if !target.eq_ignore_ascii_case("all") {
+        if !manager.shard_is_active(target) {
+            return Err(ShardError::NotFound(target.to_string()));
+        }
+        manifests.retain(|(name, _)| name == target);
+    }
+
+    if manifests.is_empty() {
+        log_warning("No enabled shards found.");
+        return Ok(());
+    }
+
+    log_step(&format!("Calculating disk usage for {} shard(s)...", manifests.len()));
+
+    let brew_client = get_client(overrides);
+    let cellar = brew_client.get_cellar_path()?;
+    let caskroom = brew_client.get_caskroom_path()?;
+
+    // The Caskroom only keeps metadata of casks whose apps were moved out of it
+    let installed_casks: Vec<String> = manifests.iter()
+        .flat_map(|(_, manifest)| &manifest.casks)
+        .filter(|name| Path::new(&caskroom).join(name).exists())
+        .cloned()
+        .collect();
+    let cask_apps: BTreeMap<String, Vec<String>> = if installed_casks.is_empty() {
+        BTreeMap::new()
+    } else {
+        match brew_client.get_cask_apps(&installed_casks) {
+            Ok(casks) => casks.into_iter().map(|cask| (cask.token, cask.apps)).collect(),
+            Err(e) => {
+                log_warning(&format!("Only counting the Caskroom for casks, their apps could not be looked up: {}", e));
+                BTreeMap::new()
+            }
+        }
+    };
+    let config = ShardConfig::load().unwrap_or_default();
+
+    // Measure every package once, even if several shards declare it
+    let mut sizes: BTreeMap<(String, &'static str), Option<u64>> = BTreeMap::new();
+
+    let mut shard_reports = Vec::new();
+    for (shard_name, manifest) in &manifests {
+        let declared = manifest.formulae.iter().map(|name| (name, PackageType::Formula, &cellar))
+            .chain(manifest.casks.iter().map(|name| (name, PackageType::Cask, &caskroom)));
+
+        let mut packages = Vec::new();
+        for (name, package_type, root) in declared {
+            let bytes = *sizes.entry((name.clone(), package_type.as_str()))
+                .or_insert_with(|| {
+                    let path = Path::new(root).join(name);
+                    if !path.exists() {
+                        return None;
+                    }
+                    let moved = match (package_type, cask_apps.get(name)) {
+                        (PackageType::Cask, Some(names)) => {
+                            let appdir = apps::appdir(&merge_options(&config.cask_options, manifest.cask_options_for(name)));
+                            apps_size(&appdir, names)
+                        }
+                        _ => 0,
+                    };
+                    Some(dir_size(&path) + moved)
+                });
+            packages.push(PackageSize { name: name.clone(), package_type, bytes });
+        }
+
+        packages.sort_by(|a, b| b.bytes.unwrap_or(0).cmp(&a.bytes.unwrap_or(0)).then_with(|| a.name.cmp(&b.name)));
+        let total: u64 = packages.iter().filter_map(|p| p.bytes).sum();
+        shard_reports.push((shard_name, total, packages));
+    }
+
+    // Largest shards first
+    shard_reports.sort_by_key(|report| std::cmp::Reverse(report.1));
+
+    for (shard_name, total, packages) in &shard_reports {
+        println!();
+        println!("{}  {} ({} package(s))", style(shard_name).bold(), style(format_size(*total)).cyan(), packages.len());
+        for package in packages {
+            let size = match package.bytes {
+                Some(bytes) => format_size(bytes),
+                None => style("not installed").dim().to_string(),
+            };
+            println!("    {:<40} {:<8} {:>12}", package.name, package.package_type.as_str(), size);
+        }
+    }
+
+    // Every package was measured once, so shared packages are not double counted
+    let grand_total: u64 = sizes.values().filter_map(|bytes| *bytes).sum();
+
+    println!();
+    log_step(&format!("Total disk usage of managed packages: {}", format_size(grand_total)));
+    Ok(())
+}
+
+/// Size of the apps a cask moved into its app directory
+///
+/// Apps that are missing, e.g. because they were deleted by hand, count as
+/// zero.
+fn apps_size(appdir: &Path, apps: &[String]) -> u64 {
+    apps.iter()
+        .map(|app| appdir.join(app))
+        .filter(|path| path.exists())
+        .map(|path| dir_size(&path))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moved_apps_are_measured_in_the_app_directory() {
+        let appdir = std::env::temp_dir().join(format!("shard-size-apps-{}", std::process::id()));
+        let contents = appdir.join("Example.app").join("Contents");
+        std::fs::create_dir_all(&contents).unwrap();
+        std::fs::write(contents.join("Info.plist"), vec![0u8; 4096]).unwrap();
+
+        let apps = vec!["Example.app".to_string(), "Missing.app".to_string()];
+        let bytes = apps_size(&appdir, &apps);
+        std::fs::remove_dir_all(&appdir).unwrap();
+
+        assert_eq!(bytes, 4096);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::Command;
+use chrono::{DateTime, Utc};
+use console::style;
+use serde::{Deserialize, Serialize};
+use crate::brew::{get_client, ClientOverrides};
+use crate::utils::{ShardError, ShardResult, ResultExt, log_step, log_success, log_warning};
+use crate::vscode;
+
+/// What is installed on a machine, to compare it with another one
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Name of the machine the snapshot was taken on
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub taps: BTreeSet<String>,
+    /// Formulae installed on request, without dependencies
+    #[serde(default)]
+    pub formulae: BTreeSet<String>,
+    #[serde(default)]
+    pub casks: BTreeSet<String>,
+    /// Lowercased VS Code extension IDs, empty without the `code` command
+    #[serde(default)]
+    pub vscode_extensions: BTreeSet<String>,
+}
+
+impl Snapshot {
+    /// Record what is installed on this machine
+    pub fn capture(overrides: ClientOverrides) -> ShardResult<Self> {
+        let brew_client = get_client(overrides);
+        let dependencies: BTreeSet<String> = brew_client.get_dependency_packages()?.into_iter().collect();
+        let vscode_extensions = if vscode::is_available() {
+            vscode::installed_extensions()?.into_iter().collect()
+        } else {
+            BTreeSet::new()
+        };
+
+        Ok(Self {
+            host: hostname(),
+            created: Some(Utc::now()),
+            taps: brew_client.get_installed_taps()?.into_iter().collect(),
+            formulae: brew_client.get_installed_formulae()?
+                .into_iter()
+                .filter(|formula| !dependencies.contains(formula))
+                .collect(),
+            casks: brew_client.get_installed_casks()?.into_iter().collect(),
+            vscode_extensions,
+        })
+    }
+
+    /// Read a snapshot written by `shard snapshot --format json`
+    pub fn load(path: &Path) -> ShardResult<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read snapshot {}", path.display()))?;
+        serde_json::from_str(&content)
+            .map_err(|e| ShardError::ValidationError(format!("Invalid snapshot {}: {}", path.display(), e)))
+    }
+
+    /// The sections of the snapshot with their labels
+    fn sections(&self) -> [(&'static str, &BTreeSet<String>); 4] {
+        [
+            ("taps", &self.taps),
+            ("formulae", &self.formulae),
+            ("casks", &self.casks),
+            ("vscode extensions", &self.vscode_extensions),
+        ]
+    }
+}
+
+/// Write what is installed on this machine
+///
+/// Only `"json"` is supported. Without `output` the snapshot is printed.
+pub fn snapshot(format: &str, output: Option<&str>, overrides: ClientOverrides) -> ShardResult<()> {
+    if !format.eq_ignore_ascii_case("json") {
+        return Err(ShardError::ValidationError(format!(
+            "Unknown snapshot format '{}', expected \"json\"", format)));
+    }
+
+    if output.is_some() {
+        log_step("Collecting installed packages...");
+    }
+    let snapshot = Snapshot::capture(overrides)?;
+    let document = serde_json::to_string_pretty(&snapshot)
+        .with_context(|| "Failed to serialize snapshot")?;
+
+    match output {
+        Some(path) => {
+            let path = shellexpand::tilde(path).to_string();
+            std::fs::write(&path, format!("{}\n", document))
+                .with_context(|| format!("Failed to write snapshot to {}", path))?;
+            log_success(&format!("Snapshot of {} written to {}", snapshot.host, path));
+        }
+        None => println!("{}", document),
+    }
+    Ok(())
+}
+
+/// Print what is installed only here or only on the machine of a snapshot
+pub fn diff_against(path: &str, overrides: ClientOverrides) -> ShardResult<()> {
+    let path = shellexpand::tilde(path).to_string();
+    let other = Snapshot::load(Path::new(&path))?;
+    let other_host = if other.host.is_empty() { path.clone() } else { other.host.clone() };
+
+    log_step(&format!("Comparing this machine with {}", other_host));
+    let local = Snapshot::capture(overrides)?;
+    if other.vscode_extensions.is_empty() != local.vscode_extensions.is_empty() {
+        log_warning("Only one side has VS Code extensions, the `code` command may be missing on the other");
+    }
+
+    let mut differences = 0;
+    for ((label, ours), (_, theirs)) in local.sections().into_iter().zip(other.sections()) {
+        let missing: Vec<&String> = theirs.difference(ours).collect();
+        let extra: Vec<&String> = ours.difference(theirs).collect();
+        if missing.is_empty() && extra.is_empty() {
+            continue;
+        }
+
+        differences += missing.len() + extra.len();
+        println!();
+        println!("{}", style(label).bold());
+        for name in missing {
+            println!("  {} {:<40} {}", style("+").green(), name, style(format!("only on {}", other_host)).dim());
+        }
+        for name in extra {
+            println!("  {} {:<40} {}", style("-").red(), name, style("only on this machine").dim());
+        }
+    }
+    println!();
+
+    if differences == 0 {
+        log_success(&format!("This machine has the same packages as {}", other_host));
+    } else {
+        log_step(&format!("{} package(s) differ from {}", differences, other_host));
+    }
+    Ok(())
+}
+
+/// Name of this machine, `unknown` if it cannot be determined
+pub fn hostname() -> String {
+    Command::new("hostname")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
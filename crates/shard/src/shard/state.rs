@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use sapphire_core::paths::SapphirePaths;
+use crate::brew::BrewClient;
+use crate::core::manifest::Manifest;
+use crate::shard::{context, update};
+use crate::utils::{ShardResult, ResultExt, ensure_parent_dir_exists, log_debug};
+
+/// Last successful `apply all`
+const STATE_FILE: &str = "state.json";
+
+/// What `apply all` depends on
+///
+/// The directories brew installs into and taps are cloned to change their
+/// modification time when packages or taps are added or removed, also outside
+/// of shard. Upgrades only touch subdirectories, which does not matter since
+/// the declared packages stay installed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Fingerprint {
+    /// Hash of the enabled manifests and the configuration file
+    manifests: String,
+    /// Modification times of the brew directories, in seconds
+    brew_mtimes: BTreeMap<String, u64>,
+}
+
+/// Persisted after every successful `apply all`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ApplyState {
+    fingerprint: Fingerprint,
+    /// Unix timestamp of the apply
+    applied_at: u64,
+    /// Snapshot of the installed packages after the apply
+    formulae: Vec<String>,
+    casks: Vec<String>,
+}
+
+/// Whether nothing changed since the last successful `apply all`
+///
+/// Any error, e.g. a missing or unreadable state file, counts as changed.
+pub fn is_unchanged(brew_client: &BrewClient, manifests: &[(String, Manifest)]) -> bool {
+    let Some(state) = load() else {
+        return false;
+    };
+
+    match fingerprint(brew_client, manifests) {
+        Ok(current) => current == state.fingerprint,
+        Err(e) => {
+            log_debug(&format!("Could not fingerprint the system: {}", e));
+            false
+        }
+    }
+}
+
+/// Remember the current state after a successful `apply all`
+pub fn record(brew_client: &BrewClient, manifests: &[(String, Manifest)]) -> ShardResult<()> {
+    let state = ApplyState {
+        fingerprint: fingerprint(brew_client, manifests)?,
+        applied_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        formulae: brew_client.get_installed_formulae()?,
+        casks: brew_client.get_installed_casks()?,
+    };
+
+    let path = state_path();
+    ensure_parent_dir_exists(&path)?;
+    let content = serde_json::to_string_pretty(&state)
+        .with_context(|| "Failed to serialize apply state")?;
+    std::fs::write(&path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+fn load() -> Option<ApplyState> {
+    let content = std::fs::read_to_string(state_path()).ok()?;
+    serde_json::from_str(&content)
+        .inspect_err(|e| log_debug(&format!("Ignoring unreadable apply state: {}", e)))
+        .ok()
+}
+
+fn fingerprint(brew_client: &BrewClient, manifests: &[(String, Manifest)]) -> ShardResult<Fingerprint> {
+    let mut hasher = DefaultHasher::new();
+    for (name, manifest) in manifests {
+        name.hash(&mut hasher);
+        toml::to_string(manifest)
+            .with_context(|| format!("Failed to serialize shard {}", name))?
+            .hash(&mut hasher);
+    }
+    // Cask options and brew environment come from the configuration
+    std::fs::read_to_string(SapphirePaths::resolve().config_file()).unwrap_or_default().hash(&mut hasher);
+    // Switching context changes which packages apply
+    context::active()?.hash(&mut hasher);
+
+    let taps = PathBuf::from(brew_client.get_repository_path()?).join("Library/Taps");
+    let mut brew_mtimes = BTreeMap::new();
+    for (key, path) in [
+        ("cellar", PathBuf::from(brew_client.get_cellar_path()?)),
+        ("caskroom", PathBuf::from(brew_client.get_caskroom_path()?)),
+        ("taps", taps),
+        ("updated", update::stamp_path()),
+    ] {
+        brew_mtimes.insert(key.to_string(), mtime(&path));
+    }
+
+    Ok(Fingerprint {
+        manifests: format!("{:016x}", hasher.finish()),
+        brew_mtimes,
+    })
+}
+
+/// Modification time in seconds, 0 if the path does not exist
+fn mtime(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |age| age.as_secs())
+}
+
+fn state_path() -> PathBuf {
+    SapphirePaths::resolve().root().join(STATE_FILE)
+}
@@ -0,0 +1,63 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use sapphire_core::paths::SapphirePaths;
+use crate::package::processor::PackageType;
+use crate::utils::{ShardResult, ResultExt, ensure_parent_dir_exists, path_exists};
+
+/// Packages kept installed when `apply all` wanted to uninstall them, one
+/// `<type> <name>` per line
+const SUGGESTIONS_FILE: &str = "adopt_suggestions";
+
+/// Packages the user chose to keep, as `(type, name)`, suggested for adding to a shard
+pub fn suggested() -> BTreeSet<(String, String)> {
+    std::fs::read_to_string(suggestions_path())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.trim().split_once(' '))
+        .map(|(package_type, name)| (package_type.to_string(), name.to_string()))
+        .collect()
+}
+
+/// Remember packages the user excluded from removal
+pub fn record(packages: &[(PackageType, String)]) -> ShardResult<()> {
+    let mut suggested = suggested();
+    let before = suggested.len();
+    suggested.extend(packages.iter().map(|(package_type, name)| (package_type.as_str().to_string(), name.clone())));
+    if suggested.len() == before {
+        return Ok(());
+    }
+    save(&suggested)
+}
+
+/// Forget packages that were uninstalled or are declared by a shard now
+pub fn forget(names: &[String]) -> ShardResult<()> {
+    let mut suggested = suggested();
+    let before = suggested.len();
+    suggested.retain(|(_, name)| !names.contains(name));
+    if suggested.len() == before {
+        return Ok(());
+    }
+    save(&suggested)
+}
+
+fn save(suggested: &BTreeSet<(String, String)>) -> ShardResult<()> {
+    let path = suggestions_path();
+    if suggested.is_empty() {
+        if path_exists(&path) {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+        return Ok(());
+    }
+
+    ensure_parent_dir_exists(&path)?;
+    let content: String = suggested.iter()
+        .map(|(package_type, name)| format!("{} {}\n", package_type, name))
+        .collect();
+    std::fs::write(&path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn suggestions_path() -> PathBuf {
+    SapphirePaths::resolve().root().join(SUGGESTIONS_FILE)
+}
@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+use sapphire_core::paths::SapphirePaths;
+use crate::core::manifest::Manifest;
+use crate::utils::{ShardError, ShardResult, ResultExt, log_debug};
+
+/// Curated package sets compiled into the binary
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("web-dev", include_str!("../../templates/web-dev.toml")),
+    ("rust", include_str!("../../templates/rust.toml")),
+    ("data-science", include_str!("../../templates/data-science.toml")),
+    ("media", include_str!("../../templates/media.toml")),
+];
+
+/// Load a shard template by name
+///
+/// `~/.sapphire/templates/<name>.toml` overrides the built-in template of the
+/// same name and can also add new templates.
+pub fn load_template(name: &str) -> ShardResult<Manifest> {
+    let user_path = user_templates_dir().join(format!("{}.toml", name));
+    if user_path.is_file() {
+        log_debug(&format!("Using user template: {}", user_path.display()));
+        return Manifest::from_file(&user_path);
+    }
+
+    let Some((_, content)) = BUILTIN_TEMPLATES.iter().find(|(builtin, _)| *builtin == name) else {
+        return Err(ShardError::ValidationError(format!(
+            "Unknown template '{}'. Available templates: {}", name, list_templates().join(", "))));
+    };
+
+    log_debug(&format!("Using built-in template: {}", name));
+    let manifest = toml::from_str(content)
+        .with_context(|| format!("Failed to parse built-in template: {}", name))?;
+    Ok(manifest)
+}
+
+/// Names of all built-in and user templates, sorted
+pub fn list_templates() -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN_TEMPLATES.iter().map(|(name, _)| name.to_string()).collect();
+
+    if let Ok(entries) = std::fs::read_dir(user_templates_dir()) {
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if let Some(stem) = path.file_stem().filter(|_| path.extension().is_some_and(|ext| ext == "toml")) {
+                names.push(stem.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Directory with user templates, which take precedence over built-in ones
+fn user_templates_dir() -> PathBuf {
+    SapphirePaths::resolve().templates_dir()
+}
@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use sapphire_core::paths::SapphirePaths;
+use crate::brew::{get_client, ClientOverrides};
+use crate::core::config::ShardConfig;
+use crate::utils::{ShardResult, ResultExt, ensure_parent_dir_exists, log_debug, log_step, log_success, log_warning};
+
+/// Records when `brew update` last succeeded
+const STAMP_FILE: &str = "brew_updated";
+
+/// Whether `brew update` runs before applying or upgrading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdatePolicy {
+    /// Only if the last update is older than `update_interval_hours`
+    Auto,
+    /// `--update`: always, failing if brew cannot update
+    Always,
+    /// `--no-update`: never
+    Never,
+}
+
+impl UpdatePolicy {
+    /// Policy for the `--update` and `--no-update` flags
+    pub fn from_flags(update: bool, no_update: bool) -> Self {
+        match (update, no_update) {
+            (true, _) => UpdatePolicy::Always,
+            (_, true) => UpdatePolicy::Never,
+            _ => UpdatePolicy::Auto,
+        }
+    }
+}
+
+/// Run `brew update` if the policy and the last update call for it
+///
+/// Automatic updates that fail, e.g. when offline, only print a warning so
+/// applies keep working with the existing package index.
+pub fn refresh(policy: UpdatePolicy, overrides: ClientOverrides) -> ShardResult<()> {
+    let interval_hours = ShardConfig::load().unwrap_or_default().brew.update_interval_hours;
+
+    match policy {
+        UpdatePolicy::Never => return Ok(()),
+        UpdatePolicy::Auto if interval_hours == 0 => return Ok(()),
+        UpdatePolicy::Auto => {
+            if let Some(age) = last_update_age() && age < Duration::from_secs(interval_hours * 60 * 60) {
+                log_debug(&format!("Homebrew was updated {} minute(s) ago, skipping brew update", age.as_secs() / 60));
+                return Ok(());
+            }
+        }
+        UpdatePolicy::Always => {}
+    }
+
+    log_step("Updating Homebrew...");
+    match get_client(overrides).update() {
+        Ok(()) => {
+            log_success("Homebrew is up to date");
+            if let Err(e) = write_stamp() {
+                log_warning(&format!("Could not record the Homebrew update: {}", e));
+            }
+            Ok(())
+        }
+        Err(e) if policy == UpdatePolicy::Auto => {
+            log_warning(&format!("brew update failed, continuing with the existing package index: {}", e));
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Time since the last recorded update, `None` if there is none
+fn last_update_age() -> Option<Duration> {
+    std::fs::metadata(stamp_path()).ok()?
+        .modified().ok()?
+        .elapsed().ok()
+}
+
+fn write_stamp() -> ShardResult<()> {
+    let path = stamp_path();
+    ensure_parent_dir_exists(&path)?;
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    std::fs::write(&path, format!("{}\n", now.as_secs()))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+pub fn stamp_path() -> PathBuf {
+    SapphirePaths::resolve().state_dir().join(STAMP_FILE)
+}
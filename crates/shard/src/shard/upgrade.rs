@@ -0,0 +1,167 @@
+use std::collections::BTreeSet;
+use crate::brew::{get_client, BatchFailure, ClientOverrides};
+use crate::brew::validate as validation;
+use crate::core::config::ShardConfig;
+use crate::core::env;
+use crate::core::manifest::merge_options;
+use crate::shard::manager::ShardManager;
+use crate::utils::{ShardError, ShardResult, log_step, log_success, log_warning, log_error, log_debug};
+
+/// Upgrade managed packages without reconciling shards
+///
+/// Each target may be a package name, the name of an enabled shard, or "all".
+/// Only packages that brew reports as outdated are upgraded, pinned formulae
+/// are left alone, and nothing is ever uninstalled.
+pub fn upgrade(targets: &[String], greedy: bool, dry_run: bool, overrides: ClientOverrides) -> ShardResult<()> {
+    let manager = ShardManager::new()?;
+    let manifests = manager.load_active_manifests()?;
+
+    let mut formulae = BTreeSet::new();
+    let mut casks = BTreeSet::new();
+
+    for target in targets {
+        if target.eq_ignore_ascii_case("all") {
+            for (_, manifest) in &manifests {
+                formulae.extend(manifest.formulae.iter().cloned());
+                casks.extend(manifest.casks.iter().cloned());
+            }
+        } else if let Some((_, manifest)) = manifests.iter().find(|(name, _)| name == target) {
+            log_debug(&format!("Selecting all packages from shard '{}'", target));
+            formulae.extend(manifest.formulae.iter().cloned());
+            casks.extend(manifest.casks.iter().cloned());
+        } else {
+            validation::validate_package_name(target)?;
+
+            let is_formula = manifests.iter().any(|(_, m)| m.formulae.contains(target));
+            let is_cask = manifests.iter().any(|(_, m)| m.casks.contains(target));
+
+            if !is_formula && !is_cask {
+                log_warning(&format!("'{}' is neither an enabled shard nor a managed package. Skipping.", target));
+                continue;
+            }
+            if is_formula {
+                formulae.insert(target.clone());
+            }
+            if is_cask {
+                casks.insert(target.clone());
+            }
+        }
+    }
+
+    let config = ShardConfig::load().unwrap_or_default();
+    let ignored: Vec<String> = formulae.iter().chain(&casks)
+        .filter(|name| config.ignore.is_ignored(name))
+        .cloned()
+        .collect();
+    if !ignored.is_empty() {
+        log_step(&format!("Skipping {} package(s) matched by shard.ignore: {}", ignored.len(), ignored.join(", ")));
+        formulae.retain(|name| !config.ignore.is_ignored(name));
+        casks.retain(|name| !config.ignore.is_ignored(name));
+    }
+
+    if formulae.is_empty() && casks.is_empty() {
+        log_warning("No managed packages selected for upgrade.");
+        return Ok(());
+    }
+
+    log_step("Checking for available upgrades...");
+    let env = env::merge(std::iter::once(&config.env).chain(manifests.iter().map(|(_, m)| &m.env)));
+    let brew_client = get_client(overrides).with_env(env::resolve(&env)?);
+    let report = brew_client.get_outdated(greedy)?;
+
+    let mut formulae_to_upgrade = Vec::new();
+    for package in report.formulae.iter().filter(|p| formulae.contains(&p.name)) {
+        if package.pinned {
+            log_warning(&format!("Skipping pinned formula {} ({})", package.name, package.installed_version()));
+        } else {
+            formulae_to_upgrade.push(package.name.clone());
+        }
+    }
+
+    let casks_to_upgrade: Vec<String> = report.casks.iter()
+        .filter(|p| casks.contains(&p.name))
+        .map(|p| p.name.clone())
+        .collect();
+
+    if formulae_to_upgrade.is_empty() && casks_to_upgrade.is_empty() {
+        log_success("All selected packages are up to date");
+        return Ok(());
+    }
+
+    if dry_run {
+        if !formulae_to_upgrade.is_empty() {
+            log_step(&format!("Would upgrade {} formula(s): {}", formulae_to_upgrade.len(), formulae_to_upgrade.join(", ")));
+        }
+        if !casks_to_upgrade.is_empty() {
+            log_step(&format!("Would upgrade {} cask(s): {}", casks_to_upgrade.len(), casks_to_upgrade.join(", ")));
+        }
+        return Ok(());
+    }
+
+    let mut failures = Vec::new();
+
+    if !formulae_to_upgrade.is_empty() {
+        log_step(&format!("Upgrading {} formula(s)...", formulae_to_upgrade.len()));
+
+        // Formulae built from source or HEAD need their own brew invocation
+        let mut batch = Vec::new();
+        for formula in &formulae_to_upgrade {
+            let options = manifests.iter()
+                .find_map(|(_, m)| m.formula_builds.get(formula))
+                .map(|build| build.upgrade_args())
+                .unwrap_or_default();
+
+            if options.is_empty() {
+                batch.push(formula.clone());
+            } else if let Err(e) = brew_client.upgrade_formula_with_options(formula, &options) {
+                log_warning(&format!("Error upgrading {}: {}", formula, e));
+                failures.push(BatchFailure { name: formula.clone(), error: e.to_string() });
+            }
+        }
+
+        failures.extend(BatchFailure::collect("upgrade", &batch, brew_client.batch_upgrade_formulae(&batch)?));
+    }
+
+    if !casks_to_upgrade.is_empty() {
+        log_step(&format!("Upgrading {} cask(s)...", casks_to_upgrade.len()));
+        let default_options = &config.cask_options;
+
+        let mut batch = Vec::new();
+        for cask in &casks_to_upgrade {
+            let declared = manifests.iter()
+                .find(|(_, m)| m.casks.contains(cask))
+                .map(|(_, m)| m.cask_options_for(cask))
+                .unwrap_or_default();
+            let mut options = merge_options(default_options, declared);
+            // Auto-updating casks are only upgraded by brew when --greedy is passed
+            if greedy {
+                options.push("--greedy".to_string());
+            }
+
+            if options.is_empty() {
+                batch.push(cask.clone());
+            } else if let Err(e) = brew_client.upgrade_cask_with_options(cask, &options) {
+                log_warning(&format!("Error upgrading {}: {}", cask, e));
+                failures.push(BatchFailure { name: cask.clone(), error: e.to_string() });
+            }
+        }
+
+        failures.extend(BatchFailure::collect("upgrade", &batch, brew_client.batch_upgrade_casks(&batch)?));
+    }
+
+    let total = formulae_to_upgrade.len() + casks_to_upgrade.len();
+    if failures.is_empty() {
+        log_success(&format!("Upgraded {} package(s)", total));
+        return Ok(());
+    }
+
+    if failures.len() < total {
+        log_success(&format!("Upgraded {} package(s)", total - failures.len()));
+    }
+    log_error(&format!("{} of {} package(s) failed to upgrade:", failures.len(), total));
+    for failure in &failures {
+        log_error(&format!("  {}: {}", failure.name, failure.error));
+    }
+    let names: Vec<&str> = failures.iter().map(|failure| failure.name.as_str()).collect();
+    Err(ShardError::PackageError(format!("Failed to upgrade {}", names.join(", "))))
+}
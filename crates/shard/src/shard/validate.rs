@@ -0,0 +1,229 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use crate::brew::{get_client, options, ClientOverrides, validate as validation, BrewClient, FormulaRenames};
+use crate::core::manifest::{link_conflicts, Manifest};
+use crate::shard::context;
+use crate::shard::manager::ShardManager;
+use crate::utils::{ShardError, ShardResult, filesystem, log_debug, log_error, log_step, log_success, log_warning};
+use crate::vscode;
+
+/// Link preferences of undeclared formulae and versions that all request linking
+fn check_links(manifest: &Manifest) -> Vec<String> {
+    let mut problems: Vec<String> = manifest.links.keys()
+        .filter(|formula| !manifest.formulae.contains(formula))
+        .map(|formula| format!("Link preference of '{}', which is not a declared formula", formula))
+        .collect();
+
+    let linked = manifest.links.iter().filter(|(_, link)| link.is_linked()).map(|(formula, _)| formula);
+    problems.extend(link_conflicts(linked).into_iter()
+        .map(|versions| format!("{} all request linking, only one version can be linked", versions.join(" and "))));
+    problems
+}
+
+/// Check that shards parse, declare valid names and avoid disabled packages
+///
+/// Deprecated and renamed packages are reported as warnings, anything that
+/// would make apply fail is an error. `"all"` includes the disabled shards.
+pub fn validate(target: &str, overrides: ClientOverrides) -> ShardResult<()> {
+    let shards: Vec<(String, PathBuf)> = if target.eq_ignore_ascii_case("all") {
+        let manager = ShardManager::new()?;
+        let mut names = manager.list_shards()?;
+        names.sort();
+        let mut disabled = manager.list_disabled_shards()?;
+        disabled.sort();
+
+        // Disabled shards are checked too, they rot unnoticed until enabled again
+        let enabled = names.into_iter()
+            .map(|name| { let path = manager.get_shard_path(&name); (name, path) });
+        let disabled = disabled.into_iter()
+            .map(|name| { let path = manager.get_disabled_shard_path(&name); (format!("{} (disabled)", name), path) });
+        enabled.chain(disabled).collect()
+    } else {
+        let path = PathBuf::from(filesystem::resolve_manifest_path(target)?);
+        if !filesystem::path_exists(&path) {
+            return Err(ShardError::NotFound(target.to_string()));
+        }
+        vec![(target.to_string(), path)]
+    };
+
+    let brew_client = get_client(overrides);
+    // Renames are only a hint, the check is skipped if they cannot be fetched
+    let renames = FormulaRenames::load().ok();
+    let mut errors = 0;
+    let mut warnings = 0;
+
+    for (name, path) in shards {
+        log_step(&format!("Validating shard '{}'...", name));
+
+        let manifest = match Manifest::from_file(&path) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                log_error(&e.to_string());
+                errors += 1;
+                continue;
+            }
+        };
+
+        let problems = check_names(&manifest);
+        for problem in &problems {
+            log_error(problem);
+        }
+        errors += problems.len();
+
+        for unknown in options::check(&brew_client, &manifest.formula_options) {
+            log_warning(&unknown.describe());
+            warnings += 1;
+        }
+
+        let link_problems = check_links(&manifest);
+        for problem in &link_problems {
+            log_warning(problem);
+        }
+        warnings += link_problems.len();
+
+        if let Some(renames) = &renames {
+            let renamed = check_renamed(renames, &manifest);
+            for problem in &renamed {
+                log_warning(problem);
+            }
+            warnings += renamed.len();
+        }
+
+        // Invalid names are reported above and would fail the whole query
+        let valid = |names: &[String]| -> Vec<String> {
+            names.iter().filter(|name| validation::is_valid_package_name(name)).cloned().collect()
+        };
+        let report = match brew_client.get_deprecations(&valid(&manifest.formulae), &valid(&manifest.casks)) {
+            Ok(report) => report,
+            Err(e) => {
+                log_warning(&format!("Could not check for deprecated packages: {}", e));
+                continue;
+            }
+        };
+
+        for (kind, deprecation) in report.formulae.iter().map(|d| ("Formula", d))
+            .chain(report.casks.iter().map(|d| ("Cask", d))) {
+            let message = format!("{} '{}' is {}", kind, deprecation.name, deprecation.describe());
+            if deprecation.disabled {
+                log_error(&message);
+                errors += 1;
+            } else {
+                log_warning(&message);
+                warnings += 1;
+            }
+        }
+    }
+
+    if errors > 0 {
+        return Err(ShardError::ValidationError(format!("{} problem(s) and {} warning(s) found", errors, warnings)));
+    }
+
+    if warnings > 0 {
+        log_warning(&format!("Valid with {} warning(s)", warnings));
+    } else {
+        log_success("All shards are valid");
+    }
+    Ok(())
+}
+
+/// Declared formulae that Homebrew has renamed
+fn check_renamed(renames: &FormulaRenames, manifest: &Manifest) -> Vec<String> {
+    manifest.formulae.iter()
+        .filter_map(|name| renames.current_name(name)
+            .map(|current| format!("Formula '{}' was renamed to '{}', see `shard fix-renames`", name, current)))
+        .collect()
+}
+
+/// Declared packages that were renamed or disabled in Homebrew
+///
+/// Used when a shard that sat in the disabled directory for a while comes
+/// back. Lookups that fail are skipped, the shard is checked as far as possible.
+pub fn check_removed(brew_client: &BrewClient, manifest: &Manifest) -> Vec<String> {
+    let mut problems = match FormulaRenames::load() {
+        Ok(renames) => check_renamed(&renames, manifest),
+        Err(e) => {
+            log_debug(&format!("Skipping the check for renamed formulae: {}", e));
+            Vec::new()
+        }
+    };
+
+    let valid = |names: &[String]| -> Vec<String> {
+        names.iter().filter(|name| validation::is_valid_package_name(name)).cloned().collect()
+    };
+    match brew_client.get_deprecations(&valid(&manifest.formulae), &valid(&manifest.casks)) {
+        Ok(report) => problems.extend(report.formulae.iter().map(|d| ("Formula", d))
+            .chain(report.casks.iter().map(|d| ("Cask", d)))
+            .filter(|(_, deprecation)| deprecation.disabled)
+            .map(|(kind, deprecation)| format!("{} '{}' is {}", kind, deprecation.name, deprecation.describe()))),
+        Err(e) => log_debug(&format!("Skipping the check for disabled packages: {}", e)),
+    }
+
+    problems
+}
+
+/// Invalid package names, taps, options and contexts declared by a manifest
+pub fn check_names(manifest: &Manifest) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for name in manifest.formulae.iter().chain(&manifest.casks) {
+        if let Err(e) = validation::validate_package_name(name) {
+            problems.push(e.to_string());
+        }
+    }
+
+    for tap in &manifest.taps {
+        if let Err(e) = validation::validate_tap_name(&tap.name) {
+            problems.push(e.to_string());
+        }
+        if let Some(url) = &tap.url && let Err(e) = validation::validate_tap_url(url) {
+            problems.push(e.to_string());
+        }
+    }
+
+    for extension in &manifest.vscode_extensions {
+        if let Err(e) = vscode::validate_extension_id(extension) {
+            problems.push(e.to_string());
+        }
+    }
+
+    for options in manifest.cask_options.values().chain(manifest.formula_options.values()) {
+        if let Err(e) = validation::validate_options(options) {
+            problems.push(e.to_string());
+        }
+    }
+
+    for (package, contexts) in &manifest.contexts {
+        for name in contexts {
+            if context::validate_name(name).is_err() {
+                problems.push(format!("Invalid context '{}' of {}", name, package));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Taps and packages a manifest declares more than once
+pub fn check_duplicates(manifest: &Manifest) -> Vec<String> {
+    let taps: Vec<String> = manifest.taps.iter().map(|tap| tap.name.clone()).collect();
+    // VS Code treats extension IDs case-insensitively
+    let extensions: Vec<String> = manifest.vscode_extensions.iter().map(|id| id.to_lowercase()).collect();
+    let mut problems = Vec::new();
+
+    for (kind, names) in [
+        ("Tap", &taps),
+        ("Formula", &manifest.formulae),
+        ("Cask", &manifest.casks),
+        ("VS Code extension", &extensions),
+    ] {
+        let mut seen = BTreeSet::new();
+        let mut reported = BTreeSet::new();
+        for name in names {
+            if !seen.insert(name) && reported.insert(name) {
+                problems.push(format!("{} '{}' is listed more than once", kind, name));
+            }
+        }
+    }
+
+    problems
+}
@@ -77,18 +77,60 @@ pub fn backup_file(path: &Path) -> ShardResult<Option<PathBuf>> {
     Ok(Some(backup_path))
 }
 
+/// Calculates the total size in bytes of a file or directory tree
+/// Symlinks are not followed, so linked files are only counted once
+pub fn dir_size(path: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| dir_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Formats a byte count as a human readable size (e.g. "1.5 GB")
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
 /// Resolve a manifest name or path to a full path
-/// Handles special shard names like "user", "system", or any custom shard name
+/// Handles special shard names like "user", "system", or any custom shard name,
+/// including shards in the managed system layer
 /// Returns a full path to the manifest file
 pub fn resolve_manifest_path(manifest_target: &str) -> ShardResult<String> {
     // If it looks like a path, just expand tilde
-    if manifest_target.contains('/') || manifest_target.ends_with(".toml") {
+    if manifest_target.contains('/') || crate::core::manifest::ManifestFormat::is_manifest(Path::new(manifest_target)) {
         Ok(shellexpand::tilde(manifest_target).to_string())
     } else {
         // Assume it's a shard name (validate it)
         crate::brew::validate::validate_package_name(manifest_target)
             .with_context(|| format!("Invalid shard name: {}", manifest_target))?;
-        let shards_dir = shellexpand::tilde("~/.sapphire/shards").to_string();
-        Ok(format!("{}/{}.toml", shards_dir, manifest_target))
+        // The manager knows whether the shard lives in the system layer
+        let manager = crate::shard::manager::ShardManager::new()?;
+        Ok(manager.get_shard_path(manifest_target).to_string_lossy().into_owned())
     }
 } 
\ No newline at end of file
@@ -10,10 +10,12 @@ pub use observability::{
     log_warning,
     log_error,
     log_step,
+    log_plain,
     log_debug,
     log_trace,
     Logger,
     LogLevel,
+    Glyph,
 };
 
 // Re-export commonly used helper functions
@@ -26,4 +28,6 @@ pub use filesystem::{
     rename_path,
     remove_file,
     backup_file,
+    dir_size,
+    format_size,
 }; 
\ No newline at end of file
@@ -3,14 +3,21 @@ use std::error::Error as StdError;
 use std::io;
 use std::path::PathBuf;
 use thiserror::Error;
-use tracing::{debug, error, info, trace, warn, Level};
+use tracing::{debug, trace, Level};
 use tracing_subscriber::{FmtSubscriber, EnvFilter};
 use console::style;
 use std::sync::Once;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
 // Static to ensure we only initialize logging once
 static INIT_LOGGER: Once = Once::new();
 
+// Level of console output, a LogLevel as u8 (Info until set)
+static CONSOLE_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+// Whether markers are unicode glyphs or plain ASCII
+static GLYPHS: AtomicBool = AtomicBool::new(true);
+
 //-------------------------------------------------------------------------------
 // Error Handling
 //-------------------------------------------------------------------------------
@@ -30,6 +37,9 @@ pub enum ShardError {
     #[error("Cannot modify protected shard: {0}")]
     Protected(String),
     
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    
     #[error("Filesystem error at {path}: {source}")]
     Filesystem { 
         path: PathBuf, 
@@ -48,6 +58,24 @@ pub enum ShardError {
     #[error("Homebrew error: {0}")]
     BrewError(String),
     
+    #[error("`brew {command}` failed: {failure}")]
+    BrewFailure {
+        command: String,
+        failure: crate::brew::BrewFailure,
+        /// brew's full stderr, the message only shows the recognised failure
+        stderr: String,
+    },
+    
+    #[error("`brew {command}` timed out after {seconds} seconds{}. {hint}", on_packages(.packages))]
+    BrewTimeout {
+        command: String,
+        /// Packages brew was working on, all packages of the command if unknown
+        packages: Vec<String>,
+        seconds: u64,
+        /// How to allow more time
+        hint: String,
+    },
+    
     #[error("Package error: {0}")]
     PackageError(String),
     
@@ -66,10 +94,38 @@ pub enum ShardError {
     #[error(transparent)]
     Io(#[from] std::io::Error),
     
+    #[error(transparent)]
+    Secret(#[from] sapphire_core::secrets::SecretError),
+    
+    #[error(transparent)]
+    Lock(#[from] sapphire_core::lock::LockError),
+    
+    #[error(transparent)]
+    ReadOnly(#[from] sapphire_core::read_only::ReadOnlyError),
+    
     #[error("User interaction error: {0}")]
     Interaction(String),
 }
 
+impl ShardError {
+    /// The brew failure behind this error, if it was recognised
+    pub fn brew_failure(&self) -> Option<&crate::brew::BrewFailure> {
+        match self {
+            ShardError::BrewFailure { failure, .. } => Some(failure),
+            _ => None,
+        }
+    }
+}
+
+/// ` on a, b` naming the packages of an error, empty without packages
+fn on_packages(packages: &[String]) -> String {
+    if packages.is_empty() {
+        String::new()
+    } else {
+        format!(" on {}", packages.join(", "))
+    }
+}
+
 // Implement From for dialoguer::Error
 impl From<dialoguer::Error> for ShardError {
     fn from(err: dialoguer::Error) -> Self {
@@ -109,7 +165,9 @@ where
 //-------------------------------------------------------------------------------
 
 /// LogLevel enum for type-safe log level selection
-#[derive(Debug, Clone, Copy)]
+///
+/// Ordered from quietest to most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
     Error,
     Warn,
@@ -140,6 +198,17 @@ impl LogLevel {
             _ => LogLevel::Trace,
         }
     }
+    
+    /// Level for the `-q` and `-v` flags of a command line: errors only when
+    /// quiet, debug output for `-v` and brew command lines for `-vv`
+    pub fn from_flags(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => LogLevel::Error,
+            (false, 0) => LogLevel::Info,
+            (false, 1) => LogLevel::Debug,
+            (false, _) => LogLevel::Trace,
+        }
+    }
 }
 
 /// Logger struct for managing logging initialization
@@ -147,7 +216,12 @@ pub struct Logger;
 
 impl Logger {
     /// Initialize the logging subsystem with a specific LogLevel
+    ///
+    /// The tracing subscriber is only installed by the first call, later
+    /// calls still change the level of console output.
     pub fn init(level: LogLevel) {
+        Self::set_level(level);
+        
         // Only initialize once
         INIT_LOGGER.call_once(|| {
             let log_level = level.to_tracing_level();
@@ -160,7 +234,7 @@ impl Logger {
             let subscriber = FmtSubscriber::builder()
                 .with_env_filter(filter)
                 .with_target(false)
-                .with_ansi(true)
+                .with_ansi(console::colors_enabled())
                 .finish();
             
             // Set the global default subscriber
@@ -172,6 +246,37 @@ impl Logger {
         });
     }
     
+    /// Set which console messages are printed without installing a subscriber
+    ///
+    /// For programs that embed shard and set up tracing themselves.
+    pub fn set_level(level: LogLevel) {
+        CONSOLE_LEVEL.store(level as u8, Ordering::Relaxed);
+    }
+    
+    /// Disable colors in console and tracing output, e.g. for `--no-color`
+    ///
+    /// Call before [`Logger::init`]. `NO_COLOR` and output that is not a
+    /// terminal already disable them.
+    pub fn disable_colors() {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    
+    /// Use unicode glyphs such as ✓ and → as markers, or ASCII replacements
+    pub fn set_glyphs(enabled: bool) {
+        GLYPHS.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// The current level of console output
+    pub fn level() -> LogLevel {
+        LogLevel::from_verbosity(CONSOLE_LEVEL.load(Ordering::Relaxed))
+    }
+    
+    /// Whether messages of a level are printed
+    pub fn enabled(level: LogLevel) -> bool {
+        level <= Self::level()
+    }
+    
     /// Initialize with default level (Info)
     pub fn init_default() {
         Self::init(LogLevel::Info)
@@ -192,28 +297,79 @@ pub fn init_logging(verbosity: Option<u8>) {
     }
 }
 
+/// A marker in console output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Glyph {
+    Success,
+    Step,
+    Warning,
+    Error,
+    /// List item
+    Bullet,
+    /// From one value to another, e.g. between versions
+    Arrow,
+    /// Something missing, e.g. a tap that would be added
+    Missing,
+}
+
+impl Glyph {
+    /// The glyph, or its ASCII replacement if glyphs are disabled
+    pub fn as_str(self) -> &'static str {
+        let unicode = GLYPHS.load(Ordering::Relaxed);
+        match self {
+            Glyph::Success => if unicode { "✓" } else { "ok" },
+            Glyph::Step => if unicode { "→" } else { "->" },
+            Glyph::Warning => "!",
+            Glyph::Error => if unicode { "✗" } else { "x" },
+            Glyph::Bullet => if unicode { "•" } else { "*" },
+            Glyph::Arrow => if unicode { "→" } else { "->" },
+            Glyph::Missing => if unicode { "❌" } else { "x" },
+        }
+    }
+}
+
+impl fmt::Display for Glyph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Console messages are printed directly rather than through tracing, which
+// would print them a second time with a timestamp. Quiet mode only keeps errors.
+// Output a command was asked for, such as the tables of `list` or `diff`, is
+// printed with println! and not affected by the level.
+
 /// Log a success message
 pub fn log_success(message: &str) {
-    info!("{} {}", style("✓").bold().green(), message);
-    println!("{} {}", style("✓").bold().green(), message);
+    if Logger::enabled(LogLevel::Info) {
+        println!("{} {}", style(Glyph::Success).bold().green(), message);
+    }
 }
 
 /// Log a warning message
 pub fn log_warning(message: &str) {
-    warn!("{} {}", style("!").bold().yellow(), message);
-    println!("{} {}", style("!").bold().yellow(), message);
+    if Logger::enabled(LogLevel::Warn) {
+        println!("{} {}", style(Glyph::Warning).bold().yellow(), message);
+    }
 }
 
 /// Log an error message
 pub fn log_error(message: &str) {
-    error!("{} {}", style("✗").bold().red(), message);
-    eprintln!("{} {}", style("✗").bold().red(), message);
+    eprintln!("{} {}", style(Glyph::Error).bold().red(), message);
 }
 
 /// Log a step message
 pub fn log_step(message: &str) {
-    info!("{} {}", style("→").bold().blue(), message);
-    println!("{} {}", style("→").bold().blue(), message);
+    if Logger::enabled(LogLevel::Info) {
+        println!("{} {}", style(Glyph::Step).bold().blue(), message);
+    }
+}
+
+/// Print a line of progress output without a marker, e.g. a summary table row
+pub fn log_plain(message: &str) {
+    if Logger::enabled(LogLevel::Info) {
+        println!("{}", message);
+    }
 }
 
 /// Log a debug message 
@@ -0,0 +1,67 @@
+//! VS Code extensions declared by shards.
+//!
+//! Extensions are installed and listed with the `code` command line tool,
+//! which VS Code puts on the `PATH` through "Shell Command: Install 'code'
+//! command in PATH". Extension IDs are `publisher.name` and compared without
+//! regard to case, as VS Code does.
+
+use std::process::Command;
+use lazy_static::lazy_static;
+use regex::Regex;
+use crate::utils::{ShardError, ShardResult};
+
+/// The VS Code command line tool
+const CODE_CLI: &str = "code";
+
+lazy_static! {
+    // publisher.name, never starting with a dash so an ID cannot be taken for an option
+    static ref EXTENSION_ID_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9][a-zA-Z0-9_\-]*\.[a-zA-Z0-9][a-zA-Z0-9_\-]*$").unwrap();
+}
+
+/// Validate an extension ID such as `rust-lang.rust-analyzer`
+pub fn validate_extension_id(id: &str) -> ShardResult<&str> {
+    if !EXTENSION_ID_REGEX.is_match(id) {
+        return Err(ShardError::ValidationError(
+            format!("Invalid VS Code extension ID: '{}'. IDs must be in the format 'publisher.name'", id)
+        ));
+    }
+    Ok(id)
+}
+
+/// Whether the `code` command line tool can be run
+pub fn is_available() -> bool {
+    Command::new(CODE_CLI)
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// IDs of the installed extensions, lowercased
+pub fn installed_extensions() -> ShardResult<Vec<String>> {
+    let output = Command::new(CODE_CLI).arg("--list-extensions").output()?;
+    if !output.status.success() {
+        return Err(ShardError::ApplicationError(format!("Failed to list VS Code extensions: {}",
+            String::from_utf8_lossy(&output.stderr).trim())));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_lowercase)
+        .collect())
+}
+
+/// Install an extension from the marketplace
+pub fn install_extension(id: &str) -> ShardResult<()> {
+    validate_extension_id(id)?;
+    let output = Command::new(CODE_CLI)
+        .args(["--install-extension", id])
+        .output()?;
+
+    if !output.status.success() {
+        return Err(ShardError::ApplicationError(format!("Failed to install VS Code extension {}: {}",
+            id, String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(())
+}